@@ -12,34 +12,52 @@
 //
 // You should have received a copy of the GNU Lesser General Public License
 // along with fileext. If not, see <http://www.gnu.org/licenses/>.
-use crate::platform::*;
+
+//! Applications and the options used to open them.
+use crate::backend::{Association, Backend, Scope};
+use crate::command::CommandTemplate;
+use crate::icon::IconImage;
+use crate::mime::{MimeDatabase, MimePackage, MimeType};
+use crate::plan::Plan;
+use crate::platform;
 use crate::result::{Error, Result};
-use std::{env, path::PathBuf};
+use crate::snapshot::Snapshot;
+use crate::transaction::Transaction;
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{env, fs};
 
 pub(crate) const APP_READ: u32 = 0b00000001;
 pub(crate) const APP_WRITE: u32 = 0b00000010;
 pub(crate) const APP_STRICT: u32 = 0b00000100;
+pub(crate) const APP_DRY_RUN: u32 = 0b00001000;
 pub(crate) fn bitflag_eq(lhs: u32, rhs: u32) -> bool {
     (lhs & rhs) == rhs
 }
 
+/// Options and flags which can be used to configure how an [Application] is opened or created.
 #[derive(Debug, Clone)]
 pub struct OpenOptions {
     flags: u32,
     path: String,
+    backend: Arc<dyn Backend>,
+    scope: Option<Scope>,
 }
 impl OpenOptions {
+    /// Create a blank set of options for the executable at `path`, using [the platform default backend](platform::default_backend).
     pub fn new(path: impl ToString) -> Self {
         Self {
             flags: 0,
             path: path.to_string(),
+            backend: platform::default_backend(),
+            scope: None,
         }
     }
+    /// Create a blank set of options for the currently running executable.
     pub fn current() -> Self {
-        Self {
-            flags: 0,
-            path: current_exe_path().unwrap(),
-        }
+        Self::new(current_exe_path().unwrap())
     }
 
     fn flag_set(mut self, flag: u32, yes: bool) -> Self {
@@ -51,46 +69,344 @@ impl OpenOptions {
         self
     }
 
-    pub fn read(mut self, yes: bool) -> Self {
+    /// Allow reading the file type associations of the application.
+    pub fn read(self, yes: bool) -> Self {
         self.flag_set(APP_READ, yes)
     }
-    pub fn write(mut self, yes: bool) -> Self {
+    /// Allow creating, deleting and modifying the application and its file type associations.
+    pub fn write(self, yes: bool) -> Self {
         self.flag_set(APP_WRITE, yes)
     }
-    pub fn strict(mut self, yes: bool) -> Self {
+    /// Enable strict mode.
+    ///
+    /// In strict mode, [creating](OpenOptions::create) and [opening](OpenOptions::get) an application
+    /// as well as [associating file types](Application::set_file_type_association) with it fail, unless:
+    /// - the executable the options were created for exists and is executable,
+    /// - the application ID is [well-formed](validate_id) and the icon file and [icon images](IconImage::validate)
+    ///   of the application exist, when creating it,
+    /// - the application isn't already registered with another executable,
+    /// - the [backend](Backend::validate) accepts the application, e.g. the desktop entry written for it on Linux
+    ///   passes [validation](crate::platform::xdg::desktop_entry::DesktopEntry::validate), when creating it.
+    pub fn strict(self, yes: bool) -> Self {
         self.flag_set(APP_STRICT, yes)
     }
+    /// Enable dry-run mode.
+    ///
+    /// In dry-run mode, [creating](OpenOptions::create) an application and every change made through the [Application]
+    /// or [Transaction] it returns, like [associating file types](Application::set_file_type_association) and
+    /// [deleting](Application::delete) it, only record the files or registry keys and values they would create, modify
+    /// or remove into a [Plan], returned by [Application::plan] and [Transaction::plan]. Nothing is touched.
+    ///
+    /// Every application [created](OpenOptions::create) or [opened](OpenOptions::get) starts a new plan, which sees the
+    /// changes recorded into it before, e.g. associating file types with an application created in the same dry run.
+    /// [Creating](OpenOptions::create) and [opening](OpenOptions::get) the application fail with
+    /// [Error::Unsupported] if the backend can't plan its changes.
+    pub fn dry_run(self, yes: bool) -> Self {
+        self.flag_set(APP_DRY_RUN, yes)
+    }
+    /// Use `backend` instead of the platform default one.
+    pub fn backend(mut self, backend: impl Backend + 'static) -> Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+    /// Register the application for the current user or for every user of the machine, instead of
+    /// the scope the backend uses by default, which is [Scope::User] for the platform ones.
+    ///
+    /// [Creating](OpenOptions::create) and [opening](OpenOptions::get) the application fail with
+    /// [Error::Unsupported] if the backend has no scopes.
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+    /// Get the backend of the options, switched to their [scope](OpenOptions::scope) if one is set,
+    /// together with the plan it records into in [dry-run mode](OpenOptions::dry_run).
+    fn scoped_backend(&self) -> Result<(Arc<dyn Backend>, Option<Plan>)> {
+        let backend = match self.scope {
+            Some(scope) => self.backend.scoped(scope)?,
+            None => Arc::clone(&self.backend),
+        };
+        if bitflag_eq(self.flags, APP_DRY_RUN) {
+            let plan = Plan::new();
+            Ok((backend.dry_run(&plan)?, Some(plan)))
+        } else {
+            Ok((backend, None))
+        }
+    }
 
+    /// Register a new application.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the options don't allow [writing](OpenOptions::write).
+    /// - Any error of [strict mode](OpenOptions::strict) validation, if it's enabled.
     pub fn create(self, descriptor: ApplicationDescriptor) -> Result<Application> {
         if bitflag_eq(self.flags, APP_WRITE) {
-            #[cfg(windows)]
-            unsafe {
-                windows::CreateProgID(descriptor.id.clone(), descriptor.name, descriptor.icon_path)?
-            };
+            let (backend, plan) = self.scoped_backend()?;
+            if bitflag_eq(self.flags, APP_STRICT) {
+                validate_descriptor(backend.as_ref(), &self.path, &descriptor)?;
+            }
+            backend.create(&self.path, &descriptor)?;
 
-            Ok(self.get(descriptor.id)?.unwrap())
+            Ok(Application {
+                id: descriptor.id,
+                path: self.path,
+                flags: self.flags,
+                backend,
+                plan,
+            })
         } else {
             Err(Error::WritePermissionRequired)
         }
     }
+    /// Open a registered application, returning [None] if there is no application with the supplied ID.
+    ///
+    /// # Errors
+    /// - Any error of [strict mode](OpenOptions::strict) validation, if it's enabled.
     pub fn get(self, id: impl ToString) -> Result<Option<Application>> {
-        Ok(
-            #[cfg(windows)]
-            unsafe {
-                windows::GetProcID(id.to_string()).map(|id| Application {
-                    id: id.id,
-                    path: self.path,
-                    flags: self.flags,
-                })
-            },
-        )
+        let id = id.to_string();
+        if bitflag_eq(self.flags, APP_STRICT) {
+            validate_executable(&self.path)?;
+        }
+
+        let (backend, plan) = self.scoped_backend()?;
+        let Some(descriptor) = backend.get(&id)? else {
+            return Ok(None);
+        };
+        if bitflag_eq(self.flags, APP_STRICT) {
+            validate_owner(backend.as_ref(), &id, &self.path)?;
+        }
+        Ok(Some(Application {
+            id: descriptor.id,
+            path: self.path,
+            flags: self.flags,
+            backend,
+            plan,
+        }))
+    }
+    /// Capture the registrations of the applications `ids`, including the file types they're associated with, and the
+    /// handlers of `file_types` into a [Snapshot], in the [scope](OpenOptions::scope) of the options.
+    ///
+    /// On Windows this captures the ProgID keys of the applications, and the default value and `OpenWithProgids` of
+    /// the extensions. On Linux it captures the desktop entries, icons and MIME packages of the applications, and the
+    /// `mimeapps.list` entries listing them or the MIME types of `file_types`.
+    ///
+    /// # Errors
+    /// - [Error::ReadPermissionRequired] if the options don't allow [reading](OpenOptions::read).
+    /// - [Error::Unsupported] if the backend can't take snapshots, or can't look up a file type of `file_types`.
+    /// - [Error::InvalidMimeType] if one of `file_types` is a string which isn't a valid MIME type.
+    pub fn snapshot<T: IntoFileType>(
+        self,
+        ids: impl IntoIterator<Item = impl ToString>,
+        file_types: impl IntoIterator<Item = T>,
+    ) -> Result<Snapshot> {
+        if !bitflag_eq(self.flags, APP_READ) {
+            return Err(Error::ReadPermissionRequired);
+        }
+        let ids: Vec<String> = ids.into_iter().map(|id| id.to_string()).collect();
+        let file_types = collect_file_types(file_types)?;
+        let (backend, _) = self.scoped_backend()?;
+        backend.snapshot(&ids, &file_types)
+    }
+    /// Put back the state captured by [OpenOptions::snapshot], in the scope the snapshot was taken in.
+    ///
+    /// Captured targets get their captured values back, and the ones which didn't exist are removed. Associations of
+    /// the captured applications made since are removed first.
+    /// Caches derived from the restored files, like the MIME database on Linux, are regenerated rather than restored.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the options don't allow [writing](OpenOptions::write).
+    /// - [Error::Unsupported] if the backend can't restore the snapshot, or in [dry-run mode](OpenOptions::dry_run),
+    ///   which has no plan to return.
+    pub fn restore(self, snapshot: &Snapshot) -> Result<()> {
+        if !bitflag_eq(self.flags, APP_WRITE) {
+            return Err(Error::WritePermissionRequired);
+        }
+        if bitflag_eq(self.flags, APP_DRY_RUN) {
+            return Err(Error::Unsupported);
+        }
+        let (backend, _) = self.scoped_backend()?;
+        backend.restore(snapshot)
+    }
+    /// Start a [Transaction] registering applications launched through the executable the options were created for.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the options don't allow [writing](OpenOptions::write).
+    /// - [Error::Unsupported] if a [scope](OpenOptions::scope) is set and the backend has no scopes.
+    pub fn transaction(self) -> Result<Transaction> {
+        if !bitflag_eq(self.flags, APP_WRITE) {
+            return Err(Error::WritePermissionRequired);
+        }
+        let (backend, plan) = self.scoped_backend()?;
+        Ok(Transaction {
+            backend,
+            plan,
+            path: self.path,
+            flags: self.flags,
+            operations: Vec::new(),
+        })
     }
 }
 
+/// Description of an application to register.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ApplicationDescriptor {
+    /// Unique identifier of the application, e.g. `Vendor.Application`.
     pub id: String,
+    /// Human-readable name of the application.
     pub name: String,
+    /// Path to the icon of the application, empty if it has none.
     pub icon_path: String,
+    /// Images of the icon of the application at several sizes.
+    ///
+    /// On Linux they're installed into the [hicolor](crate::icon::HICOLOR) icon theme under the ID of the application,
+    /// which the desktop entry refers to instead of [icon_path](ApplicationDescriptor::icon_path), and removed along
+    /// with the application. Windows can't use PNG or SVG images as icons, so they're ignored there.
+    pub icon_images: Vec<IconImage>,
+    /// Actions the application can perform on files it's associated with.
+    ///
+    /// If there are none, the application gets a single `open` verb launching its executable with the file as an argument.
+    pub verbs: Vec<Verb>,
+    /// Name of the verb used when a file is double-clicked. If [None], the shell picks one, preferring `open`.
+    pub default_verb: Option<String>,
+}
+impl ApplicationDescriptor {
+    /// Get the verb used when a file is double-clicked:
+    /// the [default verb](ApplicationDescriptor::default_verb), the `open` verb or the first verb, in that order.
+    pub fn primary_verb(&self) -> Option<&Verb> {
+        let find = |name: &str| self.verbs.iter().find(|verb| verb.name == name);
+
+        self.default_verb
+            .as_deref()
+            .and_then(find)
+            .or_else(|| find(VERB_OPEN))
+            .or_else(|| self.verbs.first())
+    }
+}
+
+/// Name of the verb opening a file.
+pub const VERB_OPEN: &str = "open";
+
+/// Action an application can perform on a file, such as `open`, `edit` or `print`.
+///
+/// Verbs are stored under `shell\<name>\command` of the ProgID on Windows.
+/// On Linux the `open` verb becomes the `Exec` key of the desktop entry and every other verb becomes a `[Desktop Action <name>]` group.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verb {
+    /// Name of the verb, e.g. `open`. Desktop actions only allow ASCII letters, digits and dashes in their names.
+    pub name: String,
+    /// Text shown in context menus, e.g. `Open with Test`. Empty to let the shell pick one.
+    pub label: String,
+    /// Command performing the action.
+    pub command: CommandTemplate,
+}
+
+/// File type an application can be associated with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FileType {
+    /// File extension, including the leading dot, e.g. `.txt`.
+    Extension(String),
+    /// MIME type, e.g. `text/plain`.
+    MimeType(MimeType),
+}
+impl FileType {
+    /// Create a [FileType::Extension], prepending a dot to `extension` if it doesn't start with one.
+    pub fn extension(extension: impl ToString) -> Self {
+        let extension = extension.to_string();
+        if extension.starts_with('.') {
+            Self::Extension(extension)
+        } else {
+            Self::Extension(format!(".{extension}"))
+        }
+    }
+    /// Parse a file type. Strings containing a slash are parsed as MIME types, everything else is treated as a file extension.
+    ///
+    /// # Errors
+    /// - [Error::InvalidMimeType] if `file_type` contains a slash, but isn't a valid MIME type.
+    pub fn parse(file_type: &str) -> Result<Self> {
+        if file_type.contains('/') {
+            Ok(Self::MimeType(MimeType::parse(file_type)?))
+        } else {
+            Ok(Self::extension(file_type))
+        }
+    }
+    /// Guess file types of a file from its name, from the best match to the worst one.
+    ///
+    /// These are the MIME types matching the name in the [MIME database](MimeDatabase::load) of the current user,
+    /// followed by the extension of the file if it has one, so that the first file type a backend supports can be
+    /// associated with an application. The file doesn't have to exist.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Vec<Self>> {
+        let path = path.as_ref();
+        let mut file_types: Vec<Self> = MimeDatabase::load()?
+            .mime_types_for_path(path)
+            .into_iter()
+            .map(Self::MimeType)
+            .collect();
+        if let Some(extension) = path.extension() {
+            file_types.push(Self::extension(extension.to_string_lossy()));
+        }
+        Ok(file_types)
+    }
+}
+/// MIME types are displayed without their parameters, which don't matter for associations.
+impl Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Extension(extension) => extension.fmt(f),
+            Self::MimeType(mime_type) => mime_type.essence().fmt(f),
+        }
+    }
+}
+impl FromStr for FileType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+impl From<MimeType> for FileType {
+    fn from(value: MimeType) -> Self {
+        Self::MimeType(value)
+    }
+}
+
+/// Conversion into a [FileType], which fails for strings that look like MIME types but aren't valid ones.
+pub trait IntoFileType {
+    /// Perform the conversion.
+    fn into_file_type(self) -> Result<FileType>;
+}
+impl IntoFileType for FileType {
+    fn into_file_type(self) -> Result<FileType> {
+        Ok(self)
+    }
+}
+impl IntoFileType for &FileType {
+    fn into_file_type(self) -> Result<FileType> {
+        Ok(self.clone())
+    }
+}
+impl IntoFileType for MimeType {
+    fn into_file_type(self) -> Result<FileType> {
+        Ok(FileType::MimeType(self))
+    }
+}
+impl IntoFileType for &MimeType {
+    fn into_file_type(self) -> Result<FileType> {
+        Ok(FileType::MimeType(self.clone()))
+    }
+}
+impl IntoFileType for &str {
+    fn into_file_type(self) -> Result<FileType> {
+        FileType::parse(self)
+    }
+}
+impl IntoFileType for String {
+    fn into_file_type(self) -> Result<FileType> {
+        FileType::parse(&self)
+    }
+}
+impl IntoFileType for &String {
+    fn into_file_type(self) -> Result<FileType> {
+        FileType::parse(self)
+    }
 }
 
 /// Representation of the application to manipulate file type associations in.
@@ -99,43 +415,309 @@ pub struct Application {
     pub(crate) id: String,
     pub(crate) path: String,
     pub(crate) flags: u32,
+    pub(crate) backend: Arc<dyn Backend>,
+    pub(crate) plan: Option<Plan>,
 }
 
 impl Application {
+    /// Get the unique identifier of the application.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    /// Get the path to the executable of the application.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    /// Get the changes recorded so far in [dry-run mode](OpenOptions::dry_run), [None] if it isn't enabled.
+    ///
+    /// The plan keeps recording changes made through the application, including [deleting](Application::delete) it.
+    pub fn plan(&self) -> Option<&Plan> {
+        self.plan.as_ref()
+    }
+
+    /// List file extensions associated with the application.
+    ///
+    /// # Errors
+    /// - [Error::ReadPermissionRequired] if the application wasn't opened for [reading](OpenOptions::read).
     pub fn enumerate_associations(&self) -> Result<impl Iterator<Item = String>> {
         if bitflag_eq(self.flags, APP_READ) {
-            Ok(
-                #[cfg(windows)]
-                unsafe {
-                    windows::EnumerateFileTypeAssociations(self.id.clone())
-                        .map_err(|why| Error::Io(why))?
-                        .into_iter()
-                },
-            )
+            Ok(self.backend.enumerate(&self.id)?.into_iter())
+        } else {
+            Err(Error::ReadPermissionRequired)
+        }
+    }
+    /// List file types associated with the application, together with the [Scope] each association comes from.
+    ///
+    /// # Errors
+    /// - [Error::ReadPermissionRequired] if the application wasn't opened for [reading](OpenOptions::read).
+    pub fn associations(&self) -> Result<impl Iterator<Item = Association>> {
+        if bitflag_eq(self.flags, APP_READ) {
+            Ok(self.backend.associations(&self.id)?.into_iter())
         } else {
             Err(Error::ReadPermissionRequired)
         }
     }
-    pub fn set_file_type_association(&self) -> Result<()> {
+    /// Associate a file extension or a MIME type with the application,
+    /// making the application the default one for that file type if `default` is set.
+    ///
+    /// Returns ID of the application which was the default one before, if this call has replaced it.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the application wasn't opened for [writing](OpenOptions::write).
+    /// - [Error::Unsupported] if the backend can't associate applications with this kind of file type.
+    /// - [Error::InvalidMimeType] if `file_type` is a string which isn't a valid MIME type.
+    /// - Any error of [strict mode](OpenOptions::strict) validation, if it's enabled.
+    pub fn set_file_type_association(
+        &self,
+        file_type: impl IntoFileType,
+        default: bool,
+    ) -> Result<Option<String>> {
+        if bitflag_eq(self.flags, APP_WRITE) {
+            if bitflag_eq(self.flags, APP_STRICT) {
+                validate_executable(&self.path)?;
+                validate_owner(self.backend.as_ref(), &self.id, &self.path)?;
+            }
+            self.backend
+                .associate(&self.id, &file_type.into_file_type()?, default)
+        } else {
+            Err(Error::WritePermissionRequired)
+        }
+    }
+
+    /// Stop the application from handling a file extension or a MIME type, while keeping the application registered.
+    ///
+    /// If the application is the default one for the file type, the file type is left without a default application.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the application wasn't opened for [writing](OpenOptions::write).
+    /// - [Error::Unsupported] if the backend can't associate applications with this kind of file type.
+    /// - [Error::InvalidMimeType] if `file_type` is a string which isn't a valid MIME type.
+    pub fn remove_file_type_association(&self, file_type: impl IntoFileType) -> Result<()> {
         if bitflag_eq(self.flags, APP_WRITE) {
-            Ok(())
+            self.backend
+                .dissociate(&self.id, &file_type.into_file_type()?)
         } else {
             Err(Error::WritePermissionRequired)
         }
     }
 
+    /// Make the application the default one for every file type of `file_types`, in order.
+    ///
+    /// On Linux this puts the application first under `[Default Applications]` of the user's `mimeapps.list`,
+    /// keeping the other entries of the file and their order.
+    ///
+    /// # Errors
+    /// Same as [Application::set_file_type_association]. Nothing is changed if one of `file_types` is invalid.
+    pub fn set_as_default_for<T: IntoFileType>(
+        &self,
+        file_types: impl IntoIterator<Item = T>,
+    ) -> Result<()> {
+        self.require_write(true)?;
+        for file_type in collect_file_types(file_types)? {
+            self.backend.associate(&self.id, &file_type, true)?;
+        }
+        Ok(())
+    }
+    /// Make the application a handler of every file type of `file_types`, without making it the default one.
+    ///
+    /// On Linux this lists the application under `[Added Associations]` of the user's `mimeapps.list`,
+    /// after the applications which are listed there already.
+    ///
+    /// # Errors
+    /// Same as [Application::set_file_type_association]. Nothing is changed if one of `file_types` is invalid.
+    pub fn add_associations<T: IntoFileType>(
+        &self,
+        file_types: impl IntoIterator<Item = T>,
+    ) -> Result<()> {
+        self.require_write(true)?;
+        for file_type in collect_file_types(file_types)? {
+            self.backend.add_association(&self.id, &file_type)?;
+        }
+        Ok(())
+    }
+    /// Hide the application from the handlers of every file type of `file_types`, even if it declares it can open them.
+    ///
+    /// On Linux this lists the application under `[Removed Associations]` of the user's `mimeapps.list`,
+    /// and removes it from the default and added applications of those MIME types.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the application wasn't opened for [writing](OpenOptions::write).
+    /// - [Error::Unsupported] if the backend can't hide applications.
+    /// - [Error::InvalidMimeType] if one of `file_types` is a string which isn't a valid MIME type. Nothing is changed then.
+    pub fn hide_for<T: IntoFileType>(&self, file_types: impl IntoIterator<Item = T>) -> Result<()> {
+        self.require_write(false)?;
+        for file_type in collect_file_types(file_types)? {
+            self.backend.hide_association(&self.id, &file_type)?;
+        }
+        Ok(())
+    }
+    /// Declare new MIME types, such as the document format of the application, so that they can be associated with it.
+    /// The package is identified by the ID of the application, and replaces the one registered before.
+    ///
+    /// On Linux this writes the package to `$XDG_DATA_HOME/mime/packages/<id>.xml` and regenerates the MIME database.
+    /// On Windows every `*.<extension>` glob creates `HKEY_CLASSES_ROOT\.<extension>` with the MIME type as its `Content Type`.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the application wasn't opened for [writing](OpenOptions::write).
+    /// - [Error::Unsupported] if the backend can't declare MIME types.
+    pub fn register_mime_types(&self, package: &MimePackage) -> Result<()> {
+        self.require_write(false)?;
+        self.backend.register_mime_package(&self.id, package)
+    }
+    /// Remove the MIME types declared by [Application::register_mime_types] with the same package.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the application wasn't opened for [writing](OpenOptions::write).
+    /// - [Error::Unsupported] if the backend can't declare MIME types.
+    pub fn unregister_mime_types(&self, package: &MimePackage) -> Result<()> {
+        self.require_write(false)?;
+        self.backend.unregister_mime_package(&self.id, package)
+    }
+
+    /// Check that the application was opened for writing, and run [strict mode](OpenOptions::strict) validation if `validate` is set.
+    fn require_write(&self, validate: bool) -> Result<()> {
+        if !bitflag_eq(self.flags, APP_WRITE) {
+            return Err(Error::WritePermissionRequired);
+        }
+        if validate && bitflag_eq(self.flags, APP_STRICT) {
+            validate_executable(&self.path)?;
+            validate_owner(self.backend.as_ref(), &self.id, &self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Unregister the application.
+    ///
+    /// # Errors
+    /// - [Error::WritePermissionRequired] if the application wasn't opened for [writing](OpenOptions::write).
     pub fn delete(self) -> Result<()> {
         if bitflag_eq(self.flags, APP_WRITE) {
-            #[cfg(windows)]
-            unsafe {
-                windows::DeleteProcID(self.id).map_err(|why| Error::Io(why))
-            }
+            self.backend.delete(&self.id)
         } else {
             Err(Error::WritePermissionRequired)
         }
     }
 }
 
+fn collect_file_types<T: IntoFileType>(
+    file_types: impl IntoIterator<Item = T>,
+) -> Result<Vec<FileType>> {
+    file_types
+        .into_iter()
+        .map(IntoFileType::into_file_type)
+        .collect()
+}
+
+/// Look up the application opening a file type by default on this system, which doesn't have to be registered through this crate.
+///
+/// The returned descriptor carries ID, name and icon of the application, and its [primary verb](ApplicationDescriptor::primary_verb)
+/// is the command files are opened with. Returns [None] if no application opens the file type.
+///
+/// On Windows, the `UserChoice` made in Explorer takes precedence over the per-user and then the system-wide default of
+/// the extension. On Linux, `mimeapps.list` files and desktop entries are looked up in the order of the XDG specifications.
+///
+/// # Errors
+/// - [Error::InvalidMimeType] if `file_type` looks like a MIME type, but isn't a valid one.
+/// - [Error::Unsupported] if the operating system has no native backend.
+pub fn default_application_for(
+    file_type: impl IntoFileType,
+) -> Result<Option<ApplicationDescriptor>> {
+    platform::default_backend().default_application_for(&file_type.into_file_type()?)
+}
+
+/// Check whether an application ID is well-formed.
+///
+/// A well-formed ID is at most 255 characters long and consists of ASCII letters, digits, dots, dashes and underscores.
+/// It starts with a letter or an underscore, doesn't end with a dot and doesn't contain empty dot-separated parts,
+/// which makes it both a valid ProgID and a valid desktop file ID.
+///
+/// # Errors
+/// - [Error::InvalidId] if the ID isn't well-formed.
+pub fn validate_id(id: &str) -> Result<()> {
+    let valid = id.len() <= 255
+        && id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        && id.split('.').all(|part| !part.is_empty());
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidId(id.to_string()))
+    }
+}
+
+/// Check whether the file at `path` exists and is executable.
+fn validate_executable(path: &str) -> Result<()> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(_) => return Err(Error::ExecutableDoesntExist),
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::ExecutableDoesntExist)
+        }
+        Err(why) => return Err(Error::Io(why)),
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(Error::ExecutableNotExecutable);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    Ok(())
+}
+
+/// Check whether the icon file exists, if `icon_path` refers to a file rather than to a themed icon name.
+///
+/// Windows icon locations may end with a resource index (`app.exe,0`), which is ignored.
+fn validate_icon(icon_path: &str) -> Result<()> {
+    let file = match icon_path.rsplit_once(',') {
+        Some((file, index)) if index.trim().trim_start_matches('-').parse::<u32>().is_ok() => file,
+        _ => icon_path,
+    };
+    if file.is_empty() || !Path::new(file).is_absolute() || Path::new(file).is_file() {
+        Ok(())
+    } else {
+        Err(Error::IconDoesntExist(icon_path.to_string()))
+    }
+}
+
+/// Run the [strict mode](OpenOptions::strict) checks of [creating](OpenOptions::create) an application.
+pub(crate) fn validate_descriptor(
+    backend: &dyn Backend,
+    path: &str,
+    descriptor: &ApplicationDescriptor,
+) -> Result<()> {
+    validate_id(&descriptor.id)?;
+    validate_executable(path)?;
+    validate_icon(&descriptor.icon_path)?;
+    for image in &descriptor.icon_images {
+        image.validate()?;
+    }
+    validate_owner(backend, &descriptor.id, path)?;
+    backend.validate(path, descriptor)
+}
+/// Check whether the application, if it's registered, is launched through the executable at `path`.
+fn validate_owner(backend: &dyn Backend, id: &str, path: &str) -> Result<()> {
+    match backend.executable(id)? {
+        Some(registered) if !same_file(&registered, path) => {
+            Err(Error::ExecutableMismatch(registered))
+        }
+        _ => Ok(()),
+    }
+}
+fn same_file(lhs: &str, rhs: &str) -> bool {
+    lhs == rhs
+        || matches!(
+            (fs::canonicalize(lhs), fs::canonicalize(rhs)),
+            (Ok(lhs), Ok(rhs)) if lhs == rhs
+        )
+}
+
 pub(crate) fn current_exe_path() -> Result<String> {
     env::current_exe()
         .map(|pathbuf| {
@@ -144,13 +726,11 @@ pub(crate) fn current_exe_path() -> Result<String> {
                 .into_string()
                 .unwrap_or(String::new())
         })
-        .map_err(|why| Error::Io(why))
+        .map_err(Error::Io)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::app::{Application, ApplicationDescriptor as Descriptor};
-
     mod options {
         use crate::app;
 
@@ -175,36 +755,527 @@ mod tests {
 
     mod app {
         use crate::app;
+        use crate::backend::{Association, Backend, MemoryBackend, Scope};
+        use crate::command::{Arg, CommandTemplate};
+        use crate::icon::IconImage;
+        use crate::mime::{MimePackage, MimeType, MimeTypeDefinition};
+        use crate::plan::{Target, Value};
+        use crate::result::Error;
+        use crate::snapshot::Snapshot;
+        use std::path::PathBuf;
 
         pub const ID: &str = "Fileext.Test";
 
+        fn descriptor() -> app::ApplicationDescriptor {
+            app::ApplicationDescriptor {
+                id: String::from(ID),
+                name: String::from("fileext crate"),
+                ..Default::default()
+            }
+        }
+        fn register(backend: &MemoryBackend) -> app::Application {
+            app::OpenOptions::current()
+                .write(true)
+                .backend(backend.clone())
+                .create(descriptor())
+                .unwrap()
+        }
+
         #[test]
         fn create() {
+            let backend = MemoryBackend::new();
+            let application = register(&backend);
+
+            assert_eq!(application.id(), ID);
+            assert_eq!(backend.application(ID).unwrap().descriptor, descriptor());
+            assert!(app::OpenOptions::current()
+                .backend(backend)
+                .get(ID)
+                .unwrap()
+                .is_some());
+        }
+
+        #[test]
+        fn delete() {
+            let backend = MemoryBackend::new();
+            let application = register(&backend);
+            application.hide_for(["text/csv"]).unwrap();
+            application.delete().unwrap();
+            assert!(backend.file_type("text/csv").unwrap().hidden.is_empty());
+
+            assert!(app::OpenOptions::current()
+                .backend(backend)
+                .get(ID)
+                .unwrap()
+                .is_none());
+        }
+
+        #[test]
+        fn enumerate_associations() {
+            let backend = MemoryBackend::new();
+            let registered = register(&backend);
+            registered.set_file_type_association(".txt", false).unwrap();
+            registered.set_file_type_association(".log", true).unwrap();
+            registered.set_file_type_association(".csv", false).unwrap();
+
+            let application = app::OpenOptions::current()
+                .read(true)
+                .backend(backend)
+                .get(ID)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                application
+                    .enumerate_associations()
+                    .unwrap()
+                    .collect::<Vec<_>>(),
+                vec![
+                    String::from(".csv"),
+                    String::from(".log"),
+                    String::from(".txt")
+                ]
+            );
+            registered.remove_file_type_association(".csv").unwrap();
+            registered.remove_file_type_association(".log").unwrap();
+            assert_eq!(
+                application
+                    .enumerate_associations()
+                    .unwrap()
+                    .collect::<Vec<_>>(),
+                vec![String::from(".txt")]
+            );
+        }
+
+        #[test]
+        fn scope() {
+            let backend = MemoryBackend::new();
+            let system = app::OpenOptions::current()
+                .write(true)
+                .backend(backend.clone())
+                .scope(Scope::System)
+                .create(descriptor())
+                .unwrap();
+            system.set_file_type_association(".csv", true).unwrap();
+            assert_eq!(backend.application(ID), None);
+            assert!(backend
+                .clone()
+                .scope(Scope::System)
+                .application(ID)
+                .is_some());
+            assert_eq!(
+                backend
+                    .default_application_for(&app::FileType::parse(".csv").unwrap())
+                    .unwrap(),
+                Some(descriptor())
+            );
+
+            register(&backend)
+                .set_file_type_association(".txt", false)
+                .unwrap();
+            let application = app::OpenOptions::current()
+                .read(true)
+                .backend(backend.clone())
+                .get(ID)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                application.associations().unwrap().collect::<Vec<_>>(),
+                vec![
+                    Association {
+                        file_type: String::from(".txt"),
+                        scope: Scope::User,
+                    },
+                    Association {
+                        file_type: String::from(".csv"),
+                        scope: Scope::System,
+                    },
+                ]
+            );
+            let application = app::OpenOptions::current()
+                .read(true)
+                .backend(backend)
+                .scope(Scope::System)
+                .get(ID)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                application
+                    .enumerate_associations()
+                    .unwrap()
+                    .collect::<Vec<_>>(),
+                vec![String::from(".csv")]
+            );
+
+            assert!(matches!(
+                app::OpenOptions::current()
+                    .backend(crate::platform::UnsupportedBackend)
+                    .scope(Scope::User)
+                    .get(ID),
+                Err(Error::Unsupported)
+            ));
+        }
+
+        #[test]
+        fn dry_run() {
+            let backend = MemoryBackend::new();
+            let application = app::OpenOptions::current()
+                .write(true)
+                .dry_run(true)
+                .backend(backend.clone())
+                .create(descriptor())
+                .unwrap();
+            application
+                .set_file_type_association("text/plain", true)
+                .unwrap();
+            assert_eq!(backend.application(ID), None);
+            assert_eq!(backend.file_type("text/plain"), None);
+
+            let changes = application.plan().unwrap().changes();
+            let target = |group: &str, key: &str| Target::KeyFileEntry {
+                path: PathBuf::from("memory:user"),
+                group: group.to_string(),
+                key: key.to_string(),
+            };
+            assert_eq!(
+                changes
+                    .iter()
+                    .map(|change| &change.target)
+                    .collect::<Vec<_>>(),
+                [
+                    &target("Applications", ID),
+                    &target("File Types", "text/plain")
+                ]
+            );
+            assert_eq!(changes[0].before, None);
+            assert_eq!(
+                changes[1].after,
+                Some(Value::Text(format!("default\t{ID}\nhandler\t{ID}\n")))
+            );
+
+            let application = register(&backend);
+            assert!(application.plan().is_none());
+        }
+
+        #[test]
+        fn snapshot() {
+            let backend = MemoryBackend::new();
+            let application = app::OpenOptions::current()
+                .write(true)
+                .backend(backend.clone())
+                .create(app::ApplicationDescriptor {
+                    icon_images: vec![IconImage::png("/icons/test.png", 48)],
+                    verbs: vec![app::Verb {
+                        name: String::from("edit"),
+                        label: String::from("Edit\twith test"),
+                        command: CommandTemplate::new("/opt/My App/test")
+                            .arg("--edit")
+                            .arg(Arg::File),
+                    }],
+                    default_verb: Some(String::from("edit")),
+                    ..descriptor()
+                })
+                .unwrap();
+            application
+                .set_file_type_association("text/plain", true)
+                .unwrap();
+            application.hide_for(["image/png"]).unwrap();
+            application
+                .register_mime_types(
+                    &MimePackage::new().mime_type(
+                        MimeTypeDefinition::new(
+                            MimeType::parse("application/x-fileext-test").unwrap(),
+                            "Test document",
+                        )
+                        .glob("*.fileext-test"),
+                    ),
+                )
+                .unwrap();
+            let options = app::OpenOptions::current()
+                .read(true)
+                .write(true)
+                .backend(backend.clone());
+            let snapshot = options.clone().snapshot([ID], ["text/csv"]).unwrap();
+            assert_eq!(snapshot.entries.len(), 5);
+            let before = (
+                backend.application(ID),
+                backend.file_type("text/plain"),
+                backend.file_type("image/png"),
+                backend.mime_package(ID),
+            );
+
+            application.delete().unwrap();
+            let application = register(&backend);
+            application
+                .set_as_default_for(["text/csv", "text/html"])
+                .unwrap();
+            let snapshot = Snapshot::parse(&snapshot.to_string()).unwrap();
+            options.clone().restore(&snapshot).unwrap();
+            assert_eq!(
+                (
+                    backend.application(ID),
+                    backend.file_type("text/plain"),
+                    backend.file_type("image/png"),
+                    backend.mime_package(ID),
+                ),
+                before
+            );
+            assert_eq!(backend.file_type("text/csv"), None);
+            assert_eq!(backend.default_application("text/html"), None);
+
+            // A snapshot with entries of another backend or scope restores nothing
+            let mut foreign = snapshot.clone();
+            foreign.scope = Scope::System;
+            application.delete().unwrap();
+            let Err(Error::Io(why)) = options.restore(&foreign) else {
+                panic!("restored a snapshot of another scope");
+            };
+            assert_eq!(why.kind(), std::io::ErrorKind::InvalidData);
+            assert_eq!(backend.application(ID), None);
+        }
+
+        #[test]
+        fn set_file_type_association() {
+            let backend = MemoryBackend::new();
+            let application = register(&backend);
             app::OpenOptions::current()
                 .write(true)
+                .backend(backend.clone())
                 .create(app::ApplicationDescriptor {
-                    id: String::from(ID),
-                    name: String::from("fileext crate"),
-                    icon_path: String::new(),
+                    id: String::from("Other.App"),
+                    ..descriptor()
                 })
+                .unwrap()
+                .set_file_type_association("text/plain", true)
                 .unwrap();
+
+            assert_eq!(
+                application
+                    .set_file_type_association("text/plain", false)
+                    .unwrap(),
+                None
+            );
+            assert_eq!(
+                backend.default_application("text/plain").as_deref(),
+                Some("Other.App")
+            );
+            assert_eq!(
+                application
+                    .set_file_type_association("text/plain", true)
+                    .unwrap()
+                    .as_deref(),
+                Some("Other.App")
+            );
+            assert_eq!(
+                application
+                    .set_file_type_association("text/plain", true)
+                    .unwrap(),
+                None
+            );
+            assert_eq!(
+                backend.default_application("text/plain").as_deref(),
+                Some(ID)
+            );
         }
 
         #[test]
-        fn delete() {
-            let application = match app::OpenOptions::current().write(true).get(ID).unwrap() {
-                Some(app) => app,
-                None => {
-                    create();
-                    app::OpenOptions::current()
-                        .write(true)
-                        .get(ID)
-                        .unwrap()
-                        .unwrap()
-                }
+        fn default_application_for() {
+            let backend = MemoryBackend::new();
+            let application = register(&backend);
+            let csv = app::FileType::parse(".csv").unwrap();
+            assert_eq!(backend.default_application_for(&csv).unwrap(), None);
+
+            application
+                .set_file_type_association(".csv", false)
+                .unwrap();
+            assert_eq!(backend.default_application_for(&csv).unwrap(), None);
+            application.set_file_type_association(".csv", true).unwrap();
+            assert_eq!(
+                backend.default_application_for(&csv).unwrap(),
+                Some(descriptor())
+            );
+        }
+
+        #[test]
+        fn mimeapps() {
+            let backend = MemoryBackend::new();
+            let application = register(&backend);
+            application
+                .set_as_default_for(["text/plain", "text/csv"])
+                .unwrap();
+            assert_eq!(backend.default_application("text/csv").as_deref(), Some(ID));
+            application.add_associations(["image/png"]).unwrap();
+            assert_eq!(backend.default_application("image/png"), None);
+            assert!(backend
+                .file_type("image/png")
+                .unwrap()
+                .handlers
+                .contains(ID));
+
+            application.hide_for(["text/csv", "image/png"]).unwrap();
+            let csv = backend.file_type("text/csv").unwrap();
+            assert_eq!(csv.default, None);
+            assert!(!csv.handlers.contains(ID));
+            assert!(csv.hidden.contains(ID));
+            application.add_associations(["text/csv"]).unwrap();
+            assert!(backend.file_type("text/csv").unwrap().hidden.is_empty());
+
+            assert!(matches!(
+                application.set_as_default_for(["image/jpeg", "image/"]),
+                Err(Error::InvalidMimeType(_))
+            ));
+            assert_eq!(backend.file_type("image/jpeg"), None);
+        }
+
+        #[test]
+        fn mime_types() {
+            let backend = MemoryBackend::new();
+            let application = register(&backend);
+            let package = MimePackage::new().mime_type(
+                MimeTypeDefinition::new(
+                    MimeType::parse("application/x-fileext-test").unwrap(),
+                    "Test document",
+                )
+                .glob("*.fileext-test"),
+            );
+
+            let read_only = app::OpenOptions::current()
+                .backend(backend.clone())
+                .get(ID)
+                .unwrap()
+                .unwrap();
+            assert!(matches!(
+                read_only.register_mime_types(&package),
+                Err(Error::WritePermissionRequired)
+            ));
+            application.register_mime_types(&package).unwrap();
+            assert_eq!(backend.mime_package(ID), Some(package.clone()));
+            application.unregister_mime_types(&package).unwrap();
+            assert_eq!(backend.mime_package(ID), None);
+        }
+
+        #[test]
+        fn remove_file_type_association() {
+            let backend = MemoryBackend::new();
+            let application = register(&backend);
+            application.set_file_type_association(".txt", true).unwrap();
+            application.set_file_type_association(".csv", true).unwrap();
+
+            application.remove_file_type_association("txt").unwrap();
+            assert_eq!(backend.default_application(".txt"), None);
+            assert_eq!(backend.default_application(".csv").as_deref(), Some(ID));
+            assert_eq!(backend.application(ID).unwrap().descriptor, descriptor());
+        }
+
+        #[test]
+        fn strict() {
+            let backend = MemoryBackend::new();
+            let strict = app::OpenOptions::current()
+                .write(true)
+                .strict(true)
+                .backend(backend.clone());
+
+            for id in ["", "1App", "App..Test", "App.", "App Test", "App/Test"] {
+                let result = strict.clone().create(app::ApplicationDescriptor {
+                    id: String::from(id),
+                    ..descriptor()
+                });
+                assert!(matches!(result, Err(Error::InvalidId(_))), "{id:?}");
+            }
+            let result = strict.clone().create(app::ApplicationDescriptor {
+                icon_path: String::from("/nonexistent/fileext/icon.png"),
+                ..descriptor()
+            });
+            assert!(matches!(result, Err(Error::IconDoesntExist(_))));
+            let result = strict.clone().create(app::ApplicationDescriptor {
+                icon_images: vec![IconImage::svg("/nonexistent/fileext/icon.svg")],
+                ..descriptor()
+            });
+            assert!(matches!(result, Err(Error::IconDoesntExist(_))));
+            let result = app::OpenOptions::new("/nonexistent/fileext/app")
+                .write(true)
+                .strict(true)
+                .backend(backend.clone())
+                .create(descriptor());
+            assert!(matches!(result, Err(Error::ExecutableDoesntExist)));
+
+            // Themed icon names aren't files
+            strict
+                .clone()
+                .create(app::ApplicationDescriptor {
+                    icon_path: String::from("text-editor"),
+                    ..descriptor()
+                })
+                .unwrap();
+            assert!(strict.clone().get(ID).unwrap().is_some());
+
+            let foreign = app::OpenOptions::new("/nonexistent/fileext/app")
+                .write(true)
+                .backend(backend.clone())
+                .create(app::ApplicationDescriptor {
+                    id: String::from("Other.App"),
+                    ..descriptor()
+                })
+                .unwrap();
+            assert!(matches!(
+                strict.clone().get("Other.App"),
+                Err(Error::ExecutableMismatch(path)) if path == "/nonexistent/fileext/app"
+            ));
+            assert!(matches!(
+                foreign.set_file_type_association(".txt", true),
+                Ok(None)
+            ));
+        }
+
+        #[test]
+        fn primary_verb() {
+            let verb = |name: &str| app::Verb {
+                name: String::from(name),
+                ..Default::default()
+            };
+            let mut descriptor = app::ApplicationDescriptor {
+                verbs: vec![verb("edit"), verb("open"), verb("print")],
+                ..descriptor()
             };
+            assert_eq!(descriptor.primary_verb().unwrap().name, "open");
+            descriptor.default_verb = Some(String::from("print"));
+            assert_eq!(descriptor.primary_verb().unwrap().name, "print");
+            descriptor.verbs.remove(1);
+            descriptor.default_verb = None;
+            assert_eq!(descriptor.primary_verb().unwrap().name, "edit");
+        }
+
+        #[test]
+        fn file_type() {
+            assert_eq!(
+                app::FileType::parse("txt").unwrap(),
+                app::FileType::extension(".txt")
+            );
+            assert_eq!(
+                "text/plain; charset=utf-8"
+                    .parse::<app::FileType>()
+                    .unwrap()
+                    .to_string(),
+                "text/plain"
+            );
+            assert!(matches!(
+                app::FileType::parse("text/"),
+                Err(Error::InvalidMimeType(_))
+            ));
+        }
+
+        #[test]
+        fn permissions() {
+            let backend = MemoryBackend::new();
+            let result = app::OpenOptions::current()
+                .backend(backend.clone())
+                .create(descriptor());
+            assert!(matches!(result, Err(Error::WritePermissionRequired)));
 
-            application.delete().unwrap()
+            let application = register(&backend);
+            assert!(matches!(
+                application.enumerate_associations(),
+                Err(Error::ReadPermissionRequired)
+            ));
         }
     }
 }
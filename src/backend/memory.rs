@@ -0,0 +1,634 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! In-memory [Backend], for testing code which registers applications without touching the operating system.
+use super::{Association, Backend, Scope};
+use crate::app::{ApplicationDescriptor, FileType, Verb};
+use crate::command::{CommandTemplate, Syntax};
+use crate::icon::{IconFormat, IconImage, IconSize};
+use crate::mime::MimePackage;
+use crate::plan::{Change, Plan, Target, Value};
+use crate::result::{Error, Result};
+use crate::snapshot::{escape, unescape, Entry, Snapshot};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Group of the entries of applications, keyed by their IDs.
+const APPLICATIONS: &str = "Applications";
+/// Group of the entries of file types, keyed by the file extensions and MIME types.
+const FILE_TYPES: &str = "File Types";
+/// Group of the entries of MIME packages, keyed by their IDs.
+const MIME_PACKAGES: &str = "MIME Packages";
+
+/// Registered application, as stored by a [MemoryBackend].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryApplication {
+    /// Executable the application was registered with.
+    pub executable: String,
+    /// Descriptor the application was registered with.
+    pub descriptor: ApplicationDescriptor,
+}
+impl MemoryApplication {
+    /// Write the application as the value of its entry, with a line per field.
+    fn to_value(&self) -> Value {
+        let descriptor = &self.descriptor;
+        let mut text = String::new();
+        push_line(&mut text, &["executable", &self.executable]);
+        push_line(&mut text, &["name", &descriptor.name]);
+        push_line(&mut text, &["icon", &descriptor.icon_path]);
+        for image in &descriptor.icon_images {
+            push_line(
+                &mut text,
+                &[
+                    "icon-image",
+                    &image.path.to_string_lossy(),
+                    image.format.extension(),
+                    &image.size.dir_name(),
+                ],
+            );
+        }
+        for verb in &descriptor.verbs {
+            push_line(
+                &mut text,
+                &[
+                    "verb",
+                    &verb.name,
+                    &verb.label,
+                    &verb.command.render(Syntax::DesktopEntry),
+                ],
+            );
+        }
+        if let Some(default_verb) = &descriptor.default_verb {
+            push_line(&mut text, &["default-verb", default_verb]);
+        }
+        Value::Text(text)
+    }
+    /// Read the application `id` from the value of its entry, returning [None] if it's malformed.
+    fn from_value(id: &str, value: &Value) -> Option<Self> {
+        let mut executable = String::new();
+        let mut descriptor = ApplicationDescriptor {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        for line in parse_lines(value)? {
+            let (kind, fields) = line.split_first()?;
+            match (kind.as_str(), fields) {
+                ("executable", [path]) => executable = path.clone(),
+                ("name", [name]) => descriptor.name = name.clone(),
+                ("icon", [path]) => descriptor.icon_path = path.clone(),
+                ("icon-image", [path, format, size]) => descriptor.icon_images.push(IconImage {
+                    path: PathBuf::from(path),
+                    format: match format.as_str() {
+                        "png" => IconFormat::Png,
+                        "svg" => IconFormat::Svg,
+                        _ => return None,
+                    },
+                    size: match size.split_once('x') {
+                        _ if size == "scalable" => IconSize::Scalable,
+                        Some((width, height)) if width == height => {
+                            IconSize::Pixels(width.parse().ok()?)
+                        }
+                        _ => return None,
+                    },
+                }),
+                ("verb", [name, label, command]) => descriptor.verbs.push(Verb {
+                    name: name.clone(),
+                    label: label.clone(),
+                    command: CommandTemplate::parse(command, Syntax::DesktopEntry).ok()?,
+                }),
+                ("default-verb", [name]) => descriptor.default_verb = Some(name.clone()),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            executable,
+            descriptor,
+        })
+    }
+}
+
+/// File extension or MIME type, as stored by a [MemoryBackend].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryFileType {
+    /// ID of the application opening the file type by default.
+    pub default: Option<String>,
+    /// IDs of all applications which can open the file type.
+    pub handlers: BTreeSet<String>,
+    /// IDs of applications which have been [hidden](Backend::hide_association) from the handlers of the file type.
+    pub hidden: BTreeSet<String>,
+}
+impl MemoryFileType {
+    /// Check whether the application `id` opens the file type, by default or not, or is hidden from it.
+    fn mentions(&self, id: &str) -> bool {
+        self.default.as_deref() == Some(id)
+            || self.handlers.contains(id)
+            || self.hidden.contains(id)
+    }
+    /// Stop the application `id` from opening the file type and from being hidden from it.
+    fn forget(&mut self, id: &str) {
+        self.handlers.remove(id);
+        self.hidden.remove(id);
+        if self.default.as_deref() == Some(id) {
+            self.default = None;
+        }
+    }
+    /// Write the file type as the value of its entry, with a line per application.
+    fn to_value(&self) -> Value {
+        let mut text = String::new();
+        if let Some(default) = &self.default {
+            push_line(&mut text, &["default", default]);
+        }
+        for id in &self.handlers {
+            push_line(&mut text, &["handler", id]);
+        }
+        for id in &self.hidden {
+            push_line(&mut text, &["hidden", id]);
+        }
+        Value::Text(text)
+    }
+    /// Read the file type from the value of its entry, returning [None] if it's malformed.
+    fn from_value(value: &Value) -> Option<Self> {
+        let mut file_type = Self::default();
+        for line in parse_lines(value)? {
+            let (kind, fields) = line.split_first()?;
+            match (kind.as_str(), fields) {
+                ("default", [id]) => file_type.default = Some(id.clone()),
+                ("handler", [id]) => {
+                    file_type.handlers.insert(id.clone());
+                }
+                ("hidden", [id]) => {
+                    file_type.hidden.insert(id.clone());
+                }
+                _ => return None,
+            }
+        }
+        Some(file_type)
+    }
+}
+
+/// Append a line to the value of an entry, whose fields are separated by tabs and escaped like in [Snapshot]s.
+fn push_line(text: &mut String, fields: &[&str]) {
+    let fields: Vec<String> = fields.iter().map(|field| escape(field)).collect();
+    text.push_str(&fields.join("\t"));
+    text.push('\n');
+}
+/// Split the value of an entry into lines of unescaped fields, returning [None] if it's malformed.
+fn parse_lines(value: &Value) -> Option<Vec<Vec<String>>> {
+    let Value::Text(text) = value else {
+        return None;
+    };
+    text.lines()
+        .map(|line| line.split('\t').map(unescape).collect())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    applications: BTreeMap<String, MemoryApplication>,
+    file_types: BTreeMap<String, MemoryFileType>,
+    mime_packages: BTreeMap<String, MimePackage>,
+}
+impl State {
+    /// Get the values of the entries standing for the state, by group and key.
+    fn entries(&self) -> BTreeMap<(&'static str, String), Value> {
+        let applications = self
+            .applications
+            .iter()
+            .map(|(id, application)| ((APPLICATIONS, id.clone()), application.to_value()));
+        let file_types = self
+            .file_types
+            .iter()
+            .map(|(name, file_type)| ((FILE_TYPES, name.clone()), file_type.to_value()));
+        let mime_packages = self.mime_packages.iter().map(|(id, package)| {
+            (
+                (MIME_PACKAGES, id.clone()),
+                Value::Text(package.to_string()),
+            )
+        });
+        applications
+            .chain(file_types)
+            .chain(mime_packages)
+            .collect()
+    }
+}
+
+/// Entry of a [Snapshot] read back, before it's restored.
+enum Restored {
+    Application(String, Option<MemoryApplication>),
+    FileType(String, Option<MemoryFileType>),
+    MimePackage(String, Option<MimePackage>),
+}
+
+/// Insert `value` into `map`, or remove the key if it's [None].
+fn put<T>(map: &mut BTreeMap<String, T>, key: String, value: Option<T>) {
+    match value {
+        Some(value) => {
+            map.insert(key, value);
+        }
+        None => {
+            map.remove(&key);
+        }
+    }
+}
+
+/// [Backend] keeping applications, file types and their default applications in memory.
+///
+/// Clones of a backend share the same state, so a clone can be handed to [OpenOptions](crate::app::OpenOptions)
+/// while the original one is used to inspect what the code under test did.
+///
+/// Every [Scope] has a state of its own. The [Scope::User] one reads the associations and default applications
+/// of the [Scope::System] one as well, like the user scopes of the platform backends do.
+///
+/// [Snapshots](Backend::snapshot) and [plans](Backend::dry_run) see the state of a scope as a key file at
+/// `memory:<scope>`, e.g. `memory:user`, with an [entry](Target::KeyFileEntry) per application, file type and MIME
+/// package in the `Applications`, `File Types` and `MIME Packages` groups. A dry run works on a copy of the state of
+/// every scope, taken when it starts.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    user: Arc<Mutex<State>>,
+    system: Arc<Mutex<State>>,
+    scope: Scope,
+    plan: Option<Plan>,
+}
+impl MemoryBackend {
+    /// Create an empty backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Work on the state of `scope`, [Scope::User] by default.
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state_of(self.scope)
+    }
+    fn state_of(&self, scope: Scope) -> MutexGuard<'_, State> {
+        lock(match scope {
+            Scope::User => &self.user,
+            Scope::System => &self.system,
+        })
+    }
+    /// Change the state of the scope of the backend, recording the entries it changes into the plan of a dry run.
+    fn write<T>(&self, change: impl FnOnce(&mut State) -> Result<T>) -> Result<T> {
+        let mut state = self.state();
+        let Some(plan) = &self.plan else {
+            return change(&mut state);
+        };
+        let before = state.entries();
+        let result = change(&mut state)?;
+        let mut after = state.entries();
+        for ((group, key), before) in before {
+            plan.push(Change {
+                target: self.target(group, &key),
+                after: after.remove(&(group, key)),
+                before: Some(before),
+            });
+        }
+        for ((group, key), after) in after {
+            plan.push(Change {
+                target: self.target(group, &key),
+                before: None,
+                after: Some(after),
+            });
+        }
+        Ok(result)
+    }
+    /// Get the entry standing for the item `key` of `group` of the state of the scope of the backend.
+    fn target(&self, group: &str, key: &str) -> Target {
+        Target::KeyFileEntry {
+            path: PathBuf::from(format!("memory:{}", self.scope)),
+            group: group.to_string(),
+            key: key.to_string(),
+        }
+    }
+    /// Read back an entry of a snapshot of the scope of the backend.
+    fn read_entry(&self, entry: &Entry) -> Result<Restored> {
+        let invalid = || -> Error {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: not an entry of the backend", entry.target),
+            )
+            .into()
+        };
+        let Target::KeyFileEntry { group, key, .. } = &entry.target else {
+            return Err(Error::Unsupported);
+        };
+        if entry.target != self.target(group, key) {
+            return Err(invalid());
+        }
+        let value = entry.value.as_ref();
+        let key = key.clone();
+        Ok(match group.as_str() {
+            APPLICATIONS => {
+                let application = value
+                    .map(|value| MemoryApplication::from_value(&key, value).ok_or_else(invalid))
+                    .transpose()?;
+                Restored::Application(key, application)
+            }
+            FILE_TYPES => {
+                let file_type = value
+                    .map(|value| MemoryFileType::from_value(value).ok_or_else(invalid))
+                    .transpose()?;
+                Restored::FileType(key, file_type)
+            }
+            MIME_PACKAGES => {
+                let package = value
+                    .map(|value| match value {
+                        Value::Text(document) => {
+                            MimePackage::parse(document).map_err(|_| invalid())
+                        }
+                        _ => Err(invalid()),
+                    })
+                    .transpose()?;
+                Restored::MimePackage(key, package)
+            }
+            _ => return Err(invalid()),
+        })
+    }
+    /// Get the scopes the backend reads, from the most important one to the least important one.
+    fn visible_scopes(&self) -> &'static [Scope] {
+        match self.scope {
+            Scope::User => &[Scope::User, Scope::System],
+            Scope::System => &[Scope::System],
+        }
+    }
+
+    /// Get a registered application.
+    pub fn application(&self, id: &str) -> Option<MemoryApplication> {
+        self.state().applications.get(id).cloned()
+    }
+    /// Get IDs of all registered applications.
+    pub fn applications(&self) -> Vec<String> {
+        self.state().applications.keys().cloned().collect()
+    }
+    /// Get a file extension or MIME type which has been associated with an application at least once.
+    pub fn file_type(&self, file_type: &str) -> Option<MemoryFileType> {
+        self.state().file_types.get(file_type).cloned()
+    }
+    /// Get a registered [MIME package](Backend::register_mime_package).
+    pub fn mime_package(&self, id: &str) -> Option<MimePackage> {
+        self.state().mime_packages.get(id).cloned()
+    }
+    /// Get ID of the application opening a file extension or MIME type by default.
+    pub fn default_application(&self, file_type: &str) -> Option<String> {
+        self.file_type(file_type)?.default
+    }
+}
+/// Lock the state of a scope.
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    // A panicking test shouldn't poison the state for every other user of the backend
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Backend for MemoryBackend {
+    fn scoped(&self, scope: Scope) -> Result<Arc<dyn Backend>> {
+        Ok(Arc::new(self.clone().scope(scope)))
+    }
+    fn dry_run(&self, plan: &Plan) -> Result<Arc<dyn Backend>> {
+        let copy = |state: &Mutex<State>| Arc::new(Mutex::new(lock(state).clone()));
+        Ok(Arc::new(Self {
+            user: copy(&self.user),
+            system: copy(&self.system),
+            scope: self.scope,
+            plan: Some(plan.clone()),
+        }))
+    }
+    fn create(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        self.write(|state| {
+            state.applications.insert(
+                descriptor.id.clone(),
+                MemoryApplication {
+                    executable: executable.to_string(),
+                    descriptor: descriptor.clone(),
+                },
+            );
+            Ok(())
+        })
+    }
+    fn get(&self, id: &str) -> Result<Option<ApplicationDescriptor>> {
+        Ok(self
+            .application(id)
+            .map(|application| application.descriptor))
+    }
+    fn executable(&self, id: &str) -> Result<Option<String>> {
+        Ok(self
+            .application(id)
+            .map(|application| application.executable))
+    }
+    fn delete(&self, id: &str) -> Result<()> {
+        self.write(|state| {
+            if state.applications.remove(id).is_none() {
+                return Err(io::Error::from(io::ErrorKind::NotFound).into());
+            }
+            for file_type in state.file_types.values_mut() {
+                file_type.forget(id);
+            }
+            Ok(())
+        })
+    }
+    fn enumerate(&self, id: &str) -> Result<Vec<String>> {
+        let state = self.state();
+        if !state.applications.contains_key(id) {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        }
+        Ok(state
+            .file_types
+            .iter()
+            .filter(|(_, file_type)| file_type.handlers.contains(id))
+            .map(|(name, _)| name.clone())
+            .collect())
+    }
+    fn associations(&self, id: &str) -> Result<Vec<Association>> {
+        if !self.state().applications.contains_key(id) {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        }
+        let mut associations = Vec::new();
+        for &scope in self.visible_scopes() {
+            let state = self.state_of(scope);
+            associations.extend(
+                state
+                    .file_types
+                    .iter()
+                    .filter(|(_, file_type)| file_type.handlers.contains(id))
+                    .map(|(name, _)| Association {
+                        file_type: name.clone(),
+                        scope,
+                    }),
+            );
+        }
+        Ok(associations)
+    }
+    fn handles(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        Ok(self
+            .file_type(&file_type.to_string())
+            .is_some_and(|file_type| file_type.handlers.contains(id)))
+    }
+    fn is_default_for(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        Ok(self.default_application(&file_type.to_string()).as_deref() == Some(id))
+    }
+    fn associate(&self, id: &str, file_type: &FileType, default: bool) -> Result<Option<String>> {
+        self.write(|state| {
+            if !state.applications.contains_key(id) {
+                return Err(io::Error::from(io::ErrorKind::NotFound).into());
+            }
+            let file_type = state.file_types.entry(file_type.to_string()).or_default();
+            file_type.handlers.insert(id.to_string());
+            file_type.hidden.remove(id);
+            if default {
+                Ok(file_type
+                    .default
+                    .replace(id.to_string())
+                    .filter(|previous| previous != id))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+    fn dissociate(&self, id: &str, file_type: &FileType) -> Result<()> {
+        self.write(|state| {
+            if !state.applications.contains_key(id) {
+                return Err(io::Error::from(io::ErrorKind::NotFound).into());
+            }
+            if let Some(file_type) = state.file_types.get_mut(&file_type.to_string()) {
+                file_type.handlers.remove(id);
+                if file_type.default.as_deref() == Some(id) {
+                    file_type.default = None;
+                }
+            }
+            Ok(())
+        })
+    }
+    fn hide_association(&self, id: &str, file_type: &FileType) -> Result<()> {
+        self.write(|state| {
+            if !state.applications.contains_key(id) {
+                return Err(io::Error::from(io::ErrorKind::NotFound).into());
+            }
+            let file_type = state.file_types.entry(file_type.to_string()).or_default();
+            file_type.handlers.remove(id);
+            file_type.hidden.insert(id.to_string());
+            if file_type.default.as_deref() == Some(id) {
+                file_type.default = None;
+            }
+            Ok(())
+        })
+    }
+    fn register_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+        self.write(|state| {
+            state.mime_packages.insert(id.to_string(), package.clone());
+            Ok(())
+        })
+    }
+    fn unregister_mime_package(&self, id: &str, _package: &MimePackage) -> Result<()> {
+        self.write(|state| {
+            state.mime_packages.remove(id);
+            Ok(())
+        })
+    }
+    /// Captures the entries of the applications and their MIME packages, and the entries of the file types the
+    /// applications are associated with or hidden from.
+    fn snapshot(&self, ids: &[String], file_types: &[FileType]) -> Result<Snapshot> {
+        let state = self.state();
+        let mut snapshot = Snapshot::new(self.scope);
+        for id in ids {
+            snapshot.applications.push(id.clone());
+            snapshot.push(
+                self.target(APPLICATIONS, id),
+                state.applications.get(id).map(MemoryApplication::to_value),
+            );
+            snapshot.push(
+                self.target(MIME_PACKAGES, id),
+                state
+                    .mime_packages
+                    .get(id)
+                    .map(|package| Value::Text(package.to_string())),
+            );
+            for (name, file_type) in &state.file_types {
+                if file_type.mentions(id) {
+                    snapshot.push(self.target(FILE_TYPES, name), Some(file_type.to_value()));
+                }
+            }
+        }
+        for file_type in file_types {
+            snapshot.file_types.push(file_type.clone());
+            let name = file_type.to_string();
+            snapshot.push(
+                self.target(FILE_TYPES, &name),
+                state.file_types.get(&name).map(MemoryFileType::to_value),
+            );
+        }
+        Ok(snapshot)
+    }
+    /// Only restores the entries of the scope of the snapshot, and fails with [io::ErrorKind::InvalidData] before
+    /// changing anything if the snapshot has other ones.
+    fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let backend = self.clone().scope(snapshot.scope);
+        let restored = snapshot
+            .entries
+            .iter()
+            .map(|entry| backend.read_entry(entry))
+            .collect::<Result<Vec<_>>>()?;
+
+        backend.write(|state| {
+            // What the applications got since the snapshot was taken is removed before the captured state is put back
+            for id in &snapshot.applications {
+                for (name, file_type) in state.file_types.iter_mut() {
+                    if snapshot.get(&backend.target(FILE_TYPES, name)).is_none() {
+                        file_type.forget(id);
+                    }
+                }
+            }
+            for restored in restored {
+                match restored {
+                    Restored::Application(id, application) => {
+                        put(&mut state.applications, id, application)
+                    }
+                    Restored::FileType(name, file_type) => {
+                        put(&mut state.file_types, name, file_type)
+                    }
+                    Restored::MimePackage(id, package) => {
+                        put(&mut state.mime_packages, id, package)
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+    fn default_application_for(
+        &self,
+        file_type: &FileType,
+    ) -> Result<Option<ApplicationDescriptor>> {
+        let file_type = file_type.to_string();
+        let Some(id) = self.visible_scopes().iter().find_map(|&scope| {
+            self.state_of(scope)
+                .file_types
+                .get(&file_type)
+                .and_then(|file_type| file_type.default.clone())
+        }) else {
+            return Ok(None);
+        };
+        Ok(self.visible_scopes().iter().find_map(|&scope| {
+            self.state_of(scope)
+                .applications
+                .get(&id)
+                .map(|application| application.descriptor.clone())
+        }))
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage backends used by [OpenOptions](crate::app::OpenOptions) and [Application](crate::app::Application).
+//!
+//! Every operating system keeps its file type associations in a different place (Windows Registry, XDG data directories, ...).
+//! A [Backend] hides those details behind a common set of operations, so the same application code runs everywhere.
+use crate::app::{ApplicationDescriptor, FileType};
+use crate::mime::MimePackage;
+use crate::plan::Plan;
+use crate::result::{Error, Result};
+use crate::snapshot::Snapshot;
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::sync::Arc;
+
+pub mod memory;
+
+pub use self::memory::MemoryBackend;
+
+/// Whether applications and their associations are registered for the current user only, or for every user of the machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scope {
+    /// Registrations of the current user: `HKEY_CURRENT_USER\Software\Classes` on Windows,
+    /// `$XDG_DATA_HOME` and `$XDG_CONFIG_HOME` on Linux.
+    #[default]
+    User,
+    /// Registrations of every user, which usually require administrator rights:
+    /// `HKEY_LOCAL_MACHINE\Software\Classes` on Windows, `/usr/share` and `/etc/xdg` on Linux.
+    System,
+}
+impl Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::User => "user",
+            Scope::System => "system",
+        })
+    }
+}
+
+/// File type handled by an application, together with the [Scope] the association is stored in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Association {
+    /// File extension or MIME type.
+    pub file_type: String,
+    /// Scope the association comes from.
+    pub scope: Scope,
+}
+
+/// Storage of applications and their file type associations.
+///
+/// [OpenOptions](crate::app::OpenOptions) uses [the platform default](crate::platform::default_backend) unless another backend is supplied
+/// through [OpenOptions::backend](crate::app::OpenOptions::backend).
+pub trait Backend: Debug + Send + Sync {
+    /// Register a new application described by `descriptor`, which is launched through the executable at `executable`.
+    /// If an application with the same ID already exists, it is overwritten.
+    fn create(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()>;
+    /// Check that the application described by `descriptor` can be registered as it is, as part of
+    /// [strict mode](crate::app::OpenOptions::strict) validation.
+    ///
+    /// Backends storing applications in files of their own, like desktop entries, validate the file they'd write.
+    fn validate(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        let _ = (executable, descriptor);
+        Ok(())
+    }
+    /// Get a backend storing applications in `scope`, configured like this one otherwise.
+    ///
+    /// Backends without scopes return [Error::Unsupported].
+    fn scoped(&self, scope: Scope) -> Result<Arc<dyn Backend>> {
+        let _ = scope;
+        Err(Error::Unsupported)
+    }
+    /// Get a backend which records the changes this backend would make into `plan`, instead of making them.
+    ///
+    /// The returned backend reads the state this backend stores. Whether its reads see the changes recorded before
+    /// depends on the backend, but its writes take them into account.
+    /// Backends which can't plan their changes return [Error::Unsupported].
+    fn dry_run(&self, plan: &Plan) -> Result<Arc<dyn Backend>> {
+        let _ = plan;
+        Err(Error::Unsupported)
+    }
+    /// Look up an application by its ID, returning [None] if it isn't registered.
+    fn get(&self, id: &str) -> Result<Option<ApplicationDescriptor>>;
+    /// Get path to the executable a registered application is launched through.
+    ///
+    /// Returns [None] if the application isn't registered or the backend can't tell which executable it uses.
+    fn executable(&self, id: &str) -> Result<Option<String>> {
+        let _ = id;
+        Ok(None)
+    }
+    /// Unregister an application.
+    fn delete(&self, id: &str) -> Result<()>;
+    /// List file types handled by an application.
+    fn enumerate(&self, id: &str) -> Result<Vec<String>>;
+    /// List file types handled by an application together with the [Scope] each association comes from,
+    /// including the associations of broader scopes the backend reads, e.g. [Scope::System] ones of a [Scope::User] backend.
+    ///
+    /// Backends without scopes report every [enumerated](Backend::enumerate) file type in [Scope::User].
+    fn associations(&self, id: &str) -> Result<Vec<Association>> {
+        Ok(self
+            .enumerate(id)?
+            .into_iter()
+            .map(|file_type| Association {
+                file_type,
+                scope: Scope::User,
+            })
+            .collect())
+    }
+    /// Check whether an application handles a file type in the scope of the backend, which it doesn't if it isn't
+    /// registered. The file type is resolved like [Backend::associate] does, e.g. an extension to its MIME type.
+    ///
+    /// By default, the file type is looked up among the [enumerated](Backend::enumerate) ones as it's written.
+    fn handles(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        match self.enumerate(id) {
+            Ok(file_types) => Ok(file_types.contains(&file_type.to_string())),
+            Err(Error::Io(why)) if why.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(why) => Err(why),
+        }
+    }
+    /// Check whether an application is the default one of exactly the file type, where [Backend::associate] stores it.
+    ///
+    /// Unlike [Backend::default_application_for], this doesn't fall back on other scopes or parent MIME types.
+    /// By default, the application is compared with the [default application](Backend::default_application_for),
+    /// which is what backends without scopes or parent types store.
+    fn is_default_for(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        match self.default_application_for(file_type) {
+            Ok(default) => Ok(default.is_some_and(|default| default.id == id)),
+            Err(Error::Unsupported) => Ok(false),
+            Err(why) => Err(why),
+        }
+    }
+    /// Make an application a handler of a file type, and the default one if `default` is set.
+    ///
+    /// Returns ID of the application which was the default handler before, unless it was the same application.
+    /// Backends which can't store associations for one of the kinds of [FileType] return [Error::Unsupported](crate::result::Error::Unsupported).
+    fn associate(&self, id: &str, file_type: &FileType, default: bool) -> Result<Option<String>>;
+    /// Stop an application from handling a file type, leaving the application itself registered.
+    ///
+    /// The default handler of the file type is only cleared if it is still the same application.
+    fn dissociate(&self, id: &str, file_type: &FileType) -> Result<()>;
+    /// Make an application a handler of a file type without making it the default one.
+    ///
+    /// Backends with a separate list of user-chosen associations, like `[Added Associations]` of `mimeapps.list`, add the
+    /// application there, others do the same as [Backend::associate] without `default`.
+    fn add_association(&self, id: &str, file_type: &FileType) -> Result<()> {
+        self.associate(id, file_type, false).map(drop)
+    }
+    /// Hide an application from the handlers of a file type, even though the application declares it can open it,
+    /// and stop it from being the default one. Associating the application with the file type again reverts this.
+    ///
+    /// Backends which can't hide handlers return [Error::Unsupported](crate::result::Error::Unsupported).
+    fn hide_association(&self, id: &str, file_type: &FileType) -> Result<()> {
+        let _ = (id, file_type);
+        Err(Error::Unsupported)
+    }
+    /// Declare the new MIME types of a package identified by `id`, replacing the package previously registered with that ID.
+    ///
+    /// Backends which can't declare MIME types return [Error::Unsupported](crate::result::Error::Unsupported).
+    fn register_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+        let _ = (id, package);
+        Err(Error::Unsupported)
+    }
+    /// Remove the MIME types declared by [Backend::register_mime_package].
+    fn unregister_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+        let _ = (id, package);
+        Err(Error::Unsupported)
+    }
+    /// Capture the registrations of the applications `ids`, including the file types they're associated with,
+    /// and the handlers of `file_types` into a [Snapshot] of the scope of the backend.
+    ///
+    /// Backends which can't take snapshots return [Error::Unsupported].
+    fn snapshot(&self, ids: &[String], file_types: &[FileType]) -> Result<Snapshot> {
+        let _ = (ids, file_types);
+        Err(Error::Unsupported)
+    }
+    /// Put back the state captured by [Backend::snapshot], in the scope the snapshot was taken in.
+    ///
+    /// Backends which can't restore snapshots, or the kinds of targets of this one, return [Error::Unsupported].
+    fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let _ = snapshot;
+        Err(Error::Unsupported)
+    }
+    /// Look up the application opening a file type by default, which doesn't have to be registered through this crate.
+    ///
+    /// Returns [None] if no application opens the file type.
+    fn default_application_for(
+        &self,
+        file_type: &FileType,
+    ) -> Result<Option<ApplicationDescriptor>>;
+}
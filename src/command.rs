@@ -0,0 +1,341 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Command line templates launching an application with the files it has to open.
+//!
+//! Windows and desktop entries use different placeholders for the files (`"%1"` and `%*` versus `%f`, `%F`, `%u`, ...)
+//! and different quoting rules, so commands are stored as a [CommandTemplate] and rendered in the [Syntax] of the backend.
+use crate::platform::xdg::{quote_exec_arg, split_exec};
+use crate::result::{Error, Result};
+
+/// Syntax of a command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Syntax {
+    /// Commands of ProgID verbs, parsed by [`CommandLineToArgvW`](https://learn.microsoft.com/en-us/windows/win32/api/shellapi/nf-shellapi-commandlinetoargvw).
+    Windows,
+    /// `Exec` key of a [desktop entry](https://specifications.freedesktop.org/desktop-entry-spec/latest/exec-variables.html).
+    DesktopEntry,
+}
+
+/// Argument of a [CommandTemplate].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Arg {
+    /// Argument passed as is.
+    Literal(String),
+    /// A single file path (`"%1"` on Windows, `%f` in desktop entries).
+    File,
+    /// A list of file paths (`%*` on Windows, `%F` in desktop entries).
+    Files,
+    /// A single URL (`"%1"` on Windows, `%u` in desktop entries).
+    Url,
+    /// A list of URLs (`%*` on Windows, `%U` in desktop entries).
+    Urls,
+    /// `--icon` followed by the icon of the application (`%i` in desktop entries, omitted on Windows).
+    Icon,
+    /// Translated name of the application (`%c` in desktop entries, omitted on Windows).
+    Name,
+    /// Location of the desktop file (`%k` in desktop entries, omitted on Windows).
+    DesktopFile,
+}
+impl From<&str> for Arg {
+    fn from(value: &str) -> Self {
+        Self::Literal(value.to_string())
+    }
+}
+impl From<String> for Arg {
+    fn from(value: String) -> Self {
+        Self::Literal(value)
+    }
+}
+
+/// Program together with the arguments it's launched with.
+///
+/// ```
+/// use fileext::command::{Arg, CommandTemplate, Syntax};
+///
+/// let command = CommandTemplate::new("/opt/My App/app").arg("--new-window").arg(Arg::Files);
+/// assert_eq!(command.render(Syntax::DesktopEntry), "\"/opt/My App/app\" --new-window %F");
+/// assert_eq!(command.render(Syntax::Windows), "\"/opt/My App/app\" --new-window %*");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CommandTemplate {
+    program: String,
+    args: Vec<Arg>,
+}
+impl CommandTemplate {
+    /// Create a template launching `program` without arguments.
+    pub fn new(program: impl ToString) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+        }
+    }
+    /// Append an argument.
+    pub fn arg(mut self, arg: impl Into<Arg>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+    /// Append several arguments.
+    pub fn args<A: Into<Arg>>(mut self, args: impl IntoIterator<Item = A>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Get the program launched by the command.
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+    /// Get arguments the program is launched with.
+    pub fn arguments(&self) -> &[Arg] {
+        &self.args
+    }
+
+    /// Render the command line, quoting the program and literal arguments as required by `syntax`.
+    pub fn render(&self, syntax: Syntax) -> String {
+        let mut parts = Vec::with_capacity(self.args.len() + 1);
+        match syntax {
+            Syntax::Windows => {
+                parts.push(format!("\"{}\"", self.program));
+                for arg in &self.args {
+                    match arg {
+                        Arg::Literal(literal) => parts.push(quote_windows_arg(literal)),
+                        Arg::File | Arg::Url => parts.push(String::from("\"%1\"")),
+                        Arg::Files | Arg::Urls => parts.push(String::from("%*")),
+                        Arg::Icon | Arg::Name | Arg::DesktopFile => (),
+                    }
+                }
+            }
+            Syntax::DesktopEntry => {
+                parts.push(quote_exec_arg(&self.program.replace('%', "%%")));
+                for arg in &self.args {
+                    parts.push(match arg {
+                        Arg::Literal(literal) => quote_exec_arg(&literal.replace('%', "%%")),
+                        Arg::File => String::from("%f"),
+                        Arg::Files => String::from("%F"),
+                        Arg::Url => String::from("%u"),
+                        Arg::Urls => String::from("%U"),
+                        Arg::Icon => String::from("%i"),
+                        Arg::Name => String::from("%c"),
+                        Arg::DesktopFile => String::from("%k"),
+                    });
+                }
+            }
+        }
+        parts.join(" ")
+    }
+
+    /// Parse a command line written in `syntax`.
+    ///
+    /// Placeholders Windows doesn't distinguish are parsed as [Arg::File] and [Arg::Files],
+    /// and deprecated field codes of desktop entries are dropped.
+    ///
+    /// # Errors
+    /// - [Error::InvalidCommand] if the command is empty or has an unterminated quote.
+    pub fn parse(command: &str, syntax: Syntax) -> Result<Self> {
+        let invalid = || Error::InvalidCommand(command.to_string());
+
+        let (program, args) = match syntax {
+            Syntax::Windows => {
+                let (program, rest) = split_windows_program(command).ok_or_else(invalid)?;
+                let args = split_windows_args(rest)
+                    .ok_or_else(invalid)?
+                    .into_iter()
+                    .map(|arg| match arg.as_str() {
+                        "%1" | "%L" | "%l" => Arg::File,
+                        "%*" => Arg::Files,
+                        _ => Arg::Literal(arg),
+                    })
+                    .collect();
+                (program, args)
+            }
+            Syntax::DesktopEntry => {
+                let mut parts = split_exec(command).ok_or_else(invalid)?.into_iter();
+                let program = parts.next().ok_or_else(invalid)?.replace("%%", "%");
+                let args = parts
+                    .filter_map(|arg| match arg.as_str() {
+                        "%f" => Some(Arg::File),
+                        "%F" => Some(Arg::Files),
+                        "%u" => Some(Arg::Url),
+                        "%U" => Some(Arg::Urls),
+                        "%i" => Some(Arg::Icon),
+                        "%c" => Some(Arg::Name),
+                        "%k" => Some(Arg::DesktopFile),
+                        "%d" | "%D" | "%n" | "%N" | "%v" | "%m" => None,
+                        _ => Some(Arg::Literal(arg.replace("%%", "%"))),
+                    })
+                    .collect();
+                (program, args)
+            }
+        };
+        if program.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self { program, args })
+    }
+}
+
+/// Quote an argument following the rules of `CommandLineToArgvW`.
+fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            c => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Backslashes in front of the closing quote would escape it
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Split off the program of a Windows command line, which unlike other arguments can't contain escaped quotes.
+fn split_windows_program(command: &str) -> Option<(String, &str)> {
+    let command = command.trim_start();
+    if let Some(quoted) = command.strip_prefix('"') {
+        let (program, rest) = quoted.split_once('"')?;
+        Some((program.to_string(), rest))
+    } else {
+        let end = command.find([' ', '\t']).unwrap_or(command.len());
+        Some((command[..end].to_string(), &command[end..]))
+    }
+}
+
+/// Split arguments of a Windows command line, following the rules of `CommandLineToArgvW`.
+fn split_windows_args(args: &str) -> Option<Vec<String>> {
+    let mut result = Vec::new();
+    let mut chars = args.chars().peekable();
+
+    loop {
+        while chars.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut arg = String::new();
+        let mut quoted = false;
+        let mut backslashes = 0;
+        for c in chars.by_ref() {
+            match c {
+                '\\' => {
+                    backslashes += 1;
+                    continue;
+                }
+                '"' => {
+                    arg.extend(std::iter::repeat_n('\\', backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        arg.push('"');
+                    } else {
+                        quoted = !quoted;
+                    }
+                }
+                ' ' | '\t' if !quoted => {
+                    arg.extend(std::iter::repeat_n('\\', backslashes));
+                    backslashes = 0;
+                    break;
+                }
+                c => {
+                    arg.extend(std::iter::repeat_n('\\', backslashes));
+                    arg.push(c);
+                }
+            }
+            backslashes = 0;
+        }
+        arg.extend(std::iter::repeat_n('\\', backslashes));
+        if quoted {
+            return None;
+        }
+        result.push(arg);
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let command = CommandTemplate::new("C:\\Program Files\\App\\app.exe")
+            .arg("--title")
+            .arg("100% \"done\"")
+            .arg(Arg::File)
+            .arg(Arg::Icon);
+
+        assert_eq!(
+            command.render(Syntax::Windows),
+            "\"C:\\Program Files\\App\\app.exe\" --title \"100% \\\"done\\\"\" \"%1\""
+        );
+        assert_eq!(
+            command.render(Syntax::DesktopEntry),
+            "\"C:\\\\Program Files\\\\App\\\\app.exe\" --title \"100%% \\\"done\\\"\" %f %i"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let command = CommandTemplate::new("/opt/My App/app")
+            .args(["--open", "a\\b\\", "quote\"d", "100%", ""])
+            .arg(Arg::Files);
+
+        for syntax in [Syntax::Windows, Syntax::DesktopEntry] {
+            let rendered = command.render(syntax);
+            assert_eq!(
+                CommandTemplate::parse(&rendered, syntax).unwrap(),
+                command,
+                "{rendered}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            CommandTemplate::parse("C:\\Windows\\notepad.exe %1", Syntax::Windows).unwrap(),
+            CommandTemplate::new("C:\\Windows\\notepad.exe").arg(Arg::File)
+        );
+        assert_eq!(
+            CommandTemplate::parse("gimp-2.10 %U %d", Syntax::DesktopEntry).unwrap(),
+            CommandTemplate::new("gimp-2.10").arg(Arg::Urls)
+        );
+        for (command, syntax) in [
+            ("", Syntax::Windows),
+            ("\"C:\\app.exe", Syntax::Windows),
+            ("app \"%1", Syntax::Windows),
+            ("  ", Syntax::DesktopEntry),
+            ("\"/usr/bin/app", Syntax::DesktopEntry),
+        ] {
+            assert!(matches!(
+                CommandTemplate::parse(command, syntax),
+                Err(Error::InvalidCommand(_))
+            ));
+        }
+    }
+}
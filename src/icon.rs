@@ -0,0 +1,248 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Icon images of applications and MIME types.
+//!
+//! On Linux, icons are installed into the `hicolor` theme of the
+//! [Icon Theme Specification](https://specifications.freedesktop.org/icon-theme-spec/latest/), which every other theme
+//! falls back to, and desktop entries and MIME types refer to them by name. An icon consists of images of the same
+//! picture at several sizes, stored in one directory per size.
+use crate::result::{Error, Result};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Name of the fallback icon theme icons are installed into.
+pub const HICOLOR: &str = "hicolor";
+/// Sizes in pixels of the bitmap directories of the `hicolor` theme. Images of other sizes wouldn't be found.
+pub const HICOLOR_SIZES: [u32; 13] = [16, 22, 24, 32, 36, 48, 64, 72, 96, 128, 192, 256, 512];
+/// Signature every PNG file starts with.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Kind of thing an icon represents, which decides the theme subdirectory it's installed into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IconContext {
+    /// Icon of an application, in `apps`.
+    Applications,
+    /// Icon of documents of a MIME type, in `mimetypes`.
+    MimeTypes,
+}
+impl IconContext {
+    /// Get the name of the theme subdirectory of the context.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Self::Applications => "apps",
+            Self::MimeTypes => "mimetypes",
+        }
+    }
+}
+
+/// File format of an icon image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IconFormat {
+    /// Bitmap image of a fixed size.
+    Png,
+    /// Vector image, which scales to any size.
+    Svg,
+}
+impl IconFormat {
+    /// Get the extension of files of the format, without the leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+/// Size an icon image is meant to be shown at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IconSize {
+    /// Square bitmap of this many pixels per side.
+    Pixels(u32),
+    /// Any size, for vector images.
+    Scalable,
+}
+impl IconSize {
+    /// Get the name of the theme directory of the size, e.g. `48x48` or `scalable`.
+    pub fn dir_name(self) -> String {
+        match self {
+            Self::Pixels(size) => format!("{size}x{size}"),
+            Self::Scalable => String::from("scalable"),
+        }
+    }
+}
+
+/// Image file of an icon at one size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconImage {
+    /// Path of the image file.
+    pub path: PathBuf,
+    /// Format of the image.
+    pub format: IconFormat,
+    /// Size the image is meant to be shown at.
+    pub size: IconSize,
+}
+impl IconImage {
+    /// Describe an image file, telling the format from its extension and reading the size of PNG images from the file.
+    ///
+    /// # Errors
+    /// - [Error::IconDoesntExist] if the file doesn't exist.
+    /// - [Error::InvalidIcon] if the file isn't a square PNG image or an SVG image.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("png") => {
+                let size = png_size(&path)?;
+                Ok(Self::png(path, size))
+            }
+            Some("svg") => Ok(Self::svg(path)),
+            _ => Err(invalid(&path)),
+        }
+    }
+    /// Describe a square PNG image of `size` pixels per side.
+    pub fn png(path: impl Into<PathBuf>, size: u32) -> Self {
+        Self {
+            path: path.into(),
+            format: IconFormat::Png,
+            size: IconSize::Pixels(size),
+        }
+    }
+    /// Describe a scalable SVG image.
+    pub fn svg(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            format: IconFormat::Svg,
+            size: IconSize::Scalable,
+        }
+    }
+
+    /// Check that the image exists and can be installed into the `hicolor` theme.
+    ///
+    /// # Errors
+    /// - [Error::IconDoesntExist] if the file doesn't exist.
+    /// - [Error::InvalidIcon] if a PNG image isn't as large as its [size](IconImage::size) says,
+    ///   or the size isn't one of the [sizes of the theme](HICOLOR_SIZES).
+    pub fn validate(&self) -> Result<()> {
+        if !self.path.is_file() {
+            return Err(Error::IconDoesntExist(self.path.display().to_string()));
+        }
+        match (self.format, self.size) {
+            (IconFormat::Png, IconSize::Pixels(size)) if HICOLOR_SIZES.contains(&size) => {
+                if png_size(&self.path)? == size {
+                    Ok(())
+                } else {
+                    Err(invalid(&self.path))
+                }
+            }
+            (IconFormat::Svg, _) => Ok(()),
+            _ => Err(invalid(&self.path)),
+        }
+    }
+    /// Get the path the image is installed to in an icon theme directory, e.g. `48x48/apps/org.example.App.png`.
+    pub fn theme_path(&self, theme_dir: &Path, context: IconContext, name: &str) -> PathBuf {
+        theme_dir
+            .join(self.size.dir_name())
+            .join(context.dir_name())
+            .join(format!("{name}.{}", self.format.extension()))
+    }
+}
+
+fn invalid(path: &Path) -> Error {
+    Error::InvalidIcon(path.display().to_string())
+}
+
+/// Read the size of a square PNG image from the `IHDR` chunk following its signature.
+fn png_size(path: &Path) -> Result<u32> {
+    let mut header = [0; 24];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_err(|why| match why.kind() {
+            io::ErrorKind::NotFound => Error::IconDoesntExist(path.display().to_string()),
+            io::ErrorKind::UnexpectedEof => invalid(path),
+            _ => Error::Io(why),
+        })?;
+    let word = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+    if !header.starts_with(PNG_SIGNATURE) || &header[12..16] != b"IHDR" || word(16) != word(20) {
+        return Err(invalid(path));
+    }
+    Ok(word(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    /// Build the start of a PNG file, which is all the size is read from.
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(13u32.to_be_bytes());
+        png.extend(b"IHDR");
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0]);
+        png
+    }
+
+    #[test]
+    fn images() {
+        let dir = env::temp_dir().join(format!("fileext-icon-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("icon.png"), png(48, 48)).unwrap();
+        fs::write(dir.join("wide.PNG"), png(48, 32)).unwrap();
+        fs::write(dir.join("odd.png"), png(50, 50)).unwrap();
+        fs::write(dir.join("icon.svg"), "<svg/>").unwrap();
+        fs::write(dir.join("icon.ico"), "").unwrap();
+
+        let image = IconImage::new(dir.join("icon.png")).unwrap();
+        assert_eq!(image, IconImage::png(dir.join("icon.png"), 48));
+        image.validate().unwrap();
+        assert_eq!(
+            image.theme_path(Path::new("hicolor"), IconContext::Applications, "app"),
+            Path::new("hicolor/48x48/apps/app.png")
+        );
+        let image = IconImage::new(dir.join("icon.svg")).unwrap();
+        image.validate().unwrap();
+        assert_eq!(
+            image.theme_path(Path::new("hicolor"), IconContext::MimeTypes, "text-x-app"),
+            Path::new("hicolor/scalable/mimetypes/text-x-app.svg")
+        );
+
+        for name in ["wide.PNG", "icon.ico"] {
+            let result = IconImage::new(dir.join(name));
+            assert!(matches!(result, Err(Error::InvalidIcon(_))), "{name}");
+        }
+        for image in [
+            IconImage::new(dir.join("odd.png")).unwrap(),
+            IconImage::png(dir.join("icon.png"), 32),
+        ] {
+            assert!(matches!(image.validate(), Err(Error::InvalidIcon(_))));
+        }
+        assert!(matches!(
+            IconImage::new(dir.join("missing.png")),
+            Err(Error::IconDoesntExist(_))
+        ));
+        assert!(matches!(
+            IconImage::svg(dir.join("missing.svg")).validate(),
+            Err(Error::IconDoesntExist(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -19,5 +19,14 @@
 #![warn(missing_docs)]
 
 pub mod app;
+pub mod backend;
+pub mod command;
+pub mod icon;
+pub mod mime;
+pub mod plan;
 pub mod platform;
 pub mod result;
+pub mod snapshot;
+pub mod transaction;
+
+pub use self::app::default_application_for;
@@ -0,0 +1,420 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Writer of `mime.cache`, the binary index of a `mime` directory in version 1.2 of the format, which GLib and other
+//! shared-mime-info readers map into memory instead of parsing the text files.
+//!
+//! All numbers are big-endian 32-bit words and all offsets are relative to the start of the file. Strings are
+//! NUL-terminated and, like every other record, aligned to 4 bytes.
+use super::glob::GlobKind;
+use super::magic::MagicRule;
+use super::update::MimeInfo;
+use std::collections::{BTreeMap, HashMap};
+
+/// Major and minor version of the format.
+const VERSION: (u16, u16) = (1, 2);
+/// Number of list offsets following the version in the header.
+const LISTS: usize = 10;
+/// Flag of the weight of case-sensitive literals and suffixes.
+const CASE_SENSITIVE: u32 = 0x100;
+
+/// Build the cache of the declarations of a `mime` directory.
+pub(super) fn build(info: &MimeInfo) -> Vec<u8> {
+    let mut cache = Cache::default();
+    let header = cache.alloc(4 + 4 * LISTS);
+    cache.data[..2].copy_from_slice(&VERSION.0.to_be_bytes());
+    cache.data[2..4].copy_from_slice(&VERSION.1.to_be_bytes());
+    let lists = [
+        aliases,
+        parents,
+        literals,
+        suffix_tree,
+        globs,
+        magic,
+        namespaces,
+        icons,
+        generic_icons,
+        types,
+    ];
+    for (i, list) in lists.into_iter().enumerate() {
+        let offset = list(&mut cache, info);
+        cache.put(header + 4 + 4 * i, offset);
+    }
+    cache.data
+}
+
+#[derive(Default)]
+struct Cache {
+    data: Vec<u8>,
+    strings: HashMap<Vec<u8>, u32>,
+}
+impl Cache {
+    /// Reserve a zeroed record of `size` bytes, returning its offset.
+    fn alloc(&mut self, size: usize) -> usize {
+        let offset = self.data.len();
+        self.data.resize(offset + size.next_multiple_of(4), 0);
+        offset
+    }
+    /// Reserve a list starting with its number of entries, returning the offset of the first entry.
+    fn list(&mut self, entries: usize, entry_size: usize) -> usize {
+        let offset = self.alloc(4 + entries * entry_size);
+        self.put(offset, entries as u32);
+        offset + 4
+    }
+    fn put(&mut self, offset: usize, value: u32) {
+        self.data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    /// Store bytes once, returning their offset.
+    fn bytes(&mut self, bytes: &[u8]) -> u32 {
+        if let Some(&offset) = self.strings.get(bytes) {
+            return offset;
+        }
+        let offset = self.alloc(bytes.len());
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.strings.insert(bytes.to_vec(), offset as u32);
+        offset as u32
+    }
+    /// Store a NUL-terminated string once, returning its offset.
+    fn string(&mut self, string: &str) -> u32 {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.push(0);
+        self.bytes(&bytes)
+    }
+    /// Write a list of string tuples, which readers search with `strcmp` if it's sorted by the first string.
+    fn string_list<const N: usize>(&mut self, entries: &[[&str; N]]) -> u32 {
+        let list = self.list(entries.len(), 4 * N);
+        for (i, entry) in entries.iter().enumerate() {
+            for (j, string) in entry.iter().enumerate() {
+                let offset = self.string(string);
+                self.put(list + 4 * (N * i + j), offset);
+            }
+        }
+        (list - 4) as u32
+    }
+}
+
+fn aliases(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let aliases: Vec<_> = info
+        .sorted_aliases()
+        .into_iter()
+        .map(|(alias, canonical)| [alias, canonical])
+        .collect();
+    cache.string_list(&aliases)
+}
+
+fn parents(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let mime_types = info.sorted_parents();
+    let list = cache.list(mime_types.len(), 8);
+    for (i, (mime_type, parents)) in mime_types.into_iter().enumerate() {
+        let offset = cache.string(mime_type);
+        cache.put(list + 8 * i, offset);
+        let record = cache.list(parents.len(), 4);
+        cache.put(list + 8 * i + 4, (record - 4) as u32);
+        for (j, parent) in parents.into_iter().enumerate() {
+            let offset = cache.string(parent);
+            cache.put(record + 4 * j, offset);
+        }
+    }
+    (list - 4) as u32
+}
+
+/// Write the globs of a kind as `(pattern, MIME type, weight and flags)` entries, ordered by pattern.
+fn glob_list(cache: &mut Cache, info: &MimeInfo, kind: GlobKind) -> u32 {
+    let mut globs = info.sorted_globs();
+    globs.retain(|glob| glob.kind() == kind);
+    if kind == GlobKind::Literal {
+        globs.sort_by(|lhs, rhs| lhs.pattern.cmp(&rhs.pattern));
+    }
+    let list = cache.list(globs.len(), 12);
+    for (i, glob) in globs.iter().enumerate() {
+        let pattern = cache.string(&glob.pattern);
+        let mime_type = cache.string(glob.mime_type.essence());
+        cache.put(list + 12 * i, pattern);
+        cache.put(list + 12 * i + 4, mime_type);
+        cache.put(list + 12 * i + 8, weight(glob.weight, glob.case_sensitive));
+    }
+    (list - 4) as u32
+}
+
+fn weight(weight: u32, case_sensitive: bool) -> u32 {
+    match case_sensitive {
+        true => weight | CASE_SENSITIVE,
+        false => weight,
+    }
+}
+
+fn literals(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    glob_list(cache, info, GlobKind::Literal)
+}
+
+fn globs(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    glob_list(cache, info, GlobKind::Pattern)
+}
+
+/// Node of the tree of reversed suffixes, with the MIME types of the suffix ending at the node.
+#[derive(Default)]
+struct Node<'a> {
+    children: BTreeMap<char, Node<'a>>,
+    leaves: Vec<(&'a str, u32)>,
+}
+
+/// Write the tree of the reversed suffixes of `*.ext`-like globs, which readers walk from the end of file names.
+///
+/// Each node is a `(character, number of children, first child)` record, and the MIME types of the suffix ending at
+/// a node are `(0, MIME type, weight and flags)` records sorted before its other children.
+fn suffix_tree(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let globs = info.sorted_globs();
+    let mut root = Node::default();
+    for glob in globs.iter().filter(|glob| glob.kind() == GlobKind::Suffix) {
+        let node = glob.pattern[1..]
+            .chars()
+            .rev()
+            .fold(&mut root, |node, c| node.children.entry(c).or_default());
+        node.leaves.push((
+            glob.mime_type.essence(),
+            weight(glob.weight, glob.case_sensitive),
+        ));
+    }
+
+    let offset = cache.alloc(8);
+    cache.put(offset, root.children.len() as u32);
+    let first = write_nodes(cache, &root);
+    cache.put(offset + 4, first);
+    offset as u32
+}
+
+/// Write the leaves and children of a node, followed by their descendants, returning the offset of the first one.
+fn write_nodes(cache: &mut Cache, node: &Node) -> u32 {
+    let first = cache.alloc(12 * (node.leaves.len() + node.children.len()));
+    for (i, &(mime_type, weight)) in node.leaves.iter().enumerate() {
+        let mime_type = cache.string(mime_type);
+        cache.put(first + 12 * i + 4, mime_type);
+        cache.put(first + 12 * i + 8, weight);
+    }
+    for (i, (&c, child)) in node.children.iter().enumerate() {
+        let record = first + 12 * (node.leaves.len() + i);
+        cache.put(record, c as u32);
+        cache.put(
+            record + 4,
+            (child.leaves.len() + child.children.len()) as u32,
+        );
+        let children = write_nodes(cache, child);
+        cache.put(record + 8, children);
+    }
+    first as u32
+}
+
+/// Write the magic sections as `(priority, MIME type, number of matchlets, first matchlet)` records,
+/// from the highest priority to the lowest one.
+fn magic(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let sections = info.sorted_magic();
+    let offset = cache.alloc(12);
+    let extent = sections
+        .iter()
+        .flat_map(|section| &section.rules)
+        .map(MagicRule::extent)
+        .max()
+        .unwrap_or(0);
+    let first = cache.alloc(16 * sections.len());
+    cache.put(offset, sections.len() as u32);
+    cache.put(offset + 4, extent as u32);
+    cache.put(offset + 8, first as u32);
+    for (i, section) in sections.iter().enumerate() {
+        let record = first + 16 * i;
+        let mime_type = cache.string(section.mime_type.essence());
+        cache.put(record, section.priority);
+        cache.put(record + 4, mime_type);
+        let (matchlets, first_matchlet) = write_matchlets(cache, &section.rules);
+        cache.put(record + 8, matchlets);
+        cache.put(record + 12, first_matchlet);
+    }
+    offset as u32
+}
+
+/// Write the top-level rules of a slice as matchlets, followed by their nested rules,
+/// returning their number and the offset of the first one.
+///
+/// Readers compare values and masks with the data byte by byte, so words are stored in the byte order of the host.
+fn write_matchlets(cache: &mut Cache, rules: &[MagicRule]) -> (u32, u32) {
+    let mut top_level = Vec::new();
+    let mut i = 0;
+    while i < rules.len() {
+        let end = rules[i + 1..]
+            .iter()
+            .position(|rule| rule.indent <= rules[i].indent)
+            .map_or(rules.len(), |at| i + 1 + at);
+        top_level.push((&rules[i], &rules[i + 1..end]));
+        i = end;
+    }
+
+    if top_level.is_empty() {
+        return (0, 0);
+    }
+    let count = top_level.len() as u32;
+    let first = cache.alloc(32 * top_level.len());
+    for (i, (rule, children)) in top_level.into_iter().enumerate() {
+        let record = first + 32 * i;
+        let (value, mask) = rule.host_order();
+        let value = cache.bytes(&value);
+        let mask = mask.map_or(0, |mask| cache.bytes(&mask));
+        let (children, first_child) = write_matchlets(cache, children);
+        let fields = [
+            rule.offset as u32,
+            rule.range as u32,
+            rule.word_size as u32,
+            rule.value.len() as u32,
+            value,
+            mask,
+            children,
+            first_child,
+        ];
+        for (j, field) in fields.into_iter().enumerate() {
+            cache.put(record + 4 * j, field);
+        }
+    }
+    (count, first as u32)
+}
+
+fn namespaces(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let namespaces: Vec<_> = info
+        .sorted_namespaces()
+        .into_iter()
+        .map(|(namespace_uri, local_name, mime_type)| [namespace_uri, local_name, mime_type])
+        .collect();
+    cache.string_list(&namespaces)
+}
+
+fn icons(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let icons: Vec<_> = info
+        .sorted_icons(|definition| &definition.icon)
+        .into_iter()
+        .map(|(mime_type, icon)| [mime_type, icon])
+        .collect();
+    cache.string_list(&icons)
+}
+
+fn generic_icons(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let icons: Vec<_> = info
+        .sorted_icons(|definition| &definition.generic_icon)
+        .into_iter()
+        .map(|(mime_type, icon)| [mime_type, icon])
+        .collect();
+    cache.string_list(&icons)
+}
+
+fn types(cache: &mut Cache, info: &MimeInfo) -> u32 {
+    let types: Vec<_> = info
+        .mime_types()
+        .map(|definition| [definition.mime_type.essence()])
+        .collect();
+    cache.string_list(&types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mime::{MimePackage, MimeType, MimeTypeDefinition};
+
+    fn mime(essence: &str) -> MimeType {
+        MimeType::parse(essence).unwrap()
+    }
+    fn word(cache: &[u8], offset: u32) -> u32 {
+        let offset = offset as usize;
+        u32::from_be_bytes(cache[offset..offset + 4].try_into().unwrap())
+    }
+    fn string(cache: &[u8], offset: u32) -> &str {
+        let bytes = &cache[offset as usize..];
+        let end = bytes.iter().position(|&byte| byte == 0).unwrap();
+        std::str::from_utf8(&bytes[..end]).unwrap()
+    }
+    /// Look up a file name in the suffix tree the way GLib does, from its last character.
+    fn lookup_suffix<'a>(cache: &'a [u8], file_name: &str) -> Vec<(&'a str, u32)> {
+        let tree = word(cache, 16);
+        let (mut count, mut first) = (word(cache, tree), word(cache, tree + 4));
+        let mut matches = Vec::new();
+        for c in file_name.chars().rev() {
+            let node = (0..count)
+                .map(|i| first + 12 * i)
+                .find(|&node| word(cache, node) == c as u32);
+            let Some(node) = node else { break };
+            (count, first) = (word(cache, node + 4), word(cache, node + 8));
+            matches = (0..count)
+                .map(|i| first + 12 * i)
+                .take_while(|&leaf| word(cache, leaf) == 0)
+                .map(|leaf| (string(cache, word(cache, leaf + 4)), word(cache, leaf + 8)))
+                .collect();
+        }
+        matches
+    }
+
+    #[test]
+    fn build() {
+        let mut info = MimeInfo::new();
+        info.add(
+            &MimePackage::new()
+                .mime_type(
+                    MimeTypeDefinition::new(mime("application/x-foo"), "Foo")
+                        .glob("*.foo")
+                        .weighted_glob("*.FOO", 60, true)
+                        .glob("Foofile")
+                        .glob("*.f[oi]o")
+                        .alias(mime("application/foo"))
+                        .sub_class_of(mime("application/zip")),
+                )
+                .mime_type(MimeTypeDefinition::new(mime("text/x-bar"), "Bar").glob("*.bar")),
+        );
+        let cache = super::build(&info);
+
+        assert_eq!(&cache[..4], b"\0\x01\0\x02");
+        assert_eq!(cache.len() % 4, 0);
+        let list = |i: u32| word(&cache, 4 + 4 * i);
+
+        let aliases = list(0);
+        assert_eq!(word(&cache, aliases), 1);
+        assert_eq!(string(&cache, word(&cache, aliases + 4)), "application/foo");
+        assert_eq!(
+            string(&cache, word(&cache, aliases + 8)),
+            "application/x-foo"
+        );
+
+        let parents = list(1);
+        let record = word(&cache, parents + 8);
+        assert_eq!(word(&cache, record), 1);
+        assert_eq!(string(&cache, word(&cache, record + 4)), "application/zip");
+
+        let literals = list(2);
+        assert_eq!(word(&cache, literals), 1);
+        assert_eq!(string(&cache, word(&cache, literals + 4)), "foofile");
+
+        assert_eq!(
+            lookup_suffix(&cache, "x.foo"),
+            vec![("application/x-foo", 50)]
+        );
+        assert_eq!(
+            lookup_suffix(&cache, "X.FOO"),
+            vec![("application/x-foo", 60 | CASE_SENSITIVE)]
+        );
+        assert_eq!(lookup_suffix(&cache, "x.bar"), vec![("text/x-bar", 50)]);
+        assert!(lookup_suffix(&cache, "x.baz").is_empty());
+
+        let globs = list(4);
+        assert_eq!(word(&cache, globs), 1);
+        assert_eq!(string(&cache, word(&cache, globs + 4)), "*.f[oi]o");
+
+        let types = list(9);
+        assert_eq!(word(&cache, types), 2);
+        assert_eq!(string(&cache, word(&cache, types + 8)), "text/x-bar");
+    }
+}
@@ -0,0 +1,459 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Relations between MIME types, file names and contents, loaded from the shared-mime-info database.
+use super::glob::GlobKind;
+use super::{Globs, Magic, MimeType};
+use crate::platform::xdg;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Get the `mime` directories of the shared-mime-info database, from the most important one to the least important one.
+///
+/// These are `$XDG_DATA_HOME/mime` followed by `mime` under every directory of `$XDG_DATA_DIRS`.
+pub fn mime_dirs() -> Vec<PathBuf> {
+    xdg::base_dirs::BaseDirs::from_env().mime_dirs()
+}
+
+/// Aliases, subclasses, [globs](Globs) and [magic](Magic) of MIME types, as described by the
+/// [shared-mime-info specification](https://specifications.freedesktop.org/shared-mime-info-spec/latest/ar01s02.html#subclassing).
+///
+/// MIME types are looked up case-insensitively and without their parameters.
+#[derive(Debug, Clone, Default)]
+pub struct MimeDatabase {
+    aliases: HashMap<String, MimeType>,
+    parents: HashMap<String, Vec<MimeType>>,
+    globs: Globs,
+    magic: Magic,
+}
+impl MimeDatabase {
+    /// Create an empty database, which still knows the implicit subclasses of the specification.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Load the database from the [mime directories](mime_dirs) of the current user.
+    pub fn load() -> io::Result<Self> {
+        Self::load_dirs(mime_dirs())
+    }
+    /// Load the database from `mime` directories, ordered from the most important one to the least important one.
+    ///
+    /// Missing directories and files are skipped.
+    pub fn load_dirs(dirs: impl IntoIterator<Item = impl AsRef<Path>>) -> io::Result<Self> {
+        let mut database = Self::new();
+        for dir in dirs {
+            database.load_dir(dir.as_ref())?;
+        }
+        Ok(database)
+    }
+    /// Merge the database of a `mime` directory less important than the ones loaded before.
+    pub fn load_dir(&mut self, dir: &Path) -> io::Result<()> {
+        for (alias, canonical) in read_pairs(&dir.join("aliases"))? {
+            if !self.aliases.contains_key(&key(&alias)) {
+                self.add_alias(alias, canonical);
+            }
+        }
+        for (child, parent) in read_pairs(&dir.join("subclasses"))? {
+            self.add_parent(child, parent);
+        }
+        self.globs.load_dir(dir)?;
+        self.magic.load_dir(dir)
+    }
+
+    /// Declare `alias` as another name of `canonical`.
+    pub fn add_alias(&mut self, alias: MimeType, canonical: MimeType) {
+        self.aliases
+            .insert(key(&alias), canonical.without_parameters());
+    }
+    /// Declare `parent` as a direct parent of `child`.
+    pub fn add_parent(&mut self, child: MimeType, parent: MimeType) {
+        let parents = self
+            .parents
+            .entry(key(&self.canonical(&child)))
+            .or_default();
+        let parent = parent.without_parameters();
+        if !parents.contains(&parent) {
+            parents.push(parent);
+        }
+    }
+
+    /// Resolve an alias to the canonical MIME type, returning MIME types which aren't aliases as they are, without parameters.
+    pub fn canonical(&self, mime_type: &MimeType) -> MimeType {
+        self.aliases
+            .get(&key(mime_type))
+            .cloned()
+            .unwrap_or_else(|| mime_type.without_parameters())
+    }
+    /// Get all aliases of a MIME type.
+    pub fn aliases(&self, mime_type: &MimeType) -> Vec<MimeType> {
+        let canonical = self.canonical(mime_type);
+        let mut aliases: Vec<MimeType> = self
+            .aliases
+            .iter()
+            .filter(|(_, target)| **target == canonical)
+            .filter_map(|(alias, _)| MimeType::parse(alias).ok())
+            .collect();
+        aliases.sort_by(|lhs, rhs| lhs.essence().cmp(rhs.essence()));
+        aliases
+    }
+    /// Get direct parents of a MIME type, including the implicit ones:
+    /// every `text/*` type is a subclass of `text/plain`, and every type except `inode/*` ones is a subclass of `application/octet-stream`.
+    pub fn parents(&self, mime_type: &MimeType) -> Vec<MimeType> {
+        let canonical = self.canonical(mime_type);
+        let mut parents = self
+            .parents
+            .get(&key(&canonical))
+            .cloned()
+            .unwrap_or_default();
+
+        let mut implicit = |essence: &str| {
+            let parent = MimeType::parse(essence).expect("valid MIME type");
+            if canonical != parent && !parents.contains(&parent) {
+                parents.push(parent);
+            }
+        };
+        if canonical.media_type().eq_ignore_ascii_case("text") {
+            implicit("text/plain");
+        }
+        if !canonical.media_type().eq_ignore_ascii_case("inode") {
+            implicit("application/octet-stream");
+        }
+        parents
+    }
+    /// Get the globs of the database.
+    pub fn globs(&self) -> &Globs {
+        &self.globs
+    }
+    /// Get mutable access to the globs of the database.
+    pub fn globs_mut(&mut self) -> &mut Globs {
+        &mut self.globs
+    }
+    /// Guess canonical MIME types of a file from its name, from the best match to the worst one.
+    ///
+    /// Only the last component of the path is looked at, the file doesn't have to exist.
+    pub fn mime_types_for_path(&self, path: impl AsRef<Path>) -> Vec<MimeType> {
+        let Some(file_name) = path.as_ref().file_name() else {
+            return Vec::new();
+        };
+        let mut mime_types: Vec<MimeType> = Vec::new();
+        for mime_type in self.globs.mime_types(&file_name.to_string_lossy()) {
+            let canonical = self.canonical(&mime_type);
+            if !mime_types.contains(&canonical) {
+                mime_types.push(canonical);
+            }
+        }
+        mime_types
+    }
+
+    /// Get the magic of the database.
+    pub fn magic(&self) -> &Magic {
+        &self.magic
+    }
+    /// Get mutable access to the magic of the database.
+    pub fn magic_mut(&mut self) -> &mut Magic {
+        &mut self.magic
+    }
+
+    /// Detect the canonical MIME type of a file from its name and its contents,
+    /// following the [recommended checking order](https://specifications.freedesktop.org/shared-mime-info-spec/latest/ar01s02.html)
+    /// of the specification.
+    ///
+    /// The contents are only read if the name matches no glob or globs of several MIME types.
+    /// Directories are detected as `inode/directory`.
+    pub fn detect(&self, path: impl AsRef<Path>) -> io::Result<MimeType> {
+        let path = path.as_ref();
+        if fs::metadata(path)?.is_dir() {
+            return Ok(MimeType::parse("inode/directory").expect("valid MIME type"));
+        }
+        let candidates = self.glob_candidates(path);
+        if let [mime_type] = candidates.as_slice() {
+            return Ok(mime_type.clone());
+        }
+
+        let mut data = Vec::new();
+        fs::File::open(path)?
+            .take(self.magic.extent().max(TEXT_SNIFF_LENGTH) as u64)
+            .read_to_end(&mut data)?;
+        Ok(self.resolve(candidates, &data))
+    }
+    /// Detect the canonical MIME type of data, optionally using the name of the file it was read from,
+    /// in the same way as [MimeDatabase::detect].
+    pub fn detect_data(&self, path: Option<&Path>, data: &[u8]) -> MimeType {
+        let candidates = path
+            .map(|path| self.glob_candidates(path))
+            .unwrap_or_default();
+        if let [mime_type] = candidates.as_slice() {
+            return mime_type.clone();
+        }
+        self.resolve(candidates, data)
+    }
+
+    /// Get MIME types of the best globs matching a path: the ones of the best [kind](GlobKind),
+    /// then of the highest weight, then of the longest pattern.
+    fn glob_candidates(&self, path: &Path) -> Vec<MimeType> {
+        let Some(file_name) = path.file_name() else {
+            return Vec::new();
+        };
+        let matches = self.globs.matches(&file_name.to_string_lossy());
+        let Some(best) = matches.first() else {
+            return Vec::new();
+        };
+        let (kind, weight): (GlobKind, u32) = (best.kind(), best.weight);
+        let length = |pattern: &str| pattern.chars().count();
+        let longest = matches
+            .iter()
+            .filter(|glob| glob.kind() == kind && glob.weight == weight)
+            .map(|glob| length(&glob.pattern))
+            .max()
+            .unwrap_or(0);
+
+        let mut candidates: Vec<MimeType> = Vec::new();
+        for glob in matches.iter().filter(|glob| {
+            glob.kind() == kind && glob.weight == weight && length(&glob.pattern) == longest
+        }) {
+            let canonical = self.canonical(&glob.mime_type);
+            if !candidates.contains(&canonical) {
+                candidates.push(canonical);
+            }
+        }
+        candidates
+    }
+    /// Decide between conflicting glob candidates, or the lack of them, by sniffing the data.
+    fn resolve(&self, candidates: Vec<MimeType>, data: &[u8]) -> MimeType {
+        let Some(sniffed) = self.magic.sniff(data) else {
+            return candidates.into_iter().next().unwrap_or_else(|| {
+                let fallback = if is_text(data) {
+                    "text/plain"
+                } else {
+                    "application/octet-stream"
+                };
+                MimeType::parse(fallback).expect("valid MIME type")
+            });
+        };
+        let sniffed = self.canonical(&sniffed.mime_type);
+
+        // A glob candidate more specific than the sniffed type, e.g. a text file named `*.csv`, is the better answer
+        candidates
+            .iter()
+            .find(|candidate| self.is_subclass_of(candidate, &sniffed))
+            .or(candidates.first())
+            .cloned()
+            .unwrap_or(sniffed)
+    }
+
+    /// Check whether `mime_type` is `parent`, an alias of it or a subclass of it, following parents recursively.
+    ///
+    /// A `parent` with `*` as its subtype, e.g. `image/*`, matches every MIME type of that media type.
+    pub fn is_subclass_of(&self, mime_type: &MimeType, parent: &MimeType) -> bool {
+        let parent = self.canonical(parent);
+        let mut visited = HashSet::new();
+        let mut queue = vec![self.canonical(mime_type)];
+
+        while let Some(current) = queue.pop() {
+            if current == parent
+                || (parent.subtype() == "*"
+                    && current
+                        .media_type()
+                        .eq_ignore_ascii_case(parent.media_type()))
+            {
+                return true;
+            }
+            if visited.insert(key(&current)) {
+                queue.extend(
+                    self.parents(&current)
+                        .into_iter()
+                        .map(|parent| self.canonical(&parent)),
+                );
+            }
+        }
+        false
+    }
+}
+
+/// Number of bytes checked for control characters to tell text from binary data.
+const TEXT_SNIFF_LENGTH: usize = 128;
+
+/// Check whether data looks like text: whether its start has no ASCII control characters other than whitespace.
+///
+/// Bytes with the high bit set are allowed, since they appear in UTF-8 text.
+fn is_text(data: &[u8]) -> bool {
+    data.iter()
+        .take(TEXT_SNIFF_LENGTH)
+        .all(|&byte| !byte.is_ascii_control() || matches!(byte, b'\t' | b'\n' | b'\r' | 0x0c))
+}
+
+fn key(mime_type: &MimeType) -> String {
+    mime_type.essence().to_ascii_lowercase()
+}
+
+/// Read a file consisting of lines with two space-separated MIME types, skipping comments and malformed lines.
+fn read_pairs(path: &Path) -> io::Result<Vec<(MimeType, MimeType)>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(why) => return Err(why),
+    };
+
+    Ok(contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (lhs, rhs) = line.trim().split_once(' ')?;
+            Some((
+                MimeType::parse(lhs).ok()?,
+                MimeType::parse(rhs.trim()).ok()?,
+            ))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn mime(essence: &str) -> MimeType {
+        MimeType::parse(essence).unwrap()
+    }
+
+    #[test]
+    fn load() {
+        let dir = env::temp_dir().join(format!("fileext-mime-database-{}", process::id()));
+        let (home, system) = (dir.join("home/mime"), dir.join("system/mime"));
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        fs::write(home.join("aliases"), "text/x-c text/x-csrc\n").unwrap();
+        fs::write(
+            system.join("aliases"),
+            "# comment\ntext/x-c text/x-chdr\napplication/x-pdf application/pdf\n",
+        )
+        .unwrap();
+        fs::write(
+            system.join("subclasses"),
+            "text/x-csrc text/plain\nimage/svg+xml application/xml\napplication/xml text/plain\n",
+        )
+        .unwrap();
+
+        let database = MimeDatabase::load_dirs([&home, &system, &dir.join("missing")]).unwrap();
+        assert_eq!(database.canonical(&mime("text/x-c")), mime("text/x-csrc"));
+        assert_eq!(
+            database.canonical(&mime("Application/X-PDF; version=1.7")),
+            mime("application/pdf")
+        );
+        assert_eq!(
+            database.aliases(&mime("application/pdf")),
+            vec![mime("application/x-pdf")]
+        );
+
+        assert!(database.is_subclass_of(&mime("text/x-c"), &mime("text/plain")));
+        assert!(database.is_subclass_of(&mime("image/svg+xml"), &mime("text/plain")));
+        assert!(database.is_subclass_of(&mime("image/svg+xml"), &mime("image/*")));
+        assert!(database.is_subclass_of(&mime("image/png"), &mime("application/octet-stream")));
+        assert!(!database.is_subclass_of(&mime("image/png"), &mime("text/plain")));
+        assert!(
+            !database.is_subclass_of(&mime("inode/directory"), &mime("application/octet-stream"))
+        );
+
+        fs::write(
+            system.join("globs2"),
+            "50:text/x-c:*.c\n50:application/pdf:*.pdf\n",
+        )
+        .unwrap();
+        let database = MimeDatabase::load_dirs([&home, &system]).unwrap();
+        assert_eq!(
+            database.mime_types_for_path("/tmp/main.c"),
+            vec![mime("text/x-csrc")]
+        );
+        assert_eq!(
+            database.mime_types_for_path("Report.PDF"),
+            vec![mime("application/pdf")]
+        );
+        assert!(database.mime_types_for_path("/").is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn detect() {
+        let dir = env::temp_dir().join(format!("fileext-mime-detect-{}", process::id()));
+        let mime_dir = dir.join("mime");
+        fs::create_dir_all(&mime_dir).unwrap();
+        fs::write(
+            mime_dir.join("globs2"),
+            "50:application/msword:*.doc\n\
+             50:text/x-readme:README\n\
+             50:application/x-ole-storage:*.doc\n\
+             50:application/pdf:*.pdf\n",
+        )
+        .unwrap();
+        fs::write(
+            mime_dir.join("subclasses"),
+            "application/msword application/x-ole-storage\n",
+        )
+        .unwrap();
+        let mut magic =
+            b"MIME-Magic\0\n[50:application/x-ole-storage]\n>0=\0\x04\xd0\xcf\x11\xe0\n".to_vec();
+        magic.extend(b"[50:application/pdf]\n>0=\0\x05%PDF-\n");
+        fs::write(mime_dir.join("magic"), magic).unwrap();
+        let database = MimeDatabase::load_dirs([&mime_dir]).unwrap();
+
+        let detect = |name: &str, contents: &[u8]| {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            database.detect(&path).unwrap().to_string()
+        };
+        // Globs of a single MIME type win without looking at the contents
+        assert_eq!(detect("report.pdf", b"plain text"), "application/pdf");
+        assert_eq!(detect("README", b"\0\x01"), "text/x-readme");
+        // Conflicting globs are resolved with the magic, preferring the more specific type
+        assert_eq!(
+            detect("letter.doc", b"\xd0\xcf\x11\xe0"),
+            "application/msword"
+        );
+        assert_eq!(detect("letter.doc", b"plain text"), "application/msword");
+        // Without globs, the magic or the kind of the contents decides
+        assert_eq!(detect("document", b"%PDF-1.7"), "application/pdf");
+        assert_eq!(
+            detect("notes", "plain text \u{e9}".as_bytes()),
+            "text/plain"
+        );
+        assert_eq!(detect("binary", b"\x7fELF\x02"), "application/octet-stream");
+        assert_eq!(
+            database.detect(&dir).unwrap().to_string(),
+            "inode/directory"
+        );
+        assert_eq!(
+            database
+                .detect_data(Some(Path::new("upload.bin")), b"%PDF-1.4")
+                .to_string(),
+            "application/pdf"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn implicit_parents() {
+        let database = MimeDatabase::new();
+        assert_eq!(
+            database.parents(&mime("text/x-python")),
+            vec![mime("text/plain"), mime("application/octet-stream")]
+        );
+        assert_eq!(
+            database.parents(&mime("text/plain")),
+            vec![mime("application/octet-stream")]
+        );
+        assert!(database.parents(&mime("inode/directory")).is_empty());
+    }
+}
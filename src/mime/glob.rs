@@ -0,0 +1,346 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! File name patterns of MIME types, loaded from the `globs2` files of the shared-mime-info database.
+use super::MimeType;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::path::Path;
+use std::{fs, io};
+
+/// Weight of globs which don't specify one.
+pub const DEFAULT_WEIGHT: u32 = 50;
+/// Pattern discarding globs of a MIME type defined in less important directories.
+pub const NOGLOBS: &str = "__NOGLOBS__";
+
+/// File name pattern of a MIME type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    /// MIME type of files matching the pattern.
+    pub mime_type: MimeType,
+    /// Pattern in the syntax of `fnmatch`, e.g. `*.txt` or `Makefile`.
+    pub pattern: String,
+    /// Weight of the pattern, from 0 to 100. Matches with a higher weight are preferred.
+    pub weight: u32,
+    /// Whether the pattern is matched case-sensitively.
+    pub case_sensitive: bool,
+}
+impl Glob {
+    /// Create a case-insensitive glob with the [default weight](DEFAULT_WEIGHT).
+    pub fn new(mime_type: MimeType, pattern: impl ToString) -> Self {
+        Self {
+            mime_type,
+            pattern: pattern.to_string(),
+            weight: DEFAULT_WEIGHT,
+            case_sensitive: false,
+        }
+    }
+
+    /// Get the kind of the pattern, which decides how its matches are ranked.
+    pub fn kind(&self) -> GlobKind {
+        let is_literal = |pattern: &str| !pattern.contains(['*', '?', '[']);
+        if is_literal(&self.pattern) {
+            GlobKind::Literal
+        } else if self
+            .pattern
+            .strip_prefix('*')
+            .is_some_and(|suffix| !suffix.is_empty() && is_literal(suffix))
+        {
+            GlobKind::Suffix
+        } else {
+            GlobKind::Pattern
+        }
+    }
+    /// Check whether a file name, without the directory it's in, matches the pattern.
+    pub fn matches(&self, file_name: &str) -> bool {
+        if self.case_sensitive {
+            fnmatch(&self.pattern, file_name)
+        } else {
+            fnmatch(&self.pattern.to_lowercase(), &file_name.to_lowercase())
+        }
+    }
+}
+
+/// Kind of a [Glob], from the most preferred one to the least preferred one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GlobKind {
+    /// The whole file name, e.g. `Makefile`.
+    Literal,
+    /// `*` followed by a literal suffix, e.g. `*.tar.gz`.
+    Suffix,
+    /// Any other pattern, e.g. `*.[ch]` or `README*`.
+    Pattern,
+}
+
+/// Set of [globs](Glob) matching file names to MIME types.
+#[derive(Debug, Clone, Default)]
+pub struct Globs {
+    globs: Vec<Glob>,
+    /// MIME types whose globs from less important directories are discarded.
+    overridden: HashSet<String>,
+}
+impl Globs {
+    /// Create an empty set of globs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Merge globs of a `mime` directory less important than the ones loaded before.
+    ///
+    /// The `globs2` file is preferred, the `globs` file of the older format without weights is read if it doesn't exist.
+    pub fn load_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let (contents, with_weights) = match fs::read_to_string(dir.join("globs2")) {
+            Ok(contents) => (contents, true),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => {
+                match fs::read_to_string(dir.join("globs")) {
+                    Ok(contents) => (contents, false),
+                    Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(()),
+                    Err(why) => return Err(why),
+                }
+            }
+            Err(why) => return Err(why),
+        };
+
+        let globs = contents
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| parse_line(line, with_weights));
+        let mut overridden = HashSet::new();
+        let mut added = Vec::new();
+        for glob in globs {
+            let key = key(&glob.mime_type);
+            if self.overridden.contains(&key) {
+                continue;
+            }
+            if glob.pattern == NOGLOBS {
+                overridden.insert(key);
+            } else {
+                added.push(glob);
+            }
+        }
+
+        self.globs.extend(added);
+        self.overridden.extend(overridden);
+        Ok(())
+    }
+    /// Add a glob.
+    pub fn add(&mut self, glob: Glob) {
+        self.globs.push(glob);
+    }
+    /// Get all globs.
+    pub fn iter(&self) -> impl Iterator<Item = &Glob> {
+        self.globs.iter()
+    }
+
+    /// Get every glob matching a file name, without the directory it's in, from the best match to the worst one.
+    ///
+    /// Literal patterns rank above suffix patterns, which rank above other patterns. Within the same kind,
+    /// higher weights rank first, then longer patterns, then case-sensitive ones.
+    pub fn matches(&self, file_name: &str) -> Vec<&Glob> {
+        let mut matches: Vec<&Glob> = self
+            .globs
+            .iter()
+            .filter(|glob| glob.matches(file_name))
+            .collect();
+        matches.sort_by_key(|glob| {
+            (
+                glob.kind(),
+                Reverse(glob.weight),
+                Reverse(glob.pattern.chars().count()),
+                !glob.case_sensitive,
+            )
+        });
+        matches
+    }
+    /// Get MIME types of a file name, without the directory it's in, from the best match to the worst one.
+    pub fn mime_types(&self, file_name: &str) -> Vec<MimeType> {
+        let mut mime_types: Vec<MimeType> = Vec::new();
+        for glob in self.matches(file_name) {
+            if !mime_types.contains(&glob.mime_type) {
+                mime_types.push(glob.mime_type.clone());
+            }
+        }
+        mime_types
+    }
+}
+
+fn key(mime_type: &MimeType) -> String {
+    mime_type.essence().to_ascii_lowercase()
+}
+
+/// Parse a `weight:mimetype:glob[:flags]` line of `globs2`, or a `mimetype:glob` line of `globs`.
+fn parse_line(line: &str, with_weights: bool) -> Option<Glob> {
+    let (weight, rest) = if with_weights {
+        let (weight, rest) = line.split_once(':')?;
+        (weight.parse().ok()?, rest)
+    } else {
+        (DEFAULT_WEIGHT, line)
+    };
+    let (mime_type, rest) = rest.split_once(':')?;
+    let (pattern, flags) = if with_weights {
+        rest.split_once(':').unwrap_or((rest, ""))
+    } else {
+        (rest, "")
+    };
+    if pattern.is_empty() {
+        return None;
+    }
+
+    Some(Glob {
+        mime_type: MimeType::parse(mime_type).ok()?,
+        pattern: pattern.to_string(),
+        weight,
+        case_sensitive: flags.split(',').any(|flag| flag == "cs"),
+    })
+}
+
+/// Match `name` against a shell wildcard pattern supporting `*`, `?` and bracket expressions such as `[a-z]` and `[!0-9]`.
+pub fn fnmatch(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position in the pattern after the last `*` and position in the name it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_bracket(&pattern[p..], name[n]),
+            Some(&c) => (c == name[n]).then_some(1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(length), _) => {
+                p += length;
+                n += 1;
+            }
+            (None, Some((star, tried))) => {
+                p = star;
+                n = tried + 1;
+                backtrack = Some((star, tried + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match a character against a bracket expression at the start of `pattern`,
+/// returning length of the expression if it matches.
+fn match_bracket(pattern: &[char], c: char) -> Option<usize> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let &start = pattern.get(i)?;
+        if start == ']' && !first {
+            break;
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+    (matched != negated).then_some(i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn wildcards() {
+        assert!(fnmatch("*.txt", "notes.txt"));
+        assert!(fnmatch("*", ""));
+        assert!(fnmatch("README*", "README.md"));
+        assert!(fnmatch("*.[ch]", "main.c"));
+        assert!(fnmatch("*.[!ch]", "main.o"));
+        assert!(fnmatch("core.[0-9]*", "core.1234"));
+        assert!(fnmatch("a?c*d", "abcxyzd"));
+        assert!(!fnmatch("*.txt", "notes.txt.bak"));
+        assert!(!fnmatch("*.[ch]", "main.o"));
+        assert!(!fnmatch("a?c", "ac"));
+        // Unterminated bracket expressions never match
+        assert!(!fnmatch("[abc", "a"));
+    }
+
+    #[test]
+    fn ranking() {
+        let dir = env::temp_dir().join(format!("fileext-mime-glob-{}", process::id()));
+        let (home, system) = (dir.join("home/mime"), dir.join("system/mime"));
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        fs::write(
+            home.join("globs2"),
+            "# comment\n50:text/x-vendor-csv:__NOGLOBS__\n50:text/x-vendor-csv:*.vcsv\n",
+        )
+        .unwrap();
+        fs::write(
+            system.join("globs2"),
+            "50:application/gzip:*.gz\n\
+             50:application/x-compressed-tar:*.tar.gz\n\
+             10:text/x-readme:README*\n\
+             50:text/x-makefile:makefile\n\
+             50:text/x-makefile:Makefile:cs\n\
+             50:text/x-c++src:*.C:cs\n\
+             50:text/x-csrc:*.c\n\
+             80:text/x-vendor-csv:*.csv\n\
+             50:text/csv:*.csv\n",
+        )
+        .unwrap();
+
+        let mut globs = Globs::new();
+        globs.load_dir(&home).unwrap();
+        globs.load_dir(&system).unwrap();
+        let mime_types = |file_name: &str| -> Vec<String> {
+            globs
+                .mime_types(file_name)
+                .iter()
+                .map(|mime_type| mime_type.to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            mime_types("report.tar.gz"),
+            vec!["application/x-compressed-tar", "application/gzip"]
+        );
+        assert_eq!(
+            mime_types("REPORT.TAR.GZ")[0],
+            "application/x-compressed-tar"
+        );
+        assert_eq!(mime_types("main.C"), vec!["text/x-c++src", "text/x-csrc"]);
+        assert_eq!(mime_types("main.c"), vec!["text/x-csrc"]);
+        assert_eq!(mime_types("Makefile"), vec!["text/x-makefile"]);
+        assert_eq!(mime_types("README.c"), vec!["text/x-csrc", "text/x-readme"]);
+        assert_eq!(mime_types("data.csv"), vec!["text/csv"]);
+        assert_eq!(mime_types("data.vcsv"), vec!["text/x-vendor-csv"]);
+        assert!(mime_types("data.unknown").is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
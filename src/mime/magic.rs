@@ -0,0 +1,450 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Content sniffing rules of MIME types, loaded from the `magic` files of the shared-mime-info database.
+use super::MimeType;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Header every `magic` file starts with.
+pub const MAGIC_HEADER: &[u8] = b"MIME-Magic\0\n";
+/// Rule discarding magic of a MIME type defined in less important directories.
+pub const NOMAGIC: &[u8] = b"__NOMAGIC__";
+
+/// Single test of a [MagicSection], comparing bytes at an offset of the data with a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagicRule {
+    /// Nesting level of the rule. Rules are only tested if their parent, the closest previous rule with a lower level, matches.
+    pub indent: u32,
+    /// First offset the value is looked for at.
+    pub offset: usize,
+    /// Bytes the data is compared with.
+    pub value: Vec<u8>,
+    /// Mask applied to both the value and the data before comparing them, as long as the value.
+    pub mask: Option<Vec<u8>>,
+    /// Size of the words of the value in bytes, which are stored big-endian and swapped to the host byte order if larger than 1.
+    pub word_size: usize,
+    /// Number of offsets the value is looked for at, starting with [MagicRule::offset].
+    pub range: usize,
+}
+impl MagicRule {
+    /// Check whether the data contains the value at any offset of the range, ignoring nested rules.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        let (value, mask) = self.host_order();
+        (self.offset..self.offset.saturating_add(self.range)).any(|offset| {
+            let Some(bytes) = offset
+                .checked_add(value.len())
+                .and_then(|end| data.get(offset..end))
+            else {
+                return false;
+            };
+            match &mask {
+                Some(mask) => bytes
+                    .iter()
+                    .zip(&value)
+                    .zip(mask)
+                    .all(|((byte, value), mask)| byte & mask == value & mask),
+                None => bytes == value,
+            }
+        })
+    }
+    /// Get the number of bytes of data the rule looks at, [usize::MAX] if it doesn't fit.
+    pub fn extent(&self) -> usize {
+        self.offset
+            .saturating_add(self.range.max(1) - 1)
+            .saturating_add(self.value.len())
+    }
+
+    /// Get the value and the mask with their words in the byte order of the host.
+    pub(super) fn host_order(&self) -> (Vec<u8>, Option<Vec<u8>>) {
+        let swap = |bytes: &[u8]| {
+            let mut bytes = bytes.to_vec();
+            if cfg!(target_endian = "little") && self.word_size > 1 {
+                for word in bytes.chunks_exact_mut(self.word_size) {
+                    word.reverse();
+                }
+            }
+            bytes
+        };
+        (swap(&self.value), self.mask.as_deref().map(swap))
+    }
+}
+
+/// Magic of a MIME type: a priority and a tree of [rules](MagicRule), which matches if any path from a top-level rule matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagicSection {
+    /// Priority of the section, from 0 to 100. Sections with a higher priority are tested first.
+    pub priority: u32,
+    /// MIME type of data matching the section.
+    pub mime_type: MimeType,
+    /// Rules of the section, in the order of the file.
+    pub rules: Vec<MagicRule>,
+}
+impl MagicSection {
+    /// Check whether the data matches the section.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        evaluate(&self.rules, data)
+    }
+}
+
+/// Check whether any rule of the top level of `rules` matches along with one of its nested rules, if it has any.
+fn evaluate(rules: &[MagicRule], data: &[u8]) -> bool {
+    let mut i = 0;
+    while i < rules.len() {
+        let indent = rules[i].indent;
+        let end = rules[i + 1..]
+            .iter()
+            .position(|rule| rule.indent <= indent)
+            .map_or(rules.len(), |at| i + 1 + at);
+        let children = &rules[i + 1..end];
+        if rules[i].matches(data) && (children.is_empty() || evaluate(children, data)) {
+            return true;
+        }
+        i = end;
+    }
+    false
+}
+
+/// Set of [magic sections](MagicSection), ordered from the highest priority to the lowest one.
+#[derive(Debug, Clone, Default)]
+pub struct Magic {
+    sections: Vec<MagicSection>,
+    /// MIME types whose magic from less important directories is discarded.
+    overridden: HashSet<String>,
+}
+impl Magic {
+    /// Create an empty set of magic.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Merge the `magic` file of a `mime` directory less important than the ones loaded before.
+    ///
+    /// # Errors
+    /// - [io::ErrorKind::InvalidData] if the file doesn't start with the [header](MAGIC_HEADER).
+    pub fn load_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let contents = match fs::read(dir.join("magic")) {
+            Ok(contents) => contents,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(why) => return Err(why),
+        };
+        let (sections, overridden) = parse(&contents)?;
+
+        for section in sections {
+            if !self.overridden.contains(&key(&section.mime_type)) {
+                self.add(section);
+            }
+        }
+        self.overridden.extend(overridden);
+        Ok(())
+    }
+    /// Add a section after the sections of the same or a higher priority.
+    pub fn add(&mut self, section: MagicSection) {
+        let at = self
+            .sections
+            .partition_point(|existing| existing.priority >= section.priority);
+        self.sections.insert(at, section);
+    }
+    /// Get all sections, from the highest priority to the lowest one.
+    pub fn iter(&self) -> impl Iterator<Item = &MagicSection> {
+        self.sections.iter()
+    }
+    /// Get the number of bytes from the start of a file needed to test every rule.
+    pub fn extent(&self) -> usize {
+        self.sections
+            .iter()
+            .flat_map(|section| &section.rules)
+            .map(MagicRule::extent)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get the section of the highest priority matching the data.
+    pub fn sniff(&self, data: &[u8]) -> Option<&MagicSection> {
+        self.sections.iter().find(|section| section.matches(data))
+    }
+    /// Read the first [extent](Magic::extent) bytes of a reader and get the section of the highest priority matching them.
+    pub fn sniff_reader(&self, reader: impl Read) -> io::Result<Option<&MagicSection>> {
+        let mut data = Vec::new();
+        reader.take(self.extent() as u64).read_to_end(&mut data)?;
+        Ok(self.sniff(&data))
+    }
+}
+
+fn key(mime_type: &MimeType) -> String {
+    mime_type.essence().to_ascii_lowercase()
+}
+
+/// Parse a `magic` file, returning its sections and the MIME types overriding magic of less important directories.
+///
+/// Malformed rules and sections are skipped.
+fn parse(contents: &[u8]) -> io::Result<(Vec<MagicSection>, HashSet<String>)> {
+    let mut parser = Parser {
+        contents: contents
+            .strip_prefix(MAGIC_HEADER)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a magic file"))?,
+    };
+    let mut sections = Vec::new();
+    let mut overridden = HashSet::new();
+
+    while !parser.contents.is_empty() {
+        let Some((priority, mime_type)) = parser.header() else {
+            parser.skip_section();
+            continue;
+        };
+        let mut section = MagicSection {
+            priority,
+            mime_type,
+            rules: Vec::new(),
+        };
+        while !parser.contents.is_empty() && !parser.contents.starts_with(b"[") {
+            if parser.contents.starts_with(b">") && parser.contents[1..].starts_with(NOMAGIC) {
+                overridden.insert(key(&section.mime_type));
+                parser.skip_line();
+                continue;
+            }
+            match parser.rule() {
+                Some(rule) => section.rules.push(rule),
+                None => parser.skip_line(),
+            }
+        }
+        if !section.rules.is_empty() {
+            sections.push(section);
+        }
+    }
+    Ok((sections, overridden))
+}
+
+struct Parser<'a> {
+    contents: &'a [u8],
+}
+impl Parser<'_> {
+    /// Parse a `[priority:mime/type]` line.
+    fn header(&mut self) -> Option<(u32, MimeType)> {
+        let end = self.contents.iter().position(|&byte| byte == b'\n')?;
+        let line = std::str::from_utf8(&self.contents[..end]).ok()?;
+        self.contents = &self.contents[end + 1..];
+
+        let (priority, mime_type) = line.strip_prefix('[')?.strip_suffix(']')?.split_once(':')?;
+        Some((priority.parse().ok()?, MimeType::parse(mime_type).ok()?))
+    }
+    /// Parse a `[indent]>start-offset=value[&mask][~word-size][+range-length]` line.
+    fn rule(&mut self) -> Option<MagicRule> {
+        let indent = self.number().unwrap_or(0);
+        self.expect(b'>')?;
+        let offset = self.number()?;
+        self.expect(b'=')?;
+        let length = u16::from_be_bytes([*self.contents.first()?, *self.contents.get(1)?]) as usize;
+        self.contents = &self.contents[2..];
+        let value = self.bytes(length)?;
+
+        let mut rule = MagicRule {
+            indent,
+            offset,
+            value,
+            mask: None,
+            word_size: 1,
+            range: 1,
+        };
+        loop {
+            match self.contents.first()? {
+                b'&' => {
+                    self.contents = &self.contents[1..];
+                    rule.mask = Some(self.bytes(length)?);
+                }
+                b'~' => {
+                    self.contents = &self.contents[1..];
+                    rule.word_size = self.number()?;
+                }
+                b'+' => {
+                    self.contents = &self.contents[1..];
+                    rule.range = self.number::<usize>()?.max(1);
+                }
+                b'\n' => {
+                    self.contents = &self.contents[1..];
+                    break;
+                }
+                _ => return None,
+            }
+        }
+
+        // Words which don't fit in the value can't be swapped
+        if !matches!(rule.word_size, 1 | 2 | 4) || !rule.value.len().is_multiple_of(rule.word_size)
+        {
+            return None;
+        }
+        // Neither can data be looked at past the end of the address space
+        rule.offset
+            .checked_add(rule.range)?
+            .checked_add(rule.value.len())?;
+        Some(rule)
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Option<T> {
+        let end = self
+            .contents
+            .iter()
+            .position(|byte| !byte.is_ascii_digit())
+            .unwrap_or(self.contents.len());
+        let number = std::str::from_utf8(&self.contents[..end])
+            .ok()?
+            .parse()
+            .ok()?;
+        self.contents = &self.contents[end..];
+        Some(number)
+    }
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.contents = self.contents.strip_prefix(&[byte])?;
+        Some(())
+    }
+    fn bytes(&mut self, length: usize) -> Option<Vec<u8>> {
+        let bytes = self.contents.get(..length)?.to_vec();
+        self.contents = &self.contents[length..];
+        Some(bytes)
+    }
+    fn skip_line(&mut self) {
+        let end = self
+            .contents
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(self.contents.len(), |end| end + 1);
+        self.contents = &self.contents[end..];
+    }
+    fn skip_section(&mut self) {
+        let end = self
+            .contents
+            .windows(2)
+            .position(|bytes| bytes == b"\n[")
+            .map_or(self.contents.len(), |end| end + 1);
+        self.contents = &self.contents[end..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// Encode a rule line, with the value length prepended.
+    fn rule(prefix: &str, value: &[u8], suffix: &[u8]) -> Vec<u8> {
+        let mut line = prefix.as_bytes().to_vec();
+        line.extend((value.len() as u16).to_be_bytes());
+        line.extend(value);
+        line.extend(suffix);
+        line.push(b'\n');
+        line
+    }
+
+    #[test]
+    fn sniff() {
+        let mut contents = MAGIC_HEADER.to_vec();
+        contents.extend(b"[50:application/zip]\n");
+        contents.extend(rule(">0=", b"PK\x03\x04", b""));
+        contents.extend(b"[80:application/epub+zip]\n");
+        contents.extend(rule(">0=", b"PK\x03\x04", b""));
+        contents.extend(rule("1>30=", b"mimetype", b""));
+        contents.extend(rule("2>38=", b"application/epub+zip", b""));
+        contents.extend(b"[60:text/x-shebang]\n");
+        contents.extend(rule(">0=", b"#!", b"+4"));
+        contents.extend(b"[60:image/x-masked]\n");
+        contents.extend(rule(">0=", b"\xf0\x00", b"&\xf0\x0f"));
+        contents.extend(b"[60:application/x-word]\n");
+        contents.extend(rule(">0=", b"\x12\x34", b"~2"));
+        contents.extend(b"[60:application/x-overflow]\n");
+        contents.extend(rule(&format!(">{}=", usize::MAX), b"X", b""));
+        contents.extend(rule(">0=", b"X", format!("+{}", usize::MAX).as_bytes()));
+        contents.extend(b"[bogus]\n");
+        contents.extend(rule(">0=", b"bogus", b""));
+
+        let (sections, overridden) = parse(&contents).unwrap();
+        assert_eq!(sections.len(), 5);
+        assert!(overridden.is_empty());
+        let mut magic = Magic::new();
+        for section in sections {
+            magic.add(section);
+        }
+        assert_eq!(magic.extent(), 58);
+
+        let sniff = |data: &[u8]| {
+            magic
+                .sniff(data)
+                .map(|section| section.mime_type.to_string())
+        };
+        let mut epub = b"PK\x03\x04".to_vec();
+        epub.resize(30, 0);
+        epub.extend(b"mimetypeapplication/epub+zip");
+        assert_eq!(sniff(&epub).as_deref(), Some("application/epub+zip"));
+        epub[40] = b'X';
+        assert_eq!(sniff(&epub).as_deref(), Some("application/zip"));
+        assert_eq!(sniff(b"   #!/bin/sh").as_deref(), Some("text/x-shebang"));
+        assert_eq!(sniff(b"    #!/bin/sh").as_deref(), None);
+        assert_eq!(sniff(b"\xfa\x30").as_deref(), Some("image/x-masked"));
+        assert_eq!(sniff(b"\xfa\x35").as_deref(), None);
+        let word = if cfg!(target_endian = "little") {
+            b"\x34\x12"
+        } else {
+            b"\x12\x34"
+        };
+        assert_eq!(sniff(word).as_deref(), Some("application/x-word"));
+        assert_eq!(sniff(b"PK").as_deref(), None);
+
+        let far = MagicRule {
+            indent: 0,
+            offset: usize::MAX - 1,
+            value: b"XY".to_vec(),
+            mask: None,
+            word_size: 1,
+            range: 4,
+        };
+        assert!(!far.matches(b"XY"));
+        assert_eq!(far.extent(), usize::MAX);
+    }
+
+    #[test]
+    fn load() {
+        let dir = env::temp_dir().join(format!("fileext-mime-magic-{}", process::id()));
+        let (home, system) = (dir.join("home/mime"), dir.join("system/mime"));
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        let mut contents = MAGIC_HEADER.to_vec();
+        contents.extend(b"[50:text/x-vendor]\n>__NOMAGIC__\n");
+        contents.extend(rule(">0=", b"VENDOR", b""));
+        fs::write(home.join("magic"), contents).unwrap();
+        let mut contents = MAGIC_HEADER.to_vec();
+        contents.extend(b"[90:text/x-vendor]\n");
+        contents.extend(rule(">0=", b"OLD", b""));
+        contents.extend(b"[50:application/pdf]\n");
+        contents.extend(rule(">0=", b"%PDF-", b""));
+        fs::write(system.join("magic"), contents).unwrap();
+
+        let mut magic = Magic::new();
+        magic.load_dir(&home).unwrap();
+        magic.load_dir(&system).unwrap();
+        let sniff = |data: &[u8]| {
+            magic
+                .sniff_reader(data)
+                .unwrap()
+                .map(|section| section.mime_type.to_string())
+        };
+        assert_eq!(sniff(b"VENDOR").as_deref(), Some("text/x-vendor"));
+        assert_eq!(sniff(b"OLD").as_deref(), None);
+        assert_eq!(sniff(b"%PDF-1.7").as_deref(), Some("application/pdf"));
+
+        fs::write(home.join("magic"), b"not magic").unwrap();
+        assert!(Magic::new().load_dir(&home).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
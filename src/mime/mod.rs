@@ -0,0 +1,273 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! MIME types and the [shared-mime-info](https://specifications.freedesktop.org/shared-mime-info-spec/latest/) database describing them.
+use crate::result::{Error, Result};
+use std::fmt::{self, Display, Write};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+mod cache;
+pub mod database;
+pub mod glob;
+pub mod magic;
+pub mod package;
+pub mod update;
+mod xml;
+
+pub use self::database::MimeDatabase;
+pub use self::glob::{Glob, Globs};
+pub use self::magic::{Magic, MagicSection};
+pub use self::package::{MimePackage, MimeTypeDefinition};
+pub use self::update::update_mime_database;
+
+/// Detect the MIME type of a file from its name and contents with the [MIME database](MimeDatabase::load) of the current user.
+///
+/// See [MimeDatabase::detect] for details.
+pub fn detect(path: impl AsRef<std::path::Path>) -> std::io::Result<MimeType> {
+    MimeDatabase::load()?.detect(path)
+}
+
+/// Validated MIME type, such as `text/plain`, `image/svg+xml` or `text/plain; charset=utf-8`.
+///
+/// Media types and subtypes are case-insensitive, and lowercased when parsed so that essences can be compared and
+/// stored as they are. Parameter names are compared case-insensitively, but keep the case they were written in.
+#[derive(Debug, Clone)]
+pub struct MimeType {
+    essence: String,
+    slash: usize,
+    parameters: Vec<(String, String)>,
+}
+impl MimeType {
+    /// Parse a MIME type following the grammar of [RFC 6838](https://www.rfc-editor.org/rfc/rfc6838#section-4.2)
+    /// and [RFC 2045](https://www.rfc-editor.org/rfc/rfc2045#section-5.1) for parameters.
+    ///
+    /// A `*` subtype is accepted as well, since media ranges like `image/*` are commonly used in association lists.
+    ///
+    /// # Errors
+    /// - [Error::InvalidMimeType] if `mime_type` isn't a valid MIME type.
+    pub fn parse(mime_type: &str) -> Result<Self> {
+        let invalid = || Error::InvalidMimeType(mime_type.to_string());
+
+        let (essence, mut rest) = match mime_type.find(';') {
+            Some(at) => (&mime_type[..at], &mime_type[at..]),
+            None => (mime_type, ""),
+        };
+        let essence = essence.trim();
+        let (media_type, subtype) = essence.split_once('/').ok_or_else(invalid)?;
+        if !is_restricted_name(media_type) || !(subtype == "*" || is_restricted_name(subtype)) {
+            return Err(invalid());
+        }
+
+        let mut parameters = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            let Some(parameter) = rest.strip_prefix(';') else {
+                break;
+            };
+            let parameter = parameter.trim_start();
+            if parameter.is_empty() {
+                break;
+            }
+
+            let (name, value) = parameter.split_once('=').ok_or_else(invalid)?;
+            let name = name.trim_end();
+            if name.is_empty() || !name.chars().all(is_token_char) {
+                return Err(invalid());
+            }
+            let (value, remainder) = if let Some(quoted) = value.strip_prefix('"') {
+                let mut unquoted = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next().ok_or_else(invalid)? {
+                        (i, '"') => break i,
+                        (_, '\\') => unquoted.push(chars.next().ok_or_else(invalid)?.1),
+                        (_, c) => unquoted.push(c),
+                    }
+                };
+                (unquoted, &quoted[end + 1..])
+            } else {
+                let end = value.find(';').unwrap_or(value.len());
+                let token = value[..end].trim_end();
+                if token.is_empty() || !token.chars().all(is_token_char) {
+                    return Err(invalid());
+                }
+                (token.to_string(), &value[end..])
+            };
+            parameters.push((name.to_string(), value));
+
+            rest = remainder.trim_start();
+            if !rest.is_empty() && !rest.starts_with(';') {
+                return Err(invalid());
+            }
+        }
+
+        Ok(Self {
+            slash: media_type.len(),
+            essence: essence.to_ascii_lowercase(),
+            parameters,
+        })
+    }
+
+    /// Get the MIME type without its parameters, in lowercase, e.g. `text/plain`.
+    pub fn essence(&self) -> &str {
+        &self.essence
+    }
+    /// Get the media type, e.g. `text` of `text/plain`.
+    pub fn media_type(&self) -> &str {
+        &self.essence[..self.slash]
+    }
+    /// Get the subtype, e.g. `svg+xml` of `image/svg+xml`.
+    pub fn subtype(&self) -> &str {
+        &self.essence[self.slash + 1..]
+    }
+    /// Get the structured syntax suffix of the subtype, e.g. `xml` of `image/svg+xml`.
+    pub fn suffix(&self) -> Option<&str> {
+        self.subtype()
+            .rsplit_once('+')
+            .filter(|(name, suffix)| !name.is_empty() && !suffix.is_empty())
+            .map(|(_, suffix)| suffix)
+    }
+    /// Get all parameters, in the order they were written in.
+    pub fn parameters(&self) -> &[(String, String)] {
+        &self.parameters
+    }
+    /// Get value of a parameter, e.g. `utf-8` for `charset` of `text/plain; charset=utf-8`.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    /// Get the same MIME type without parameters.
+    pub fn without_parameters(&self) -> Self {
+        Self {
+            essence: self.essence.clone(),
+            slash: self.slash,
+            parameters: Vec::new(),
+        }
+    }
+}
+impl PartialEq for MimeType {
+    fn eq(&self, other: &Self) -> bool {
+        self.essence == other.essence
+            && self.parameters.len() == other.parameters.len()
+            && self.parameters.iter().zip(&other.parameters).all(
+                |((lhs_name, lhs), (rhs_name, rhs))| {
+                    lhs_name.eq_ignore_ascii_case(rhs_name) && lhs == rhs
+                },
+            )
+    }
+}
+impl Eq for MimeType {}
+impl Hash for MimeType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.essence.hash(state);
+        for (name, value) in &self.parameters {
+            name.to_ascii_lowercase().hash(state);
+            value.hash(state);
+        }
+    }
+}
+impl Display for MimeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.essence)?;
+        for (name, value) in &self.parameters {
+            write!(f, "; {name}=")?;
+            if !value.is_empty() && value.chars().all(is_token_char) {
+                f.write_str(value)?;
+            } else {
+                f.write_char('"')?;
+                for c in value.chars() {
+                    if matches!(c, '"' | '\\') {
+                        f.write_char('\\')?;
+                    }
+                    f.write_char(c)?;
+                }
+                f.write_char('"')?;
+            }
+        }
+        Ok(())
+    }
+}
+impl FromStr for MimeType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+/// Check whether `name` is a `restricted-name` of RFC 6838.
+fn is_restricted_name(name: &str) -> bool {
+    (1..=127).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+}
+/// Check whether `c` may be a part of a `token` of RFC 2045.
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?=".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mime_type = MimeType::parse("Image/SVG+xml").unwrap();
+        assert_eq!(mime_type.media_type(), "image");
+        assert_eq!(mime_type.subtype(), "svg+xml");
+        assert_eq!(mime_type.suffix(), Some("xml"));
+        assert_eq!(mime_type, MimeType::parse("image/svg+xml").unwrap());
+
+        let mime_type =
+            MimeType::parse("text/plain ; charset=UTF-8; format=\"flowed \\\"x\\\"\"").unwrap();
+        assert_eq!(mime_type.essence(), "text/plain");
+        assert_eq!(mime_type.suffix(), None);
+        assert_eq!(mime_type.parameter("Charset"), Some("UTF-8"));
+        assert_eq!(mime_type.parameter("format"), Some("flowed \"x\""));
+        assert_eq!(
+            mime_type.to_string(),
+            "text/plain; charset=UTF-8; format=\"flowed \\\"x\\\"\""
+        );
+        assert_eq!(MimeType::parse(&mime_type.to_string()).unwrap(), mime_type);
+    }
+
+    #[test]
+    fn invalid() {
+        for mime_type in [
+            "",
+            "text",
+            "text/",
+            "/plain",
+            "text/plain/x",
+            "*/*",
+            "text/pla in",
+            "-text/plain",
+            "text/plain; charset",
+            "text/plain; charset=",
+            "text/plain; charset=\"utf-8",
+            "text/plain; charset=utf 8",
+        ] {
+            assert!(
+                matches!(MimeType::parse(mime_type), Err(Error::InvalidMimeType(_))),
+                "{mime_type:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,1052 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+//! MIME packages: XML files declaring new MIME types to the shared-mime-info database, as described by the
+//! [shared-mime-info specification](https://specifications.freedesktop.org/shared-mime-info-spec/latest/ar01s02.html#id-1.3.4).
+//!
+//! Packages are installed into the `packages` subdirectory of a [mime directory](super::database::mime_dirs), from which
+//! [update_mime_database](super::update::update_mime_database) generates the `globs2`, `magic` and other files
+//! [MimeDatabase](super::MimeDatabase) reads.
+use super::glob::{Glob, GlobKind, DEFAULT_WEIGHT};
+use super::magic::{MagicRule, MagicSection};
+use super::xml::{self, Element};
+use super::MimeType;
+use crate::icon::IconImage;
+use crate::platform::xdg::write_atomically;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Write};
+use std::path::Path;
+use std::{fs, io};
+
+/// XML namespace of MIME packages.
+pub const MIME_INFO_NAMESPACE: &str = "http://www.freedesktop.org/standards/shared-mime-info";
+/// Name of the `mime` subdirectory packages are installed into.
+pub const PACKAGES_DIR: &str = "packages";
+/// Priority of magic and tree magic sections which don't specify one.
+pub const DEFAULT_PRIORITY: u32 = 50;
+
+/// Kind of file a [TreeMagicRule] looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TreeMagicFileType {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A symbolic link.
+    Link,
+}
+impl TreeMagicFileType {
+    /// Get the name of the file type, as used by packages and the `treemagic` file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Directory => "directory",
+            Self::Link => "link",
+        }
+    }
+}
+
+/// Test of a [TreeMagicSection], checking a path relative to the root of a mounted volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeMagicRule {
+    /// Nesting level of the rule. Rules are only tested if their parent, the closest previous rule with a lower level, matches.
+    pub indent: u32,
+    /// Path of the file looked for, e.g. `DCIM`.
+    pub path: String,
+    /// Kind of file the path has to be, or [None] for any kind.
+    pub file_type: Option<TreeMagicFileType>,
+    /// Whether the path is matched case-sensitively.
+    pub match_case: bool,
+    /// Whether the file has to be executable.
+    pub executable: bool,
+    /// Whether the directory has to contain at least one file.
+    pub non_empty: bool,
+    /// MIME type the file has to have.
+    pub mime_type: Option<MimeType>,
+}
+impl TreeMagicRule {
+    /// Create a top-level rule for any kind of file at `path`.
+    pub fn new(path: impl ToString) -> Self {
+        Self {
+            indent: 0,
+            path: path.to_string(),
+            file_type: None,
+            match_case: false,
+            executable: false,
+            non_empty: false,
+            mime_type: None,
+        }
+    }
+}
+
+/// Tree magic of an `x-content/*` MIME type, which identifies the content of a volume, such as the photos of a camera.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeMagicSection {
+    /// Priority of the section, from 0 to 100. Sections with a higher priority are tested first.
+    pub priority: u32,
+    /// Rules of the section, in the order of the file.
+    pub rules: Vec<TreeMagicRule>,
+}
+
+/// Declaration of a MIME type: its description, file name patterns, magic, icons and relations to other MIME types.
+///
+/// ```
+/// use fileext::mime::package::MimeTypeDefinition;
+/// use fileext::mime::MimeType;
+///
+/// let definition = MimeTypeDefinition::new(MimeType::parse("application/x-example").unwrap(), "Example document")
+///     .localized_comment("de", "Beispieldokument")
+///     .glob("*.example")
+///     .sub_class_of(MimeType::parse("application/zip").unwrap())
+///     .generic_icon("x-office-document");
+/// assert_eq!(definition.extensions(), vec![".example"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeTypeDefinition {
+    /// The MIME type being declared.
+    pub mime_type: MimeType,
+    /// Description of the MIME type in English, e.g. `PNG image`.
+    pub comment: String,
+    /// Translated descriptions, by language, e.g. `de`.
+    pub localized_comments: BTreeMap<String, String>,
+    /// Acronym of the MIME type and its expansion, e.g. `PDF` and `Portable Document Format`.
+    pub acronym: Option<(String, String)>,
+    /// Whether globs of the MIME type declared by less important packages and directories are discarded.
+    pub delete_globs: bool,
+    /// Whether magic of the MIME type declared by less important packages and directories is discarded.
+    pub delete_magic: bool,
+    /// File name patterns of the MIME type.
+    pub globs: Vec<Glob>,
+    /// Content sniffing rules of the MIME type.
+    pub magic: Vec<MagicSection>,
+    /// Rules identifying volumes with content of the MIME type.
+    pub tree_magic: Vec<TreeMagicSection>,
+    /// Namespace URIs and local names of the root elements of XML documents of the MIME type.
+    pub root_xml: Vec<(String, String)>,
+    /// MIME types this one is a subclass of, e.g. `application/zip` for an archive-based document format.
+    pub parents: Vec<MimeType>,
+    /// Other names of the MIME type.
+    pub aliases: Vec<MimeType>,
+    /// Name of the icon of the MIME type in the icon theme. Derived from the MIME type, e.g. `application-x-example`, if [None].
+    pub icon: Option<String>,
+    /// Name of the generic icon used if the icon theme has no [icon](MimeTypeDefinition::icon), e.g. `x-office-document`.
+    pub generic_icon: Option<String>,
+    /// Images of the icon of the MIME type at several sizes, which backends with an icon theme install under the
+    /// [icon name](MimeTypeDefinition::icon_name) along with the package. They aren't part of the package document.
+    pub icon_images: Vec<IconImage>,
+}
+impl MimeTypeDefinition {
+    /// Create a declaration of a MIME type with a description, but no globs, magic or relations.
+    pub fn new(mime_type: MimeType, comment: impl ToString) -> Self {
+        Self {
+            mime_type: mime_type.without_parameters(),
+            comment: comment.to_string(),
+            localized_comments: BTreeMap::new(),
+            acronym: None,
+            delete_globs: false,
+            delete_magic: false,
+            globs: Vec::new(),
+            magic: Vec::new(),
+            tree_magic: Vec::new(),
+            root_xml: Vec::new(),
+            parents: Vec::new(),
+            aliases: Vec::new(),
+            icon: None,
+            generic_icon: None,
+            icon_images: Vec::new(),
+        }
+    }
+    /// Add a translated description.
+    pub fn localized_comment(mut self, language: impl ToString, comment: impl ToString) -> Self {
+        self.localized_comments
+            .insert(language.to_string(), comment.to_string());
+        self
+    }
+    /// Set the acronym of the MIME type and its expansion.
+    pub fn acronym(mut self, acronym: impl ToString, expanded: impl ToString) -> Self {
+        self.acronym = Some((acronym.to_string(), expanded.to_string()));
+        self
+    }
+    /// Discard globs of the MIME type declared by less important packages and directories.
+    pub fn delete_globs(mut self) -> Self {
+        self.delete_globs = true;
+        self
+    }
+    /// Discard magic of the MIME type declared by less important packages and directories.
+    pub fn delete_magic(mut self) -> Self {
+        self.delete_magic = true;
+        self
+    }
+    /// Add a case-insensitive file name pattern with the default weight, e.g. `*.example`.
+    pub fn glob(self, pattern: impl ToString) -> Self {
+        self.weighted_glob(pattern, DEFAULT_WEIGHT, false)
+    }
+    /// Add a file name pattern with a weight from 0 to 100, which is matched case-sensitively if `case_sensitive` is set.
+    pub fn weighted_glob(
+        mut self,
+        pattern: impl ToString,
+        weight: u32,
+        case_sensitive: bool,
+    ) -> Self {
+        self.globs.push(Glob {
+            mime_type: self.mime_type.clone(),
+            pattern: pattern.to_string(),
+            weight,
+            case_sensitive,
+        });
+        self
+    }
+    /// Add a magic section with a priority from 0 to 100, matching if any of its top-level rules matches.
+    pub fn magic(mut self, priority: u32, rules: Vec<MagicRule>) -> Self {
+        self.magic.push(MagicSection {
+            priority,
+            mime_type: self.mime_type.clone(),
+            rules,
+        });
+        self
+    }
+    /// Add a tree magic section with a priority from 0 to 100.
+    pub fn tree_magic(mut self, priority: u32, rules: Vec<TreeMagicRule>) -> Self {
+        self.tree_magic.push(TreeMagicSection { priority, rules });
+        self
+    }
+    /// Identify XML documents whose root element has the local name `local_name` in the namespace `namespace_uri`.
+    pub fn root_xml(mut self, namespace_uri: impl ToString, local_name: impl ToString) -> Self {
+        self.root_xml
+            .push((namespace_uri.to_string(), local_name.to_string()));
+        self
+    }
+    /// Declare the MIME type a subclass of `parent`.
+    pub fn sub_class_of(mut self, parent: MimeType) -> Self {
+        self.parents.push(parent.without_parameters());
+        self
+    }
+    /// Add another name of the MIME type.
+    pub fn alias(mut self, alias: MimeType) -> Self {
+        self.aliases.push(alias.without_parameters());
+        self
+    }
+    /// Set the name of the icon of the MIME type.
+    pub fn icon(mut self, name: impl ToString) -> Self {
+        self.icon = Some(name.to_string());
+        self
+    }
+    /// Set the name of the generic icon of the MIME type.
+    pub fn generic_icon(mut self, name: impl ToString) -> Self {
+        self.generic_icon = Some(name.to_string());
+        self
+    }
+    /// Add an image of the icon of the MIME type.
+    pub fn icon_image(mut self, image: IconImage) -> Self {
+        self.icon_images.push(image);
+        self
+    }
+
+    /// Get the file extensions of the `*.<extension>` globs, including the leading dot, e.g. `.tar.gz`.
+    pub fn extensions(&self) -> Vec<String> {
+        self.globs
+            .iter()
+            .filter(|glob| glob.kind() == GlobKind::Suffix)
+            .filter_map(|glob| glob.pattern.strip_prefix('*'))
+            .filter(|suffix| suffix.starts_with('.') && suffix.len() > 1)
+            .map(String::from)
+            .collect()
+    }
+    /// Get the name of the icon of the MIME type: the [icon](MimeTypeDefinition::icon) if it's set,
+    /// or the MIME type with the slash replaced by a dash, e.g. `application-x-example`.
+    pub fn icon_name(&self) -> String {
+        self.icon
+            .clone()
+            .unwrap_or_else(|| self.mime_type.essence().replace('/', "-"))
+    }
+    /// Get the document `update-mime-database` writes to `<media>/<subtype>.xml`, without magic and other rules.
+    pub(super) fn standalone_document(&self) -> String {
+        Standalone(self).to_string()
+    }
+}
+
+/// Set of [MIME type declarations](MimeTypeDefinition), serialized as a `mime-info` XML document by [Display].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MimePackage {
+    /// Declared MIME types.
+    pub mime_types: Vec<MimeTypeDefinition>,
+}
+impl MimePackage {
+    /// Create an empty package.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a MIME type declaration.
+    pub fn mime_type(mut self, definition: MimeTypeDefinition) -> Self {
+        self.mime_types.push(definition);
+        self
+    }
+    /// Parse a package. Unknown elements and attributes are ignored.
+    ///
+    /// # Errors
+    /// - [io::ErrorKind::InvalidData] if the package isn't well-formed XML, isn't a `mime-info` document,
+    ///   or declares an invalid MIME type, glob or magic rule.
+    pub fn parse(document: &str) -> io::Result<Self> {
+        let root = xml::parse(document)?;
+        if root.local_name() != "mime-info" {
+            return Err(invalid(format!(
+                "expected a <mime-info> document, not <{}>",
+                root.name
+            )));
+        }
+        let mime_types = root
+            .children
+            .iter()
+            .filter(|element| element.local_name() == "mime-type")
+            .map(parse_definition)
+            .collect::<io::Result<_>>()?;
+        Ok(Self { mime_types })
+    }
+    /// Read a package file.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+    /// Write the package to a file, replacing it atomically. Missing parent directories are created.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write_atomically(path.as_ref(), self.to_string().as_bytes())
+    }
+}
+impl Display for MimePackage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(f, "<mime-info xmlns=\"{MIME_INFO_NAMESPACE}\">")?;
+        for definition in &self.mime_types {
+            write_definition(f, definition, false)?;
+        }
+        writeln!(f, "</mime-info>")
+    }
+}
+
+/// [MimeTypeDefinition] written as the standalone document of its MIME type.
+struct Standalone<'a>(&'a MimeTypeDefinition);
+impl Display for Standalone<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_definition(f, self.0, true)
+    }
+}
+
+/// Write a `mime-type` element, either nested in a package or as the standalone document of the MIME type which
+/// `update-mime-database` generates, which has no magic and identifies itself as generated.
+fn write_definition(
+    f: &mut fmt::Formatter<'_>,
+    definition: &MimeTypeDefinition,
+    standalone: bool,
+) -> fmt::Result {
+    let pad = if standalone { "  " } else { "    " };
+    if standalone {
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        writeln!(
+            f,
+            "<mime-type xmlns=\"{MIME_INFO_NAMESPACE}\" type=\"{}\">",
+            escape_xml(definition.mime_type.essence())
+        )?;
+        writeln!(
+            f,
+            "{pad}<!--Created automatically by update-mime-database. DO NOT EDIT!-->"
+        )?;
+    } else {
+        writeln!(
+            f,
+            "  <mime-type type=\"{}\">",
+            escape_xml(definition.mime_type.essence())
+        )?;
+    }
+    writeln!(
+        f,
+        "{pad}<comment>{}</comment>",
+        escape_xml(&definition.comment)
+    )?;
+    for (language, comment) in &definition.localized_comments {
+        writeln!(
+            f,
+            "{pad}<comment xml:lang=\"{}\">{}</comment>",
+            escape_xml(language),
+            escape_xml(comment)
+        )?;
+    }
+    if let Some((acronym, expanded)) = &definition.acronym {
+        writeln!(f, "{pad}<acronym>{}</acronym>", escape_xml(acronym))?;
+        writeln!(
+            f,
+            "{pad}<expanded-acronym>{}</expanded-acronym>",
+            escape_xml(expanded)
+        )?;
+    }
+    if let Some(icon) = &definition.icon {
+        writeln!(f, "{pad}<icon name=\"{}\"/>", escape_xml(icon))?;
+    }
+    if let Some(icon) = &definition.generic_icon {
+        writeln!(f, "{pad}<generic-icon name=\"{}\"/>", escape_xml(icon))?;
+    }
+    if definition.delete_globs {
+        writeln!(f, "{pad}<glob-deleteall/>")?;
+    }
+    if definition.delete_magic && !standalone {
+        writeln!(f, "{pad}<magic-deleteall/>")?;
+    }
+    for glob in &definition.globs {
+        write!(f, "{pad}<glob pattern=\"{}\"", escape_xml(&glob.pattern))?;
+        if glob.weight != DEFAULT_WEIGHT {
+            write!(f, " weight=\"{}\"", glob.weight)?;
+        }
+        if glob.case_sensitive {
+            write!(f, " case-sensitive=\"true\"")?;
+        }
+        writeln!(f, "/>")?;
+    }
+    for section in definition.magic.iter().filter(|_| !standalone) {
+        writeln!(f, "{pad}<magic priority=\"{}\">", section.priority)?;
+        write_rules(f, &section.rules)?;
+        writeln!(f, "{pad}</magic>")?;
+    }
+    for section in definition.tree_magic.iter().filter(|_| !standalone) {
+        writeln!(f, "{pad}<treemagic priority=\"{}\">", section.priority)?;
+        write_tree_rules(f, &section.rules)?;
+        writeln!(f, "{pad}</treemagic>")?;
+    }
+    for (namespace_uri, local_name) in definition.root_xml.iter().filter(|_| !standalone) {
+        writeln!(
+            f,
+            "{pad}<root-XML namespaceURI=\"{}\" localName=\"{}\"/>",
+            escape_xml(namespace_uri),
+            escape_xml(local_name)
+        )?;
+    }
+    for parent in &definition.parents {
+        writeln!(
+            f,
+            "{pad}<sub-class-of type=\"{}\"/>",
+            escape_xml(parent.essence())
+        )?;
+    }
+    for alias in &definition.aliases {
+        writeln!(f, "{pad}<alias type=\"{}\"/>", escape_xml(alias.essence()))?;
+    }
+    match standalone {
+        true => writeln!(f, "</mime-type>"),
+        false => writeln!(f, "  </mime-type>"),
+    }
+}
+
+/// Write magic rules as nested `match` elements, following their [indents](MagicRule::indent).
+fn write_rules(f: &mut fmt::Formatter<'_>, rules: &[MagicRule]) -> fmt::Result {
+    let mut open: Vec<u32> = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        while open.last().is_some_and(|&indent| indent >= rule.indent) {
+            open.pop();
+            writeln!(f, "{}</match>", "  ".repeat(open.len() + 3))?;
+        }
+        let indentation = "  ".repeat(open.len() + 3);
+        let (kind, value, mask) = match_attributes(rule);
+        write!(
+            f,
+            "{indentation}<match type=\"{kind}\" value=\"{}\" offset=\"{}",
+            escape_xml(&value),
+            rule.offset
+        )?;
+        if rule.range > 1 {
+            write!(f, ":{}", rule.offset + rule.range - 1)?;
+        }
+        f.write_char('"')?;
+        if let Some(mask) = mask {
+            write!(f, " mask=\"{mask}\"")?;
+        }
+        if rules
+            .get(i + 1)
+            .is_some_and(|next| next.indent > rule.indent)
+        {
+            writeln!(f, ">")?;
+            open.push(rule.indent);
+        } else {
+            writeln!(f, "/>")?;
+        }
+    }
+    while open.pop().is_some() {
+        writeln!(f, "{}</match>", "  ".repeat(open.len() + 3))?;
+    }
+    Ok(())
+}
+
+/// Write tree magic rules as nested `treematch` elements.
+fn write_tree_rules(f: &mut fmt::Formatter<'_>, rules: &[TreeMagicRule]) -> fmt::Result {
+    let mut open: Vec<u32> = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        while open.last().is_some_and(|&indent| indent >= rule.indent) {
+            open.pop();
+            writeln!(f, "{}</treematch>", "  ".repeat(open.len() + 3))?;
+        }
+        write!(
+            f,
+            "{}<treematch path=\"{}\"",
+            "  ".repeat(open.len() + 3),
+            escape_xml(&rule.path)
+        )?;
+        if let Some(file_type) = rule.file_type {
+            write!(f, " type=\"{}\"", file_type.name())?;
+        }
+        for (flag, name) in [
+            (rule.match_case, "match-case"),
+            (rule.executable, "executable"),
+            (rule.non_empty, "non-empty"),
+        ] {
+            if flag {
+                write!(f, " {name}=\"true\"")?;
+            }
+        }
+        if let Some(mime_type) = &rule.mime_type {
+            write!(f, " mimetype=\"{mime_type}\"")?;
+        }
+        if rules
+            .get(i + 1)
+            .is_some_and(|next| next.indent > rule.indent)
+        {
+            writeln!(f, ">")?;
+            open.push(rule.indent);
+        } else {
+            writeln!(f, "/>")?;
+        }
+    }
+    while open.pop().is_some() {
+        writeln!(f, "{}</treematch>", "  ".repeat(open.len() + 3))?;
+    }
+    Ok(())
+}
+
+/// Get the `type`, `value` and `mask` attributes of a `match` element.
+///
+/// Values of a single 16 or 32 bit word swapped to the host byte order become `host16` and `host32` numbers,
+/// everything else is a `string` with escape sequences.
+fn match_attributes(rule: &MagicRule) -> (&'static str, String, Option<String>) {
+    let hex = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .fold(String::from("0x"), |hex, byte| hex + &format!("{byte:02x}"))
+    };
+    let kind = match (rule.word_size, rule.value.len()) {
+        (2, 2) => "host16",
+        (4, 4) => "host32",
+        _ => "string",
+    };
+    let value = if kind == "string" {
+        let mut value = String::new();
+        for &byte in &rule.value {
+            match byte {
+                b'\\' => value.push_str("\\\\"),
+                b' '..=b'~' => value.push(byte as char),
+                _ => value.push_str(&format!("\\x{byte:02x}")),
+            }
+        }
+        value
+    } else {
+        hex(&rule.value)
+    };
+    (kind, value, rule.mask.as_deref().map(hex))
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn parse_mime_type(mime_type: Option<&str>, element: &Element) -> io::Result<MimeType> {
+    let mime_type =
+        mime_type.ok_or_else(|| invalid(format!("<{}> without a type", element.name)))?;
+    MimeType::parse(mime_type).map_err(|why| invalid(why.to_string()))
+}
+
+fn parse_number<T: std::str::FromStr>(
+    value: Option<&str>,
+    element: &Element,
+    name: &str,
+) -> io::Result<Option<T>> {
+    value
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| invalid(format!("invalid {name} \"{value}\" of <{}>", element.name)))
+        })
+        .transpose()
+}
+
+fn parse_flag(element: &Element, name: &str) -> io::Result<bool> {
+    match element.attribute(name) {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(value) => Err(invalid(format!(
+            "invalid {name} \"{value}\" of <{}>",
+            element.name
+        ))),
+    }
+}
+
+/// Parse a `mime-type` element.
+fn parse_definition(element: &Element) -> io::Result<MimeTypeDefinition> {
+    let mime_type = parse_mime_type(element.attribute("type"), element)?;
+    let mut definition = MimeTypeDefinition::new(mime_type.clone(), "");
+    let mut acronym = None;
+    let mut expanded = None;
+    for child in &element.children {
+        match child.local_name() {
+            "comment" => match child.attribute("xml:lang") {
+                Some(language) => {
+                    definition
+                        .localized_comments
+                        .insert(language.to_string(), child.text.clone());
+                }
+                None => definition.comment = child.text.clone(),
+            },
+            "acronym" if child.attribute("xml:lang").is_none() => {
+                acronym = Some(child.text.clone())
+            }
+            "expanded-acronym" if child.attribute("xml:lang").is_none() => {
+                expanded = Some(child.text.clone())
+            }
+            "icon" => definition.icon = child.attribute("name").map(str::to_string),
+            "generic-icon" => definition.generic_icon = child.attribute("name").map(str::to_string),
+            "glob-deleteall" => definition.delete_globs = true,
+            "magic-deleteall" => definition.delete_magic = true,
+            "glob" => {
+                let pattern = child
+                    .attribute("pattern")
+                    .filter(|pattern| !pattern.is_empty())
+                    .ok_or_else(|| invalid("<glob> without a pattern"))?;
+                definition.globs.push(Glob {
+                    mime_type: mime_type.clone(),
+                    pattern: pattern.to_string(),
+                    weight: parse_number(child.attribute("weight"), child, "weight")?
+                        .unwrap_or(DEFAULT_WEIGHT),
+                    case_sensitive: parse_flag(child, "case-sensitive")?,
+                });
+            }
+            "magic" => {
+                let mut rules = Vec::new();
+                parse_matches(child, 0, &mut rules)?;
+                definition.magic.push(MagicSection {
+                    priority: parse_number(child.attribute("priority"), child, "priority")?
+                        .unwrap_or(DEFAULT_PRIORITY),
+                    mime_type: mime_type.clone(),
+                    rules,
+                });
+            }
+            "treemagic" => {
+                let mut rules = Vec::new();
+                parse_tree_matches(child, 0, &mut rules)?;
+                definition.tree_magic.push(TreeMagicSection {
+                    priority: parse_number(child.attribute("priority"), child, "priority")?
+                        .unwrap_or(DEFAULT_PRIORITY),
+                    rules,
+                });
+            }
+            "root-XML" => {
+                let namespace_uri = child.attribute("namespaceURI").unwrap_or_default();
+                let local_name = child
+                    .attribute("localName")
+                    .ok_or_else(|| invalid("<root-XML> without a localName"))?;
+                definition
+                    .root_xml
+                    .push((namespace_uri.to_string(), local_name.to_string()));
+            }
+            "sub-class-of" => definition
+                .parents
+                .push(parse_mime_type(child.attribute("type"), child)?),
+            "alias" => definition
+                .aliases
+                .push(parse_mime_type(child.attribute("type"), child)?),
+            _ => {}
+        }
+    }
+    if let (Some(acronym), Some(expanded)) = (acronym, expanded) {
+        definition.acronym = Some((acronym, expanded));
+    }
+    Ok(definition)
+}
+
+/// Parse the `match` children of an element into rules at the nesting level `indent`, followed by their own children.
+fn parse_matches(element: &Element, indent: u32, rules: &mut Vec<MagicRule>) -> io::Result<()> {
+    for child in element
+        .children
+        .iter()
+        .filter(|child| child.local_name() == "match")
+    {
+        rules.push(parse_match(child, indent)?);
+        parse_matches(child, indent + 1, rules)?;
+    }
+    Ok(())
+}
+
+/// Parse a `match` element, the way `update-mime-database` encodes it in the `magic` file.
+fn parse_match(element: &Element, indent: u32) -> io::Result<MagicRule> {
+    let attribute = |name: &str| {
+        element
+            .attribute(name)
+            .ok_or_else(|| invalid(format!("<match> without a {name}")))
+    };
+    let kind = attribute("type")?;
+    let value = attribute("value")?;
+    let offset = attribute("offset")?;
+
+    let (start, end) = match offset.split_once(':') {
+        Some((start, end)) => (start, Some(end)),
+        None => (offset, None),
+    };
+    let start: usize = parse_number(Some(start), element, "offset")?.unwrap_or_default();
+    let end: usize = parse_number(end, element, "offset")?.unwrap_or(start);
+    if end < start {
+        return Err(invalid(format!("invalid offset \"{offset}\" of <match>")));
+    }
+
+    let (size, word_size, little_endian) = match kind {
+        "string" => (0, 1, false),
+        "byte" => (1, 1, false),
+        "big16" => (2, 1, false),
+        "big32" => (4, 1, false),
+        "little16" => (2, 1, true),
+        "little32" => (4, 1, true),
+        "host16" => (2, 2, false),
+        "host32" => (4, 4, false),
+        _ => return Err(invalid(format!("unknown <match> type \"{kind}\""))),
+    };
+    let number = |text: &str| -> io::Result<Vec<u8>> {
+        let number = parse_integer(text)
+            .filter(|&number| size == 4 || number < 1 << (size * 8))
+            .ok_or_else(|| invalid(format!("invalid {kind} value \"{text}\" of <match>")))?;
+        let bytes = (number as u32).to_be_bytes()[4 - size..].to_vec();
+        Ok(match little_endian {
+            true => bytes.into_iter().rev().collect(),
+            false => bytes,
+        })
+    };
+    let value = match size {
+        0 => unescape_string(value)?,
+        _ => number(value)?,
+    };
+    if value.is_empty() {
+        return Err(invalid("<match> with an empty value"));
+    }
+    let mask = match element.attribute("mask") {
+        None => None,
+        Some(mask) if size > 0 => Some(number(mask)?),
+        Some(mask) => {
+            let digits = mask
+                .strip_prefix("0x")
+                .filter(|digits| digits.len() % 2 == 0)
+                .ok_or_else(|| invalid(format!("invalid string mask \"{mask}\" of <match>")))?;
+            let mut bytes = (0..digits.len())
+                .step_by(2)
+                .map(|at| u8::from_str_radix(&digits[at..at + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| invalid(format!("invalid string mask \"{mask}\" of <match>")))?;
+            if bytes.len() > value.len() {
+                return Err(invalid(format!(
+                    "string mask \"{mask}\" longer than the value"
+                )));
+            }
+            bytes.resize(value.len(), 0);
+            Some(bytes)
+        }
+    };
+    Ok(MagicRule {
+        indent,
+        offset: start,
+        value,
+        mask,
+        word_size,
+        range: end - start + 1,
+    })
+}
+
+/// Parse an integer in the syntax of `strtoul` with base 0: hexadecimal with `0x`, octal with `0`, decimal otherwise.
+fn parse_integer(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if text.len() > 1 && text.starts_with('0') {
+        u64::from_str_radix(&text[1..], 8).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Decode the escape sequences of a `string` value: `\xHH`, octal `\NNN` and the C escapes `\n`, `\t` etc.,
+/// with any other escaped character standing for itself.
+fn unescape_string(value: &str) -> io::Result<Vec<u8>> {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            unescaped.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let escaped = *bytes
+            .get(i + 1)
+            .ok_or_else(|| invalid(format!("incomplete escape sequence in \"{value}\"")))?;
+        i += 2;
+        match escaped {
+            b'x' => {
+                let digits = bytes[i..]
+                    .iter()
+                    .take(2)
+                    .take_while(|byte| byte.is_ascii_hexdigit())
+                    .count();
+                let byte = std::str::from_utf8(&bytes[i..i + digits])
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| invalid(format!("invalid escape sequence in \"{value}\"")))?;
+                unescaped.push(byte);
+                i += digits;
+            }
+            b'0'..=b'7' => {
+                let digits = 1 + bytes[i..]
+                    .iter()
+                    .take(2)
+                    .take_while(|byte| (b'0'..=b'7').contains(byte))
+                    .count();
+                let byte = u32::from_str_radix(
+                    std::str::from_utf8(&bytes[i - 1..i - 1 + digits]).unwrap(),
+                    8,
+                )
+                .unwrap();
+                unescaped.push(
+                    u8::try_from(byte)
+                        .map_err(|_| invalid(format!("invalid escape sequence in \"{value}\"")))?,
+                );
+                i += digits - 1;
+            }
+            b'n' => unescaped.push(b'\n'),
+            b'r' => unescaped.push(b'\r'),
+            b't' => unescaped.push(b'\t'),
+            b'b' => unescaped.push(0x08),
+            b'f' => unescaped.push(0x0c),
+            b'v' => unescaped.push(0x0b),
+            other => unescaped.push(other),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Parse the `treematch` children of an element into rules at the nesting level `indent`, followed by their own children.
+fn parse_tree_matches(
+    element: &Element,
+    indent: u32,
+    rules: &mut Vec<TreeMagicRule>,
+) -> io::Result<()> {
+    for child in element
+        .children
+        .iter()
+        .filter(|child| child.local_name() == "treematch")
+    {
+        let path = child
+            .attribute("path")
+            .ok_or_else(|| invalid("<treematch> without a path"))?;
+        let file_type = match child.attribute("type") {
+            None => None,
+            Some("file") => Some(TreeMagicFileType::File),
+            Some("directory") => Some(TreeMagicFileType::Directory),
+            Some("link") => Some(TreeMagicFileType::Link),
+            Some(other) => return Err(invalid(format!("unknown <treematch> type \"{other}\""))),
+        };
+        rules.push(TreeMagicRule {
+            indent,
+            path: path.to_string(),
+            file_type,
+            match_case: parse_flag(child, "match-case")?,
+            executable: parse_flag(child, "executable")?,
+            non_empty: parse_flag(child, "non-empty")?,
+            mime_type: child
+                .attribute("mimetype")
+                .map(|mime_type| parse_mime_type(Some(mime_type), child))
+                .transpose()?,
+        });
+        parse_tree_matches(child, indent + 1, rules)?;
+    }
+    Ok(())
+}
+
+/// Escape the characters of XML markup in text and attribute values.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mime(mime_type: &str) -> MimeType {
+        MimeType::parse(mime_type).unwrap()
+    }
+    fn rule(indent: u32, offset: usize, value: &[u8], word_size: usize, range: usize) -> MagicRule {
+        MagicRule {
+            indent,
+            offset,
+            value: value.to_vec(),
+            mask: None,
+            word_size,
+            range,
+        }
+    }
+
+    fn example() -> MimePackage {
+        MimePackage::new().mime_type(
+            MimeTypeDefinition::new(mime("application/x-example"), "Example <document>")
+                .localized_comment("de", "Beispieldokument")
+                .glob("*.example")
+                .weighted_glob("Examplefile", 80, true)
+                .magic(
+                    60,
+                    vec![
+                        rule(0, 0, b"EXMP\x00", 1, 1),
+                        rule(1, 8, b"\x01\x02", 2, 4),
+                        rule(2, 16, b"v\\2", 1, 1),
+                        rule(0, 0, b"PK\x03\x04", 4, 1),
+                    ],
+                )
+                .sub_class_of(mime("application/zip"))
+                .alias(mime("application/example"))
+                .generic_icon("x-office-document"),
+        )
+    }
+
+    #[test]
+    fn xml() {
+        assert_eq!(
+            example().to_string(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+  <mime-type type="application/x-example">
+    <comment>Example &lt;document&gt;</comment>
+    <comment xml:lang="de">Beispieldokument</comment>
+    <generic-icon name="x-office-document"/>
+    <glob pattern="*.example"/>
+    <glob pattern="Examplefile" weight="80" case-sensitive="true"/>
+    <magic priority="60">
+      <match type="string" value="EXMP\x00" offset="0">
+        <match type="host16" value="0x0102" offset="8:11">
+          <match type="string" value="v\\2" offset="16"/>
+        </match>
+      </match>
+      <match type="host32" value="0x504b0304" offset="0"/>
+    </magic>
+    <sub-class-of type="application/zip"/>
+    <alias type="application/example"/>
+  </mime-type>
+</mime-info>
+"#
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let package = example().mime_type(
+            MimeTypeDefinition::new(mime("x-content/image-dcf"), "Digital camera")
+                .acronym("DCF", "Design rule for Camera File system")
+                .delete_globs()
+                .delete_magic()
+                .tree_magic(
+                    70,
+                    vec![
+                        TreeMagicRule {
+                            file_type: Some(TreeMagicFileType::Directory),
+                            non_empty: true,
+                            ..TreeMagicRule::new("dcim")
+                        },
+                        TreeMagicRule {
+                            indent: 1,
+                            match_case: true,
+                            mime_type: Some(mime("image/jpeg")),
+                            ..TreeMagicRule::new("dcim/100CANON")
+                        },
+                    ],
+                )
+                .root_xml("http://example.org/dcf", "dcf"),
+        );
+        assert_eq!(MimePackage::parse(&package.to_string()).unwrap(), package);
+
+        let package = MimePackage::parse(
+            r#"<?xml version="1.0"?>
+<!DOCTYPE mime-info [ <!ELEMENT mime-info (mime-type)+> ]>
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+  <mime-type type="application/x-test">
+    <comment>Test</comment>
+    <unknown-element/>
+    <magic>
+      <match type="little16" value="0x0102" offset="4:7" mask="0xff00"/>
+      <match type="big32" value="258" offset="0"/>
+      <match type="byte" value="010" offset="1"/>
+      <match type="string" value="a\t\101\x42\q" offset="2" mask="0xff00"/>
+    </magic>
+  </mime-type>
+</mime-info>"#,
+        )
+        .unwrap();
+        let definition = &package.mime_types[0];
+        assert_eq!(definition.comment, "Test");
+        let section = &definition.magic[0];
+        assert_eq!(section.priority, DEFAULT_PRIORITY);
+        let values: Vec<_> = section
+            .rules
+            .iter()
+            .map(|rule| (rule.value.as_slice(), rule.mask.as_deref(), rule.range))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (&b"\x02\x01"[..], Some(&b"\x00\xff"[..]), 4),
+                (b"\x00\x00\x01\x02", None, 1),
+                (b"\x08", None, 1),
+                (b"a\tABq", Some(b"\xff\x00\x00\x00\x00"), 1),
+            ]
+        );
+
+        for malformed in [
+            "<mime-type type=\"text/plain\"/>",
+            "<mime-info><mime-type/></mime-info>",
+            "<mime-info><mime-type type=\"text\"/></mime-info>",
+            "<mime-info><mime-type type=\"a/b\"><glob/></mime-type></mime-info>",
+            "<mime-info><mime-type type=\"a/b\"><magic><match type=\"byte\" value=\"256\" offset=\"0\"/></magic></mime-type></mime-info>",
+            "<mime-info><mime-type type=\"a/b\"><magic><match type=\"big64\" value=\"0\" offset=\"0\"/></magic></mime-type></mime-info>",
+        ] {
+            let error = MimePackage::parse(malformed).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{malformed}");
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        let definition = MimeTypeDefinition::new(mime("application/x-example"), "Example")
+            .glob("*.example")
+            .glob("*.ex.gz")
+            .glob("*.[ch]")
+            .glob("Makefile")
+            .glob("*~");
+        assert_eq!(definition.extensions(), vec![".example", ".ex.gz"]);
+    }
+}
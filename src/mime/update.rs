@@ -0,0 +1,560 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Pure-Rust implementation of `update-mime-database`, which compiles the [packages](super::MimePackage) of a `mime`
+//! directory into the files read by applications: `globs`, `globs2`, `magic`, `aliases`, `subclasses`, `types`, `icons`,
+//! `generic-icons`, `XMLnamespaces`, `treemagic`, the standalone documents of the MIME types and the binary `mime.cache`.
+use super::cache;
+use super::glob::{Glob, NOGLOBS};
+use super::magic::{MagicRule, MagicSection, MAGIC_HEADER, NOMAGIC};
+use super::package::{MimePackage, MimeTypeDefinition, TreeMagicSection, PACKAGES_DIR};
+use super::MimeType;
+use crate::platform::xdg::write_atomically;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Name of the package applied after all others, which lets administrators override any declaration.
+pub const OVERRIDE_PACKAGE: &str = "Override.xml";
+/// Header of the `treemagic` file.
+pub const TREE_MAGIC_HEADER: &[u8] = b"MIME-TreeMagic\0\n";
+/// Comment heading the `globs` and `globs2` files.
+const GENERATED: &str = "# This file was automatically generated by the\n# update-mime-database command. DO NOT EDIT!\n";
+/// Marker identifying the standalone documents of MIME types written by `update-mime-database`.
+const GENERATED_MARKER: &str = "Created automatically by update-mime-database";
+
+/// Regenerate the database of a `mime` directory from the packages in its `packages` subdirectory.
+///
+/// Standalone documents of MIME types no package declares anymore are removed.
+///
+/// # Errors
+/// - [io::ErrorKind::InvalidData] if a package is malformed. Nothing is written in that case.
+pub fn update_mime_database(mime_dir: impl AsRef<Path>) -> io::Result<()> {
+    let mime_dir = mime_dir.as_ref();
+    MimeInfo::load(&mime_dir.join(PACKAGES_DIR))?.write(mime_dir)
+}
+
+/// Declarations of all MIME types of a `mime` directory, merged from its packages.
+#[derive(Debug, Clone, Default)]
+pub struct MimeInfo {
+    mime_types: BTreeMap<String, MimeTypeDefinition>,
+}
+impl MimeInfo {
+    /// Create an empty set of declarations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Read and merge the `*.xml` packages of a directory in the order of their names,
+    /// with the [override package](OVERRIDE_PACKAGE) last. A missing directory has no packages.
+    ///
+    /// # Errors
+    /// - [io::ErrorKind::InvalidData] if a package is malformed, with the path of the package in the message.
+    pub fn load(packages_dir: &Path) -> io::Result<Self> {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(packages_dir) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<_>>()?,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(why) => return Err(why),
+        };
+        paths.retain(|path| path.extension().is_some_and(|extension| extension == "xml"));
+        paths.sort_by_key(|path| {
+            let name = path.file_name().unwrap_or_default().to_owned();
+            (name == OVERRIDE_PACKAGE, name)
+        });
+
+        let mut info = Self::new();
+        for path in paths {
+            let package = MimePackage::read(&path)
+                .map_err(|why| io::Error::new(why.kind(), format!("{}: {why}", path.display())))?;
+            info.add(&package);
+        }
+        Ok(info)
+    }
+    /// Merge a package more important than the ones added before.
+    ///
+    /// Descriptions and icons replace the previous ones, while globs, magic and relations are added to them,
+    /// unless the package [deletes](MimeTypeDefinition::delete_globs) the previous globs or magic.
+    pub fn add(&mut self, package: &MimePackage) {
+        for definition in &package.mime_types {
+            let merged = self
+                .mime_types
+                .entry(definition.mime_type.essence().to_string())
+                .or_insert_with(|| MimeTypeDefinition::new(definition.mime_type.clone(), ""));
+            merge(merged, definition);
+        }
+    }
+    /// Get the declarations of all MIME types, ordered by MIME type.
+    pub fn mime_types(&self) -> impl Iterator<Item = &MimeTypeDefinition> {
+        self.mime_types.values()
+    }
+
+    /// Write the database to a `mime` directory, replacing each file atomically.
+    pub fn write(&self, mime_dir: &Path) -> io::Result<()> {
+        let files: [(&str, Vec<u8>); 11] = [
+            ("globs", self.globs().into_bytes()),
+            ("globs2", self.globs2().into_bytes()),
+            ("magic", self.magic()),
+            ("aliases", self.aliases().into_bytes()),
+            ("subclasses", self.subclasses().into_bytes()),
+            ("types", self.types().into_bytes()),
+            (
+                "icons",
+                self.icons(|definition| &definition.icon).into_bytes(),
+            ),
+            (
+                "generic-icons",
+                self.icons(|definition| &definition.generic_icon)
+                    .into_bytes(),
+            ),
+            ("XMLnamespaces", self.namespaces().into_bytes()),
+            ("treemagic", self.tree_magic()),
+            ("mime.cache", cache::build(self)),
+        ];
+        for definition in self.mime_types() {
+            write_atomically(
+                &standalone_path(mime_dir, &definition.mime_type),
+                definition.standalone_document().as_bytes(),
+            )?;
+        }
+        self.remove_stale_documents(mime_dir)?;
+        // mime.cache comes last, since readers use its modification time to notice changes.
+        for (name, contents) in files {
+            write_atomically(&mime_dir.join(name), &contents)?;
+        }
+        Ok(())
+    }
+
+    /// Get all globs, from the highest weight to the lowest one. Case-insensitive patterns are lowercased.
+    pub(super) fn sorted_globs(&self) -> Vec<Glob> {
+        let mut globs: Vec<Glob> = self
+            .mime_types()
+            .flat_map(|definition| &definition.globs)
+            .map(|glob| Glob {
+                pattern: match glob.case_sensitive {
+                    true => glob.pattern.clone(),
+                    false => glob.pattern.to_lowercase(),
+                },
+                ..glob.clone()
+            })
+            .collect();
+        globs.sort_by_key(|glob| Reverse(glob.weight));
+        globs
+    }
+    /// Get all magic sections, from the highest priority to the lowest one.
+    pub(super) fn sorted_magic(&self) -> Vec<&MagicSection> {
+        let mut sections: Vec<_> = self
+            .mime_types()
+            .flat_map(|definition| &definition.magic)
+            .collect();
+        sections.sort_by_key(|section| (Reverse(section.priority), section.mime_type.essence()));
+        sections
+    }
+    /// Get all `(alias, canonical MIME type)` pairs, ordered by alias.
+    pub(super) fn sorted_aliases(&self) -> BTreeSet<(&str, &str)> {
+        self.mime_types()
+            .flat_map(|definition| {
+                definition
+                    .aliases
+                    .iter()
+                    .map(|alias| (alias.essence(), definition.mime_type.essence()))
+            })
+            .collect()
+    }
+    /// Get the parents of all MIME types with any, ordered by MIME type.
+    pub(super) fn sorted_parents(&self) -> BTreeMap<&str, BTreeSet<&str>> {
+        self.mime_types()
+            .filter(|definition| !definition.parents.is_empty())
+            .map(|definition| {
+                let parents = definition.parents.iter().map(MimeType::essence).collect();
+                (definition.mime_type.essence(), parents)
+            })
+            .collect()
+    }
+    /// Get all `(namespace URI, local name, MIME type)` triples of root XML elements, ordered by namespace URI.
+    pub(super) fn sorted_namespaces(&self) -> BTreeSet<(&str, &str, &str)> {
+        self.mime_types()
+            .flat_map(|definition| {
+                definition
+                    .root_xml
+                    .iter()
+                    .map(|(namespace_uri, local_name)| {
+                        (
+                            namespace_uri.as_str(),
+                            local_name.as_str(),
+                            definition.mime_type.essence(),
+                        )
+                    })
+            })
+            .collect()
+    }
+    /// Get the MIME types and their icon, as selected by `icon`, ordered by MIME type.
+    pub(super) fn sorted_icons(
+        &self,
+        icon: impl Fn(&MimeTypeDefinition) -> &Option<String>,
+    ) -> Vec<(&str, &str)> {
+        self.mime_types()
+            .filter_map(|definition| {
+                icon(definition)
+                    .as_deref()
+                    .map(|icon| (definition.mime_type.essence(), icon))
+            })
+            .collect()
+    }
+
+    /// Generate the `globs` file, the predecessor of `globs2` without weights and flags.
+    fn globs(&self) -> String {
+        let mut globs = String::from(GENERATED);
+        for definition in self
+            .mime_types()
+            .filter(|definition| definition.delete_globs)
+        {
+            let _ = writeln!(globs, "{}:{NOGLOBS}", definition.mime_type.essence());
+        }
+        for glob in self.sorted_globs() {
+            let _ = writeln!(globs, "{}:{}", glob.mime_type.essence(), glob.pattern);
+        }
+        globs
+    }
+    /// Generate the `globs2` file.
+    fn globs2(&self) -> String {
+        let mut globs = String::from(GENERATED);
+        for definition in self
+            .mime_types()
+            .filter(|definition| definition.delete_globs)
+        {
+            let _ = writeln!(globs, "0:{}:{NOGLOBS}", definition.mime_type.essence());
+        }
+        for glob in self.sorted_globs() {
+            let (weight, mime_type) = (glob.weight, glob.mime_type.essence());
+            let _ = write!(globs, "{weight}:{mime_type}:{}", glob.pattern);
+            globs.push_str(if glob.case_sensitive { ":cs\n" } else { "\n" });
+        }
+        globs
+    }
+    /// Generate the `magic` file.
+    fn magic(&self) -> Vec<u8> {
+        let mut magic = MAGIC_HEADER.to_vec();
+        for definition in self
+            .mime_types()
+            .filter(|definition| definition.delete_magic)
+        {
+            magic.extend(format!("[0:{}]\n>0=", definition.mime_type.essence()).bytes());
+            magic.extend((NOMAGIC.len() as u16).to_be_bytes());
+            magic.extend(NOMAGIC);
+            magic.push(b'\n');
+        }
+        for section in self.sorted_magic() {
+            magic.extend(
+                format!("[{}:{}]\n", section.priority, section.mime_type.essence()).bytes(),
+            );
+            for rule in &section.rules {
+                write_rule(&mut magic, rule);
+            }
+        }
+        magic
+    }
+    /// Generate the `aliases` file.
+    fn aliases(&self) -> String {
+        self.sorted_aliases()
+            .into_iter()
+            .map(|(alias, canonical)| format!("{alias} {canonical}\n"))
+            .collect()
+    }
+    /// Generate the `subclasses` file.
+    fn subclasses(&self) -> String {
+        self.sorted_parents()
+            .into_iter()
+            .flat_map(|(child, parents)| {
+                parents
+                    .into_iter()
+                    .map(move |parent| format!("{child} {parent}\n"))
+            })
+            .collect()
+    }
+    /// Generate the `types` file.
+    fn types(&self) -> String {
+        self.mime_types()
+            .map(|definition| format!("{}\n", definition.mime_type.essence()))
+            .collect()
+    }
+    /// Generate the `icons` or `generic-icons` file.
+    fn icons(&self, icon: impl Fn(&MimeTypeDefinition) -> &Option<String>) -> String {
+        self.sorted_icons(icon)
+            .into_iter()
+            .map(|(mime_type, icon)| format!("{mime_type}:{icon}\n"))
+            .collect()
+    }
+    /// Generate the `XMLnamespaces` file.
+    fn namespaces(&self) -> String {
+        self.sorted_namespaces()
+            .into_iter()
+            .map(|(namespace_uri, local_name, mime_type)| {
+                format!("{namespace_uri} {local_name} {mime_type}\n")
+            })
+            .collect()
+    }
+    /// Generate the `treemagic` file.
+    fn tree_magic(&self) -> Vec<u8> {
+        let mut sections: Vec<(&MimeType, &TreeMagicSection)> = self
+            .mime_types()
+            .flat_map(|definition| {
+                definition
+                    .tree_magic
+                    .iter()
+                    .map(|section| (&definition.mime_type, section))
+            })
+            .collect();
+        sections
+            .sort_by_key(|(mime_type, section)| (Reverse(section.priority), mime_type.essence()));
+
+        let mut tree_magic = String::new();
+        for (mime_type, section) in sections {
+            let _ = writeln!(tree_magic, "[{}:{}]", section.priority, mime_type.essence());
+            for rule in &section.rules {
+                if rule.indent > 0 {
+                    let _ = write!(tree_magic, "{}", rule.indent);
+                }
+                let file_type = rule.file_type.map_or("any", |file_type| file_type.name());
+                let _ = write!(tree_magic, ">\"{}\"={file_type}", rule.path);
+                for (flag, name) in [
+                    (rule.match_case, "match-case"),
+                    (rule.executable, "executable"),
+                    (rule.non_empty, "non-empty"),
+                ] {
+                    if flag {
+                        let _ = write!(tree_magic, ",{name}");
+                    }
+                }
+                if let Some(mime_type) = &rule.mime_type {
+                    let _ = write!(tree_magic, ",{}", mime_type.essence());
+                }
+                tree_magic.push('\n');
+            }
+        }
+        let mut contents = TREE_MAGIC_HEADER.to_vec();
+        contents.extend(tree_magic.into_bytes());
+        contents
+    }
+
+    /// Remove the standalone documents generated for MIME types which aren't declared anymore,
+    /// and the media type directories left empty.
+    fn remove_stale_documents(&self, mime_dir: &Path) -> io::Result<()> {
+        let media_dirs = match fs::read_dir(mime_dir) {
+            Ok(entries) => entries,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(why) => return Err(why),
+        };
+        for media_dir in media_dirs {
+            let media_dir = media_dir?;
+            if !media_dir.file_type()?.is_dir() || media_dir.file_name() == PACKAGES_DIR {
+                continue;
+            }
+            for document in fs::read_dir(media_dir.path())? {
+                let path = document?.path();
+                let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let Some(subtype) = name.strip_suffix(".xml") else {
+                    continue;
+                };
+                let key = format!("{}/{subtype}", media_dir.file_name().to_string_lossy());
+                let is_generated = fs::read_to_string(&path)
+                    .is_ok_and(|document| document.contains(GENERATED_MARKER));
+                if !self.mime_types.contains_key(&key) && is_generated {
+                    fs::remove_file(&path)?;
+                }
+            }
+            // Fails if anything is left in the directory, which is fine.
+            let _ = fs::remove_dir(media_dir.path());
+        }
+        Ok(())
+    }
+}
+
+/// Merge the declaration of a MIME type from a more important package into the previous ones.
+fn merge(merged: &mut MimeTypeDefinition, definition: &MimeTypeDefinition) {
+    if !definition.comment.is_empty() {
+        merged.comment = definition.comment.clone();
+    }
+    merged.localized_comments.extend(
+        definition
+            .localized_comments
+            .iter()
+            .map(|(language, comment)| (language.clone(), comment.clone())),
+    );
+    if definition.acronym.is_some() {
+        merged.acronym = definition.acronym.clone();
+    }
+    if definition.icon.is_some() {
+        merged.icon = definition.icon.clone();
+    }
+    if definition.generic_icon.is_some() {
+        merged.generic_icon = definition.generic_icon.clone();
+    }
+    if definition.delete_globs {
+        merged.delete_globs = true;
+        merged.globs.clear();
+    }
+    if definition.delete_magic {
+        merged.delete_magic = true;
+        merged.magic.clear();
+    }
+    merged.globs.extend(definition.globs.iter().cloned());
+    merged.magic.extend(definition.magic.iter().cloned());
+    merged
+        .tree_magic
+        .extend(definition.tree_magic.iter().cloned());
+    for root_xml in &definition.root_xml {
+        if !merged.root_xml.contains(root_xml) {
+            merged.root_xml.push(root_xml.clone());
+        }
+    }
+    for parent in &definition.parents {
+        if !merged.parents.contains(parent) {
+            merged.parents.push(parent.clone());
+        }
+    }
+    for alias in &definition.aliases {
+        if !merged.aliases.contains(alias) {
+            merged.aliases.push(alias.clone());
+        }
+    }
+}
+
+/// Write a rule as a `[indent]>start-offset=value[&mask][~word-size][+range-length]` line of the `magic` file.
+fn write_rule(magic: &mut Vec<u8>, rule: &MagicRule) {
+    if rule.indent > 0 {
+        magic.extend(rule.indent.to_string().bytes());
+    }
+    magic.extend(format!(">{}=", rule.offset).bytes());
+    magic.extend((rule.value.len() as u16).to_be_bytes());
+    magic.extend(&rule.value);
+    if let Some(mask) = &rule.mask {
+        magic.push(b'&');
+        magic.extend(mask);
+    }
+    if rule.word_size > 1 {
+        magic.extend(format!("~{}", rule.word_size).bytes());
+    }
+    if rule.range > 1 {
+        magic.extend(format!("+{}", rule.range).bytes());
+    }
+    magic.push(b'\n');
+}
+
+/// Get the path of the standalone document of a MIME type, e.g. `text/plain.xml`.
+fn standalone_path(mime_dir: &Path, mime_type: &MimeType) -> PathBuf {
+    mime_dir.join(format!("{}.xml", mime_type.essence()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mime::MimeDatabase;
+    use std::{env, process};
+
+    fn mime(essence: &str) -> MimeType {
+        MimeType::parse(essence).unwrap()
+    }
+
+    #[test]
+    fn update() {
+        let dir = env::temp_dir().join(format!("fileext-mime-update-{}", process::id()));
+        let packages = dir.join(PACKAGES_DIR);
+        MimePackage::new()
+            .mime_type(
+                MimeTypeDefinition::new(mime("application/x-foo"), "Foo")
+                    .glob("*.foo")
+                    .weighted_glob("*.FOO", 60, true)
+                    .glob("Foofile")
+                    .magic(
+                        60,
+                        vec![MagicRule {
+                            indent: 0,
+                            offset: 0,
+                            value: b"FOO".to_vec(),
+                            mask: None,
+                            word_size: 1,
+                            range: 4,
+                        }],
+                    )
+                    .sub_class_of(mime("text/plain"))
+                    .alias(mime("application/foo"))
+                    .icon("foo"),
+            )
+            .mime_type(MimeTypeDefinition::new(mime("text/x-bar"), "Bar").glob("*.bar"))
+            .write(packages.join("foo.xml"))
+            .unwrap();
+        MimePackage::new()
+            .mime_type(
+                MimeTypeDefinition::new(mime("text/x-bar"), "")
+                    .delete_globs()
+                    .glob("*.baz"),
+            )
+            .write(packages.join(OVERRIDE_PACKAGE))
+            .unwrap();
+        update_mime_database(&dir).unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(
+            read("globs2"),
+            format!(
+                "{GENERATED}0:text/x-bar:__NOGLOBS__\n\
+                 60:application/x-foo:*.FOO:cs\n\
+                 50:application/x-foo:*.foo\n\
+                 50:application/x-foo:foofile\n\
+                 50:text/x-bar:*.baz\n"
+            )
+        );
+        assert_eq!(read("aliases"), "application/foo application/x-foo\n");
+        assert_eq!(read("subclasses"), "application/x-foo text/plain\n");
+        assert_eq!(read("types"), "application/x-foo\ntext/x-bar\n");
+        assert_eq!(read("icons"), "application/x-foo:foo\n");
+        assert_eq!(
+            fs::read(dir.join("magic")).unwrap(),
+            b"MIME-Magic\0\n[60:application/x-foo]\n>0=\0\x03FOO+4\n"
+        );
+        assert!(read("text/x-bar.xml").contains("<comment>Bar</comment>"));
+
+        let database = MimeDatabase::load_dirs([&dir]).unwrap();
+        assert_eq!(
+            database.canonical(&mime("application/foo")),
+            mime("application/x-foo")
+        );
+        assert_eq!(
+            database.detect_data(Some(Path::new("x.baz")), b""),
+            mime("text/x-bar")
+        );
+        assert_eq!(
+            database.detect_data(None, b"  FOO"),
+            mime("application/x-foo")
+        );
+
+        fs::remove_file(packages.join("foo.xml")).unwrap();
+        update_mime_database(&dir).unwrap();
+        assert_eq!(read("types"), "text/x-bar\n");
+        assert!(!dir.join("application").exists());
+        assert!(dir.join("text/x-bar.xml").exists());
+
+        fs::write(packages.join("broken.xml"), "<mime-info>").unwrap();
+        let error = update_mime_database(&dir).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("broken.xml"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
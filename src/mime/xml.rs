@@ -0,0 +1,295 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+//! Minimal XML reader for [MIME packages](super::package): elements, attributes and text,
+//! with comments, processing instructions and the document type declaration skipped.
+use std::io;
+
+/// Element of an XML document. Text is the concatenated character data directly inside the element.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct Element {
+    pub(super) name: String,
+    pub(super) attributes: Vec<(String, String)>,
+    pub(super) children: Vec<Element>,
+    pub(super) text: String,
+}
+impl Element {
+    /// Get the name of the element without its namespace prefix.
+    pub(super) fn local_name(&self) -> &str {
+        self.name
+            .split_once(':')
+            .map_or(self.name.as_str(), |(_, name)| name)
+    }
+    /// Get the value of an attribute, by its qualified name, e.g. `xml:lang`.
+    pub(super) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse an XML document, returning its root element.
+///
+/// # Errors
+/// - [io::ErrorKind::InvalidData] if the document isn't well-formed.
+pub(super) fn parse(document: &str) -> io::Result<Element> {
+    let mut reader = Reader {
+        document,
+        position: 0,
+    };
+    reader.misc(true)?;
+    if !reader.rest().starts_with('<') {
+        return Err(reader.error("expected the root element"));
+    }
+    let root = reader.element()?;
+    reader.misc(false)?;
+    if !reader.rest().is_empty() {
+        return Err(reader.error("content after the root element"));
+    }
+    Ok(root)
+}
+
+struct Reader<'a> {
+    document: &'a str,
+    position: usize,
+}
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.document[self.position..]
+    }
+    fn error(&self, message: &str) -> io::Error {
+        let line = self.document[..self.position].matches('\n').count() + 1;
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("malformed XML on line {line}: {message}"),
+        )
+    }
+    fn eat(&mut self, prefix: &str) -> bool {
+        if self.rest().starts_with(prefix) {
+            self.position += prefix.len();
+            true
+        } else {
+            false
+        }
+    }
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+    /// Skip everything up to and including `end`.
+    fn skip_past(&mut self, end: &str) -> io::Result<()> {
+        match self.rest().find(end) {
+            Some(at) => {
+                self.position += at + end.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("missing {end:?}"))),
+        }
+    }
+
+    /// Skip whitespace, comments, processing instructions and, if `prolog` is set, the document type declaration.
+    fn misc(&mut self, prolog: bool) -> io::Result<()> {
+        loop {
+            self.skip_whitespace();
+            if self.eat("<?") {
+                self.skip_past("?>")?;
+            } else if self.eat("<!--") {
+                self.skip_past("-->")?;
+            } else if prolog && self.eat("<!DOCTYPE") {
+                self.doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+    /// Skip a document type declaration, including its internal subset.
+    fn doctype(&mut self) -> io::Result<()> {
+        let mut in_subset = false;
+        while let Some(c) = self.rest().chars().next() {
+            match c {
+                '"' | '\'' => {
+                    self.position += 1;
+                    self.skip_past(&c.to_string())?;
+                    continue;
+                }
+                '<' if self.rest().starts_with("<!--") => {
+                    self.position += 4;
+                    self.skip_past("-->")?;
+                    continue;
+                }
+                '[' => in_subset = true,
+                ']' => in_subset = false,
+                '>' if !in_subset => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+            self.position += c.len_utf8();
+        }
+        Err(self.error("unterminated document type declaration"))
+    }
+
+    fn name(&mut self) -> io::Result<String> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '=' | '<'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.position += end;
+        Ok(rest[..end].to_string())
+    }
+    fn element(&mut self) -> io::Result<Element> {
+        self.eat("<");
+        let mut element = Element {
+            name: self.name()?,
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace();
+            if self.eat("/>") {
+                return Ok(element);
+            }
+            if self.eat(">") {
+                break;
+            }
+            let name = self.name()?;
+            self.skip_whitespace();
+            if !self.eat("=") {
+                return Err(self.error("expected \"=\" after an attribute name"));
+            }
+            self.skip_whitespace();
+            let value = self.attribute_value()?;
+            element.attributes.push((name, value));
+        }
+
+        loop {
+            let rest = self.rest();
+            let text_end = rest.find('<').unwrap_or(rest.len());
+            element.text.push_str(&self.decode(&rest[..text_end])?);
+            self.position += text_end;
+
+            if self.eat("</") {
+                let name = self.name()?;
+                if name != element.name {
+                    return Err(self.error(&format!("expected </{}>", element.name)));
+                }
+                self.skip_whitespace();
+                if !self.eat(">") {
+                    return Err(self.error("expected \">\""));
+                }
+                return Ok(element);
+            } else if self.eat("<!--") {
+                self.skip_past("-->")?;
+            } else if self.eat("<![CDATA[") {
+                let rest = self.rest();
+                let end = rest
+                    .find("]]>")
+                    .ok_or_else(|| self.error("unterminated CDATA section"))?;
+                element.text.push_str(&rest[..end]);
+                self.position += end + 3;
+            } else if self.eat("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with('<') {
+                let child = self.element()?;
+                element.children.push(child);
+            } else {
+                return Err(self.error(&format!("unterminated element <{}>", element.name)));
+            }
+        }
+    }
+    fn attribute_value(&mut self) -> io::Result<String> {
+        let quote = match self.rest().chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error("expected a quoted attribute value")),
+        };
+        self.position += 1;
+        let rest = self.rest();
+        let end = rest
+            .find(quote)
+            .ok_or_else(|| self.error("unterminated attribute value"))?;
+        let raw = rest[..end].replace(['\t', '\n', '\r'], " ");
+        let value = self.decode(&raw)?;
+        self.position += end + 1;
+        Ok(value)
+    }
+    /// Replace entity and character references.
+    fn decode(&self, raw: &str) -> io::Result<String> {
+        let mut decoded = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(at) = rest.find('&') {
+            decoded.push_str(&rest[..at]);
+            rest = &rest[at + 1..];
+            let end = rest
+                .find(';')
+                .ok_or_else(|| self.error("unterminated reference"))?;
+            let reference = &rest[..end];
+            let c = match reference {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => reference
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| reference.strip_prefix('#').map(str::parse))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error(&format!("unknown reference &{reference};")))?,
+            };
+            decoded.push(c);
+            rest = &rest[end + 1..];
+        }
+        decoded.push_str(rest);
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document() {
+        let root = parse(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE a [\n<!ELEMENT a (b)*>\n<!-- ]> -->\n<!ATTLIST a x CDATA \"]>\">\n]>\n<a xmlns='urn:x'>\n  <!-- comment -->\n  <x:b c=\"1 &amp;&#x20;2\"\n   d='&lt;&#62;'>text &quot;<![CDATA[<raw>]]></x:b>\n  <e/>\n</a>\n",
+        )
+        .unwrap();
+        assert_eq!(root.name, "a");
+        assert_eq!(root.attribute("xmlns"), Some("urn:x"));
+        assert_eq!(root.children.len(), 2);
+        let b = &root.children[0];
+        assert_eq!((b.name.as_str(), b.local_name()), ("x:b", "b"));
+        assert_eq!(b.attribute("c"), Some("1 & 2"));
+        assert_eq!(b.attribute("d"), Some("<>"));
+        assert_eq!(b.text, "text \"<raw>");
+        assert_eq!(root.children[1].name, "e");
+
+        for malformed in [
+            "",
+            "<a>",
+            "<a></b>",
+            "<a b=c/>",
+            "<a>&unknown;</a>",
+            "<a/><b/>",
+        ] {
+            let error = parse(malformed).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{malformed:?}");
+        }
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Change plans recorded by [dry runs](crate::app::OpenOptions::dry_run), listing the files or registry keys and values
+//! an operation would create, modify or remove, with their values before and after.
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Something a [Change] is made to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Target {
+    /// File, whose value is its contents.
+    File(PathBuf),
+    /// Registry key, e.g. `HKEY_CURRENT_USER\Software\Classes\.txt`, whose value is always [Value::Empty].
+    /// Removing a key removes its subkeys and values as well.
+    RegistryKey(String),
+    /// Value of a registry key.
+    RegistryValue {
+        /// Full path of the key.
+        key: String,
+        /// Name of the value, empty for the default value of the key.
+        name: String,
+    },
+    /// Entry of a file in the key file format, like `mimeapps.list`, whose value is the value of the entry as written.
+    KeyFileEntry {
+        /// Path of the file.
+        path: PathBuf,
+        /// Name of the group the entry is in.
+        group: String,
+        /// Key of the entry.
+        key: String,
+    },
+}
+impl Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::File(path) => write!(f, "{}", path.display()),
+            Target::RegistryKey(key) => f.write_str(key),
+            Target::RegistryValue { key, name } if name.is_empty() => write!(f, "{key}\\@"),
+            Target::RegistryValue { key, name } => write!(f, "{key}\\{name}"),
+            Target::KeyFileEntry { path, group, key } => {
+                write!(f, "{} [{group}] {key}", path.display())
+            }
+        }
+    }
+}
+
+/// Value of a [Target].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// No data, like registry keys and `REG_NONE` values.
+    Empty,
+    /// Text, like file contents in UTF-8 and `REG_SZ` values.
+    Text(String),
+    /// Any other data, like file contents which aren't UTF-8 and `REG_BINARY` values.
+    Binary(Vec<u8>),
+    /// Registry value of any other type, with its raw data.
+    Typed(u32, Vec<u8>),
+}
+impl Value {
+    /// Turn file contents into [Value::Text] if they're valid UTF-8, or [Value::Binary] otherwise.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => Value::Text(text),
+            Err(why) => Value::Binary(why.into_bytes()),
+        }
+    }
+    /// Get the data of the value, text being encoded in UTF-8.
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Value::Empty => &[],
+            Value::Text(text) => text.as_bytes(),
+            Value::Binary(bytes) | Value::Typed(_, bytes) => bytes,
+        }
+    }
+    /// Format the value as the data of a value in a `.reg` file.
+    fn fmt_reg(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Empty => f.write_str("hex(0):"),
+            Value::Text(text) => write!(f, "\"{}\"", escape_reg(text)),
+            Value::Binary(bytes) | Value::Typed(_, bytes) => {
+                match self {
+                    Value::Typed(value_type, _) => write!(f, "hex({value_type:x}):")?,
+                    _ => f.write_str("hex:")?,
+                }
+                for (i, byte) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{byte:02x}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Creation, modification or removal of a [Target].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// What is changed.
+    pub target: Target,
+    /// Value before the change, [None] if the target is created.
+    pub before: Option<Value>,
+    /// Value after the change, [None] if the target is removed.
+    pub after: Option<Value>,
+}
+/// Formats the change as a diff: files as a unified diff of their lines, registry keys and values in the syntax of `.reg` files.
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            Target::File(_) => {
+                let (before, after) = (self.before.as_ref(), self.after.as_ref());
+                match before {
+                    Some(_) => writeln!(f, "--- {}", self.target)?,
+                    None => writeln!(f, "--- /dev/null")?,
+                }
+                match after {
+                    Some(_) => writeln!(f, "+++ {}", self.target)?,
+                    None => writeln!(f, "+++ /dev/null")?,
+                }
+                fn text(value: Option<&Value>) -> Option<&str> {
+                    match value {
+                        Some(Value::Text(text)) => Some(text),
+                        Some(Value::Empty) | None => Some(""),
+                        Some(Value::Binary(_) | Value::Typed(..)) => None,
+                    }
+                }
+                match (text(before), text(after)) {
+                    (Some(before), Some(after)) => {
+                        for (sign, line) in diff_lines(before, after) {
+                            writeln!(f, "{sign}{line}")?;
+                        }
+                        Ok(())
+                    }
+                    _ => writeln!(f, "Binary files differ"),
+                }
+            }
+            Target::KeyFileEntry { path, group, key } => {
+                writeln!(f, " {} [{group}]", path.display())?;
+                for (sign, value) in [('-', &self.before), ('+', &self.after)] {
+                    match value {
+                        Some(Value::Text(value)) => writeln!(f, "{sign}{key}={value}")?,
+                        Some(_) => writeln!(f, "{sign}{key}")?,
+                        None => (),
+                    }
+                }
+                Ok(())
+            }
+            Target::RegistryKey(key) => match self.after {
+                Some(_) => writeln!(f, "+[{key}]"),
+                None => writeln!(f, "-[{key}]"),
+            },
+            Target::RegistryValue { key, name } => {
+                writeln!(f, " [{key}]")?;
+                let name = if name.is_empty() {
+                    String::from("@")
+                } else {
+                    format!("\"{}\"", escape_reg(name))
+                };
+                if let Some(before) = &self.before {
+                    write!(f, "-{name}=")?;
+                    before.fmt_reg(f)?;
+                    writeln!(f)?;
+                }
+                if let Some(after) = &self.after {
+                    write!(f, "+{name}=")?;
+                    after.fmt_reg(f)?;
+                    writeln!(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Changes recorded by a [dry run](crate::app::OpenOptions::dry_run), in the order they'd be made.
+///
+/// Clones of a plan share the same changes, so a plan handed to a backend can be read while it records into it.
+/// The plan is printed as a diff of every change by [Display].
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    changes: Arc<Mutex<Vec<Change>>>,
+}
+impl Plan {
+    /// Create an empty plan.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn lock(&self) -> MutexGuard<'_, Vec<Change>> {
+        self.changes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    /// Get the recorded changes.
+    pub fn changes(&self) -> Vec<Change> {
+        self.lock().clone()
+    }
+    /// Check whether nothing would change.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+    /// Record a change, unless the value stays the same.
+    pub fn push(&self, change: Change) {
+        if change.before != change.after {
+            self.lock().push(change);
+        }
+    }
+}
+impl Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.lock().iter() {
+            change.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn escape_reg(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Diff the lines of two texts, marking every line with `' '`, `'-'` or `'+'`.
+///
+/// Lines are matched by their longest common subsequence. Texts too long for that are diffed as a whole.
+fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<(char, &'a str)> {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+    if before.len().saturating_mul(after.len()) > 1 << 22 {
+        return before
+            .into_iter()
+            .map(|line| ('-', line))
+            .chain(after.into_iter().map(|line| ('+', line)))
+            .collect();
+    }
+
+    // common[i][j] is the length of the longest common subsequence of before[i..] and after[j..]
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            lines.push((' ', before[i]));
+            i += 1;
+            j += 1;
+        } else if i == before.len() || (j < after.len() && common[i][j + 1] > common[i + 1][j]) {
+            lines.push(('+', after[j]));
+            j += 1;
+        } else {
+            lines.push(('-', before[i]));
+            i += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let plan = Plan::new();
+        plan.push(Change {
+            target: Target::File(PathBuf::from("/data/applications/test.desktop")),
+            before: Some(Value::Text(String::from(
+                "[Desktop Entry]\nName=Old\nExec=app\n",
+            ))),
+            after: Some(Value::Text(String::from(
+                "[Desktop Entry]\nName=New\nExec=app\nMimeType=text/plain;\n",
+            ))),
+        });
+        plan.push(Change {
+            target: Target::File(PathBuf::from("/data/mime/mime.cache")),
+            before: None,
+            after: Some(Value::Binary(vec![0, 1, 0, 2])),
+        });
+        plan.push(Change {
+            target: Target::RegistryKey(String::from(r"HKEY_CURRENT_USER\Software\Classes\.txt")),
+            before: None,
+            after: Some(Value::Empty),
+        });
+        plan.push(Change {
+            target: Target::RegistryValue {
+                key: String::from(r"HKEY_CURRENT_USER\Software\Classes\.txt"),
+                name: String::new(),
+            },
+            before: Some(Value::Text(String::from("Other.App"))),
+            after: Some(Value::Text(String::from("Fileext.Test"))),
+        });
+        plan.push(Change {
+            target: Target::RegistryValue {
+                key: String::from(r"HKEY_CURRENT_USER\Software\Classes\.txt\OpenWithProgids"),
+                name: String::from("Fileext.Test"),
+            },
+            before: None,
+            after: Some(Value::Empty),
+        });
+        // Changes which don't change anything aren't recorded
+        plan.push(Change {
+            target: Target::RegistryKey(String::from(r"HKEY_CURRENT_USER\Software\Classes")),
+            before: Some(Value::Empty),
+            after: Some(Value::Empty),
+        });
+
+        assert_eq!(plan.changes().len(), 5);
+        assert_eq!(
+            plan.to_string(),
+            r#"--- /data/applications/test.desktop
++++ /data/applications/test.desktop
+ [Desktop Entry]
+-Name=Old
++Name=New
+ Exec=app
++MimeType=text/plain;
+--- /dev/null
++++ /data/mime/mime.cache
+Binary files differ
++[HKEY_CURRENT_USER\Software\Classes\.txt]
+ [HKEY_CURRENT_USER\Software\Classes\.txt]
+-@="Other.App"
++@="Fileext.Test"
+ [HKEY_CURRENT_USER\Software\Classes\.txt\OpenWithProgids]
++"Fileext.Test"=hex(0):
+"#
+        );
+    }
+}
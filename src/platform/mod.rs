@@ -1,17 +1,71 @@
-// Copyright (C) 2023 Egor Poleshko
-//
-// fileext is free software: you can redistribute it and/or modify
-// it under the terms of the GNU Lesser General Public License as published by
-// the Free Software Foundation, either version 3 of the License, or
-// (at your option) any later version.
-//
-// fileext is distributed in the hope that it will be useful,
-// but WITHOUT ANY WARRANTY; without even the implied warranty of
-// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
-// GNU Lesser General Public License for more details.
-//
-// You should have received a copy of the GNU Lesser General Public License
-// along with fileext. If not, see <http://www.gnu.org/licenses/>.
-
-#[cfg(windows)]
-pub mod windows;
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Platform-specific [backends](crate::backend::Backend).
+use crate::app::{ApplicationDescriptor, FileType};
+use crate::backend::Backend;
+use crate::result::{Error, Result};
+use std::sync::Arc;
+
+#[cfg(windows)]
+pub mod windows;
+pub mod xdg;
+
+/// Get the backend native to the operating system the crate was compiled for.
+///
+/// Operating systems without a native backend get an [UnsupportedBackend].
+pub fn default_backend() -> Arc<dyn Backend> {
+    #[cfg(windows)]
+    return Arc::new(windows::RegistryBackend::new());
+    #[cfg(all(unix, not(target_os = "macos")))]
+    return Arc::new(xdg::XdgBackend::new());
+    #[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+    return Arc::new(UnsupportedBackend);
+}
+
+/// Backend which fails every operation with [Error::Unsupported].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnsupportedBackend;
+impl Backend for UnsupportedBackend {
+    fn create(&self, _executable: &str, _descriptor: &ApplicationDescriptor) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    fn get(&self, _id: &str) -> Result<Option<ApplicationDescriptor>> {
+        Err(Error::Unsupported)
+    }
+    fn delete(&self, _id: &str) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    fn enumerate(&self, _id: &str) -> Result<Vec<String>> {
+        Err(Error::Unsupported)
+    }
+    fn associate(
+        &self,
+        _id: &str,
+        _file_type: &FileType,
+        _default: bool,
+    ) -> Result<Option<String>> {
+        Err(Error::Unsupported)
+    }
+    fn dissociate(&self, _id: &str, _file_type: &FileType) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    fn default_application_for(
+        &self,
+        _file_type: &FileType,
+    ) -> Result<Option<ApplicationDescriptor>> {
+        Err(Error::Unsupported)
+    }
+}
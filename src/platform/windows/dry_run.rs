@@ -0,0 +1,402 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! [Dry runs](crate::app::OpenOptions::dry_run) of the [RegistryBackend].
+//!
+//! Keys and values the backend would write are kept in an overlay on top of its [classes key](super::CLASSES_KEY), and every
+//! write is recorded as a change of the key or value. Later writes read the overlay before the registry, so they see
+//! the earlier ones, while reads of the backend, like [Backend::get], go to the registry only.
+use super::{
+    raw, ClassesKeyPath, PerceivedType, ProgIDVerbs, RegGetValue, RegOpenKey, RegistryBackend,
+    ValueFromReg,
+};
+use crate::app::{ApplicationDescriptor, FileType};
+use crate::backend::{Association, Backend};
+use crate::command::Syntax;
+use crate::mime::MimePackage;
+use crate::plan::{Change, Plan, Target, Value};
+use crate::result::{Error, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+/// Keys and values written by a dry run, by their paths relative to the classes key in lowercase,
+/// since the registry compares them case-insensitively.
+#[derive(Debug, Default)]
+struct Overlay {
+    /// Keys created, including the ones whose trees were deleted before.
+    created: BTreeSet<String>,
+    /// Keys whose trees were deleted, hiding whatever the registry has under them.
+    deleted: BTreeSet<String>,
+    /// Values written by key and name, [None] for deleted ones.
+    values: BTreeMap<(String, String), Option<Value>>,
+}
+impl Overlay {
+    /// Check whether a key is in a deleted tree, lowercase `key` included.
+    fn is_deleted(&self, key: &str) -> bool {
+        self.deleted.iter().any(|deleted| is_in_tree(key, deleted))
+    }
+}
+
+/// Check whether `key` is `tree` or one of its subkeys.
+fn is_in_tree(key: &str, tree: &str) -> bool {
+    key == tree
+        || key
+            .strip_prefix(tree)
+            .is_some_and(|rest| rest.starts_with('\\'))
+}
+
+/// [Backend] recording the changes a [RegistryBackend] would make, instead of making them.
+#[derive(Debug)]
+pub(super) struct DryRunBackend {
+    backend: RegistryBackend,
+    overlay: Mutex<Overlay>,
+    plan: Plan,
+}
+impl DryRunBackend {
+    pub(super) fn new(backend: RegistryBackend, plan: &Plan) -> Self {
+        Self {
+            backend,
+            overlay: Mutex::default(),
+            plan: plan.clone(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Overlay> {
+        self.overlay
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    /// Get the full path of a key of the classes key, e.g. `HKEY_CURRENT_USER\Software\Classes\.txt`.
+    fn full_path(&self, key: &str) -> String {
+        ClassesKeyPath(self.backend.scope, key)
+    }
+
+    /// Check whether a key exists after the changes of the overlay.
+    fn key_exists(&self, overlay: &Overlay, key: &str) -> io::Result<bool> {
+        let lowercase = key.to_lowercase();
+        if overlay.created.contains(&lowercase) {
+            return Ok(true);
+        }
+        if overlay.is_deleted(&lowercase) {
+            return Ok(false);
+        }
+        self.backend.read_classes(|classes_key| unsafe {
+            match RegOpenKey(classes_key, key, raw::KEY_READ)? {
+                Some(h_key) => {
+                    raw::RegCloseKey(h_key);
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        })
+    }
+    /// Read a value after the changes of the overlay, returning [None] if it doesn't exist.
+    fn value(&self, overlay: &Overlay, key: &str, name: &str) -> io::Result<Option<Value>> {
+        let lowercase = key.to_lowercase();
+        if let Some(value) = overlay
+            .values
+            .get(&(lowercase.clone(), name.to_lowercase()))
+        {
+            return Ok(value.clone());
+        }
+        if overlay.is_deleted(&lowercase) {
+            return Ok(None);
+        }
+        let value = self.backend.read_classes(|classes_key| unsafe {
+            let Some(h_key) = RegOpenKey(classes_key, key, raw::KEY_READ)? else {
+                return Ok(None);
+            };
+            let value = RegGetValue(h_key, name);
+            raw::RegCloseKey(h_key);
+            value
+        })?;
+        Ok(value.map(|(value_type, data)| ValueFromReg(value_type, data)))
+    }
+    /// Read a string value after the changes of the overlay, treating values of other types as missing.
+    fn string(&self, overlay: &Overlay, key: &str, name: &str) -> io::Result<Option<String>> {
+        match self.value(overlay, key, name)? {
+            Some(Value::Text(text)) => Ok(Some(text)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Create a key and the keys above it which don't exist.
+    fn create_key(&self, overlay: &mut Overlay, key: &str) -> io::Result<()> {
+        let mut path = String::new();
+        for part in key.split('\\') {
+            if !path.is_empty() {
+                path.push('\\');
+            }
+            path.push_str(part);
+            if !self.key_exists(overlay, &path)? {
+                self.plan.push(Change {
+                    target: Target::RegistryKey(self.full_path(&path)),
+                    before: None,
+                    after: Some(Value::Empty),
+                });
+                overlay.created.insert(path.to_lowercase());
+            }
+        }
+        Ok(())
+    }
+    /// Write a value, creating its key if it doesn't exist.
+    fn set_value(
+        &self,
+        overlay: &mut Overlay,
+        key: &str,
+        name: &str,
+        value: Value,
+    ) -> io::Result<()> {
+        self.create_key(overlay, key)?;
+        self.plan.push(Change {
+            target: Target::RegistryValue {
+                key: self.full_path(key),
+                name: name.to_string(),
+            },
+            before: self.value(overlay, key, name)?,
+            after: Some(value.clone()),
+        });
+        overlay
+            .values
+            .insert((key.to_lowercase(), name.to_lowercase()), Some(value));
+        Ok(())
+    }
+    /// Delete a value, doing nothing if it doesn't exist.
+    fn delete_value(&self, overlay: &mut Overlay, key: &str, name: &str) -> io::Result<()> {
+        let Some(before) = self.value(overlay, key, name)? else {
+            return Ok(());
+        };
+        self.plan.push(Change {
+            target: Target::RegistryValue {
+                key: self.full_path(key),
+                name: name.to_string(),
+            },
+            before: Some(before),
+            after: None,
+        });
+        overlay
+            .values
+            .insert((key.to_lowercase(), name.to_lowercase()), None);
+        Ok(())
+    }
+    /// Delete a key together with all of its subkeys and values, doing nothing if it doesn't exist.
+    fn delete_tree(&self, overlay: &mut Overlay, key: &str) -> io::Result<()> {
+        if !self.key_exists(overlay, key)? {
+            return Ok(());
+        }
+        self.plan.push(Change {
+            target: Target::RegistryKey(self.full_path(key)),
+            before: Some(Value::Empty),
+            after: None,
+        });
+        let lowercase = key.to_lowercase();
+        overlay
+            .created
+            .retain(|created| !is_in_tree(created, &lowercase));
+        overlay
+            .values
+            .retain(|(value_key, _), _| !is_in_tree(value_key, &lowercase));
+        overlay.deleted.insert(lowercase);
+        Ok(())
+    }
+    /// Check whether the default value of an extension names a ProgID after the changes of the overlay.
+    fn is_default(&self, overlay: &Overlay, extension: &str, id: &str) -> io::Result<bool> {
+        Ok(self
+            .string(overlay, extension, "")?
+            .is_some_and(|default| default.eq_ignore_ascii_case(id)))
+    }
+    /// Get the extension a file type is associated through.
+    fn extension(&self, file_type: &FileType) -> Result<String> {
+        self.backend.extension(file_type)?.ok_or(Error::Unsupported)
+    }
+}
+/// Mirrors the writes of [RegistryBackend], made by [CreateProgID](super::CreateProgID),
+/// [CreateFileTypeAssociation](super::CreateFileTypeAssociation) and the other functions it calls.
+impl Backend for DryRunBackend {
+    fn create(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        let overlay = &mut *self.lock();
+        let id = &descriptor.id;
+        self.set_value(overlay, id, "", Value::Text(descriptor.name.clone()))?;
+        self.set_value(
+            overlay,
+            &format!("{id}\\CurVer"),
+            "",
+            Value::Text(id.clone()),
+        )?;
+        if !descriptor.icon_path.is_empty() {
+            self.set_value(
+                overlay,
+                &format!("{id}\\DefaultIcon"),
+                "",
+                Value::Text(descriptor.icon_path.clone()),
+            )?;
+        }
+
+        let shell = format!("{id}\\shell");
+        self.delete_tree(overlay, &shell)?;
+        self.create_key(overlay, &shell)?;
+        if let Some(default_verb) = &descriptor.default_verb {
+            self.set_value(overlay, &shell, "", Value::Text(default_verb.clone()))?;
+        }
+        for verb in ProgIDVerbs(executable, descriptor).iter() {
+            let verb_key = format!("{shell}\\{}", verb.name);
+            self.create_key(overlay, &verb_key)?;
+            if !verb.label.is_empty() {
+                self.set_value(overlay, &verb_key, "", Value::Text(verb.label.clone()))?;
+            }
+            self.set_value(
+                overlay,
+                &format!("{verb_key}\\command"),
+                "",
+                Value::Text(verb.command.render(Syntax::Windows)),
+            )?;
+        }
+        Ok(())
+    }
+    fn get(&self, id: &str) -> Result<Option<ApplicationDescriptor>> {
+        self.backend.get(id)
+    }
+    fn executable(&self, id: &str) -> Result<Option<String>> {
+        self.backend.executable(id)
+    }
+    fn delete(&self, id: &str) -> Result<()> {
+        let overlay = &mut *self.lock();
+        if !self.key_exists(overlay, id)? {
+            return Err(io::Error::from_raw_os_error(raw::ERROR_FILE_NOT_FOUND).into());
+        }
+        Ok(self.delete_tree(overlay, id)?)
+    }
+    fn enumerate(&self, id: &str) -> Result<Vec<String>> {
+        self.backend.enumerate(id)
+    }
+    fn associations(&self, id: &str) -> Result<Vec<Association>> {
+        self.backend.associations(id)
+    }
+    fn handles(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        let extension = self.extension(file_type)?;
+        let overlay = &*self.lock();
+        let listed = self
+            .value(overlay, &format!("{extension}\\OpenWithProgids"), id)?
+            .is_some();
+        Ok(listed || self.is_default(overlay, &extension, id)?)
+    }
+    fn is_default_for(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        let extension = self.extension(file_type)?;
+        Ok(self.is_default(&self.lock(), &extension, id)?)
+    }
+    fn associate(&self, id: &str, file_type: &FileType, default: bool) -> Result<Option<String>> {
+        let extension = self.extension(file_type)?;
+        let overlay = &mut *self.lock();
+        if !self.key_exists(overlay, id)? {
+            return Err(io::Error::from_raw_os_error(raw::ERROR_FILE_NOT_FOUND).into());
+        }
+
+        self.set_value(
+            overlay,
+            &format!("{extension}\\OpenWithProgids"),
+            id,
+            Value::Empty,
+        )?;
+        if default {
+            let previous = self.string(overlay, &extension, "")?;
+            self.set_value(overlay, &extension, "", Value::Text(id.to_string()))?;
+            Ok(previous.filter(|previous| !previous.is_empty() && previous != id))
+        } else {
+            Ok(None)
+        }
+    }
+    fn dissociate(&self, id: &str, file_type: &FileType) -> Result<()> {
+        let extension = self.extension(file_type)?;
+        let overlay = &mut *self.lock();
+        if !self.key_exists(overlay, &extension)? {
+            return Ok(());
+        }
+
+        let open_with = format!("{extension}\\OpenWithProgids");
+        if self.key_exists(overlay, &open_with)? {
+            self.delete_value(overlay, &open_with, id)?;
+        }
+        if self.string(overlay, &extension, "")?.as_deref() == Some(id) {
+            self.delete_value(overlay, &extension, "")?;
+        }
+        Ok(())
+    }
+    fn register_mime_package(&self, _id: &str, package: &MimePackage) -> Result<()> {
+        let overlay = &mut *self.lock();
+        for definition in &package.mime_types {
+            let mime_type = definition.mime_type.essence();
+            let extensions = definition.extensions();
+            for extension in &extensions {
+                self.set_value(
+                    overlay,
+                    extension,
+                    "Content Type",
+                    Value::Text(mime_type.to_string()),
+                )?;
+                if let Some(perceived_type) = PerceivedType(definition) {
+                    self.set_value(
+                        overlay,
+                        extension,
+                        "PerceivedType",
+                        Value::Text(perceived_type.to_string()),
+                    )?;
+                }
+            }
+            if let Some(extension) = extensions.first() {
+                self.set_value(
+                    overlay,
+                    &format!("MIME\\Database\\Content Type\\{mime_type}"),
+                    "Extension",
+                    Value::Text(extension.clone()),
+                )?;
+            }
+        }
+        Ok(())
+    }
+    fn unregister_mime_package(&self, _id: &str, package: &MimePackage) -> Result<()> {
+        let overlay = &mut *self.lock();
+        for definition in &package.mime_types {
+            let mime_type = definition.mime_type.essence();
+            let extensions = definition.extensions();
+            for extension in &extensions {
+                if self.string(overlay, extension, "Content Type")?.as_deref() != Some(mime_type) {
+                    continue;
+                }
+                self.delete_value(overlay, extension, "Content Type")?;
+                if let Some(perceived_type) = PerceivedType(definition) {
+                    if self.string(overlay, extension, "PerceivedType")?.as_deref()
+                        == Some(perceived_type)
+                    {
+                        self.delete_value(overlay, extension, "PerceivedType")?;
+                    }
+                }
+            }
+            let mime_key = format!("MIME\\Database\\Content Type\\{mime_type}");
+            if let Some(extension) = self.string(overlay, &mime_key, "Extension")? {
+                if extensions.contains(&extension) {
+                    self.delete_tree(overlay, &mime_key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn default_application_for(
+        &self,
+        file_type: &FileType,
+    ) -> Result<Option<ApplicationDescriptor>> {
+        self.backend.default_application_for(file_type)
+    }
+}
@@ -12,37 +12,42 @@
 //
 // You should have received a copy of the GNU Lesser General Public License
 // along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Windows backend, registering applications as ProgIDs in the registry.
+//!
+//! File extensions name the ProgID opening them by default in their default value, and list the other ones under
+//! their `OpenWithProgids` key.
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
-use core::{ffi::c_void as void, mem, ptr};
+use core::ptr;
+use std::borrow::Cow;
 use std::io;
+use std::sync::Arc;
 
-use self::raw::RegOpenKeyExW;
+use crate::app::{ApplicationDescriptor, FileType, Verb, VERB_OPEN};
+use crate::backend::{Association, Backend, Scope};
+use crate::command::{Arg, CommandTemplate, Syntax};
+use crate::mime::{MimePackage, MimeTypeDefinition};
+use crate::plan::{Plan, Target, Value};
+use crate::result::{Error, Result};
+use crate::snapshot::Snapshot;
 
+mod dry_run;
+/// Bindings to the Win32 functions and constants the backend uses.
 pub mod raw;
 
-/// Convert a UTF-8 [String] into a Windows UTF-16 null-terminated string
-pub fn StringToLPCWSTR(string: impl ToString) -> *const u16 {
-    let mut lpcwstr = string.to_string().encode_utf16().collect::<Vec<u16>>();
-    lpcwstr.push('\0' as u16); // LPCWSTR strings must be null-terminated
-    lpcwstr.as_ptr()
-}
 /// Shortcut method to [String::from_utf16_lossy]
-pub unsafe fn VecIntoString(vector: Vec<u16>) -> String {
+pub fn VecIntoString(vector: Vec<u16>) -> String {
     String::from_utf16_lossy(vector.as_slice())
 }
-/// Convert a Windows UTF-16 null-terminated string into a UTF-8 [String].
-pub unsafe fn LPCWSTRIntoString(lpString: *const u16) -> String {
-    let len = raw::lstrlenW(lpString) as usize;
-    let vector = Vec::from_raw_parts(lpString as *mut u16, len, len);
-    VecIntoString(vector)
-}
-
 /// Get list of subkeys under a supplied HKEY
 ///
 /// # Errors
 /// - [ERROR_INVALID_HANDLE](https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-#ERROR_INVALID_HANDLE) if the supplied HKEY doesn't exist or is an invalid key handle
 /// - [ERROR_NO_MORE_ITEMS](https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-#ERROR_NO_MORE_ITEMS) if the supplied HKEY has no subkeys
+///
+/// # Safety
+/// `parent_h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
 pub unsafe fn RegQueryKeys(parent_h_key: isize) -> io::Result<Vec<String>> {
     let mut keys = Vec::new();
 
@@ -88,19 +93,71 @@ pub unsafe fn RegQueryKeys(parent_h_key: isize) -> io::Result<Vec<String>> {
     }
 }
 
+/// Get names of the values of a supplied HKEY, the default value being an empty name.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegQueryValues(h_key: isize) -> io::Result<Vec<String>> {
+    let mut num_of_values = 0;
+    let mut max_name_length = 0;
+    let res = raw::RegQueryInfoKeyW(
+        h_key,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        &mut num_of_values,
+        &mut max_name_length,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+    );
+    if res != 0 {
+        return Err(io::Error::from_raw_os_error(res));
+    }
+
+    let mut values = Vec::with_capacity(num_of_values as usize);
+    for i in 0..num_of_values {
+        let mut name_buffer = vec![0u16; max_name_length as usize + 1];
+        let mut name_length = name_buffer.len() as u32;
+        let res = raw::RegEnumValueW(
+            h_key,
+            i,
+            name_buffer.as_mut_ptr(),
+            &mut name_length,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+        if res != 0 {
+            return Err(io::Error::from_raw_os_error(res));
+        }
+        name_buffer.truncate(name_length as usize);
+        values.push(VecIntoString(name_buffer));
+    }
+    Ok(values)
+}
+
 /// Create a key in Windows Registry.
 ///
 /// # Errors:
 /// - **[ERROR_ACCESS_DENIED](std::io::ErrorKind::PermissionDenied)** if the program doesn't run as the Administrator, while the key requiring administrator rights for write access.
 /// - **[ERROR_INVALID_HANDLE](https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-#ERROR_INVALID_HANDLE)** if the supplied key doesn't exist or is invalid.
+///
+/// # Safety
+/// `h_key`, if given, must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
 pub unsafe fn RegCreateKey(h_key: Option<isize>, path: impl ToString) -> io::Result<isize> {
     let h_key = h_key.unwrap_or(0);
+    let path = StringToWide(path);
 
     let mut out_h_key: isize = 0;
 
     let res = raw::RegCreateKeyExW(
         h_key,
-        StringToLPCWSTR(path),
+        path.as_ptr(),
         0,
         ptr::null(),
         raw::REG_OPTION_NON_VOLATILE,
@@ -116,101 +173,296 @@ pub unsafe fn RegCreateKey(h_key: Option<isize>, path: impl ToString) -> io::Res
     }
 }
 
-/// Write data to the supplied key.
+/// Delete a subkey of the supplied key, which mustn't have subkeys of its own.
 ///
-/// # Errors:
-/// - **[ERROR_ACCESS_DENIED](std::io::ErrorKind::PermissionDenied)** if the program doesn't run as the Administrator, while the key requiring administrator rights for write access.
-/// - **[ERROR_INVALID_HANDLE](https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-#ERROR_INVALID_HANDLE)** if the supplied key doesn't exist or is invalid.
-/// - **[ERROR_INVALID_PARAMETER](https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-#ERROR_INVALID_PARAMETER)** if `h_key_type` is not any [**registry data type**](https://learn.microsoft.com/en-us/windows/win32/shell/hkey-type).
-pub unsafe fn RegWriteKey(
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegDeleteKey(h_key: isize, subkey: impl ToString) -> io::Result<()> {
+    let subkey = StringToWide(subkey);
+
+    let res = raw::RegDeleteKeyExW(h_key, subkey.as_ptr(), raw::KEY_WOW64_32KEY, 0);
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(res))
+    }
+}
+
+/// Convert a UTF-8 string into an owned null-terminated UTF-16 string.
+///
+/// The returned buffer stays alive for as long as the caller keeps it, so pointers into it can be passed to the API.
+pub fn StringToWide(string: impl ToString) -> Vec<u16> {
+    let mut wide = string.to_string().encode_utf16().collect::<Vec<u16>>();
+    wide.push(0);
+    wide
+}
+
+/// Open a subkey of the supplied key, returning [None] if it doesn't exist.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegOpenKey(h_key: isize, path: impl ToString, sam: u32) -> io::Result<Option<isize>> {
+    let path = StringToWide(path);
+    let mut out_h_key = 0;
+
+    match raw::RegOpenKeyExW(h_key, path.as_ptr(), 0, sam, &mut out_h_key) {
+        0 => Ok(Some(out_h_key)),
+        raw::ERROR_FILE_NOT_FOUND => Ok(None),
+        res => Err(io::Error::from_raw_os_error(res)),
+    }
+}
+
+/// Write a value of any [**registry data type**](https://learn.microsoft.com/en-us/windows/win32/shell/hkey-type) to the supplied key.
+/// An empty `value_name` refers to the default value of the key.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegSetValue(
     h_key: isize,
     value_name: impl ToString,
-    h_key_type: u32,
-    data: *const u8,
+    value_type: u32,
+    data: &[u8],
 ) -> io::Result<()> {
-    let value_name = value_name.to_string();
-    let value_name = if value_name.is_empty() {
-        StringToLPCWSTR(value_name)
-    } else {
-        ptr::null()
-    };
-    if raw::RegSetValueExW(
+    let value_name = StringToWide(value_name);
+
+    match raw::RegSetValueExW(
         h_key,
-        value_name,
+        value_name.as_ptr(),
         0,
-        h_key_type,
-        data,
-        mem::size_of::<*const u8>() as u32,
-    ) == 0
-    {
-        Ok(())
-    } else {
-        Err(io::Error::last_os_error())
+        value_type,
+        data.as_ptr(),
+        data.len() as u32,
+    ) {
+        0 => Ok(()),
+        res => Err(io::Error::from_raw_os_error(res)),
     }
 }
-pub unsafe fn RegReadKeyValue(
+
+/// Write a [REG_SZ](raw::REG_SZ) value to the supplied key.
+/// An empty `value_name` refers to the default value of the key.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegSetString(
     h_key: isize,
     value_name: impl ToString,
-    lp_type: *mut u32,
-) -> io::Result<*const u8> {
-    let mut buffer = Vec::new();
-    let value_name = value_name.to_string();
+    value: impl ToString,
+) -> io::Result<()> {
+    let data = StringToWide(value)
+        .into_iter()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<u8>>();
+    RegSetValue(h_key, value_name, raw::REG_SZ, &data)
+}
+
+/// Delete a value of the supplied key, doing nothing if it doesn't exist.
+/// An empty `value_name` refers to the default value of the key.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegDeleteValue(h_key: isize, value_name: impl ToString) -> io::Result<()> {
+    let value_name = StringToWide(value_name);
+
+    match raw::RegDeleteValueW(h_key, value_name.as_ptr()) {
+        0 | raw::ERROR_FILE_NOT_FOUND => Ok(()),
+        res => Err(io::Error::from_raw_os_error(res)),
+    }
+}
+
+/// Delete a subkey of the supplied key together with all of its subkeys and values, doing nothing if it doesn't exist.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegDeleteTree(h_key: isize, subkey: impl ToString) -> io::Result<()> {
+    let subkey = StringToWide(subkey);
+
+    match raw::RegDeleteTreeW(h_key, subkey.as_ptr()) {
+        0 | raw::ERROR_FILE_NOT_FOUND => Ok(()),
+        res => Err(io::Error::from_raw_os_error(res)),
+    }
+}
+
+/// Read a value of any [**registry data type**](https://learn.microsoft.com/en-us/windows/win32/shell/hkey-type) of the supplied key,
+/// together with its type, returning [None] if it doesn't exist.
+/// An empty `value_name` refers to the default value of the key.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegGetValue(
+    h_key: isize,
+    value_name: impl ToString,
+) -> io::Result<Option<(u32, Vec<u8>)>> {
+    let value_name = StringToWide(value_name);
 
-    let value_name = if value_name.is_empty() {
-        ptr::null()
-    } else {
-        StringToLPCWSTR(value_name)
-    };
     let mut size = 0;
-    let res = raw::RegQueryValueExW(
+    match raw::RegQueryValueExW(
         h_key,
-        value_name,
+        value_name.as_ptr(),
         ptr::null(),
-        lp_type,
-        buffer.as_mut_ptr(),
+        ptr::null_mut(),
+        ptr::null_mut(),
         &mut size,
-    );
-    if res == 0 {
-        Ok(buffer.as_ptr())
-    } else if res == 234 {
-        let mut buffer = Vec::with_capacity(size as usize);
-        let res = raw::RegQueryValueExW(
-            h_key,
-            value_name,
-            ptr::null(),
-            lp_type,
-            buffer.as_mut_ptr(),
-            &mut size,
-        );
-        if res == 0 {
-            Ok(buffer.as_ptr())
-        } else {
-            Err(io::Error::from_raw_os_error(res))
+    ) {
+        0 => (),
+        raw::ERROR_FILE_NOT_FOUND => return Ok(None),
+        res => return Err(io::Error::from_raw_os_error(res)),
+    }
+
+    let mut data = vec![0u8; size as usize];
+    let mut value_type = 0;
+    match raw::RegQueryValueExW(
+        h_key,
+        value_name.as_ptr(),
+        ptr::null(),
+        &mut value_type,
+        data.as_mut_ptr(),
+        &mut size,
+    ) {
+        0 => (),
+        raw::ERROR_FILE_NOT_FOUND => return Ok(None),
+        res => return Err(io::Error::from_raw_os_error(res)),
+    }
+    data.truncate(size as usize);
+    Ok(Some((value_type, data)))
+}
+
+/// Read a [REG_SZ](raw::REG_SZ) or [REG_EXPAND_SZ](raw::REG_EXPAND_SZ) value of the supplied key, returning [None] if it doesn't exist.
+/// An empty `value_name` refers to the default value of the key.
+///
+/// # Safety
+/// `h_key` must be an open registry key handle, or one of the predefined keys like [HKEY_CURRENT_USER](raw::HKEY_CURRENT_USER).
+pub unsafe fn RegGetString(h_key: isize, value_name: impl ToString) -> io::Result<Option<String>> {
+    let Some((value_type, data)) = RegGetValue(h_key, value_name)? else {
+        return Ok(None);
+    };
+    if value_type != raw::REG_SZ && value_type != raw::REG_EXPAND_SZ {
+        return Err(io::Error::from(io::ErrorKind::InvalidData));
+    }
+
+    let mut buffer: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    while buffer.last() == Some(&0) {
+        buffer.pop();
+    }
+    Ok(Some(VecIntoString(buffer)))
+}
+
+/// Convert a value read by [RegGetValue] into a [Value]: [REG_SZ](raw::REG_SZ) into [Value::Text],
+/// an empty [REG_NONE](raw::REG_NONE) into [Value::Empty], [REG_BINARY](raw::REG_BINARY) into [Value::Binary],
+/// and anything else into [Value::Typed], so that [ValueToReg] writes it back as it was.
+pub fn ValueFromReg(value_type: u32, data: Vec<u8>) -> Value {
+    match value_type {
+        raw::REG_SZ => {
+            let wide: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            Value::Text(
+                String::from_utf16_lossy(&wide)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            )
         }
-    } else {
-        Err(io::Error::from_raw_os_error(res))
+        raw::REG_NONE if data.is_empty() => Value::Empty,
+        raw::REG_BINARY => Value::Binary(data),
+        _ => Value::Typed(value_type, data),
     }
 }
 
-pub unsafe fn RegDeleteKey(h_key: isize, subkey: impl ToString) -> io::Result<()> {
-    let subkey = subkey.to_string();
+/// Convert a [Value] into the type and data [RegSetValue] writes.
+pub fn ValueToReg(value: &Value) -> (u32, Vec<u8>) {
+    match value {
+        Value::Empty => (raw::REG_NONE, Vec::new()),
+        Value::Text(text) => (
+            raw::REG_SZ,
+            StringToWide(text)
+                .into_iter()
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+        ),
+        Value::Binary(data) => (raw::REG_BINARY, data.clone()),
+        Value::Typed(value_type, data) => (*value_type, data.clone()),
+    }
+}
 
-    let res = raw::RegDeleteKeyExW(h_key, StringToLPCWSTR(subkey), raw::KEY_WOW64_32KEY, 0);
-    if res == 0 {
-        Ok(())
-    } else {
-        Err(io::Error::from_raw_os_error(res))
+/// Path of the key holding file types and ProgIDs under `HKEY_CURRENT_USER` and `HKEY_LOCAL_MACHINE`.
+pub const CLASSES_KEY: &str = "Software\\Classes";
+
+/// Get the registry hive applications of a [Scope] are registered in: `HKEY_CURRENT_USER` for [Scope::User],
+/// `HKEY_LOCAL_MACHINE` for [Scope::System]. Their [CLASSES_KEY] is merged into `HKEY_CLASSES_ROOT`.
+pub fn ScopeRoot(scope: Scope) -> isize {
+    match scope {
+        Scope::User => raw::HKEY_CURRENT_USER,
+        Scope::System => raw::HKEY_LOCAL_MACHINE,
     }
 }
 
+/// Get the full path of a key of the [classes key](CLASSES_KEY) of a [Scope],
+/// e.g. `HKEY_CURRENT_USER\Software\Classes\.txt` for `.txt` in [Scope::User].
+pub fn ClassesKeyPath(scope: Scope, key: &str) -> String {
+    let root = match scope {
+        Scope::User => "HKEY_CURRENT_USER",
+        Scope::System => "HKEY_LOCAL_MACHINE",
+    };
+    format!("{root}\\{CLASSES_KEY}\\{key}")
+}
+
+/// Add the state of a key of the classes key to a snapshot: the key itself, its values and its subkeys,
+/// or the absence of the key.
+unsafe fn SnapshotKey(
+    classes_key: isize,
+    scope: Scope,
+    key: &str,
+    snapshot: &mut Snapshot,
+) -> io::Result<()> {
+    let Some(h_key) = RegOpenKey(classes_key, key, raw::KEY_READ)? else {
+        snapshot.push(Target::RegistryKey(ClassesKeyPath(scope, key)), None);
+        return Ok(());
+    };
+    let result = (|| {
+        snapshot.push(
+            Target::RegistryKey(ClassesKeyPath(scope, key)),
+            Some(Value::Empty),
+        );
+        for name in RegQueryValues(h_key)? {
+            let value = RegGetValue(h_key, &name)?;
+            snapshot.push(
+                Target::RegistryValue {
+                    key: ClassesKeyPath(scope, key),
+                    name,
+                },
+                value.map(|(value_type, data)| ValueFromReg(value_type, data)),
+            );
+        }
+        for subkey in RegQueryKeys(h_key)? {
+            SnapshotKey(classes_key, scope, &format!("{key}\\{subkey}"), snapshot)?;
+        }
+        Ok(())
+    })();
+    raw::RegCloseKey(h_key);
+    result
+}
+
+/// Name and icon of a ProgID, as written by [CreateProgID] and read by [GetProcID].
 #[derive(Debug, Clone)]
 pub struct ProgID {
+    /// Name of the key of the ProgID.
     pub id: String,
+    /// Display name, the default value of the key.
     pub name: String,
+    /// Icon, the default value of the `DefaultIcon` subkey.
     pub default_icon_path: Option<String>,
 }
 
+/// Create a ProgID with a display name, a `CurVer` subkey naming the ProgID itself, and a `DefaultIcon` subkey
+/// unless `default_icon_path` is empty.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
 pub unsafe fn CreateProgID(
+    classes_key: isize,
     id: impl ToString,
     name: impl ToString,
     default_icon_path: impl ToString,
@@ -219,33 +471,27 @@ pub unsafe fn CreateProgID(
     let name = name.to_string();
     let default_icon_path = default_icon_path.to_string();
 
-    let id_key = RegCreateKey(Some(raw::HKEY_CLASSES_ROOT), id.clone())?;
-    RegWriteKey(
-        id_key,
-        "",
-        raw::REG_SZ,
-        StringToLPCWSTR(name.clone()) as *const u8,
-    )?;
-
-    RegWriteKey(
-        RegCreateKey(Some(id_key), "CurVer")?,
-        "",
-        raw::REG_SZ,
-        StringToLPCWSTR(id.clone()) as *const u8,
-    )?;
-    let default_icon_path = if !default_icon_path.is_empty() {
-        RegWriteKey(
-            RegCreateKey(Some(id_key), "DefaultIcon")?,
-            "",
-            raw::REG_SZ,
-            StringToLPCWSTR(default_icon_path.clone()) as *const u8,
-        )?;
-        Some(default_icon_path)
-    } else {
-        None
-    };
+    let id_key = RegCreateKey(Some(classes_key), &id)?;
+    let result = (|| -> io::Result<()> {
+        RegSetString(id_key, "", &name)?;
+
+        let cur_ver_key = RegCreateKey(Some(id_key), "CurVer")?;
+        let result = RegSetString(cur_ver_key, "", &id);
+        raw::RegCloseKey(cur_ver_key);
+        result?;
+
+        if !default_icon_path.is_empty() {
+            let default_icon_key = RegCreateKey(Some(id_key), "DefaultIcon")?;
+            let result = RegSetString(default_icon_key, "", &default_icon_path);
+            raw::RegCloseKey(default_icon_key);
+            result?;
+        }
+        Ok(())
+    })();
     raw::RegCloseKey(id_key);
+    result?;
 
+    let default_icon_path = Some(default_icon_path).filter(|path| !path.is_empty());
     Ok(ProgID {
         id,
         name,
@@ -253,139 +499,922 @@ pub unsafe fn CreateProgID(
     })
 }
 
-pub unsafe fn GetProcID(id: impl ToString) -> Option<ProgID> {
+/// Read the name and icon of a ProgID, returning [None] if it doesn't exist or has no name.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn GetProcID(classes_key: isize, id: impl ToString) -> io::Result<Option<ProgID>> {
     let id = id.to_string();
 
-    let mut h_key = 0;
-
-    if raw::RegOpenKeyExW(
-        raw::HKEY_CLASSES_ROOT,
-        StringToLPCWSTR(id.clone()),
-        0,
-        raw::KEY_READ,
-        &mut h_key,
-    ) == 0
-    {
-        let name = RegReadKeyValue(h_key, "", ptr::null_mut()).ok()? as *const u16;
-        let name = LPCWSTRIntoString(name);
-
-        let mut default_icon_key = 0;
-        let default_icon_path = if raw::RegOpenKeyExW(
-            h_key,
-            StringToLPCWSTR("DefaultIcon"),
-            0,
-            raw::KEY_READ,
-            &mut default_icon_key,
-        ) == 0
-        {
-            let default_icon_path =
-                RegReadKeyValue(default_icon_key, "", ptr::null_mut()).ok()? as *const u16;
-            Some(LPCWSTRIntoString(default_icon_path))
-        } else {
-            None
+    let Some(h_key) = RegOpenKey(classes_key, &id, raw::KEY_READ)? else {
+        return Ok(None);
+    };
+    let result = (|| -> io::Result<_> {
+        let Some(name) = RegGetString(h_key, "")? else {
+            return Ok(None);
         };
+        let default_icon_path = match RegOpenKey(h_key, "DefaultIcon", raw::KEY_READ)? {
+            Some(default_icon_key) => {
+                let default_icon_path = RegGetString(default_icon_key, "");
+                raw::RegCloseKey(default_icon_key);
+                default_icon_path?
+            }
+            None => None,
+        };
+        Ok(Some((name, default_icon_path)))
+    })();
+    raw::RegCloseKey(h_key);
 
-        raw::RegCloseKey(default_icon_key);
-        raw::RegCloseKey(h_key);
-
-        Some(ProgID {
-            id,
-            name,
-            default_icon_path,
-        })
-    } else {
-        None
-    }
+    Ok(result?.map(|(name, default_icon_path)| ProgID {
+        id,
+        name,
+        default_icon_path,
+    }))
 }
 
-pub unsafe fn DeleteProcID(id: impl ToString) -> io::Result<()> {
+/// Delete a ProgID together with all of its subkeys.
+///
+/// # Errors:
+/// - **[ERROR_FILE_NOT_FOUND](std::io::ErrorKind::NotFound)** if the ProgID doesn't exist.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn DeleteProcID(classes_key: isize, id: impl ToString) -> io::Result<()> {
     let id = id.to_string();
 
-    let mut h_key = 0;
-    let res = raw::RegOpenKeyExW(
-        raw::HKEY_CLASSES_ROOT,
-        StringToLPCWSTR(id.clone()),
-        0,
-        raw::KEY_ALL_ACCESS,
-        &mut h_key,
-    );
-    if res == 0 {
-        RegDeleteKey(h_key, "CurVer")?;
-        let _ = RegDeleteKey(h_key, "DefaultIcon");
-        RegDeleteKey(raw::HKEY_CLASSES_ROOT, id)
-    } else {
-        Err(io::Error::from_raw_os_error(res as i32))
-    }
+    match RegOpenKey(classes_key, &id, raw::KEY_READ)? {
+        Some(h_key) => raw::RegCloseKey(h_key),
+        None => return Err(io::Error::from_raw_os_error(raw::ERROR_FILE_NOT_FOUND)),
+    };
+    RegDeleteTree(classes_key, id)
 }
 
-pub unsafe fn EnumerateFileTypeAssociations(id: impl ToString) -> io::Result<Vec<String>> {
+/// Get the extensions associated with a ProgID, either by naming it in their default value or by listing it under
+/// their `OpenWithProgids`, like [CreateFileTypeAssociation] does with and without `default`.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn EnumerateFileTypeAssociations(
+    classes_key: isize,
+    id: impl ToString,
+) -> io::Result<Vec<String>> {
     let id = id.to_string();
     let mut associations = Vec::new();
 
-    let keys: Vec<String> = RegQueryKeys(raw::HKEY_CLASSES_ROOT)?
+    let keys: Vec<String> = RegQueryKeys(classes_key)?
         .into_iter()
         .filter(|el| el.starts_with('.'))
         .collect();
 
     for key_name in keys {
-        let mut h_key = 0;
-        let res = raw::RegOpenKeyExW(
-            raw::HKEY_CLASSES_ROOT,
-            StringToLPCWSTR(key_name.clone()),
-            0,
-            raw::KEY_READ,
-            &mut h_key,
-        );
-        if res != 0 {
-            return Err(io::Error::from_raw_os_error(res));
-        }
-
-        match RegReadKeyValue(h_key, "", ptr::null_mut()) {
-            Ok(extension_app_id) => {
-                let extension_app_id = LPCWSTRIntoString(extension_app_id as *const u16);
-                if extension_app_id == id {
-                    associations.push(key_name);
-                }
-            }
-            Err(why) => {
-                if why.kind() == io::ErrorKind::NotFound {
-                    continue;
-                } else {
-                    return Err(why);
-                }
-            }
+        if GetFileTypeAssociation(classes_key, &id, &key_name)?.is_some() {
+            associations.push(key_name);
         }
     }
 
     Ok(associations)
 }
+/// Check how a file extension is associated with a ProgID: [None] if it isn't, `Some(true)` if the default value of the
+/// extension names the ProgID, and `Some(false)` if the ProgID is only listed under its `OpenWithProgids`.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn GetFileTypeAssociation(
+    classes_key: isize,
+    id: impl ToString,
+    extension: impl ToString,
+) -> io::Result<Option<bool>> {
+    let id = id.to_string();
+    let Some(h_key) = RegOpenKey(classes_key, extension, raw::KEY_READ)? else {
+        return Ok(None);
+    };
+    let result = (|| -> io::Result<Option<bool>> {
+        // ProgIDs and value names are compared case-insensitively, like the registry does
+        match RegGetString(h_key, "") {
+            Ok(Some(default)) if default.eq_ignore_ascii_case(&id) => return Ok(Some(true)),
+            Ok(_) => (),
+            // Extensions whose default value isn't a string don't name a ProgID
+            Err(why) if why.kind() == io::ErrorKind::InvalidData => (),
+            Err(why) => return Err(why),
+        }
+        let Some(open_with_key) = RegOpenKey(h_key, "OpenWithProgids", raw::KEY_READ)? else {
+            return Ok(None);
+        };
+        let names = RegQueryValues(open_with_key);
+        raw::RegCloseKey(open_with_key);
+        Ok(names?
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&id))
+            .then_some(false))
+    })();
+    raw::RegCloseKey(h_key);
+    result
+}
+/// Associate a file extension with a ProgID by listing the ProgID under `OpenWithProgids` of the extension,
+/// and make the ProgID the default one for the extension if `default` is set.
+///
+/// Returns the ProgID which was the default one before, if it has been replaced. Both are the default value of the
+/// extension key, which a `UserChoice` overrides, see [GetDefaultProgID].
+///
+/// # Errors:
+/// - **[ERROR_FILE_NOT_FOUND](std::io::ErrorKind::NotFound)** if the ProgID doesn't exist.
+/// - **[ERROR_ACCESS_DENIED](std::io::ErrorKind::PermissionDenied)** if the program doesn't run as the Administrator, while the key requiring administrator rights for write access.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
 pub unsafe fn CreateFileTypeAssociation(
+    classes_key: isize,
     id: impl ToString,
     extension: impl ToString,
-) -> io::Result<()> {
+    default: bool,
+) -> io::Result<Option<String>> {
     let id = id.to_string();
     let extension = extension.to_string();
 
-    if RegOpenKeyExW(
-        raw::HKEY_CLASSES_ROOT,
-        StringToLPCWSTR(id.clone()),
-        0,
-        raw::KEY_READ,
-        ptr::null_mut(),
-    ) == 0
-    {
-        let h_key = RegCreateKey(Some(raw::HKEY_CLASSES_ROOT), extension)?;
-        RegWriteKey(h_key, "", raw::REG_SZ, StringToLPCWSTR(id) as *const u8)?;
-
-        raw::SHChangeNotify(
-            raw::SHCNE_ASSOCCHANGED,
-            raw::SHCNF_IDLIST,
-            ptr::null(),
-            ptr::null(),
-        );
+    match RegOpenKey(classes_key, &id, raw::KEY_READ)? {
+        Some(id_key) => raw::RegCloseKey(id_key),
+        None => return Err(io::Error::from_raw_os_error(raw::ERROR_FILE_NOT_FOUND)),
+    };
+
+    let h_key = RegCreateKey(Some(classes_key), extension)?;
+    let result = (|| {
+        let open_with_key = RegCreateKey(Some(h_key), "OpenWithProgids")?;
+        let result = RegSetValue(open_with_key, &id, raw::REG_NONE, &[]);
+        raw::RegCloseKey(open_with_key);
+        result?;
+
+        if default {
+            let previous = RegGetString(h_key, "")?;
+            RegSetString(h_key, "", &id)?;
+            Ok(previous.filter(|previous| !previous.is_empty() && previous != &id))
+        } else {
+            Ok(None)
+        }
+    })();
+    raw::RegCloseKey(h_key);
+
+    raw::SHChangeNotify(
+        raw::SHCNE_ASSOCCHANGED,
+        raw::SHCNF_IDLIST,
+        ptr::null(),
+        ptr::null(),
+    );
+    result
+}
+
+/// Stop a ProgID from handling a file extension, by removing it from `OpenWithProgids` of the extension
+/// and clearing the default value of the extension if it still refers to the ProgID.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn RemoveFileTypeAssociation(
+    classes_key: isize,
+    id: impl ToString,
+    extension: impl ToString,
+) -> io::Result<()> {
+    let id = id.to_string();
 
+    let Some(h_key) = RegOpenKey(classes_key, extension, raw::KEY_ALL_ACCESS)? else {
+        return Ok(());
+    };
+    let result = (|| {
+        if let Some(open_with_key) = RegOpenKey(h_key, "OpenWithProgids", raw::KEY_ALL_ACCESS)? {
+            let result = RegDeleteValue(open_with_key, &id);
+            raw::RegCloseKey(open_with_key);
+            result?;
+        }
+        if RegGetString(h_key, "")?.as_ref() == Some(&id) {
+            RegDeleteValue(h_key, "")?;
+        }
         Ok(())
+    })();
+    raw::RegCloseKey(h_key);
+
+    raw::SHChangeNotify(
+        raw::SHCNE_ASSOCCHANGED,
+        raw::SHCNF_IDLIST,
+        ptr::null(),
+        ptr::null(),
+    );
+    result
+}
+
+/// Replace verbs of a ProgID with `verbs`, written to `<classes key>\<ProgID>\shell`.
+///
+/// The name of the default verb is stored as the default value of the `shell` key.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn SetProgIDVerbs(
+    classes_key: isize,
+    id: impl ToString,
+    verbs: &[Verb],
+    default_verb: Option<&str>,
+) -> io::Result<()> {
+    let Some(id_key) = RegOpenKey(classes_key, id, raw::KEY_ALL_ACCESS)? else {
+        return Err(io::Error::from_raw_os_error(raw::ERROR_FILE_NOT_FOUND));
+    };
+    let result = (|| {
+        RegDeleteTree(id_key, "shell")?;
+        let shell_key = RegCreateKey(Some(id_key), "shell")?;
+        let result = (|| {
+            if let Some(default_verb) = default_verb {
+                RegSetString(shell_key, "", default_verb)?;
+            }
+            for verb in verbs {
+                let verb_key = RegCreateKey(Some(shell_key), &verb.name)?;
+                let result = (|| {
+                    if !verb.label.is_empty() {
+                        RegSetString(verb_key, "", &verb.label)?;
+                    }
+                    let command_key = RegCreateKey(Some(verb_key), "command")?;
+                    let result =
+                        RegSetString(command_key, "", verb.command.render(Syntax::Windows));
+                    raw::RegCloseKey(command_key);
+                    result
+                })();
+                raw::RegCloseKey(verb_key);
+                result?;
+            }
+            Ok(())
+        })();
+        raw::RegCloseKey(shell_key);
+        result
+    })();
+    raw::RegCloseKey(id_key);
+    result
+}
+
+/// Get verbs of a ProgID and the name of its default verb.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn GetProgIDVerbs(
+    classes_key: isize,
+    id: impl ToString,
+) -> io::Result<(Vec<Verb>, Option<String>)> {
+    let id = id.to_string();
+    let Some(shell_key) = RegOpenKey(classes_key, format!("{id}\\shell"), raw::KEY_READ)? else {
+        return Ok((Vec::new(), None));
+    };
+    let result = (|| {
+        let default_verb = RegGetString(shell_key, "")?.filter(|verb| !verb.is_empty());
+        let mut verbs = Vec::new();
+        for name in RegQueryKeys(shell_key)? {
+            // Verbs with a malformed command can't be launched anyway, so they're skipped
+            let Some(Ok(command)) = GetProgIDCommand(classes_key, &id, &name)?
+                .map(|command| CommandTemplate::parse(&command, Syntax::Windows))
+            else {
+                continue;
+            };
+            let label = match RegOpenKey(shell_key, &name, raw::KEY_READ)? {
+                Some(verb_key) => {
+                    let label = RegGetString(verb_key, "");
+                    raw::RegCloseKey(verb_key);
+                    label?.unwrap_or_default()
+                }
+                None => String::new(),
+            };
+            verbs.push(Verb {
+                name,
+                label,
+                command,
+            });
+        }
+        Ok((verbs, default_verb))
+    })();
+    raw::RegCloseKey(shell_key);
+    result
+}
+
+/// Get the command line of a verb of a ProgID, stored in `<classes key>\<ProgID>\shell\<verb>\command`.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn GetProgIDCommand(
+    classes_key: isize,
+    id: impl ToString,
+    verb: impl ToString,
+) -> io::Result<Option<String>> {
+    let path = format!("{}\\shell\\{}\\command", id.to_string(), verb.to_string());
+    match RegOpenKey(classes_key, path, raw::KEY_READ)? {
+        Some(h_key) => {
+            let command = RegGetString(h_key, "");
+            raw::RegCloseKey(h_key);
+            command
+        }
+        None => Ok(None),
+    }
+}
+
+/// Get the file extension registered for a MIME type under `<classes key>\MIME\Database\Content Type`.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn GetMimeTypeExtension(
+    classes_key: isize,
+    mime_type: impl ToString,
+) -> io::Result<Option<String>> {
+    let path = format!("MIME\\Database\\Content Type\\{}", mime_type.to_string());
+    match RegOpenKey(classes_key, path, raw::KEY_READ)? {
+        Some(h_key) => {
+            let extension = RegGetString(h_key, "Extension");
+            raw::RegCloseKey(h_key);
+            extension
+        }
+        None => Ok(None),
+    }
+}
+
+/// Read name, icon and verbs of any ProgID in `classes_key`, returning [None] if it doesn't exist.
+///
+/// Unlike [GetProcID], ProgIDs without a name are accepted. Their name is taken from the `FriendlyAppName` value
+/// used by `Applications\<executable>` keys, if there is one.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn ReadProgID(
+    classes_key: isize,
+    id: impl ToString,
+) -> io::Result<Option<ApplicationDescriptor>> {
+    let id = id.to_string();
+    let Some(id_key) = RegOpenKey(classes_key, &id, raw::KEY_READ)? else {
+        return Ok(None);
+    };
+    let result = (|| -> io::Result<_> {
+        let name = match RegGetString(id_key, "")?.filter(|name| !name.is_empty()) {
+            Some(name) => Some(name),
+            None => RegGetString(id_key, "FriendlyAppName")?,
+        };
+        let icon_path = match RegOpenKey(id_key, "DefaultIcon", raw::KEY_READ)? {
+            Some(icon_key) => {
+                let icon_path = RegGetString(icon_key, "");
+                raw::RegCloseKey(icon_key);
+                icon_path?
+            }
+            None => None,
+        };
+        Ok((name, icon_path))
+    })();
+    raw::RegCloseKey(id_key);
+    let (name, icon_path) = result?;
+    let (verbs, default_verb) = GetProgIDVerbs(classes_key, &id)?;
+
+    Ok(Some(ApplicationDescriptor {
+        id,
+        name: name.unwrap_or_default(),
+        icon_path: icon_path.unwrap_or_default(),
+        icon_images: Vec::new(),
+        verbs,
+        default_verb,
+    }))
+}
+
+/// Get the ProgID opening a file extension by default, in the order Explorer looks it up:
+/// 1. the `UserChoice` the user made through the "Open with" dialog, stored in
+///    `HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Explorer\FileExts\<extension>\UserChoice`;
+/// 2. the default value of `HKEY_CURRENT_USER\Software\Classes\<extension>`;
+/// 3. the default value of `HKEY_LOCAL_MACHINE\Software\Classes\<extension>`.
+///
+/// For [Scope::System], only the last one is looked up.
+///
+/// The `UserChoice` is protected by a hash only Explorer can compute, so the [RegistryBackend] never writes it.
+/// [Associating](Backend::associate) an application by default writes the default value of the extension key instead,
+/// and reports the value it replaced there. Neither takes effect for the user as long as a `UserChoice` overrides them.
+pub fn GetDefaultProgID(scope: Scope, extension: impl ToString) -> io::Result<Option<String>> {
+    let extension = extension.to_string();
+    let lookups = [
+        (
+            raw::HKEY_CURRENT_USER,
+            format!("Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\FileExts\\{extension}\\UserChoice"),
+            "ProgId",
+        ),
+        (
+            raw::HKEY_CURRENT_USER,
+            format!("Software\\Classes\\{extension}"),
+            "",
+        ),
+        (
+            raw::HKEY_LOCAL_MACHINE,
+            format!("Software\\Classes\\{extension}"),
+            "",
+        ),
+    ];
+
+    let skipped = match scope {
+        Scope::User => 0,
+        Scope::System => 2,
+    };
+    for (root, path, value_name) in lookups.into_iter().skip(skipped) {
+        // SAFETY: the roots are predefined keys, and the opened key is closed right after reading it
+        let id = unsafe {
+            let Some(h_key) = RegOpenKey(root, path, raw::KEY_READ)? else {
+                continue;
+            };
+            let id = RegGetString(h_key, value_name);
+            raw::RegCloseKey(h_key);
+            id
+        };
+        if let Some(id) = id?.filter(|id| !id.is_empty()) {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+/// Get the `PerceivedType` of a MIME type, which is its media type or the one of its parents if that's `text`, `image`, `audio` or `video`.
+fn PerceivedType(definition: &MimeTypeDefinition) -> Option<&'static str> {
+    std::iter::once(&definition.mime_type)
+        .chain(&definition.parents)
+        .find_map(|mime_type| match mime_type.media_type() {
+            "text" => Some("text"),
+            "image" => Some("image"),
+            "audio" => Some("audio"),
+            "video" => Some("video"),
+            _ => None,
+        })
+}
+
+/// Declare a MIME type: every extension of its `*.<extension>` globs gets the MIME type as `Content Type` and its `PerceivedType`,
+/// and `MIME\Database\Content Type\<MIME type>` points at the first extension.
+///
+/// # Errors:
+/// - **[ERROR_ACCESS_DENIED](std::io::ErrorKind::PermissionDenied)** if the program doesn't run as the Administrator, while the key requiring administrator rights for write access.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn RegisterMimeType(
+    classes_key: isize,
+    definition: &MimeTypeDefinition,
+) -> io::Result<()> {
+    let mime_type = definition.mime_type.essence();
+    let extensions = definition.extensions();
+    let perceived_type = PerceivedType(definition);
+
+    for extension in &extensions {
+        let h_key = RegCreateKey(Some(classes_key), extension)?;
+        let result = (|| {
+            RegSetString(h_key, "Content Type", mime_type)?;
+            match perceived_type {
+                Some(perceived_type) => RegSetString(h_key, "PerceivedType", perceived_type),
+                None => Ok(()),
+            }
+        })();
+        raw::RegCloseKey(h_key);
+        result?;
+    }
+    if let Some(extension) = extensions.first() {
+        let h_key = RegCreateKey(
+            Some(classes_key),
+            format!("MIME\\Database\\Content Type\\{mime_type}"),
+        )?;
+        let result = RegSetString(h_key, "Extension", extension);
+        raw::RegCloseKey(h_key);
+        result?;
+    }
+
+    raw::SHChangeNotify(
+        raw::SHCNE_ASSOCCHANGED,
+        raw::SHCNF_IDLIST,
+        ptr::null(),
+        ptr::null(),
+    );
+    Ok(())
+}
+
+/// Reverse [RegisterMimeType], leaving values which have been changed to another MIME type since untouched.
+///
+/// # Safety
+/// `classes_key` must be an open handle of a classes key, like the [CLASSES_KEY] of a hive, or [HKEY_CLASSES_ROOT](raw::HKEY_CLASSES_ROOT).
+pub unsafe fn UnregisterMimeType(
+    classes_key: isize,
+    definition: &MimeTypeDefinition,
+) -> io::Result<()> {
+    let mime_type = definition.mime_type.essence();
+    let extensions = definition.extensions();
+
+    for extension in &extensions {
+        let Some(h_key) = RegOpenKey(classes_key, extension, raw::KEY_ALL_ACCESS)? else {
+            continue;
+        };
+        let result = (|| -> io::Result<()> {
+            if RegGetString(h_key, "Content Type")?.as_deref() == Some(mime_type) {
+                RegDeleteValue(h_key, "Content Type")?;
+                if let Some(perceived_type) = PerceivedType(definition) {
+                    if RegGetString(h_key, "PerceivedType")?.as_deref() == Some(perceived_type) {
+                        RegDeleteValue(h_key, "PerceivedType")?;
+                    }
+                }
+            }
+            Ok(())
+        })();
+        raw::RegCloseKey(h_key);
+        result?;
+    }
+    if let Some(extension) = GetMimeTypeExtension(classes_key, mime_type)? {
+        if extensions.contains(&extension) {
+            RegDeleteTree(
+                classes_key,
+                format!("MIME\\Database\\Content Type\\{mime_type}"),
+            )?;
+        }
+    }
+
+    raw::SHChangeNotify(
+        raw::SHCNE_ASSOCCHANGED,
+        raw::SHCNF_IDLIST,
+        ptr::null(),
+        ptr::null(),
+    );
+    Ok(())
+}
+
+/// Get the verbs written for an application: the ones of `descriptor`, or a single `open` verb launching `executable` with the file.
+fn ProgIDVerbs<'a>(executable: &str, descriptor: &'a ApplicationDescriptor) -> Cow<'a, [Verb]> {
+    if descriptor.verbs.is_empty() {
+        Cow::Owned(vec![Verb {
+            name: String::from(VERB_OPEN),
+            label: String::new(),
+            command: CommandTemplate::new(executable).arg(Arg::File),
+        }])
     } else {
-        Err(io::Error::from_raw_os_error(2))
+        Cow::Borrowed(&descriptor.verbs)
+    }
+}
+
+/// [Backend] storing applications as ProgIDs in the `Software\Classes` key of the hive of its [Scope],
+/// `HKEY_CURRENT_USER` by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RegistryBackend {
+    scope: Scope,
+}
+impl RegistryBackend {
+    /// Create a backend registering applications for the current user.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Register applications for the current user or for every user of the machine, [Scope::User] by default.
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Run `f` with the [classes key](CLASSES_KEY) of `scope` opened with the access rights `sam`.
+    fn with_classes<T>(
+        scope: Scope,
+        sam: u32,
+        f: impl FnOnce(isize) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let Some(classes_key) = unsafe { RegOpenKey(ScopeRoot(scope), CLASSES_KEY, sam) }? else {
+            return Err(io::Error::from_raw_os_error(raw::ERROR_FILE_NOT_FOUND));
+        };
+        let result = f(classes_key);
+        unsafe { raw::RegCloseKey(classes_key) };
+        result
+    }
+    /// Run `f` with the classes key of the scope of the backend opened for writing.
+    fn write_classes<T>(&self, f: impl FnOnce(isize) -> io::Result<T>) -> io::Result<T> {
+        Self::with_classes(self.scope, raw::KEY_ALL_ACCESS, f)
+    }
+    /// Run `f` with the classes key of the scope of the backend opened for reading.
+    fn read_classes<T>(&self, f: impl FnOnce(isize) -> io::Result<T>) -> io::Result<T> {
+        Self::with_classes(self.scope, raw::KEY_READ, f)
+    }
+    /// Run `f` with the classes the backend looks up file types and default applications in:
+    /// `HKEY_CLASSES_ROOT`, which merges both scopes, for [Scope::User], and the classes key of [Scope::System] otherwise.
+    fn lookup_classes<T>(&self, f: impl FnOnce(isize) -> io::Result<T>) -> io::Result<T> {
+        match self.scope {
+            Scope::User => f(raw::HKEY_CLASSES_ROOT),
+            Scope::System => self.read_classes(f),
+        }
+    }
+    /// Get the extension a file type is associated through, looking up MIME types in the MIME database.
+    fn extension(&self, file_type: &FileType) -> io::Result<Option<String>> {
+        match file_type {
+            FileType::Extension(extension) => Ok(Some(extension.clone())),
+            FileType::MimeType(mime_type) => self.lookup_classes(|classes_key| unsafe {
+                GetMimeTypeExtension(classes_key, mime_type.essence())
+            }),
+        }
+    }
+}
+impl Backend for RegistryBackend {
+    fn scoped(&self, scope: Scope) -> Result<Arc<dyn Backend>> {
+        Ok(Arc::new(self.scope(scope)))
+    }
+    fn dry_run(&self, plan: &Plan) -> Result<Arc<dyn Backend>> {
+        Ok(Arc::new(dry_run::DryRunBackend::new(*self, plan)))
+    }
+    fn create(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        let verbs = ProgIDVerbs(executable, descriptor);
+        Ok(self.write_classes(|classes_key| unsafe {
+            CreateProgID(
+                classes_key,
+                &descriptor.id,
+                &descriptor.name,
+                &descriptor.icon_path,
+            )?;
+            SetProgIDVerbs(
+                classes_key,
+                &descriptor.id,
+                &verbs,
+                descriptor.default_verb.as_deref(),
+            )
+        })?)
+    }
+    fn get(&self, id: &str) -> Result<Option<ApplicationDescriptor>> {
+        Ok(self.read_classes(|classes_key| unsafe { ReadProgID(classes_key, id) })?)
+    }
+    fn executable(&self, id: &str) -> Result<Option<String>> {
+        Ok(self.get(id)?.and_then(|descriptor| {
+            descriptor
+                .primary_verb()
+                .map(|verb| verb.command.program().to_string())
+        }))
+    }
+    fn delete(&self, id: &str) -> Result<()> {
+        Ok(self.write_classes(|classes_key| unsafe { DeleteProcID(classes_key, id) })?)
+    }
+    fn enumerate(&self, id: &str) -> Result<Vec<String>> {
+        Ok(self.read_classes(|classes_key| unsafe {
+            EnumerateFileTypeAssociations(classes_key, id)
+        })?)
+    }
+    /// Reports the extensions of `HKEY_CURRENT_USER\Software\Classes` in [Scope::User] and the ones of
+    /// `HKEY_LOCAL_MACHINE\Software\Classes` in [Scope::System]. Backends of [Scope::System] only read the latter.
+    fn associations(&self, id: &str) -> Result<Vec<Association>> {
+        let scopes: &[Scope] = match self.scope {
+            Scope::User => &[Scope::User, Scope::System],
+            Scope::System => &[Scope::System],
+        };
+        let mut associations = Vec::new();
+        for &scope in scopes {
+            let extensions = Self::with_classes(scope, raw::KEY_READ, |classes_key| unsafe {
+                EnumerateFileTypeAssociations(classes_key, id)
+            })?;
+            associations.extend(
+                extensions
+                    .into_iter()
+                    .map(|file_type| Association { file_type, scope }),
+            );
+        }
+        Ok(associations)
+    }
+    /// Looks the extension up in the classes key of the scope, like [Backend::enumerate] does.
+    fn handles(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        let extension = self.extension(file_type)?.ok_or(Error::Unsupported)?;
+        let association = self.read_classes(|classes_key| unsafe {
+            GetFileTypeAssociation(classes_key, id, extension)
+        })?;
+        Ok(association.is_some())
+    }
+    /// Checks the default value of the extension key in the classes key of the scope, which a `UserChoice` overrides
+    /// for [Scope::User].
+    fn is_default_for(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        let extension = self.extension(file_type)?.ok_or(Error::Unsupported)?;
+        let association = self.read_classes(|classes_key| unsafe {
+            GetFileTypeAssociation(classes_key, id, extension)
+        })?;
+        Ok(association == Some(true))
+    }
+    /// Makes the application the default one through the default value of the extension key, and reports the value it
+    /// replaced there. A `UserChoice` of the extension overrides both for [Scope::User], and is left as it is, so
+    /// [Backend::default_application_for] keeps returning the application chosen in Explorer.
+    fn associate(&self, id: &str, file_type: &FileType, default: bool) -> Result<Option<String>> {
+        let extension = self.extension(file_type)?.ok_or(Error::Unsupported)?;
+        Ok(self.write_classes(|classes_key| unsafe {
+            CreateFileTypeAssociation(classes_key, id, extension, default)
+        })?)
+    }
+    fn dissociate(&self, id: &str, file_type: &FileType) -> Result<()> {
+        let extension = self.extension(file_type)?.ok_or(Error::Unsupported)?;
+        Ok(self.write_classes(|classes_key| unsafe {
+            RemoveFileTypeAssociation(classes_key, id, extension)
+        })?)
+    }
+    /// Windows has no packages, so the MIME types are declared one by one and `id` is ignored.
+    fn register_mime_package(&self, _id: &str, package: &MimePackage) -> Result<()> {
+        Ok(self.write_classes(|classes_key| {
+            for definition in &package.mime_types {
+                unsafe { RegisterMimeType(classes_key, definition) }?;
+            }
+            Ok(())
+        })?)
+    }
+    fn unregister_mime_package(&self, _id: &str, package: &MimePackage) -> Result<()> {
+        Ok(self.write_classes(|classes_key| {
+            for definition in &package.mime_types {
+                unsafe { UnregisterMimeType(classes_key, definition) }?;
+            }
+            Ok(())
+        })?)
+    }
+    /// Captures the ProgID keys of the applications and, for every file type as well as every extension the
+    /// applications are associated with, by default or through `OpenWithProgids`, the default value and
+    /// `OpenWithProgids` of its extension key.
+    /// The extensions are added to the file types of the snapshot.
+    fn snapshot(&self, ids: &[String], file_types: &[FileType]) -> Result<Snapshot> {
+        let mut snapshot = Snapshot::new(self.scope);
+        snapshot.file_types = file_types.to_vec();
+        self.read_classes(|classes_key| unsafe {
+            for id in ids {
+                snapshot.applications.push(id.clone());
+                SnapshotKey(classes_key, self.scope, id, &mut snapshot)?;
+                for extension in EnumerateFileTypeAssociations(classes_key, id)? {
+                    let file_type = FileType::Extension(extension);
+                    if !snapshot.file_types.contains(&file_type) {
+                        snapshot.file_types.push(file_type);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        for file_type in snapshot.file_types.clone() {
+            let extension = self.extension(&file_type)?.ok_or(Error::Unsupported)?;
+            self.read_classes(|classes_key| unsafe {
+                let Some(h_key) = RegOpenKey(classes_key, &extension, raw::KEY_READ)? else {
+                    snapshot.push(
+                        Target::RegistryKey(ClassesKeyPath(self.scope, &extension)),
+                        None,
+                    );
+                    return Ok(());
+                };
+                let value = RegGetValue(h_key, "");
+                raw::RegCloseKey(h_key);
+                snapshot.push(
+                    Target::RegistryKey(ClassesKeyPath(self.scope, &extension)),
+                    Some(Value::Empty),
+                );
+                snapshot.push(
+                    Target::RegistryValue {
+                        key: ClassesKeyPath(self.scope, &extension),
+                        name: String::new(),
+                    },
+                    value?.map(|(value_type, data)| ValueFromReg(value_type, data)),
+                );
+                SnapshotKey(
+                    classes_key,
+                    self.scope,
+                    &format!("{extension}\\OpenWithProgids"),
+                    &mut snapshot,
+                )
+            })?;
+        }
+        Ok(snapshot)
+    }
+    fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let backend = self.scope(snapshot.scope);
+        let prefix = ClassesKeyPath(snapshot.scope, "");
+        let mut extensions = Vec::new();
+        for file_type in &snapshot.file_types {
+            extensions.push(backend.extension(file_type)?.ok_or(Error::Unsupported)?);
+        }
+        // Every target is checked before anything is touched, so a snapshot which can't be restored changes nothing.
+        // Keys are restored relative to the classes key, which itself is never deleted.
+        let mut keys = Vec::with_capacity(snapshot.entries.len());
+        for entry in &snapshot.entries {
+            let path = match &entry.target {
+                Target::RegistryKey(path) | Target::RegistryValue { key: path, .. } => path,
+                Target::File(_) | Target::KeyFileEntry { .. } => return Err(Error::Unsupported),
+            };
+            match path.strip_prefix(&prefix) {
+                Some(key) if !key.is_empty() => keys.push(key),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{path}: outside the classes key of the snapshot"),
+                    )
+                    .into())
+                }
+            }
+        }
+
+        let result = backend.write_classes(|classes_key| unsafe {
+            // What the applications and file types got since the snapshot was taken is removed before the captured
+            // state is put back
+            for id in &snapshot.applications {
+                for extension in EnumerateFileTypeAssociations(classes_key, id)? {
+                    RemoveFileTypeAssociation(classes_key, id, extension)?;
+                }
+                RegDeleteTree(classes_key, id)?;
+            }
+            for extension in &extensions {
+                if let Some(h_key) = RegOpenKey(classes_key, extension, raw::KEY_ALL_ACCESS)? {
+                    let result = RegDeleteValue(h_key, "")
+                        .and_then(|()| RegDeleteTree(h_key, "OpenWithProgids"));
+                    raw::RegCloseKey(h_key);
+                    result?;
+                }
+            }
+
+            for (entry, key) in snapshot.entries.iter().zip(keys) {
+                match (&entry.target, &entry.value) {
+                    (Target::RegistryKey(_), Some(_)) => {
+                        raw::RegCloseKey(RegCreateKey(Some(classes_key), key)?);
+                    }
+                    (Target::RegistryKey(_), None) => RegDeleteTree(classes_key, key)?,
+                    (Target::RegistryValue { name, .. }, Some(value)) => {
+                        let h_key = RegCreateKey(Some(classes_key), key)?;
+                        let (value_type, data) = ValueToReg(value);
+                        let result = RegSetValue(h_key, name, value_type, &data);
+                        raw::RegCloseKey(h_key);
+                        result?;
+                    }
+                    (Target::RegistryValue { name, .. }, None) => {
+                        if let Some(h_key) = RegOpenKey(classes_key, key, raw::KEY_ALL_ACCESS)? {
+                            let result = RegDeleteValue(h_key, name);
+                            raw::RegCloseKey(h_key);
+                            result?;
+                        }
+                    }
+                    (Target::File(_) | Target::KeyFileEntry { .. }, _) => unreachable!(),
+                }
+            }
+            Ok(())
+        });
+        unsafe {
+            raw::SHChangeNotify(
+                raw::SHCNE_ASSOCCHANGED,
+                raw::SHCNF_IDLIST,
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        Ok(result?)
+    }
+    fn default_application_for(
+        &self,
+        file_type: &FileType,
+    ) -> Result<Option<ApplicationDescriptor>> {
+        let Some(extension) = self.extension(file_type)? else {
+            return Ok(None);
+        };
+        match GetDefaultProgID(self.scope, extension)? {
+            Some(id) => {
+                Ok(self.lookup_classes(|classes_key| unsafe { ReadProgID(classes_key, id) })?)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /// Keys created by a test under the classes key of [Scope::User], deleted when it's dropped, even if the test panics.
+    struct Cleanup(Vec<String>);
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            let _ = RegistryBackend::new().write_classes(|classes_key| {
+                for key in &self.0 {
+                    let _ = unsafe { RegDeleteTree(classes_key, key) };
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Registers a test application in the real registry of the current user, so it only runs when asked to.
+    #[test]
+    #[ignore]
+    fn associations() {
+        let backend = RegistryBackend::new();
+        let id = format!("fileext.test.associations.{}", process::id());
+        let default = format!(".fileext-test-{}-default", process::id());
+        let listed = format!(".fileext-test-{}-listed", process::id());
+        let _cleanup = Cleanup(vec![id.clone(), default.clone(), listed.clone()]);
+        let descriptor = ApplicationDescriptor {
+            id: id.clone(),
+            name: String::from("Test Application"),
+            ..Default::default()
+        };
+
+        backend
+            .create("C:\\Program Files\\Test\\test.exe", &descriptor)
+            .unwrap();
+        backend
+            .associate(&id, &FileType::Extension(default.clone()), true)
+            .unwrap();
+        backend
+            .associate(&id, &FileType::Extension(listed.clone()), false)
+            .unwrap();
+        let mut enumerated = backend.enumerate(&id).unwrap();
+        enumerated.sort();
+        assert_eq!(enumerated, vec![default.clone(), listed.clone()]);
+        assert!(backend.associations(&id).unwrap().contains(&Association {
+            file_type: listed.clone(),
+            scope: Scope::User,
+        }));
+
+        backend
+            .dissociate(&id, &FileType::Extension(listed.clone()))
+            .unwrap();
+        assert_eq!(backend.enumerate(&id).unwrap(), vec![default.clone()]);
+
+        backend.delete(&id).unwrap();
+        assert_eq!(backend.get(&id).unwrap(), None);
     }
 }
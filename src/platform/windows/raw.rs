@@ -13,23 +13,21 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with fileext. If not, see <http://www.gnu.org/licenses/>.
 
-use core::ffi::{c_long as long, c_ulong as ulong, c_void as void};
-
-macro_rules! const_hkey {
-    ($name:ident, $address:expr) => {
-        #[allow(missing_docs)]
-        pub const $name: *const void = (&($address as ulong) as *const ulong) as *const void;
-    };
-}
+use core::ffi::{c_long as long, c_void as void};
 
 /// Default sublanguage code
 pub const SUBLANG_DEFAULT: i16 = 1;
 
 // * Root keys
+/// File types and ProgIDs of the current user merged with the ones of the machine.
 pub const HKEY_CLASSES_ROOT: isize = -2147483648;
+/// Configuration of the machine.
 pub const HKEY_LOCAL_MACHINE: isize = -2147483646;
+/// Configuration of the current user.
 pub const HKEY_CURRENT_USER: isize = -2147483647;
+/// Configuration of every user loaded on the machine.
 pub const HKEY_USERS: isize = -2147483645;
+/// Hardware profile the machine currently runs with.
 pub const HKEY_CURRENT_CONFIG: isize = -2147483643;
 // * Registry options
 /// If this flag is set, the function ignores the `samDesired` parameter and attempts to open the key with the access required to backup (or restore) the key.
@@ -84,6 +82,8 @@ pub const REG_NONE: u32 = 0;
 pub const REG_SZ: u32 = 1;
 /// A Null-terminated unicode string which might contain unexpanded references to environment variables
 pub const REG_EXPAND_SZ: u32 = 2;
+/// Binary data in any form
+pub const REG_BINARY: u32 = 3;
 /// A 32-bit number
 pub const REG_DWORD: u32 = REG_DWORD_LITTLE_ENDIAN;
 /// A 32-bit number in little-endian format
@@ -104,30 +104,52 @@ pub const REG_RESOURCE_REQUIREMENTS_LIST: u32 = 10;
 pub const REG_QWORD: u32 = REG_QWORD_LITTLE_ENDIAN;
 /// A 64-bit number in little-endian format
 pub const REG_QWORD_LITTLE_ENDIAN: u32 = 11;
+// * System error codes
+/// The system cannot find the file specified.
+pub const ERROR_FILE_NOT_FOUND: i32 = 2;
+/// Access is denied.
+pub const ERROR_ACCESS_DENIED: i32 = 5;
+/// More data is available.
+pub const ERROR_MORE_DATA: i32 = 234;
 // * FormatMessage flags
+/// Allocate the buffer of the message, which has to be freed with `LocalFree`.
 pub const FORMAT_MESSAGE_ALLOCATE_BUFFER: u32 = 0x00000100;
+/// The arguments are an array of values rather than a `va_list`.
 pub const FORMAT_MESSAGE_ARGUMENT_ARRAY: u32 = 0x00002000;
+/// Look the message up in the module passed as the source.
 pub const FORMAT_MESSAGE_FROM_HMODULE: u32 = 0x00000800;
+/// The source is the message itself.
 pub const FORMAT_MESSAGE_FROM_STRING: u32 = 0x00000400;
+/// Look the message up in the messages of the system.
 pub const FORMAT_MESSAGE_FROM_SYSTEM: u32 = 0x00001000;
+/// Keep insert sequences like `%1` in the message as they are.
 pub const FORMAT_MESSAGE_IGNORE_INSERTS: u32 = 0x00000200;
 // * SHChangeNotify event types
+/// A file type association has changed.
 pub const SHCNE_ASSOCCHANGED: i32 = 0x08000000;
 // * SHChangeNotify flags
+/// The items of the event are item ID lists.
 pub const SHCNF_IDLIST: u32 = 0;
 
+/// Security descriptor of an object, and whether child processes inherit its handle.
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct SECURITY_ATTRIBUTES {
+    /// Size of the structure in bytes.
     pub nLength: u32,
+    /// Security descriptor, or null for the default one.
     pub lpSecurityDescriptor: *const void,
+    /// Whether child processes inherit the handle.
     pub bInheritHandle: bool,
 }
 
+/// Number of 100-nanosecond intervals since January 1, 1601 (UTC).
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct FILETIME {
+    /// Low 32 bits of the time.
     pub dwLowDateTime: u32,
+    /// High 32 bits of the time.
     pub dwHighDateTime: u32,
 }
 
@@ -152,6 +174,7 @@ extern "system" {
         phkResult: *mut isize,
         lpdwDisposition: *mut u32,
     ) -> long;
+    /// Opens the specified registry key.
     pub fn RegOpenKeyExW(
         h_key: isize,
         lpSubKey: *const u16,
@@ -159,6 +182,7 @@ extern "system" {
         samDesired: u32,
         phkResult: *mut isize,
     ) -> long;
+    /// Retrieves the number and maximum name lengths of the subkeys and values of the specified key, among others.
     pub fn RegQueryInfoKeyW(
         h_key: isize,
         lpClass: *mut u16,
@@ -173,6 +197,7 @@ extern "system" {
         lpcbSecurityDescriptor: *mut u32,
         lpftLastWriteTime: *mut FILETIME,
     ) -> i32;
+    /// Retrieves the name of the subkey at index `dwIndex` of the specified key.
     pub fn RegEnumKeyExW(
         h_key: isize,
         dwIndex: u32,
@@ -183,7 +208,19 @@ extern "system" {
         lpcchClass: *mut u32,
         lpftLastWriteTime: *mut FILETIME,
     ) -> i32;
+    /// Retrieves the name and optionally the type and data of the value at index `dwIndex` of the specified key.
+    pub fn RegEnumValueW(
+        h_key: isize,
+        dwIndex: u32,
+        lpValueName: *mut u16,
+        lpcchValueName: *mut u32,
+        lpReserved: *mut u32,
+        lpType: *mut u32,
+        lpData: *mut u8,
+        lpcbData: *mut u32,
+    ) -> i32;
 
+    /// Sets the data and type of a value of the specified key.
     pub fn RegSetValueExW(
         hKey: isize,
         lpValueName: *const u16,
@@ -192,6 +229,7 @@ extern "system" {
         lpData: *const u8,
         cbData: u32,
     ) -> long;
+    /// Retrieves the type and data of a value of the specified key.
     pub fn RegQueryValueExW(
         h_key: isize,
         lpValueName: *const u16,
@@ -206,6 +244,11 @@ extern "system" {
     /// ## Errors:
     /// - [ERROR_INVALID_HANDLE](https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-#ERROR_INVALID_HANDLE) if the specified key handle is invalid or the key no longer exists.
     pub fn RegCloseKey(h_key: isize) -> long;
+    /// Deletes a subkey of the specified key together with all of its subkeys and values.
+    pub fn RegDeleteTreeW(h_key: isize, lpSubKey: *const u16) -> long;
+    /// Deletes a value of the specified key.
+    pub fn RegDeleteValueW(h_key: isize, lpValueName: *const u16) -> long;
+    /// Deletes a subkey of the specified key from the registry view `samDesired` selects. The subkey mustn't have subkeys.
     pub fn RegDeleteKeyExW(
         h_key: isize,
         lpSubKey: *const u16,
@@ -224,9 +267,9 @@ extern "system" {
     /// # fn main() {
     /// let string = String::from("Hello, world!");
     /// let utf16 = string
-    /// 	.encode_utf16()
-    /// 	.collect::<Vec<u16>>()
-    /// 	.as_ptr(); // Turn UTF-8 string into an LPCWSTR
+    ///     .encode_utf16()
+    ///     .collect::<Vec<u16>>()
+    ///     .as_ptr(); // Turn UTF-8 string into an LPCWSTR
     ///
     /// let len = unsafe { lstrlenW(utf16) } as usize;
     ///
@@ -238,6 +281,7 @@ extern "system" {
     ///
     /// Read ["System Error Codes" article on MSDN](https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-) for the list of errors Win32 API methods can throw.
     pub fn GetLastError() -> u32;
+    /// Formats a message, e.g. the description of a system error code.
     pub fn FormatMessageW(
         dwFlags: u32,
         lpSource: *const void,
@@ -251,5 +295,6 @@ extern "system" {
 
 #[link(name = "Shell32")]
 extern "system" {
+    /// Notifies the shell of an event, e.g. [SHCNE_ASSOCCHANGED] for a changed file type association.
     pub fn SHChangeNotify(wEventId: long, uFlags: u32, dwItem1: *const void, dwItem2: *const void);
 }
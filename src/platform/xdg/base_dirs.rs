@@ -0,0 +1,186 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Resolution of the [XDG Base Directories](https://specifications.freedesktop.org/basedir-spec/latest/)
+//! and of the `mimeapps.list` files found in them, from the environment of the process or from an explicit set of variables.
+use super::MIMEAPPS_LIST;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+/// Default value of `$XDG_DATA_DIRS`.
+pub const DEFAULT_DATA_DIRS: &str = "/usr/local/share:/usr/share";
+/// Default value of `$XDG_CONFIG_DIRS`.
+pub const DEFAULT_CONFIG_DIRS: &str = "/etc/xdg";
+
+/// Base directories files are looked up in, and the desktop environments whose `mimeapps.list` files take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BaseDirs {
+    /// Base directory user-specific data files are written to, `$XDG_DATA_HOME`.
+    pub data_home: PathBuf,
+    /// Base directory user-specific configuration files are written to, `$XDG_CONFIG_HOME`.
+    pub config_home: PathBuf,
+    /// Preference-ordered base directories data files are searched for after the data home, `$XDG_DATA_DIRS`.
+    pub data_dirs: Vec<PathBuf>,
+    /// Preference-ordered base directories configuration files are searched for after the config home, `$XDG_CONFIG_DIRS`.
+    pub config_dirs: Vec<PathBuf>,
+    /// Names of the current desktop environment, from the most important one to the least important one, `$XDG_CURRENT_DESKTOP`.
+    pub current_desktops: Vec<String>,
+}
+impl BaseDirs {
+    /// Resolve the base directories from the environment of the process.
+    pub fn from_env() -> Self {
+        Self::from_vars(env::vars_os())
+    }
+    /// Resolve the base directories from `vars` instead of the environment of the process, e.g. to get the same result
+    /// in tests on every machine. Variables missing from `vars` get their default values.
+    ///
+    /// - `$XDG_DATA_HOME` defaults to `$HOME/.local/share`, and `$XDG_CONFIG_HOME` to `$HOME/.config`.
+    /// - `$XDG_DATA_DIRS` defaults to [DEFAULT_DATA_DIRS], and `$XDG_CONFIG_DIRS` to [DEFAULT_CONFIG_DIRS].
+    /// - Relative paths are ignored, as required by the specification.
+    pub fn from_vars<K, V>(vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<OsString>,
+        V: Into<OsString>,
+    {
+        let vars: HashMap<OsString, OsString> = vars
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        let var = |name: &str| vars.get(OsStr::new(name)).cloned();
+        let home = var("HOME").map(PathBuf::from).unwrap_or_default();
+        let home_dir = |name: &str, default: &str| {
+            var(name)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .unwrap_or_else(|| home.join(default))
+        };
+
+        Self {
+            data_home: home_dir("XDG_DATA_HOME", ".local/share"),
+            config_home: home_dir("XDG_CONFIG_HOME", ".config"),
+            data_dirs: split_dirs(var("XDG_DATA_DIRS"), DEFAULT_DATA_DIRS),
+            config_dirs: split_dirs(var("XDG_CONFIG_DIRS"), DEFAULT_CONFIG_DIRS),
+            current_desktops: var("XDG_CURRENT_DESKTOP")
+                .map(|desktops| {
+                    desktops
+                        .to_string_lossy()
+                        .split(':')
+                        .filter(|desktop| !desktop.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Get every `applications` directory desktop entries are looked up in, from the most important one to the least important one.
+    pub fn applications_dirs(&self) -> Vec<PathBuf> {
+        std::iter::once(&self.data_home)
+            .chain(&self.data_dirs)
+            .map(|dir| dir.join("applications"))
+            .collect()
+    }
+    /// Get every `mime` directory the MIME database is loaded from, from the most important one to the least important one.
+    pub fn mime_dirs(&self) -> Vec<PathBuf> {
+        std::iter::once(&self.data_home)
+            .chain(&self.data_dirs)
+            .map(|dir| dir.join("mime"))
+            .collect()
+    }
+    /// Get the names of the `mimeapps.list` files of a directory, from the most important one to the least important one:
+    /// `$desktop-mimeapps.list` for every [current desktop](BaseDirs::current_desktops) in lowercase, then `mimeapps.list`.
+    pub fn mimeapps_list_names(&self) -> Vec<String> {
+        self.current_desktops
+            .iter()
+            .map(|desktop| format!("{}-{MIMEAPPS_LIST}", desktop.to_lowercase()))
+            .chain(std::iter::once(MIMEAPPS_LIST.to_string()))
+            .collect()
+    }
+    /// Get every `mimeapps.list` file associations are looked up in, from the most important one to the least important one,
+    /// as described by the [Association between MIME types and applications](https://specifications.freedesktop.org/mime-apps-spec/latest/)
+    /// specification: the [named lists](BaseDirs::mimeapps_list_names) of the config home, the config directories,
+    /// and the [applications directories](BaseDirs::applications_dirs), in that order.
+    pub fn mimeapps_lists(&self) -> Vec<PathBuf> {
+        let names = self.mimeapps_list_names();
+        std::iter::once(&self.config_home)
+            .chain(&self.config_dirs)
+            .cloned()
+            .chain(self.applications_dirs())
+            .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+            .collect()
+    }
+}
+
+fn split_dirs(value: Option<OsString>, default: &str) -> Vec<PathBuf> {
+    let value = value
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| default.into());
+    env::split_paths(&value)
+        .filter(|path| path.is_absolute())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_vars() {
+        let dirs = BaseDirs::from_vars([("HOME", "/home/user"), ("XDG_DATA_HOME", "relative")]);
+        assert_eq!(dirs.data_home, PathBuf::from("/home/user/.local/share"));
+        assert_eq!(dirs.config_home, PathBuf::from("/home/user/.config"));
+        assert_eq!(
+            dirs.data_dirs,
+            [
+                PathBuf::from("/usr/local/share"),
+                PathBuf::from("/usr/share")
+            ]
+        );
+        assert_eq!(dirs.config_dirs, [PathBuf::from("/etc/xdg")]);
+        assert!(dirs.current_desktops.is_empty());
+
+        let dirs = BaseDirs::from_vars([
+            ("XDG_CONFIG_HOME", "/config"),
+            ("XDG_CONFIG_DIRS", "/etc/xdg/kde:relative:/etc/xdg"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_DATA_DIRS", "/usr/share"),
+            ("XDG_CURRENT_DESKTOP", "KDE::Plasma"),
+        ]);
+        assert_eq!(dirs.current_desktops, ["KDE", "Plasma"]);
+        assert_eq!(
+            dirs.mimeapps_lists(),
+            [
+                "/config/kde-mimeapps.list",
+                "/config/plasma-mimeapps.list",
+                "/config/mimeapps.list",
+                "/etc/xdg/kde/kde-mimeapps.list",
+                "/etc/xdg/kde/plasma-mimeapps.list",
+                "/etc/xdg/kde/mimeapps.list",
+                "/etc/xdg/kde-mimeapps.list",
+                "/etc/xdg/plasma-mimeapps.list",
+                "/etc/xdg/mimeapps.list",
+                "/data/applications/kde-mimeapps.list",
+                "/data/applications/plasma-mimeapps.list",
+                "/data/applications/mimeapps.list",
+                "/usr/share/applications/kde-mimeapps.list",
+                "/usr/share/applications/plasma-mimeapps.list",
+                "/usr/share/applications/mimeapps.list",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
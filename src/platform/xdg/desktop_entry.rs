@@ -0,0 +1,347 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Model of desktop entry files, as described by the [Desktop Entry Specification](https://specifications.freedesktop.org/desktop-entry-spec/latest/).
+//!
+//! Like [MimeAppsList](super::mimeapps::MimeAppsList), a [DesktopEntry] keeps comments, unknown groups and keys
+//! (such as the `X-` extensions of desktop environments) and the order of everything,
+//! so an entry which hasn't been changed serializes to exactly the bytes it was parsed from.
+use super::key_file::KeyFile;
+use super::DESKTOP_ENTRY_GROUP;
+use std::env;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::Path;
+
+/// Locale of localized keys such as `Name[de_DE]`, in the `lang_COUNTRY@MODIFIER` form of the Desktop Entry Specification.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Locale {
+    /// Language, e.g. `sr`.
+    pub language: String,
+    /// Country, e.g. `RS`.
+    pub country: Option<String>,
+    /// Modifier, e.g. `latin`.
+    pub modifier: Option<String>,
+}
+impl Locale {
+    /// Parse a POSIX locale name like `sr_RS.UTF-8@latin`. The encoding is ignored, since keys are always UTF-8.
+    ///
+    /// Returns [None] for an empty name and for the `C` and `POSIX` locales, which have no localized keys.
+    pub fn parse(locale: &str) -> Option<Self> {
+        let (rest, modifier) = match locale.split_once('@') {
+            Some((rest, modifier)) => (rest, Some(modifier)),
+            None => (locale, None),
+        };
+        let rest = rest.split_once('.').map_or(rest, |(rest, _)| rest);
+        let (language, country) = match rest.split_once('_') {
+            Some((language, country)) => (language, Some(country)),
+            None => (rest, None),
+        };
+        if language.is_empty() || language == "C" || language == "POSIX" {
+            return None;
+        }
+
+        let non_empty = |part: Option<&str>| part.filter(|part| !part.is_empty()).map(String::from);
+        Some(Self {
+            language: language.to_string(),
+            country: non_empty(country),
+            modifier: non_empty(modifier),
+        })
+    }
+    /// Get the locale messages are displayed in: the first one of `$LC_ALL`, `$LC_MESSAGES` and `$LANG` which is set.
+    pub fn current() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|variable| env::var(variable).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+    }
+    /// Get the locale suffixes of keys matching this locale, from the best match to the worst one:
+    /// `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`, `lang@MODIFIER` and `lang`, skipping the ones using missing parts.
+    pub fn candidates(&self) -> Vec<String> {
+        let language = &self.language;
+        let mut candidates = Vec::with_capacity(4);
+        if let (Some(country), Some(modifier)) = (&self.country, &self.modifier) {
+            candidates.push(format!("{language}_{country}@{modifier}"));
+        }
+        if let Some(country) = &self.country {
+            candidates.push(format!("{language}_{country}"));
+        }
+        if let Some(modifier) = &self.modifier {
+            candidates.push(format!("{language}@{modifier}"));
+        }
+        candidates.push(language.clone());
+        candidates
+    }
+}
+impl Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.language)?;
+        if let Some(country) = &self.country {
+            write!(f, "_{country}")?;
+        }
+        if let Some(modifier) = &self.modifier {
+            write!(f, "@{modifier}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Contents of a desktop entry file.
+///
+/// Values are unescaped when read and escaped when written, and lists are split on and joined with `;`.
+/// Only the first group of each name is looked at and changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopEntry {
+    file: KeyFile,
+}
+impl DesktopEntry {
+    /// Create an empty desktop entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Parse the contents of a desktop entry file. Lines which aren't valid are kept as they are.
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            file: KeyFile::parse(contents),
+        }
+    }
+    /// Read a desktop entry file.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: KeyFile::read(path.as_ref())?,
+        })
+    }
+    /// Write the desktop entry to a file, replacing it atomically. Missing parent directories are created.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.file.write(path.as_ref())
+    }
+
+    /// Get the underlying key file.
+    pub(super) fn key_file(&self) -> &KeyFile {
+        &self.file
+    }
+    /// Get names of all groups, in the order of the file.
+    pub fn groups(&self) -> Vec<String> {
+        self.file.groups().into_iter().map(String::from).collect()
+    }
+    /// Get keys of a group, in the order of the file. Localized keys are listed with their locale, e.g. `Name[de]`.
+    pub fn keys(&self, group: &str) -> Vec<String> {
+        self.file
+            .keys(group)
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Get a string value.
+    pub fn get(&self, group: &str, key: &str) -> Option<String> {
+        self.file.get(group, key)
+    }
+    /// Get a localized string value: the value of the key with the best matching [locale suffix](Locale::candidates),
+    /// or the unlocalized value if no localized one matches or `locale` is [None].
+    pub fn get_localized(&self, group: &str, key: &str, locale: Option<&Locale>) -> Option<String> {
+        locale
+            .into_iter()
+            .flat_map(Locale::candidates)
+            .find_map(|suffix| self.file.get(group, &format!("{key}[{suffix}]")))
+            .or_else(|| self.file.get(group, key))
+    }
+    /// Get a list value, which is empty if the key doesn't exist.
+    pub fn get_list(&self, group: &str, key: &str) -> Vec<String> {
+        self.file.get_list(group, key).unwrap_or_default()
+    }
+    /// Get a localized list value, looked up like [DesktopEntry::get_localized].
+    pub fn get_localized_list(
+        &self,
+        group: &str,
+        key: &str,
+        locale: Option<&Locale>,
+    ) -> Vec<String> {
+        locale
+            .into_iter()
+            .flat_map(Locale::candidates)
+            .find_map(|suffix| self.file.get_list(group, &format!("{key}[{suffix}]")))
+            .or_else(|| self.file.get_list(group, key))
+            .unwrap_or_default()
+    }
+    /// Get a boolean value, returning [None] if the key doesn't exist or its value isn't `true` or `false`.
+    pub fn get_bool(&self, group: &str, key: &str) -> Option<bool> {
+        match self.file.get_raw(group, key)? {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Set a string value. An existing key is changed in place, a new one is added after the last key of the group.
+    pub fn set(&mut self, group: &str, key: &str, value: &str) {
+        self.file.set(group, key, value);
+    }
+    /// Set a string value for a locale, e.g. `Name[de]` for `locale` `de`.
+    pub fn set_localized(&mut self, group: &str, key: &str, locale: &str, value: &str) {
+        self.file.set(group, &format!("{key}[{locale}]"), value);
+    }
+    /// Set a list value.
+    pub fn set_list(&mut self, group: &str, key: &str, values: &[impl AsRef<str>]) {
+        self.file.set_list(group, key, values);
+    }
+    /// Set a list value for a locale.
+    pub fn set_localized_list(
+        &mut self,
+        group: &str,
+        key: &str,
+        locale: &str,
+        values: &[impl AsRef<str>],
+    ) {
+        self.file
+            .set_list(group, &format!("{key}[{locale}]"), values);
+    }
+    /// Set a boolean value.
+    pub fn set_bool(&mut self, group: &str, key: &str, value: bool) {
+        self.file
+            .set_raw(group, key, if value { "true" } else { "false" });
+    }
+    /// Remove a key, returning whether it existed. Localized variants of the key are kept.
+    pub fn remove(&mut self, group: &str, key: &str) -> bool {
+        self.file.remove(group, key)
+    }
+    /// Remove a group with all of its keys, returning whether it existed.
+    pub fn remove_group(&mut self, group: &str) -> bool {
+        self.file.remove_group(group)
+    }
+
+    /// Get the `Type` of the entry, e.g. `Application`.
+    pub fn entry_type(&self) -> Option<String> {
+        self.get(DESKTOP_ENTRY_GROUP, "Type")
+    }
+    /// Get the localized `Name` of the entry.
+    pub fn name(&self, locale: Option<&Locale>) -> Option<String> {
+        self.get_localized(DESKTOP_ENTRY_GROUP, "Name", locale)
+    }
+    /// Get the localized `GenericName` of the entry, e.g. "Web Browser".
+    pub fn generic_name(&self, locale: Option<&Locale>) -> Option<String> {
+        self.get_localized(DESKTOP_ENTRY_GROUP, "GenericName", locale)
+    }
+    /// Get the localized `Comment` of the entry, which is a tooltip describing it.
+    pub fn comment(&self, locale: Option<&Locale>) -> Option<String> {
+        self.get_localized(DESKTOP_ENTRY_GROUP, "Comment", locale)
+    }
+    /// Get the localized `Keywords` of the entry, used for searching.
+    pub fn keywords(&self, locale: Option<&Locale>) -> Vec<String> {
+        self.get_localized_list(DESKTOP_ENTRY_GROUP, "Keywords", locale)
+    }
+    /// Get the localized `Icon` of the entry, which is either an absolute path or a name of an icon in the icon theme.
+    pub fn icon(&self, locale: Option<&Locale>) -> Option<String> {
+        self.get_localized(DESKTOP_ENTRY_GROUP, "Icon", locale)
+    }
+    /// Get the `Exec` command line of the entry.
+    pub fn exec(&self) -> Option<String> {
+        self.get(DESKTOP_ENTRY_GROUP, "Exec")
+    }
+    /// Get the `MimeType` list of the entry.
+    pub fn mime_types(&self) -> Vec<String> {
+        self.get_list(DESKTOP_ENTRY_GROUP, "MimeType")
+    }
+    /// Get the `Actions` list of the entry, naming its `[Desktop Action <name>]` groups.
+    pub fn actions(&self) -> Vec<String> {
+        self.get_list(DESKTOP_ENTRY_GROUP, "Actions")
+    }
+    /// Check whether the entry is `Hidden`, which means it has been deleted.
+    pub fn is_hidden(&self) -> bool {
+        self.get_bool(DESKTOP_ENTRY_GROUP, "Hidden") == Some(true)
+    }
+}
+impl Display for DesktopEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.file.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = "[Desktop Entry]\nType=Application\nName=Text Editor\nName[de]=Texteditor\nName[sr@latin]=Uređivač teksta\nName[sr_RS]=Уређивач текста\nComment=Edit\\stext\\nfiles\nKeywords=text;plain\\;text;\nKeywords[de]=Text;\nIcon=editor\nX-GNOME-UsesNotifications=true\nHidden=false\n";
+
+    fn locale(name: &str) -> Option<Locale> {
+        Locale::parse(name)
+    }
+
+    #[test]
+    fn locales() {
+        let serbian = locale("sr_RS.UTF-8@latin").unwrap();
+        assert_eq!(serbian.to_string(), "sr_RS@latin");
+        assert_eq!(
+            serbian.candidates(),
+            vec!["sr_RS@latin", "sr_RS", "sr@latin", "sr"]
+        );
+        assert_eq!(locale("de.UTF-8").unwrap().candidates(), vec!["de"]);
+        assert_eq!(locale("C.UTF-8"), None);
+        assert_eq!(locale("POSIX"), None);
+        assert_eq!(locale(""), None);
+    }
+
+    #[test]
+    fn localized() {
+        let entry = DesktopEntry::parse(ENTRY);
+        assert_eq!(entry.to_string(), ENTRY);
+        assert_eq!(entry.entry_type().as_deref(), Some("Application"));
+        assert_eq!(entry.name(None).as_deref(), Some("Text Editor"));
+        assert_eq!(
+            entry.name(locale("de_AT").as_ref()).as_deref(),
+            Some("Texteditor")
+        );
+        assert_eq!(
+            entry.name(locale("sr_RS@latin").as_ref()).as_deref(),
+            Some("Уређивач текста")
+        );
+        assert_eq!(
+            entry.name(locale("sr_ME@latin").as_ref()).as_deref(),
+            Some("Uređivač teksta")
+        );
+        assert_eq!(
+            entry.name(locale("fr_FR").as_ref()).as_deref(),
+            Some("Text Editor")
+        );
+        assert_eq!(entry.comment(None).as_deref(), Some("Edit text\nfiles"));
+        assert_eq!(entry.keywords(None), vec!["text", "plain;text"]);
+        assert_eq!(entry.keywords(locale("de").as_ref()), vec!["Text"]);
+        assert_eq!(entry.icon(locale("de").as_ref()).as_deref(), Some("editor"));
+        assert_eq!(entry.generic_name(None), None);
+        assert!(!entry.is_hidden());
+    }
+
+    #[test]
+    fn edit() {
+        let mut entry = DesktopEntry::parse(ENTRY);
+        entry.set_localized(DESKTOP_ENTRY_GROUP, "Name", "fr", "Éditeur de texte");
+        entry.set_localized_list(DESKTOP_ENTRY_GROUP, "Keywords", "fr", &["texte"]);
+        entry.set(DESKTOP_ENTRY_GROUP, "Comment", " Edit\ttext");
+        entry.set_bool(DESKTOP_ENTRY_GROUP, "Hidden", true);
+        entry.set_list("Desktop Action new", "Name", &["New;Window"]);
+        assert!(entry.remove(DESKTOP_ENTRY_GROUP, "Icon"));
+        assert_eq!(
+            entry.to_string(),
+            "[Desktop Entry]\nType=Application\nName=Text Editor\nName[de]=Texteditor\nName[sr@latin]=Uređivač teksta\nName[sr_RS]=Уређивач текста\nComment=\\sEdit\\ttext\nKeywords=text;plain\\;text;\nKeywords[de]=Text;\nX-GNOME-UsesNotifications=true\nHidden=true\nName[fr]=Éditeur de texte\nKeywords[fr]=texte;\n\n[Desktop Action new]\nName=New\\;Window;\n"
+        );
+        assert!(entry.is_hidden());
+        assert_eq!(
+            entry.get_bool(DESKTOP_ENTRY_GROUP, "X-GNOME-UsesNotifications"),
+            Some(true)
+        );
+        assert_eq!(entry.groups(), vec!["Desktop Entry", "Desktop Action new"]);
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! [Dry runs](crate::app::OpenOptions::dry_run) of the [XdgBackend].
+//!
+//! The backend runs against a copy of the files it writes to, made in a temporary directory, and every change of
+//! the copy is recorded as a change of the file it was copied from. Later changes see the earlier ones in the copy,
+//! and so do reads.
+use super::{without, XdgBackend};
+use crate::app::{ApplicationDescriptor, FileType};
+use crate::backend::{Association, Backend, Scope};
+use crate::mime::MimePackage;
+use crate::plan::{Change, Plan, Target, Value};
+use crate::result::{Error, Result};
+use crate::snapshot::Snapshot;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::{env, fs, io, process};
+
+static SANDBOXES: AtomicUsize = AtomicUsize::new(0);
+
+/// [Backend] running an [XdgBackend] against a temporary copy of the files it writes to, recording their changes.
+#[derive(Debug)]
+pub(super) struct DryRunBackend {
+    sandbox: XdgBackend,
+    root: PathBuf,
+    /// Files and directories the backend writes to, paired with their copies.
+    copies: Vec<(PathBuf, PathBuf)>,
+    /// Contents of the copied files after the last change, by the path of the file they were copied from.
+    files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+    plan: Plan,
+}
+impl DryRunBackend {
+    /// Copy the files `backend` writes to, in its scope, into a new temporary directory.
+    pub(super) fn new(backend: &XdgBackend, plan: &Plan) -> io::Result<Self> {
+        let root = env::temp_dir().join(format!(
+            "fileext-dry-run-{}-{}",
+            process::id(),
+            SANDBOXES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let (data_dir, config_dir) = (root.join("data"), root.join("config"));
+
+        // The copies replace the directories of the scope, which aren't read as data or config directories either
+        let mut sandbox = backend.clone();
+        sandbox.dirs.data_dirs = without(&backend.dirs.data_dirs, backend.scope_data_dir());
+        sandbox.dirs.config_dirs = without(&backend.dirs.config_dirs, backend.scope_config_dir());
+        match backend.scope {
+            Scope::User => {
+                sandbox.dirs.data_home = data_dir;
+                sandbox.dirs.config_home = config_dir;
+            }
+            Scope::System => {
+                sandbox.system_data_dir = data_dir;
+                sandbox.system_config_dir = config_dir;
+            }
+        }
+
+        let mut copies = vec![
+            (backend.applications_dir(), sandbox.applications_dir()),
+            (
+                backend.scope_data_dir().join("mime"),
+                sandbox.scope_data_dir().join("mime"),
+            ),
+            (backend.icon_theme_dir(), sandbox.icon_theme_dir()),
+        ];
+        for name in backend.base_dirs().mimeapps_list_names() {
+            copies.push((
+                backend.scope_config_dir().join(&name),
+                sandbox.scope_config_dir().join(name),
+            ));
+        }
+
+        let dry_run = Self {
+            sandbox,
+            root,
+            copies,
+            files: Mutex::default(),
+            plan: plan.clone(),
+        };
+        for (original, copy) in &dry_run.copies {
+            copy_all(original, copy)?;
+        }
+        *dry_run.lock_files() = dry_run.read_copies()?;
+        Ok(dry_run)
+    }
+
+    fn lock_files(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Vec<u8>>> {
+        self.files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    /// Read every copied file, keyed by the path of the file it was copied from.
+    fn read_copies(&self) -> io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut files = BTreeMap::new();
+        for (original, copy) in &self.copies {
+            for path in list_files(copy)? {
+                let contents = fs::read(&path)?;
+                let relative = path.strip_prefix(copy).unwrap_or(Path::new(""));
+                let original = if relative.as_os_str().is_empty() {
+                    original.clone()
+                } else {
+                    original.join(relative)
+                };
+                files.insert(original, contents);
+            }
+        }
+        Ok(files)
+    }
+    /// Move the path of a file target from an original file to its copy, or from a copy to the original file if
+    /// `to_copy` isn't set. Paths of other files are left as they are.
+    fn map_path(&self, target: &mut Target, to_copy: bool) {
+        let (Target::File(path) | Target::KeyFileEntry { path, .. }) = target else {
+            return;
+        };
+        for (original, copy) in &self.copies {
+            let (from, to) = if to_copy {
+                (original, copy)
+            } else {
+                (copy, original)
+            };
+            if let Ok(relative) = path.strip_prefix(from) {
+                *path = if relative.as_os_str().is_empty() {
+                    to.clone()
+                } else {
+                    to.join(relative)
+                };
+                return;
+            }
+        }
+    }
+    /// Record how the copied files changed since the last time, then pass on the result of the change.
+    fn record<T>(&self, result: Result<T>) -> Result<T> {
+        let after = self.read_copies()?;
+        let mut before = self.lock_files();
+        let paths: BTreeSet<&PathBuf> = before.keys().chain(after.keys()).collect();
+        for path in paths {
+            self.plan.push(Change {
+                target: Target::File(path.clone()),
+                before: before.get(path).cloned().map(Value::from_bytes),
+                after: after.get(path).cloned().map(Value::from_bytes),
+            });
+        }
+        *before = after;
+        result
+    }
+}
+impl Drop for DryRunBackend {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+impl Backend for DryRunBackend {
+    fn create(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        self.record(self.sandbox.create(executable, descriptor))
+    }
+    fn validate(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        self.sandbox.validate(executable, descriptor)
+    }
+    fn get(&self, id: &str) -> Result<Option<ApplicationDescriptor>> {
+        self.sandbox.get(id)
+    }
+    fn executable(&self, id: &str) -> Result<Option<String>> {
+        self.sandbox.executable(id)
+    }
+    fn delete(&self, id: &str) -> Result<()> {
+        self.record(self.sandbox.delete(id))
+    }
+    fn enumerate(&self, id: &str) -> Result<Vec<String>> {
+        self.sandbox.enumerate(id)
+    }
+    fn associations(&self, id: &str) -> Result<Vec<Association>> {
+        self.sandbox.associations(id)
+    }
+    fn handles(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        self.sandbox.handles(id, file_type)
+    }
+    fn is_default_for(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        self.sandbox.is_default_for(id, file_type)
+    }
+    fn associate(&self, id: &str, file_type: &FileType, default: bool) -> Result<Option<String>> {
+        self.record(self.sandbox.associate(id, file_type, default))
+    }
+    fn dissociate(&self, id: &str, file_type: &FileType) -> Result<()> {
+        self.record(self.sandbox.dissociate(id, file_type))
+    }
+    fn add_association(&self, id: &str, file_type: &FileType) -> Result<()> {
+        self.record(self.sandbox.add_association(id, file_type))
+    }
+    fn hide_association(&self, id: &str, file_type: &FileType) -> Result<()> {
+        self.record(self.sandbox.hide_association(id, file_type))
+    }
+    fn register_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+        self.record(self.sandbox.register_mime_package(id, package))
+    }
+    fn unregister_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+        self.record(self.sandbox.unregister_mime_package(id, package))
+    }
+    /// Captures the copies, as the files they were copied from.
+    fn snapshot(&self, ids: &[String], file_types: &[FileType]) -> Result<Snapshot> {
+        let mut snapshot = self.sandbox.snapshot(ids, file_types)?;
+        for entry in &mut snapshot.entries {
+            self.map_path(&mut entry.target, false);
+        }
+        Ok(snapshot)
+    }
+    /// Restores into the copies, so the snapshot has to be of the scope of the backend.
+    fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        if snapshot.scope != self.sandbox.scope {
+            return Err(Error::Unsupported);
+        }
+        let mut snapshot = snapshot.clone();
+        for entry in &mut snapshot.entries {
+            self.map_path(&mut entry.target, true);
+        }
+        self.record(self.sandbox.restore(&snapshot))
+    }
+    fn default_application_for(
+        &self,
+        file_type: &FileType,
+    ) -> Result<Option<ApplicationDescriptor>> {
+        self.sandbox.default_application_for(file_type)
+    }
+}
+
+/// Copy a file, or a directory with everything in it, doing nothing if it doesn't exist.
+fn copy_all(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = match fs::metadata(from) {
+        Ok(metadata) => metadata,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(why) => return Err(why),
+    };
+    if metadata.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_all(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// List a file, or every file in a directory and its subdirectories, listing nothing if it doesn't exist.
+fn list_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(why) => return Err(why),
+    };
+    if !metadata.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        files.extend(list_files(&entry?.path())?);
+    }
+    Ok(files)
+}
@@ -0,0 +1,92 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Installation of icons into an icon theme directory, such as `$XDG_DATA_HOME/icons/hicolor`.
+use super::write_atomically;
+use crate::icon::{IconContext, IconImage};
+use crate::result::Result;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Install the images of an icon named `name` into a theme directory, replacing the images previously installed under
+/// that name.
+///
+/// # Errors
+/// - Any error of [IconImage::validate]. Nothing is installed in that case.
+pub fn install_icon(
+    theme_dir: &Path,
+    context: IconContext,
+    name: &str,
+    images: &[IconImage],
+) -> Result<()> {
+    for image in images {
+        image.validate()?;
+    }
+    uninstall_icon(theme_dir, context, name)?;
+    for image in images {
+        let contents = fs::read(&image.path)?;
+        write_atomically(&image.theme_path(theme_dir, context, name), &contents)?;
+    }
+    touch(theme_dir)?;
+    Ok(())
+}
+
+/// List the installed images of an icon named `name` in a theme directory.
+pub fn installed_icons(
+    theme_dir: &Path,
+    context: IconContext,
+    name: &str,
+) -> io::Result<Vec<PathBuf>> {
+    let size_dirs = match fs::read_dir(theme_dir) {
+        Ok(entries) => entries,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(why) => return Err(why),
+    };
+    let mut icons = Vec::new();
+    for size_dir in size_dirs {
+        let context_dir = size_dir?.path().join(context.dir_name());
+        for extension in ["png", "svg"] {
+            let path = context_dir.join(format!("{name}.{extension}"));
+            if path.is_file() {
+                icons.push(path);
+            }
+        }
+    }
+    icons.sort();
+    Ok(icons)
+}
+
+/// Remove every image of an icon named `name` from a theme directory.
+pub fn uninstall_icon(theme_dir: &Path, context: IconContext, name: &str) -> io::Result<()> {
+    let icons = installed_icons(theme_dir, context, name)?;
+    for path in &icons {
+        match fs::remove_file(path) {
+            Ok(()) => (),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => (),
+            Err(why) => return Err(why),
+        }
+    }
+    if !icons.is_empty() {
+        touch(theme_dir)?;
+    }
+    Ok(())
+}
+
+/// Update the modification time of a theme directory, which icon caches compare to notice installed icons.
+fn touch(theme_dir: &Path) -> io::Result<()> {
+    File::open(theme_dir)?.set_modified(SystemTime::now())
+}
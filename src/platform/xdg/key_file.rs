@@ -0,0 +1,328 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Lossless reading and editing of files in the key file format shared by desktop entries and `mimeapps.list`.
+use super::write_atomically;
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::{fs, io};
+
+/// Lines of a key file, each with its line terminator if it has one, so that unchanged files serialize byte-identical.
+///
+/// Only the first group of each name is looked at and changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct KeyFile {
+    lines: Vec<String>,
+}
+impl KeyFile {
+    pub(super) fn parse(contents: &str) -> Self {
+        Self {
+            lines: contents.split_inclusive('\n').map(String::from).collect(),
+        }
+    }
+    pub(super) fn read(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+    pub(super) fn write(&self, path: &Path) -> io::Result<()> {
+        write_atomically(path, self.to_string().as_bytes())
+    }
+
+    /// Get all lines of the file, without their line terminators.
+    pub(super) fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines
+            .iter()
+            .map(|line| line.trim_end_matches(['\r', '\n']))
+    }
+    /// Get names of all groups, in the order of the file.
+    pub(super) fn groups(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| group_name(line))
+            .collect()
+    }
+    /// Get keys of a group, in the order of the file, including the locale suffix of localized keys.
+    pub(super) fn keys(&self, group: &str) -> Vec<&str> {
+        let Some((start, end)) = self.group(group) else {
+            return Vec::new();
+        };
+        let mut keys: Vec<&str> = Vec::new();
+        for line in &self.lines[start..end] {
+            if let Some((key, _)) = split_entry(line) {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys
+    }
+    /// Get the value of a key as it's written in the file, without unescaping it.
+    pub(super) fn get_raw(&self, group: &str, key: &str) -> Option<&str> {
+        let (_, value) = split_entry(&self.lines[self.find(group, key)?])?;
+        Some(value)
+    }
+    /// Set the value of a key as it's written in the file, without escaping it.
+    ///
+    /// An existing key is changed in place, keeping the spacing around `=`. A new key is added after the last key of the group,
+    /// and a missing group is added at the end of the file.
+    pub(super) fn set_raw(&mut self, group: &str, key: &str, raw_value: &str) {
+        if let Some(i) = self.find(group, key) {
+            let line = &self.lines[i];
+            let at = line.find('=').expect("entry lines contain `=`") + 1;
+            let spacing = line[at..].len() - line[at..].trim_start().len();
+            let terminator = &line[line.trim_end_matches(['\r', '\n']).len()..];
+            self.lines[i] = format!("{}{raw_value}{terminator}", &line[..at + spacing]);
+            return;
+        }
+
+        let newline = self.newline();
+        let end = match self.group(group) {
+            Some((_, end)) => end,
+            None => {
+                self.terminate_last_line();
+                if self.lines.iter().any(|line| !line.trim().is_empty()) {
+                    self.lines.push(newline.to_string());
+                }
+                self.lines.push(format!("[{group}]{newline}"));
+                self.lines.len()
+            }
+        };
+        // Keep blank lines and comments between the group and the next one after the new key
+        let mut at = end;
+        while at > 0 && is_blank_or_comment(&self.lines[at - 1]) {
+            at -= 1;
+        }
+        if at == self.lines.len() {
+            self.terminate_last_line();
+        }
+        self.lines.insert(at, format!("{key}={raw_value}{newline}"));
+    }
+    /// Remove every occurrence of a key from a group, returning whether there was one.
+    pub(super) fn remove(&mut self, group: &str, key: &str) -> bool {
+        let mut removed = false;
+        while let Some(i) = self.find(group, key) {
+            self.lines.remove(i);
+            removed = true;
+        }
+        removed
+    }
+    /// Remove a group with all of its keys and the comments preceding the next group, returning whether it existed.
+    /// A group at the end of the file takes the blank line separating it from the group before along.
+    pub(super) fn remove_group(&mut self, group: &str) -> bool {
+        let Some((start, end)) = self.group(group) else {
+            return false;
+        };
+        let mut header = start - 1;
+        if end == self.lines.len() && header > 0 && self.lines[header - 1].trim().is_empty() {
+            header -= 1;
+        }
+        self.lines.drain(header..end);
+        true
+    }
+    /// Remove a group which has no keys, like [KeyFile::remove_group], returning whether it was removed.
+    pub(super) fn remove_empty_group(&mut self, group: &str) -> bool {
+        self.keys(group).is_empty() && self.remove_group(group)
+    }
+
+    pub(super) fn get(&self, group: &str, key: &str) -> Option<String> {
+        self.get_raw(group, key).map(unescape_value)
+    }
+    pub(super) fn get_list(&self, group: &str, key: &str) -> Option<Vec<String>> {
+        self.get_raw(group, key).map(split_list)
+    }
+    pub(super) fn set(&mut self, group: &str, key: &str, value: &str) {
+        self.set_raw(group, key, &escape_value(value));
+    }
+    pub(super) fn set_list(&mut self, group: &str, key: &str, values: &[impl AsRef<str>]) {
+        self.set_raw(group, key, &join_list(values));
+    }
+
+    /// Range of line indices belonging to a group, header excluded.
+    fn group(&self, group: &str) -> Option<(usize, usize)> {
+        let start = self
+            .lines
+            .iter()
+            .position(|line| group_name(line) == Some(group))?
+            + 1;
+        let end = self.lines[start..]
+            .iter()
+            .position(|line| group_name(line).is_some())
+            .map_or(self.lines.len(), |offset| start + offset);
+        Some((start, end))
+    }
+    fn find(&self, group: &str, key: &str) -> Option<usize> {
+        let (start, end) = self.group(group)?;
+        (start..end).find(|&i| split_entry(&self.lines[i]).is_some_and(|(k, _)| k == key))
+    }
+    /// Line terminator new lines get, which is the one of the first line.
+    fn newline(&self) -> &'static str {
+        match self.lines.first() {
+            Some(line) if line.ends_with("\r\n") => "\r\n",
+            _ => "\n",
+        }
+    }
+    fn terminate_last_line(&mut self) {
+        let newline = self.newline();
+        if let Some(last) = self.lines.last_mut() {
+            if !last.ends_with('\n') {
+                last.push_str(newline);
+            }
+        }
+    }
+}
+impl Display for KeyFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.lines.iter().try_for_each(|line| f.write_str(line))
+    }
+}
+
+pub(super) fn group_name(line: &str) -> Option<&str> {
+    line.trim().strip_prefix('[')?.strip_suffix(']')
+}
+pub(super) fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+/// Split a `key=value` line into the key and the value, without the spacing around `=` and the line terminator.
+pub(super) fn split_entry(line: &str) -> Option<(&str, &str)> {
+    if is_blank_or_comment(line) || group_name(line).is_some() {
+        return None;
+    }
+    let (key, value) = line.split_once('=')?;
+    Some((
+        key.trim(),
+        value.trim_start().trim_end_matches(['\r', '\n']),
+    ))
+}
+
+/// Escape a string value with the `\s`, `\n`, `\t`, `\r` and `\\` escape sequences of the Desktop Entry Specification.
+pub(super) fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ' ' if i == 0 => escaped.push_str("\\s"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+/// Reverse [escape_value]. Unknown escape sequences are kept as they are.
+pub(super) fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+/// Split a raw list value on `;` and unescape its items, where `\;` stands for a semicolon within an item.
+/// Empty items are skipped.
+pub(super) fn split_list(raw_value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = raw_value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => item.push(';'),
+                Some(other) => {
+                    item.push('\\');
+                    item.push(other);
+                }
+                None => item.push('\\'),
+            },
+            ';' => items.push(std::mem::take(&mut item)),
+            c => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+        .into_iter()
+        .filter(|item| !item.is_empty())
+        .map(|item| unescape_value(&item))
+        .collect()
+}
+/// Escape items of a list and join them into a raw list value, with a `;` after every item.
+pub(super) fn join_list(items: &[impl AsRef<str>]) -> String {
+    items
+        .iter()
+        .map(|item| format!("{};", escape_value(item.as_ref()).replace(';', "\\;")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape() {
+        let value = " two\tlines\nback\\slash";
+        assert_eq!(escape_value(value), "\\stwo\\tlines\\nback\\\\slash");
+        assert_eq!(unescape_value(&escape_value(value)), value);
+
+        let items = ["a;b", " c", "d\\e"];
+        assert_eq!(join_list(&items), "a\\;b;\\sc;d\\\\e;");
+        assert_eq!(split_list(&join_list(&items)), items);
+        assert_eq!(split_list("one;;two"), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn edit() {
+        let contents = "# Comment\r\n[Group]\r\nKey = value\r\n\r\n[Other]\r\nKey=other";
+        let mut file = KeyFile::parse(contents);
+        assert_eq!(file.to_string(), contents);
+        assert_eq!(file.groups(), vec!["Group", "Other"]);
+        assert_eq!(file.get("Group", "Key").as_deref(), Some("value"));
+
+        file.set("Group", "Key", "changed");
+        file.set("Group", "New", "new");
+        file.set("Other", "New", "new");
+        file.set("Third", "Key", "third");
+        assert_eq!(
+            file.to_string(),
+            "# Comment\r\n[Group]\r\nKey = changed\r\nNew=new\r\n\r\n[Other]\r\nKey=other\r\nNew=new\r\n\r\n[Third]\r\nKey=third\r\n"
+        );
+        assert!(file.remove_group("Other"));
+        assert!(file.remove("Group", "New"));
+        assert!(!file.remove("Group", "New"));
+        assert_eq!(
+            file.to_string(),
+            "# Comment\r\n[Group]\r\nKey = changed\r\n\r\n[Third]\r\nKey=third\r\n"
+        );
+        assert!(file.remove_group("Third"));
+        assert_eq!(
+            file.to_string(),
+            "# Comment\r\n[Group]\r\nKey = changed\r\n"
+        );
+    }
+}
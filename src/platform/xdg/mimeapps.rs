@@ -0,0 +1,249 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Lossless model of `mimeapps.list` files, as described by the
+//! [Association between MIME types and applications](https://specifications.freedesktop.org/mime-apps-spec/latest/) specification.
+//!
+//! Comments, blank lines, unknown groups and keys, the order of everything and line endings are kept as they are,
+//! so a [MimeAppsList] which hasn't been changed serializes to exactly the bytes it was parsed from.
+use super::key_file::KeyFile;
+use super::{ADDED_ASSOCIATIONS_GROUP, DEFAULT_APPLICATIONS_GROUP, REMOVED_ASSOCIATIONS_GROUP};
+use std::fmt::{self, Display};
+use std::io;
+use std::path::Path;
+
+/// Standard group of a `mimeapps.list` file, mapping MIME types to lists of desktop file IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Group {
+    /// `[Default Applications]`: applications opening a MIME type by default, in the order of preference.
+    DefaultApplications,
+    /// `[Added Associations]`: applications handling a MIME type which they don't list themselves.
+    AddedAssociations,
+    /// `[Removed Associations]`: applications hidden from a MIME type which they list themselves.
+    RemovedAssociations,
+}
+impl Group {
+    /// Every standard group, in the order the specification lists them.
+    pub const ALL: [Group; 3] = [
+        Group::DefaultApplications,
+        Group::AddedAssociations,
+        Group::RemovedAssociations,
+    ];
+
+    /// Get the name of the group, as written between the brackets of its header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DefaultApplications => DEFAULT_APPLICATIONS_GROUP,
+            Self::AddedAssociations => ADDED_ASSOCIATIONS_GROUP,
+            Self::RemovedAssociations => REMOVED_ASSOCIATIONS_GROUP,
+        }
+    }
+}
+
+/// Contents of a `mimeapps.list` file.
+///
+/// Only the first group of each name is looked at and changed, and only the first key of each MIME type within it,
+/// which matches how the file is read by GLib.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MimeAppsList {
+    file: KeyFile,
+}
+impl MimeAppsList {
+    /// Create an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Parse the contents of a `mimeapps.list` file. Lines which aren't valid are kept as they are.
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            file: KeyFile::parse(contents),
+        }
+    }
+    /// Read a `mimeapps.list` file.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: KeyFile::read(path.as_ref())?,
+        })
+    }
+    /// Same as [MimeAppsList::read], but a missing file reads as an empty list.
+    pub fn read_or_default(path: impl AsRef<Path>) -> io::Result<Self> {
+        match Self::read(path) {
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            result => result,
+        }
+    }
+    /// Write the list to a file, replacing it atomically. Missing parent directories are created.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.file.write(path.as_ref())
+    }
+
+    /// Get MIME types listed in a group, in the order of the file.
+    pub fn mime_types(&self, group: Group) -> Vec<String> {
+        self.file
+            .keys(group.name())
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+    /// Get desktop file IDs listed for a MIME type in a group, in the order of the file.
+    pub fn get(&self, group: Group, mime_type: &str) -> Vec<String> {
+        self.file
+            .get_list(group.name(), mime_type)
+            .unwrap_or_default()
+    }
+    /// Get the most preferred default application of a MIME type, which doesn't have to be installed.
+    pub fn default_application(&self, mime_type: &str) -> Option<String> {
+        self.get(Group::DefaultApplications, mime_type)
+            .into_iter()
+            .next()
+    }
+
+    /// Replace desktop file IDs listed for a MIME type in a group, [removing](MimeAppsList::remove) the key if
+    /// `desktop_file_ids` is empty.
+    ///
+    /// An existing key is changed in place. A new key is added after the last key of the group,
+    /// and a missing group is added at the end of the file.
+    pub fn set(&mut self, group: Group, mime_type: &str, desktop_file_ids: &[impl AsRef<str>]) {
+        if desktop_file_ids.is_empty() {
+            self.remove(group, mime_type);
+        } else {
+            self.file
+                .set_list(group.name(), mime_type, desktop_file_ids);
+        }
+    }
+    /// Remove a MIME type from a group, returning whether it was listed there. A group left without keys is removed.
+    pub fn remove(&mut self, group: Group, mime_type: &str) -> bool {
+        if !self.file.remove(group.name(), mime_type) {
+            return false;
+        }
+        self.file.remove_empty_group(group.name());
+        true
+    }
+    /// Append a desktop file ID to the ones listed for a MIME type in a group, returning whether it wasn't listed yet.
+    pub fn add(&mut self, group: Group, mime_type: &str, desktop_file_id: &str) -> bool {
+        let mut desktop_file_ids = self.get(group, mime_type);
+        if desktop_file_ids
+            .iter()
+            .any(|existing| existing == desktop_file_id)
+        {
+            return false;
+        }
+        desktop_file_ids.push(desktop_file_id.to_string());
+        self.set(group, mime_type, &desktop_file_ids);
+        true
+    }
+    /// Put a desktop file ID first among the ones listed for a MIME type in a group, moving it if it's already listed.
+    /// Returns whether the list has changed.
+    pub fn prepend(&mut self, group: Group, mime_type: &str, desktop_file_id: &str) -> bool {
+        let mut desktop_file_ids = self.get(group, mime_type);
+        if desktop_file_ids.first().map(String::as_str) == Some(desktop_file_id) {
+            return false;
+        }
+        desktop_file_ids.retain(|existing| existing != desktop_file_id);
+        desktop_file_ids.insert(0, desktop_file_id.to_string());
+        self.set(group, mime_type, &desktop_file_ids);
+        true
+    }
+    /// Remove a desktop file ID from the ones listed for a MIME type in a group, removing the key if none are left.
+    /// Returns whether it was listed.
+    pub fn remove_entry(&mut self, group: Group, mime_type: &str, desktop_file_id: &str) -> bool {
+        let mut desktop_file_ids = self.get(group, mime_type);
+        if !desktop_file_ids
+            .iter()
+            .any(|existing| existing == desktop_file_id)
+        {
+            return false;
+        }
+        desktop_file_ids.retain(|existing| existing != desktop_file_id);
+        self.set(group, mime_type, &desktop_file_ids);
+        true
+    }
+    /// Remove a desktop file ID from every MIME type of every [standard group](Group), returning whether it was listed.
+    pub fn remove_application(&mut self, desktop_file_id: &str) -> bool {
+        let mut removed = false;
+        for group in Group::ALL {
+            for mime_type in self.mime_types(group) {
+                removed |= self.remove_entry(group, &mime_type, desktop_file_id);
+            }
+        }
+        removed
+    }
+}
+impl Display for MimeAppsList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.file.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = "# Written by hand\r\n[Default Applications]\r\ntext/plain = gedit.desktop;kate.desktop\r\nimage/png=eog.desktop;\r\n\r\n[X-Vendor Settings]\r\nkey=value\r\n\r\n[Added Associations]\r\ntext/csv=calc.desktop;\r\n# Trailing comment";
+
+    #[test]
+    fn round_trip() {
+        let list = MimeAppsList::parse(LIST);
+        assert_eq!(list.to_string(), LIST);
+        assert_eq!(
+            list.get(Group::DefaultApplications, "text/plain"),
+            vec!["gedit.desktop", "kate.desktop"]
+        );
+        assert_eq!(
+            list.default_application("image/png").as_deref(),
+            Some("eog.desktop")
+        );
+        assert_eq!(
+            list.mime_types(Group::DefaultApplications),
+            vec!["text/plain", "image/png"]
+        );
+        assert!(list
+            .get(Group::RemovedAssociations, "text/plain")
+            .is_empty());
+        assert_eq!(MimeAppsList::parse("").to_string(), "");
+    }
+
+    #[test]
+    fn edit() {
+        let mut list = MimeAppsList::parse(LIST);
+        assert!(list.prepend(Group::DefaultApplications, "text/plain", "kate.desktop"));
+        assert!(!list.prepend(Group::DefaultApplications, "text/plain", "kate.desktop"));
+        assert!(list.add(Group::DefaultApplications, "text/html", "firefox.desktop"));
+        assert!(list.add(Group::AddedAssociations, "text/csv", "gnumeric.desktop"));
+        assert!(!list.add(Group::AddedAssociations, "text/csv", "calc.desktop"));
+        assert!(list.add(Group::RemovedAssociations, "text/plain", "vim.desktop"));
+        assert!(list.remove_entry(Group::DefaultApplications, "image/png", "eog.desktop"));
+        assert!(!list.remove(Group::AddedAssociations, "image/png"));
+        assert_eq!(
+            list.to_string(),
+            "# Written by hand\r\n[Default Applications]\r\ntext/plain = kate.desktop;gedit.desktop;\r\ntext/html=firefox.desktop;\r\n\r\n[X-Vendor Settings]\r\nkey=value\r\n\r\n[Added Associations]\r\ntext/csv=calc.desktop;gnumeric.desktop;\r\n# Trailing comment\r\n\r\n[Removed Associations]\r\ntext/plain=vim.desktop;\r\n"
+        );
+
+        let mut list = MimeAppsList::new();
+        list.set(Group::AddedAssociations, "text/plain", &["a;b.desktop"]);
+        assert_eq!(
+            list.to_string(),
+            "[Added Associations]\ntext/plain=a\\;b.desktop;\n"
+        );
+        assert_eq!(
+            list.get(Group::AddedAssociations, "text/plain"),
+            vec!["a;b.desktop"]
+        );
+        list.add(Group::RemovedAssociations, "text/html", "c.desktop");
+        assert!(list.remove_entry(Group::RemovedAssociations, "text/html", "c.desktop"));
+        assert!(list.remove(Group::AddedAssociations, "text/plain"));
+        assert_eq!(list.to_string(), "");
+    }
+}
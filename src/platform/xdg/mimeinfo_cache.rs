@@ -0,0 +1,164 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Pure-Rust implementation of `update-desktop-database`, which indexes the MIME types the desktop entries of an
+//! `applications` directory handle in its `mimeinfo.cache`, so that GLib and other readers don't have to parse every
+//! desktop entry to find the applications handling a MIME type.
+use super::desktop_entry::DesktopEntry;
+use super::{desktop_files, write_atomically, MIMEINFO_CACHE, MIME_CACHE_GROUP};
+use crate::mime::MimeType;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::{fs, io};
+
+/// Regenerate the `mimeinfo.cache` of an `applications` directory from its desktop entries.
+///
+/// # Errors
+/// - [io::ErrorKind::NotFound] if the directory doesn't exist.
+pub fn update_desktop_database(applications_dir: impl AsRef<Path>) -> io::Result<()> {
+    let applications_dir = applications_dir.as_ref();
+    MimeInfoCache::scan(applications_dir)?.write(applications_dir.join(MIMEINFO_CACHE))
+}
+
+/// Desktop file IDs of the applications handling each MIME type, as listed in a `mimeinfo.cache` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MimeInfoCache {
+    mime_types: BTreeMap<String, Vec<String>>,
+}
+impl MimeInfoCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Index the desktop entries of an `applications` directory and its subdirectories, like `update-desktop-database`.
+    ///
+    /// Desktop entries in subdirectories get the [prefixed desktop file ID](super::find_desktop_file).
+    /// Entries which can't be read and `Hidden` entries are skipped, and so are invalid MIME types in `MimeType` lists.
+    ///
+    /// # Errors
+    /// - [io::ErrorKind::NotFound] if the directory doesn't exist.
+    pub fn scan(applications_dir: &Path) -> io::Result<Self> {
+        fs::read_dir(applications_dir)?;
+        let mut cache = Self::new();
+        for (desktop_file_id, path) in desktop_files(applications_dir) {
+            let Ok(entry) = DesktopEntry::read(&path) else {
+                continue;
+            };
+            if entry.is_hidden() {
+                continue;
+            }
+            for mime_type in entry.mime_types() {
+                let mime_type = mime_type.trim();
+                let is_valid =
+                    MimeType::parse(mime_type).is_ok_and(|parsed| parsed.essence() == mime_type);
+                if is_valid {
+                    cache.add(mime_type, &desktop_file_id);
+                }
+            }
+        }
+        Ok(cache)
+    }
+    /// List an application as handling a MIME type, unless it already is.
+    pub fn add(&mut self, mime_type: &str, desktop_file_id: &str) {
+        let desktop_file_ids = self.mime_types.entry(mime_type.to_string()).or_default();
+        if let Err(at) = desktop_file_ids.binary_search_by(|id| id.as_str().cmp(desktop_file_id)) {
+            desktop_file_ids.insert(at, desktop_file_id.to_string());
+        }
+    }
+    /// Get the desktop file IDs of the applications handling a MIME type, in the order of their IDs.
+    pub fn desktop_file_ids(&self, mime_type: &str) -> &[String] {
+        self.mime_types.get(mime_type).map_or(&[], Vec::as_slice)
+    }
+    /// Get every MIME type handled by any application, in the order of their names.
+    pub fn mime_types(&self) -> impl Iterator<Item = &str> {
+        self.mime_types.keys().map(String::as_str)
+    }
+    /// Write the cache to a file, replacing it atomically.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write_atomically(path.as_ref(), self.to_string().as_bytes())
+    }
+}
+impl Display for MimeInfoCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[{MIME_CACHE_GROUP}]")?;
+        for (mime_type, desktop_file_ids) in &self.mime_types {
+            write!(f, "{mime_type}=")?;
+            for desktop_file_id in desktop_file_ids {
+                write!(f, "{desktop_file_id};")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn update() {
+        let dir = env::temp_dir().join(format!("fileext-mimeinfo-cache-{}", process::id()));
+        fs::create_dir_all(dir.join("kde/deep")).unwrap();
+        let entry = |name: &str, extra: &str| {
+            fs::write(
+                dir.join(name),
+                format!("[Desktop Entry]\nType=Application\nName=App\nExec=app\n{extra}"),
+            )
+            .unwrap();
+        };
+        entry(
+            "editor.desktop",
+            "MimeType=text/plain;image/png;text/plain;\n",
+        );
+        entry(
+            "kde/viewer.desktop",
+            "MimeType=image/png; x-scheme-handler/http;\n",
+        );
+        entry(
+            "kde/deep/hidden.desktop",
+            "MimeType=image/png;\nHidden=true\n",
+        );
+        entry("invalid.desktop", "MimeType=bogus;text/x-log;text/*;\n");
+        entry("none.desktop", "");
+        entry("notes.txt", "MimeType=text/plain;\n");
+        fs::write(dir.join("broken.desktop"), b"\xff\xfe").unwrap();
+
+        update_desktop_database(&dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join(MIMEINFO_CACHE)).unwrap(),
+            "[MIME Cache]\n\
+             image/png=editor.desktop;kde-viewer.desktop;\n\
+             text/*=invalid.desktop;\n\
+             text/plain=editor.desktop;\n\
+             text/x-log=invalid.desktop;\n\
+             x-scheme-handler/http=kde-viewer.desktop;\n"
+        );
+
+        let cache = MimeInfoCache::scan(&dir).unwrap();
+        assert_eq!(
+            cache.desktop_file_ids("image/png"),
+            ["editor.desktop", "kde-viewer.desktop"]
+        );
+        assert!(cache.desktop_file_ids("image/jpeg").is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            update_desktop_database(&dir).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,2086 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Freedesktop.org (XDG) backend, used on Linux and other Unix-like operating systems.
+//!
+//! Applications are registered as [Desktop Entries](https://specifications.freedesktop.org/desktop-entry-spec/latest/)
+//! in `$XDG_DATA_HOME/applications`, and the MIME types they handle are listed under their `MimeType` key.
+use crate::app::{ApplicationDescriptor, FileType, Verb, VERB_OPEN};
+use crate::backend::{Association, Backend, Scope};
+use crate::command::{Arg, CommandTemplate, Syntax};
+use crate::icon::{IconContext, HICOLOR};
+use crate::mime::package::PACKAGES_DIR;
+use crate::mime::{update_mime_database, MimeDatabase, MimePackage, MimeType};
+use crate::plan::{Plan, Target, Value};
+use crate::result::{Error, Result};
+use crate::snapshot::Snapshot;
+use std::collections::btree_map::{self, BTreeMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::{fs, io};
+
+pub mod base_dirs;
+pub mod desktop_entry;
+mod dry_run;
+pub mod icon_theme;
+mod key_file;
+pub mod mimeapps;
+pub mod mimeinfo_cache;
+pub mod validate;
+
+use self::base_dirs::BaseDirs;
+use self::desktop_entry::{DesktopEntry, Locale};
+use self::dry_run::DryRunBackend;
+use self::icon_theme::{install_icon, installed_icons, uninstall_icon};
+use self::key_file::KeyFile;
+use self::mimeapps::{Group, MimeAppsList};
+use self::mimeinfo_cache::update_desktop_database;
+use self::validate::Issue;
+
+/// Name of the group every desktop entry starts with.
+pub const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+/// Prefix of groups describing additional actions of an application, followed by the action name.
+pub const DESKTOP_ACTION_GROUP_PREFIX: &str = "Desktop Action ";
+/// Extension of desktop entry files.
+pub const DESKTOP_FILE_EXTENSION: &str = ".desktop";
+/// Name of the file default applications are stored in.
+pub const MIMEAPPS_LIST: &str = "mimeapps.list";
+/// Group of `mimeapps.list` mapping MIME types to their default applications.
+pub const DEFAULT_APPLICATIONS_GROUP: &str = "Default Applications";
+/// Group of `mimeapps.list` adding applications to MIME types they don't list themselves.
+pub const ADDED_ASSOCIATIONS_GROUP: &str = "Added Associations";
+/// Group of `mimeapps.list` hiding applications from MIME types they list themselves.
+pub const REMOVED_ASSOCIATIONS_GROUP: &str = "Removed Associations";
+/// Name of the file indexing the MIME types handled by the desktop entries of an `applications` directory.
+pub const MIMEINFO_CACHE: &str = "mimeinfo.cache";
+/// Group of `mimeinfo.cache` mapping MIME types to desktop file IDs.
+pub const MIME_CACHE_GROUP: &str = "MIME Cache";
+/// Base directory data files of [Scope::System] registrations are written to.
+pub const SYSTEM_DATA_DIR: &str = "/usr/share";
+/// Base directory configuration files of [Scope::System] registrations are written to.
+pub const SYSTEM_CONFIG_DIR: &str = "/etc/xdg";
+
+/// Get the base directory user-specific data files are written to.
+///
+/// That is `$XDG_DATA_HOME`, or `$HOME/.local/share` if it isn't set or isn't an absolute path.
+pub fn data_home() -> PathBuf {
+    BaseDirs::from_env().data_home
+}
+/// Get the preference-ordered set of base directories to search for data files in addition to the [data home](data_home).
+///
+/// That is `$XDG_DATA_DIRS`, or `/usr/local/share:/usr/share` if it isn't set. Relative paths are ignored.
+pub fn data_dirs() -> Vec<PathBuf> {
+    BaseDirs::from_env().data_dirs
+}
+
+/// Get the base directory user-specific configuration files are written to.
+///
+/// That is `$XDG_CONFIG_HOME`, or `$HOME/.config` if it isn't set or isn't an absolute path.
+pub fn config_home() -> PathBuf {
+    BaseDirs::from_env().config_home
+}
+/// Get the preference-ordered set of base directories to search for configuration files in addition to the [config home](config_home).
+///
+/// That is `$XDG_CONFIG_DIRS`, or `/etc/xdg` if it isn't set. Relative paths are ignored.
+pub fn config_dirs() -> Vec<PathBuf> {
+    BaseDirs::from_env().config_dirs
+}
+
+/// Turn an application ID into a desktop file ID by appending [DESKTOP_FILE_EXTENSION] to it, unless it already ends with one.
+pub fn desktop_file_id(id: &str) -> String {
+    if id.ends_with(DESKTOP_FILE_EXTENSION) {
+        id.to_string()
+    } else {
+        format!("{id}{DESKTOP_FILE_EXTENSION}")
+    }
+}
+
+/// Find a desktop file by its desktop file ID in an `applications` directory.
+///
+/// A desktop file in a subdirectory gets the subdirectory name and a dash prepended to its ID,
+/// so `kde4-kate.desktop` may be stored either as `kde4-kate.desktop` or as `kde4/kate.desktop`.
+pub fn find_desktop_file(applications_dir: &Path, desktop_file_id: &str) -> Option<PathBuf> {
+    let direct = applications_dir.join(desktop_file_id);
+    if direct.is_file() {
+        return Some(direct);
+    }
+
+    for entry in fs::read_dir(applications_dir).ok()?.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let prefix = format!("{}-", entry.file_name().to_string_lossy());
+        if let Some(rest) = desktop_file_id.strip_prefix(prefix.as_str()) {
+            if let Some(found) = find_desktop_file(&path, rest) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+/// List all desktop files of an `applications` directory with their desktop file IDs, sorted by the ID.
+///
+/// Desktop files in subdirectories are listed with the subdirectory prefix described in [find_desktop_file].
+pub fn desktop_files(applications_dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut found = Vec::new();
+    let Ok(entries) = fs::read_dir(applications_dir) else {
+        return found;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if path.is_dir() {
+            found.extend(
+                desktop_files(&path)
+                    .into_iter()
+                    .map(|(id, path)| (format!("{name}-{id}"), path)),
+            );
+        } else if name.ends_with(DESKTOP_FILE_EXTENSION) {
+            found.push((name, path));
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Quote an argument of the `Exec` key according to the Desktop Entry Specification.
+pub fn quote_exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(',
+        ')', '`',
+    ];
+
+    if !arg.is_empty() && !arg.contains(RESERVED) {
+        return arg.to_string();
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Split a value of the `Exec` key into arguments, undoing the quoting done by [quote_exec_arg].
+///
+/// Field codes such as `%f` are returned as separate arguments, untouched.
+/// Returns [None] if a quoted argument isn't terminated.
+pub fn split_exec(exec: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = exec.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut arg = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' if quoted => {
+                    if let Some(escaped) = chars.next() {
+                        arg.push(escaped);
+                    }
+                }
+                c if c.is_whitespace() && !quoted => break,
+                c => arg.push(c),
+            }
+        }
+        if quoted {
+            return None;
+        }
+        args.push(arg);
+    }
+    Some(args)
+}
+
+/// Replace contents of the file at `path` without leaving a partially written file behind on failure.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+/// Read a file, returning [None] if it doesn't exist.
+fn read_optional(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(why) => Err(why),
+    }
+}
+/// Check whether `path` is below `dir`, without climbing out of it through `..` components.
+fn is_below(path: &Path, dir: &Path) -> bool {
+    path != dir
+        && path.starts_with(dir)
+        && !path
+            .components()
+            .any(|component| component == Component::ParentDir)
+}
+/// Read a key file, a missing one reading as an empty file.
+fn read_key_file(path: &Path) -> io::Result<KeyFile> {
+    match KeyFile::read(path) {
+        Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(KeyFile::default()),
+        result => result,
+    }
+}
+
+/// The in-memory edit of the key file at `path` and its contents before, read on first use.
+fn edited_key_file<'a>(
+    key_files: &'a mut BTreeMap<PathBuf, (KeyFile, KeyFile)>,
+    path: &Path,
+) -> io::Result<&'a mut KeyFile> {
+    Ok(match key_files.entry(path.to_path_buf()) {
+        btree_map::Entry::Occupied(files) => &mut files.into_mut().1,
+        btree_map::Entry::Vacant(files) => {
+            let file = read_key_file(path)?;
+            &mut files.insert((file.clone(), file)).1
+        }
+    })
+}
+
+/// Build the desktop entry of an application launched through the executable at `executable`.
+fn desktop_entry(executable: &str, descriptor: &ApplicationDescriptor) -> DesktopEntry {
+    let exec = match descriptor.primary_verb() {
+        Some(verb) => verb.command.render(Syntax::DesktopEntry),
+        None => CommandTemplate::new(executable)
+            .arg(Arg::Files)
+            .render(Syntax::DesktopEntry),
+    };
+    let actions: Vec<&Verb> = descriptor
+        .verbs
+        .iter()
+        .filter(|verb| verb.name != VERB_OPEN)
+        .collect();
+
+    let mut file = DesktopEntry::new();
+    file.set(DESKTOP_ENTRY_GROUP, "Type", "Application");
+    file.set(DESKTOP_ENTRY_GROUP, "Name", &descriptor.name);
+    file.set(DESKTOP_ENTRY_GROUP, "Exec", &exec);
+    if !descriptor.icon_images.is_empty() {
+        file.set(DESKTOP_ENTRY_GROUP, "Icon", &icon_name(&descriptor.id));
+    } else if !descriptor.icon_path.is_empty() {
+        file.set(DESKTOP_ENTRY_GROUP, "Icon", &descriptor.icon_path);
+    }
+    if !actions.is_empty() {
+        let names: Vec<String> = actions.iter().map(|verb| verb.name.clone()).collect();
+        file.set_list(DESKTOP_ENTRY_GROUP, "Actions", &names);
+    }
+    for verb in actions {
+        let group = format!("{DESKTOP_ACTION_GROUP_PREFIX}{}", verb.name);
+        let label = if verb.label.is_empty() {
+            &verb.name
+        } else {
+            &verb.label
+        };
+        file.set(&group, "Name", label);
+        file.set(&group, "Exec", &verb.command.render(Syntax::DesktopEntry));
+    }
+    file
+}
+/// Keys of the `[Desktop Entry]` group [desktop_entry] writes, which replace the ones of an existing desktop entry.
+/// `Hidden` is among them so that registering an application reveals it again.
+const MANAGED_KEYS: [&str; 6] = ["Type", "Name", "Exec", "Icon", "Actions", "Hidden"];
+
+/// Merge the keys of a desktop entry made by [desktop_entry] into the existing desktop entry of the application.
+///
+/// Keys this crate doesn't manage, like `MimeType` or the ones of other applications, are kept as they are,
+/// and so are their groups. The groups of actions the application doesn't have anymore are removed.
+fn merge_desktop_entry(mut existing: DesktopEntry, entry: &DesktopEntry) -> DesktopEntry {
+    let actions = entry.actions();
+    for action in existing.actions() {
+        if !actions.contains(&action) {
+            existing.remove_group(&format!("{DESKTOP_ACTION_GROUP_PREFIX}{action}"));
+        }
+    }
+    for key in MANAGED_KEYS {
+        match entry.get(DESKTOP_ENTRY_GROUP, key) {
+            Some(_) if key == "Actions" => existing.set_list(DESKTOP_ENTRY_GROUP, key, &actions),
+            Some(value) => existing.set(DESKTOP_ENTRY_GROUP, key, &value),
+            None => {
+                existing.remove(DESKTOP_ENTRY_GROUP, key);
+            }
+        }
+    }
+    for action in &actions {
+        let group = format!("{DESKTOP_ACTION_GROUP_PREFIX}{action}");
+        for key in ["Name", "Exec"] {
+            if let Some(value) = entry.get(&group, key) {
+                existing.set(&group, key, &value);
+            }
+        }
+    }
+    existing
+}
+/// Get the name the icon images of an application are installed under, which is its ID.
+fn icon_name(id: &str) -> String {
+    id.strip_suffix(DESKTOP_FILE_EXTENSION)
+        .unwrap_or(id)
+        .to_string()
+}
+
+/// Read a desktop entry as the descriptor of the application with the ID `id`.
+fn read_descriptor(id: &str, path: &Path) -> io::Result<ApplicationDescriptor> {
+    let file = DesktopEntry::read(path)?;
+    let locale = Locale::current();
+
+    // Verbs with a malformed `Exec` key can't be launched anyway, so they're skipped
+    let command = |group: &str| {
+        file.get(group, "Exec")
+            .and_then(|exec| CommandTemplate::parse(&exec, Syntax::DesktopEntry).ok())
+    };
+    let mut verbs = Vec::new();
+    if let Some(command) = command(DESKTOP_ENTRY_GROUP) {
+        verbs.push(Verb {
+            name: String::from(VERB_OPEN),
+            label: String::new(),
+            command,
+        });
+    }
+    for action in file.actions() {
+        let group = format!("{DESKTOP_ACTION_GROUP_PREFIX}{action}");
+        if let Some(command) = command(&group) {
+            verbs.push(Verb {
+                label: file.get(&group, "Name").unwrap_or_default(),
+                name: action,
+                command,
+            });
+        }
+    }
+
+    Ok(ApplicationDescriptor {
+        id: id
+            .strip_suffix(DESKTOP_FILE_EXTENSION)
+            .unwrap_or(id)
+            .to_string(),
+        name: file.name(locale.as_ref()).unwrap_or_default(),
+        icon_path: file.icon(locale.as_ref()).unwrap_or_default(),
+        icon_images: Vec::new(),
+        verbs,
+        default_verb: None,
+    })
+}
+
+/// [Backend] storing applications as desktop entries in `$XDG_DATA_HOME/applications`
+/// and default applications in `$XDG_CONFIG_HOME/mimeapps.list`.
+///
+/// In [Scope::System], they're written to [SYSTEM_DATA_DIR] and [SYSTEM_CONFIG_DIR] instead,
+/// and the data home and the config home of the user aren't read.
+#[derive(Debug, Clone)]
+pub struct XdgBackend {
+    dirs: BaseDirs,
+    system_data_dir: PathBuf,
+    system_config_dir: PathBuf,
+    scope: Scope,
+    update_desktop_database: bool,
+}
+impl XdgBackend {
+    /// Create a backend writing to the [data home](data_home) and the [config home](config_home) of the current user.
+    pub fn new() -> Self {
+        Self::with_base_dirs(BaseDirs::from_env())
+    }
+    /// Create a backend using `dirs` instead of the base directories and the current desktops of the environment.
+    pub fn with_base_dirs(dirs: BaseDirs) -> Self {
+        Self {
+            dirs,
+            system_data_dir: PathBuf::from(SYSTEM_DATA_DIR),
+            system_config_dir: PathBuf::from(SYSTEM_CONFIG_DIR),
+            scope: Scope::User,
+            update_desktop_database: false,
+        }
+    }
+    /// Create a backend writing to `data_home` and `config_home` instead of `$XDG_DATA_HOME` and `$XDG_CONFIG_HOME`.
+    ///
+    /// The backend doesn't read any [data directories](data_dirs) or [config directories](config_dirs)
+    /// unless they're set with [XdgBackend::data_dirs] and [XdgBackend::config_dirs], and doesn't read
+    /// desktop-specific `mimeapps.list` files unless [XdgBackend::current_desktops] are set.
+    pub fn with_dirs(data_home: impl Into<PathBuf>, config_home: impl Into<PathBuf>) -> Self {
+        Self::with_base_dirs(BaseDirs {
+            data_home: data_home.into(),
+            config_home: config_home.into(),
+            ..Default::default()
+        })
+    }
+    /// Set the directories desktop entries and the MIME database are read from after the data home, instead of `$XDG_DATA_DIRS`.
+    pub fn data_dirs(mut self, data_dirs: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        self.dirs.data_dirs = data_dirs.into_iter().map(Into::into).collect();
+        self
+    }
+    /// Set the directories `mimeapps.list` is read from after the config home, instead of `$XDG_CONFIG_DIRS`.
+    pub fn config_dirs(
+        mut self,
+        config_dirs: impl IntoIterator<Item = impl Into<PathBuf>>,
+    ) -> Self {
+        self.dirs.config_dirs = config_dirs.into_iter().map(Into::into).collect();
+        self
+    }
+    /// Set the names of the current desktop environment, whose `$desktop-mimeapps.list` files are read before the
+    /// `mimeapps.list` of the same directory, instead of `$XDG_CURRENT_DESKTOP`.
+    pub fn current_desktops(mut self, desktops: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.dirs.current_desktops = desktops
+            .into_iter()
+            .map(|desktop| desktop.to_string())
+            .collect();
+        self
+    }
+    /// Set the directories [Scope::System] registrations are written to, instead of [SYSTEM_DATA_DIR] and [SYSTEM_CONFIG_DIR].
+    pub fn system_dirs(
+        mut self,
+        data_dir: impl Into<PathBuf>,
+        config_dir: impl Into<PathBuf>,
+    ) -> Self {
+        self.system_data_dir = data_dir.into();
+        self.system_config_dir = config_dir.into();
+        self
+    }
+    /// Write registrations for the current user or for every user of the machine, [Scope::User] by default.
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+    /// Regenerate the [mimeinfo.cache](mimeinfo_cache) of the applications directory whenever a desktop entry is
+    /// created, deleted or changes its MIME types, like packages do by running `update-desktop-database`.
+    ///
+    /// Off by default, since readers like GLib fall back to the desktop entries for directories without a cache,
+    /// but a stale cache hides new applications from them.
+    pub fn update_desktop_database(mut self, yes: bool) -> Self {
+        self.update_desktop_database = yes;
+        self
+    }
+
+    /// Get the base directory data files are written to in the scope of the backend.
+    fn scope_data_dir(&self) -> &Path {
+        match self.scope {
+            Scope::User => &self.dirs.data_home,
+            Scope::System => &self.system_data_dir,
+        }
+    }
+    /// Get the base directory configuration files are written to in the scope of the backend.
+    fn scope_config_dir(&self) -> &Path {
+        match self.scope {
+            Scope::User => &self.dirs.config_home,
+            Scope::System => &self.system_config_dir,
+        }
+    }
+    /// Get the base directories the backend reads in its scope: the [Scope::System] one replaces the homes of the user
+    /// with the directories it writes to, which it doesn't read a second time as data or config directories.
+    fn base_dirs(&self) -> BaseDirs {
+        match self.scope {
+            Scope::User => self.dirs.clone(),
+            Scope::System => BaseDirs {
+                data_home: self.system_data_dir.clone(),
+                config_home: self.system_config_dir.clone(),
+                data_dirs: without(&self.dirs.data_dirs, &self.system_data_dir),
+                config_dirs: without(&self.dirs.config_dirs, &self.system_config_dir),
+                current_desktops: self.dirs.current_desktops.clone(),
+            },
+        }
+    }
+
+    /// Load the MIME database from the data home and the data directories of the backend.
+    pub fn mime_database(&self) -> io::Result<MimeDatabase> {
+        MimeDatabase::load_dirs(self.base_dirs().mime_dirs())
+    }
+    /// Get the MIME type of a file type, looking up extensions in the [globs](crate::mime::Globs) of the MIME database.
+    ///
+    /// # Errors
+    /// - [Error::Unsupported] if no MIME type matches the extension.
+    fn mime_type(&self, file_type: &FileType) -> Result<MimeType> {
+        match file_type {
+            FileType::MimeType(mime_type) => Ok(mime_type.clone()),
+            FileType::Extension(extension) => self
+                .mime_database()?
+                .mime_types_for_path(format!("file{extension}"))
+                .into_iter()
+                .next()
+                .ok_or(Error::Unsupported),
+        }
+    }
+
+    /// Get the directory desktop entries are written to.
+    pub fn applications_dir(&self) -> PathBuf {
+        self.scope_data_dir().join("applications")
+    }
+    /// Get the `mimeapps.list` file default applications are written to.
+    pub fn mimeapps_list(&self) -> PathBuf {
+        self.scope_config_dir().join(MIMEAPPS_LIST)
+    }
+    /// Get the file a [MIME package](crate::mime::MimePackage) with the ID `id` is written to.
+    pub fn mime_package(&self, id: &str) -> PathBuf {
+        self.scope_data_dir()
+            .join("mime")
+            .join(PACKAGES_DIR)
+            .join(format!("{id}.xml"))
+    }
+    /// Get the directory of the [hicolor](HICOLOR) icon theme icons are installed into.
+    pub fn icon_theme_dir(&self) -> PathBuf {
+        self.scope_data_dir().join("icons").join(HICOLOR)
+    }
+    /// Get every `applications` directory desktop entries are looked up in, from the most important one to the least important one.
+    pub fn applications_dirs(&self) -> Vec<PathBuf> {
+        self.base_dirs().applications_dirs()
+    }
+    /// Get every `mimeapps.list` file associations are looked up in, from the most important one to the least important one,
+    /// as [resolved](BaseDirs::mimeapps_lists) from the base directories and the current desktops of the backend.
+    pub fn mimeapps_lists(&self) -> Vec<PathBuf> {
+        self.base_dirs().mimeapps_lists()
+    }
+    /// Get every [mimeapps.list](XdgBackend::mimeapps_lists) file together with the scope it belongs to:
+    /// the ones next to the files the backend writes are in its scope, the others are in [Scope::System].
+    fn scoped_mimeapps_lists(&self) -> Vec<(PathBuf, Scope)> {
+        let applications_dir = self.applications_dir();
+        self.mimeapps_lists()
+            .into_iter()
+            .map(|path| {
+                let dir = path.parent();
+                if dir == Some(self.scope_config_dir()) || dir == Some(&applications_dir) {
+                    (path, self.scope)
+                } else {
+                    (path, Scope::System)
+                }
+            })
+            .collect()
+    }
+    /// Find the desktop file of an application.
+    pub fn desktop_file(&self, id: &str) -> Option<PathBuf> {
+        find_desktop_file(&self.applications_dir(), &desktop_file_id(id))
+    }
+    /// Find the desktop file of an installed application in any [applications directory](XdgBackend::applications_dirs).
+    ///
+    /// Desktop entries with `Hidden=true` count as deleted, and hide the ones of less important directories.
+    pub fn installed_desktop_file(&self, id: &str) -> Option<PathBuf> {
+        let desktop_file_id = desktop_file_id(id);
+        let path = self
+            .applications_dirs()
+            .iter()
+            .find_map(|dir| find_desktop_file(dir, &desktop_file_id))?;
+        let hidden = DesktopEntry::read(&path).ok()?.is_hidden();
+        (!hidden).then_some(path)
+    }
+    /// Regenerate the `mimeinfo.cache` of the applications directory if [enabled](XdgBackend::update_desktop_database).
+    fn desktop_entries_changed(&self) -> io::Result<()> {
+        if self.update_desktop_database {
+            update_desktop_database(self.applications_dir())?;
+        }
+        Ok(())
+    }
+    fn existing_desktop_file(&self, id: &str) -> io::Result<PathBuf> {
+        self.desktop_file(id)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    /// Find the desktop file ID of the application opening a MIME type by default.
+    ///
+    /// The first installed application listed under `[Default Applications]` of the most important `mimeapps.list` wins.
+    /// Without one, the first installed application associated with the MIME type is used: the ones listed under
+    /// `[Added Associations]`, then the ones listing the MIME type in their desktop entry, except for those listed
+    /// under `[Removed Associations]` of the same or a more important `mimeapps.list`.
+    fn default_desktop_file_id(&self, mime_type: &str) -> io::Result<Option<String>> {
+        let lists = self
+            .mimeapps_lists()
+            .iter()
+            .map(MimeAppsList::read_or_default)
+            .collect::<io::Result<Vec<MimeAppsList>>>()?;
+        let installed =
+            |desktop_file_id: &String| self.installed_desktop_file(desktop_file_id).is_some();
+
+        for list in &lists {
+            let defaults = list.get(Group::DefaultApplications, mime_type);
+            if let Some(default) = defaults.into_iter().find(installed) {
+                return Ok(Some(default));
+            }
+        }
+
+        let mut removed = Vec::new();
+        for list in &lists {
+            removed.extend(list.get(Group::RemovedAssociations, mime_type));
+            let added = list.get(Group::AddedAssociations, mime_type);
+            if let Some(added) = added
+                .into_iter()
+                .find(|added| !removed.contains(added) && installed(added))
+            {
+                return Ok(Some(added));
+            }
+        }
+        for dir in self.applications_dirs() {
+            for (desktop_file_id, path) in desktop_files(&dir) {
+                if removed.contains(&desktop_file_id) || !installed(&desktop_file_id) {
+                    continue;
+                }
+                let mime_types = DesktopEntry::read(&path)?.mime_types();
+                if mime_types.iter().any(|existing| existing == mime_type) {
+                    return Ok(Some(desktop_file_id));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Make the application the first entry of `mime_type` in `[Default Applications]`, returning the previous first entry.
+    fn set_default(&self, id: &str, mime_type: &str) -> io::Result<Option<String>> {
+        let desktop_file_id = desktop_file_id(id);
+        let path = self.mimeapps_list();
+        let mut list = MimeAppsList::read_or_default(&path)?;
+
+        let unhidden = list.remove_entry(Group::RemovedAssociations, mime_type, &desktop_file_id);
+        let previous = list.default_application(mime_type);
+        let changed = list.prepend(Group::DefaultApplications, mime_type, &desktop_file_id);
+        if unhidden || changed {
+            list.write(&path)?;
+        }
+        if !changed {
+            return Ok(None);
+        }
+
+        Ok(previous.map(|previous| {
+            previous
+                .strip_suffix(DESKTOP_FILE_EXTENSION)
+                .unwrap_or(&previous)
+                .to_string()
+        }))
+    }
+}
+fn without(dirs: &[PathBuf], excluded: &Path) -> Vec<PathBuf> {
+    dirs.iter()
+        .filter(|dir| dir.as_path() != excluded)
+        .cloned()
+        .collect()
+}
+impl Default for XdgBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Backend for XdgBackend {
+    fn scoped(&self, scope: Scope) -> Result<Arc<dyn Backend>> {
+        Ok(Arc::new(self.clone().scope(scope)))
+    }
+    fn dry_run(&self, plan: &Plan) -> Result<Arc<dyn Backend>> {
+        Ok(Arc::new(DryRunBackend::new(self, plan)?))
+    }
+    fn create(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        let path = self.desktop_file(&descriptor.id).unwrap_or_else(|| {
+            self.applications_dir()
+                .join(desktop_file_id(&descriptor.id))
+        });
+        if !descriptor.icon_images.is_empty() {
+            install_icon(
+                &self.icon_theme_dir(),
+                IconContext::Applications,
+                &icon_name(&descriptor.id),
+                &descriptor.icon_images,
+            )?;
+        }
+        let existing = match DesktopEntry::read(&path) {
+            Err(why) if why.kind() == io::ErrorKind::NotFound => DesktopEntry::new(),
+            result => result?,
+        };
+        merge_desktop_entry(existing, &desktop_entry(executable, descriptor)).write(&path)?;
+        Ok(self.desktop_entries_changed()?)
+    }
+    fn validate(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+        let errors: Vec<String> = desktop_entry(executable, descriptor)
+            .validate()
+            .into_iter()
+            .filter(Issue::is_error)
+            .map(|issue| issue.to_string())
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidDesktopEntry(errors))
+        }
+    }
+    fn get(&self, id: &str) -> Result<Option<ApplicationDescriptor>> {
+        match self.desktop_file(id) {
+            Some(path) => Ok(Some(read_descriptor(id, &path)?)),
+            None => Ok(None),
+        }
+    }
+    fn executable(&self, id: &str) -> Result<Option<String>> {
+        let Some(path) = self.desktop_file(id) else {
+            return Ok(None);
+        };
+        let file = DesktopEntry::read(&path)?;
+        match file.exec() {
+            Some(exec) => Ok(Some(
+                CommandTemplate::parse(&exec, Syntax::DesktopEntry)?
+                    .program()
+                    .to_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+    /// Removes the desktop entry and icons of the application, and every entry of the `mimeapps.list` of the scope
+    /// naming its desktop file ID.
+    fn delete(&self, id: &str) -> Result<()> {
+        fs::remove_file(self.existing_desktop_file(id)?)?;
+        uninstall_icon(
+            &self.icon_theme_dir(),
+            IconContext::Applications,
+            &icon_name(id),
+        )?;
+        let path = self.mimeapps_list();
+        let mut list = MimeAppsList::read_or_default(&path)?;
+        if list.remove_application(&desktop_file_id(id)) {
+            list.write(&path)?;
+        }
+        Ok(self.desktop_entries_changed()?)
+    }
+    fn enumerate(&self, id: &str) -> Result<Vec<String>> {
+        let file = DesktopEntry::read(&self.existing_desktop_file(id)?)?;
+        Ok(file.mime_types())
+    }
+    /// Reports the MIME types of the desktop entry in the scope of the backend, followed by the ones the application is
+    /// listed for under `[Default Applications]` or `[Added Associations]` of each `mimeapps.list`, in the scope of the list.
+    fn associations(&self, id: &str) -> Result<Vec<Association>> {
+        let mut associations: Vec<Association> = self
+            .enumerate(id)?
+            .into_iter()
+            .map(|file_type| Association {
+                file_type,
+                scope: self.scope,
+            })
+            .collect();
+
+        let desktop_file_id = desktop_file_id(id);
+        for (path, scope) in self.scoped_mimeapps_lists() {
+            let list = MimeAppsList::read_or_default(&path)?;
+            for group in [Group::DefaultApplications, Group::AddedAssociations] {
+                for mime_type in list.mime_types(group) {
+                    let association = Association {
+                        file_type: mime_type,
+                        scope,
+                    };
+                    if !associations.contains(&association)
+                        && list
+                            .get(group, &association.file_type)
+                            .contains(&desktop_file_id)
+                    {
+                        associations.push(association);
+                    }
+                }
+            }
+        }
+        Ok(associations)
+    }
+    /// Looks the MIME type up in the desktop entry in the scope of the backend.
+    fn handles(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        let Some(path) = self.desktop_file(id) else {
+            return Ok(false);
+        };
+        let mime_type = self.mime_type(file_type)?;
+        Ok(DesktopEntry::read(&path)?
+            .mime_types()
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(mime_type.essence())))
+    }
+    /// Checks whether the application comes first under `[Default Applications]` of the `mimeapps.list` of the scope.
+    fn is_default_for(&self, id: &str, file_type: &FileType) -> Result<bool> {
+        let mime_type = self.mime_type(file_type)?;
+        let list = MimeAppsList::read_or_default(self.mimeapps_list())?;
+        Ok(list.default_application(mime_type.essence()) == Some(desktop_file_id(id)))
+    }
+    fn associate(&self, id: &str, file_type: &FileType, default: bool) -> Result<Option<String>> {
+        // Desktop entries can only refer to MIME types, so extensions are resolved to the MIME type they belong to
+        let mime_type = self.mime_type(file_type)?.essence().to_string();
+
+        let path = self.existing_desktop_file(id)?;
+        let mut file = DesktopEntry::read(&path)?;
+        let mut mime_types = file.mime_types();
+        if !mime_types
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(&mime_type))
+        {
+            mime_types.push(mime_type.clone());
+            file.set_list(DESKTOP_ENTRY_GROUP, "MimeType", &mime_types);
+            file.write(&path)?;
+            self.desktop_entries_changed()?;
+        }
+
+        if default {
+            Ok(self.set_default(id, &mime_type)?)
+        } else {
+            Ok(None)
+        }
+    }
+    /// Looks up the MIME type first, then its parents except for `application/octet-stream`,
+    /// so that e.g. a text editor opens `text/x-python` files if nothing handles them specifically.
+    fn default_application_for(
+        &self,
+        file_type: &FileType,
+    ) -> Result<Option<ApplicationDescriptor>> {
+        let mime_type = match self.mime_type(file_type) {
+            Ok(mime_type) => mime_type,
+            Err(Error::Unsupported) => return Ok(None),
+            Err(why) => return Err(why),
+        };
+        let database = self.mime_database()?;
+        let octet_stream = MimeType::parse("application/octet-stream").expect("valid MIME type");
+
+        let mut queue = std::collections::VecDeque::from([database.canonical(&mime_type)]);
+        let mut visited = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            if visited.contains(&current) || current == octet_stream {
+                continue;
+            }
+            if let Some(desktop_file_id) = self.default_desktop_file_id(current.essence())? {
+                let Some(path) = self.installed_desktop_file(&desktop_file_id) else {
+                    continue;
+                };
+                return Ok(Some(read_descriptor(&desktop_file_id, &path)?));
+            }
+            queue.extend(database.parents(&current));
+            visited.push(current);
+        }
+        Ok(None)
+    }
+    fn dissociate(&self, id: &str, file_type: &FileType) -> Result<()> {
+        let mime_type = self.mime_type(file_type)?;
+        let mime_type = mime_type.essence();
+
+        let path = self.existing_desktop_file(id)?;
+        let mut file = DesktopEntry::read(&path)?;
+        let mut mime_types = file.mime_types();
+        if mime_types
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(mime_type))
+        {
+            mime_types.retain(|existing| !existing.eq_ignore_ascii_case(mime_type));
+            if mime_types.is_empty() {
+                file.remove(DESKTOP_ENTRY_GROUP, "MimeType");
+            } else {
+                file.set_list(DESKTOP_ENTRY_GROUP, "MimeType", &mime_types);
+            }
+            file.write(&path)?;
+            self.desktop_entries_changed()?;
+        }
+
+        let desktop_file_id = desktop_file_id(id);
+        let path = self.mimeapps_list();
+        let mut list = MimeAppsList::read_or_default(&path)?;
+        let removed_default =
+            list.remove_entry(Group::DefaultApplications, mime_type, &desktop_file_id);
+        let removed_added =
+            list.remove_entry(Group::AddedAssociations, mime_type, &desktop_file_id);
+        if removed_default || removed_added {
+            list.write(&path)?;
+        }
+        Ok(())
+    }
+    /// Lists the application under `[Added Associations]` of the user's `mimeapps.list`,
+    /// after the applications listed there before, and removes it from `[Removed Associations]`.
+    fn add_association(&self, id: &str, file_type: &FileType) -> Result<()> {
+        let mime_type = self.mime_type(file_type)?;
+        let mime_type = mime_type.essence();
+        self.existing_desktop_file(id)?;
+
+        let desktop_file_id = desktop_file_id(id);
+        let path = self.mimeapps_list();
+        let mut list = MimeAppsList::read_or_default(&path)?;
+        let unhidden = list.remove_entry(Group::RemovedAssociations, mime_type, &desktop_file_id);
+        let added = list.add(Group::AddedAssociations, mime_type, &desktop_file_id);
+        if unhidden || added {
+            list.write(&path)?;
+        }
+        Ok(())
+    }
+    /// Lists the application under `[Removed Associations]` of the user's `mimeapps.list`,
+    /// and removes it from `[Default Applications]` and `[Added Associations]`.
+    /// The desktop entry of the application is left untouched.
+    fn hide_association(&self, id: &str, file_type: &FileType) -> Result<()> {
+        let mime_type = self.mime_type(file_type)?;
+        let mime_type = mime_type.essence();
+
+        let desktop_file_id = desktop_file_id(id);
+        let path = self.mimeapps_list();
+        let mut list = MimeAppsList::read_or_default(&path)?;
+        let removed_default =
+            list.remove_entry(Group::DefaultApplications, mime_type, &desktop_file_id);
+        let removed_added =
+            list.remove_entry(Group::AddedAssociations, mime_type, &desktop_file_id);
+        let hidden = list.add(Group::RemovedAssociations, mime_type, &desktop_file_id);
+        if removed_default || removed_added || hidden {
+            list.write(&path)?;
+        }
+        Ok(())
+    }
+    /// Writes the package to `$XDG_DATA_HOME/mime/packages/<id>.xml` and [regenerates](update_mime_database) the database.
+    ///
+    /// Icon images of the MIME types are installed into the icon theme, and the package refers to them by name.
+    fn register_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+        let mut package = package.clone();
+        for definition in &mut package.mime_types {
+            if !definition.icon_images.is_empty() {
+                install_icon(
+                    &self.icon_theme_dir(),
+                    IconContext::MimeTypes,
+                    &definition.icon_name(),
+                    &definition.icon_images,
+                )?;
+                definition.icon = Some(definition.icon_name());
+            }
+        }
+        package.write(self.mime_package(id))?;
+        Ok(update_mime_database(self.scope_data_dir().join("mime"))?)
+    }
+    fn unregister_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+        for definition in &package.mime_types {
+            if !definition.icon_images.is_empty() {
+                uninstall_icon(
+                    &self.icon_theme_dir(),
+                    IconContext::MimeTypes,
+                    &definition.icon_name(),
+                )?;
+            }
+        }
+        match fs::remove_file(self.mime_package(id)) {
+            Ok(()) => Ok(update_mime_database(self.scope_data_dir().join("mime"))?),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(why) => Err(why.into()),
+        }
+    }
+    /// Captures the desktop entries, application icons and MIME packages of the applications, the entries of the
+    /// `mimeapps.list` of the scope listing them, and the entries of the MIME types of `file_types` in every group.
+    fn snapshot(&self, ids: &[String], file_types: &[FileType]) -> Result<Snapshot> {
+        let mut snapshot = Snapshot::new(self.scope);
+        let mimeapps_list = self.mimeapps_list();
+        let list = read_key_file(&mimeapps_list)?;
+        let capture_entry = |snapshot: &mut Snapshot, group: Group, key: &str| {
+            snapshot.push(
+                Target::KeyFileEntry {
+                    path: mimeapps_list.clone(),
+                    group: group.name().to_string(),
+                    key: key.to_string(),
+                },
+                list.get_raw(group.name(), key)
+                    .map(|raw| Value::Text(raw.to_string())),
+            );
+        };
+
+        for id in ids {
+            snapshot.applications.push(id.clone());
+            let desktop_file_id = desktop_file_id(id);
+            let desktop_file = self
+                .desktop_file(id)
+                .unwrap_or_else(|| self.applications_dir().join(&desktop_file_id));
+            let icons = installed_icons(
+                &self.icon_theme_dir(),
+                IconContext::Applications,
+                &icon_name(id),
+            )?;
+            for path in [desktop_file, self.mime_package(id)]
+                .into_iter()
+                .chain(icons)
+            {
+                let contents = read_optional(&path)?.map(Value::from_bytes);
+                snapshot.push(Target::File(path), contents);
+            }
+            for group in Group::ALL {
+                for mime_type in list.keys(group.name()) {
+                    let listed = list
+                        .get_list(group.name(), mime_type)
+                        .unwrap_or_default()
+                        .contains(&desktop_file_id);
+                    if listed {
+                        capture_entry(&mut snapshot, group, mime_type);
+                    }
+                }
+            }
+        }
+        for file_type in file_types {
+            snapshot.file_types.push(file_type.clone());
+            let mime_type = self.mime_type(file_type)?;
+            for group in Group::ALL {
+                capture_entry(&mut snapshot, group, mime_type.essence());
+            }
+        }
+        Ok(snapshot)
+    }
+    /// Only restores files below the data and config directories of the scope of the snapshot, and fails with
+    /// [io::ErrorKind::InvalidData] before changing anything if the snapshot has other ones.
+    fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let backend = self.clone().scope(snapshot.scope);
+        let mut desktop_entries_changed = false;
+        let mut packages_changed = false;
+
+        // Every target is checked before anything is touched, so a snapshot which can't be restored changes nothing
+        for entry in &snapshot.entries {
+            let path = match (&entry.target, &entry.value) {
+                (Target::File(path), _) => path,
+                (Target::KeyFileEntry { path, .. }, Some(Value::Text(_)) | None) => path,
+                (Target::KeyFileEntry { .. }, Some(_)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: not a key file value", entry.target),
+                    )
+                    .into())
+                }
+                (Target::RegistryKey(_) | Target::RegistryValue { .. }, _) => {
+                    return Err(Error::Unsupported)
+                }
+            };
+            if !is_below(path, backend.scope_data_dir())
+                && !is_below(path, backend.scope_config_dir())
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: outside the directories of the backend", path.display()),
+                )
+                .into());
+            }
+        }
+
+        // Key files are edited in memory and written at the end, each with its contents before the restore
+        let mut key_files: BTreeMap<PathBuf, (KeyFile, KeyFile)> = BTreeMap::new();
+
+        // What the applications got since the snapshot was taken is removed before the captured state is put back
+        let mimeapps_list = backend.mimeapps_list();
+        for id in &snapshot.applications {
+            if let Some(path) = backend.desktop_file(id) {
+                if snapshot.get(&Target::File(path.clone())).is_none() {
+                    fs::remove_file(path)?;
+                    desktop_entries_changed = true;
+                }
+            }
+            uninstall_icon(
+                &backend.icon_theme_dir(),
+                IconContext::Applications,
+                &icon_name(id),
+            )?;
+            let desktop_file_id = desktop_file_id(id);
+            let list = edited_key_file(&mut key_files, &mimeapps_list)?;
+            for group in Group::ALL {
+                let mime_types: Vec<String> = list
+                    .keys(group.name())
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                for mime_type in mime_types {
+                    let mut desktop_file_ids =
+                        list.get_list(group.name(), &mime_type).unwrap_or_default();
+                    if !desktop_file_ids.contains(&desktop_file_id) {
+                        continue;
+                    }
+                    desktop_file_ids.retain(|listed| listed != &desktop_file_id);
+                    if desktop_file_ids.is_empty() {
+                        list.remove(group.name(), &mime_type);
+                    } else {
+                        list.set_list(group.name(), &mime_type, &desktop_file_ids);
+                    }
+                }
+            }
+        }
+
+        for entry in &snapshot.entries {
+            match &entry.target {
+                Target::File(path) => {
+                    let contents = entry.value.as_ref().map(Value::bytes);
+                    if read_optional(path)?.as_deref() == contents {
+                        continue;
+                    }
+                    match contents {
+                        Some(contents) => write_atomically(path, contents)?,
+                        None => fs::remove_file(path)?,
+                    }
+                    desktop_entries_changed |= path.starts_with(backend.applications_dir());
+                    packages_changed |=
+                        path.parent().and_then(Path::file_name) == Some(PACKAGES_DIR.as_ref());
+                }
+                Target::KeyFileEntry { path, group, key } => {
+                    let file = edited_key_file(&mut key_files, path)?;
+                    match &entry.value {
+                        Some(Value::Text(raw)) => file.set_raw(group, key, raw),
+                        _ => {
+                            file.remove(group, key);
+                        }
+                    }
+                }
+                Target::RegistryKey(_) | Target::RegistryValue { .. } => unreachable!(),
+            }
+        }
+
+        // Groups emptied by the restore go as well, and so do files left empty
+        for (path, (original, mut file)) in key_files {
+            for group in original.groups() {
+                if !original.keys(group).is_empty() {
+                    file.remove_empty_group(group);
+                }
+            }
+            if file == original {
+                continue;
+            }
+            if file.to_string().is_empty() {
+                match fs::remove_file(&path) {
+                    Err(why) if why.kind() != io::ErrorKind::NotFound => return Err(why.into()),
+                    _ => (),
+                }
+            } else {
+                file.write(&path)?;
+            }
+        }
+
+        if packages_changed {
+            update_mime_database(backend.scope_data_dir().join("mime"))?;
+        }
+        if desktop_entries_changed {
+            backend.desktop_entries_changed()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icon::IconImage;
+    use crate::mime::MimeTypeDefinition;
+    use crate::plan::{Target, Value};
+    use crate::snapshot::Snapshot;
+    use std::ffi::OsString;
+    use std::{env, process};
+
+    fn temporary_dir(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("fileext-xdg-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+    fn temporary_backend(dir: &Path) -> XdgBackend {
+        XdgBackend::with_dirs(dir.join("data"), dir.join("config"))
+    }
+
+    #[test]
+    fn quote() {
+        assert_eq!(quote_exec_arg("/usr/bin/app"), "/usr/bin/app");
+        assert_eq!(
+            quote_exec_arg("/opt/My App/app"),
+            "\"/opt/My App/app\"".to_string()
+        );
+        assert_eq!(quote_exec_arg("a\"b$c"), "\"a\\\"b\\$c\"");
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            split_exec("\"/opt/My App/app\" --new-window %F").unwrap(),
+            vec!["/opt/My App/app", "--new-window", "%F"]
+        );
+        assert_eq!(split_exec("  a\"b\\\"c\"  ").unwrap(), vec!["ab\"c"]);
+        assert_eq!(split_exec("app \"unterminated"), None);
+        for arg in ["/usr/bin/app", "/opt/My App/app", "a\"b$c\\d"] {
+            assert_eq!(split_exec(&quote_exec_arg(arg)).unwrap(), vec![arg]);
+        }
+    }
+
+    #[test]
+    fn lifecycle() {
+        let dir = temporary_dir("lifecycle");
+        let backend = temporary_backend(&dir);
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test Application"),
+            icon_path: String::from("org.example.Test"),
+            ..Default::default()
+        };
+
+        backend.create("/opt/My App/app", &descriptor).unwrap();
+        let contents =
+            fs::read_to_string(dir.join("data/applications/org.example.Test.desktop")).unwrap();
+        assert_eq!(
+            contents,
+            "[Desktop Entry]\nType=Application\nName=Test Application\nExec=\"/opt/My App/app\" %F\nIcon=org.example.Test\n"
+        );
+        let registered = backend.get("org.example.Test.desktop").unwrap().unwrap();
+        assert_eq!(registered.id, descriptor.id);
+        assert_eq!(registered.name, descriptor.name);
+        assert_eq!(registered.icon_path, descriptor.icon_path);
+        assert_eq!(
+            backend.executable("org.example.Test").unwrap().as_deref(),
+            Some("/opt/My App/app")
+        );
+
+        let plain = FileType::parse("text/plain").unwrap();
+        backend
+            .associate("org.example.Test", &plain, false)
+            .unwrap();
+        backend
+            .associate("org.example.Test", &plain, false)
+            .unwrap();
+        backend
+            .associate(
+                "org.example.Test",
+                &FileType::parse("text/csv").unwrap(),
+                false,
+            )
+            .unwrap();
+        assert!(matches!(
+            backend.associate("org.example.Test", &FileType::parse(".csv").unwrap(), false),
+            Err(Error::Unsupported)
+        ));
+        // MIME types are case-insensitive
+        backend
+            .associate(
+                "org.example.Test",
+                &FileType::parse("Text/Plain").unwrap(),
+                false,
+            )
+            .unwrap();
+        assert_eq!(
+            backend.enumerate("org.example.Test").unwrap(),
+            vec!["text/plain", "text/csv"]
+        );
+        backend
+            .dissociate("org.example.Test", &FileType::parse("TEXT/CSV").unwrap())
+            .unwrap();
+        assert_eq!(
+            backend.enumerate("org.example.Test").unwrap(),
+            vec!["text/plain"]
+        );
+
+        backend.associate("org.example.Test", &plain, true).unwrap();
+        backend
+            .hide_association("org.example.Test", &FileType::parse("image/png").unwrap())
+            .unwrap();
+        let mimeapps_list = dir.join("config/mimeapps.list");
+        fs::write(
+            &mimeapps_list,
+            fs::read_to_string(&mimeapps_list).unwrap()
+                + "\n[Added Associations]\ntext/csv=org.example.Test.desktop;other.desktop;\n",
+        )
+        .unwrap();
+
+        backend.delete("org.example.Test").unwrap();
+        assert_eq!(backend.get("org.example.Test").unwrap(), None);
+        assert_eq!(
+            fs::read_to_string(&mimeapps_list).unwrap(),
+            "[Added Associations]\ntext/csv=other.desktop;\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recreate() {
+        let dir = temporary_dir("recreate");
+        let backend = temporary_backend(&dir);
+        let mut descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test"),
+            icon_path: String::from("org.example.Test"),
+            verbs: vec![Verb {
+                name: String::from("edit"),
+                label: String::from("Edit"),
+                command: CommandTemplate::new("/usr/bin/test")
+                    .arg("--edit")
+                    .arg(Arg::File),
+            }],
+            ..Default::default()
+        };
+        backend.create("/usr/bin/test", &descriptor).unwrap();
+        backend
+            .associate(
+                "org.example.Test",
+                &FileType::parse("text/plain").unwrap(),
+                false,
+            )
+            .unwrap();
+        let path = dir.join("data/applications/org.example.Test.desktop");
+        let contents = fs::read_to_string(&path)
+            .unwrap()
+            .replace("Type=Application\n", "Type=Application\nX-Vendor=kept\n");
+        fs::write(&path, contents).unwrap();
+
+        descriptor.name = String::from("Renamed");
+        descriptor.icon_path = String::new();
+        descriptor.verbs.clear();
+        backend.create("/usr/bin/test", &descriptor).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[Desktop Entry]\nType=Application\nX-Vendor=kept\nName=Renamed\nExec=/usr/bin/test %F\nMimeType=text/plain;\n"
+        );
+        assert_eq!(
+            backend.enumerate("org.example.Test").unwrap(),
+            vec!["text/plain"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extensions() {
+        let dir = temporary_dir("extensions");
+        fs::create_dir_all(dir.join("system/mime")).unwrap();
+        fs::write(
+            dir.join("system/mime/globs2"),
+            "50:application/gzip:*.gz\n50:application/x-compressed-tar:*.tar.gz\n",
+        )
+        .unwrap();
+        let backend = temporary_backend(&dir).data_dirs([dir.join("system")]);
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test"),
+            ..Default::default()
+        };
+        backend.create("/usr/bin/test", &descriptor).unwrap();
+
+        let extension = FileType::extension("tar.gz");
+        backend
+            .associate("org.example.Test", &extension, false)
+            .unwrap();
+        assert_eq!(
+            backend.enumerate("org.example.Test").unwrap(),
+            vec!["application/x-compressed-tar"]
+        );
+        backend.dissociate("org.example.Test", &extension).unwrap();
+        assert!(backend.enumerate("org.example.Test").unwrap().is_empty());
+        assert!(matches!(
+            backend.associate("org.example.Test", &FileType::extension("zst"), false),
+            Err(Error::Unsupported)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn default_application_for() {
+        let dir = temporary_dir("default-for");
+        let (user, system) = (
+            dir.join("data/applications"),
+            dir.join("system/applications"),
+        );
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(system.join("kde4")).unwrap();
+        fs::create_dir_all(dir.join("system/mime")).unwrap();
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::create_dir_all(dir.join("xdg")).unwrap();
+        let entry = |name: &str, mime_types: &str| {
+            format!("[Desktop Entry]\nType=Application\nName={name}\nExec={name} %f\nIcon={name}\nMimeType={mime_types}\n")
+        };
+        fs::write(system.join("gedit.desktop"), entry("gedit", "text/plain;")).unwrap();
+        fs::write(
+            system.join("kde4/kate.desktop"),
+            entry("kate", "text/plain;text/csv;"),
+        )
+        .unwrap();
+        fs::write(system.join("calc.desktop"), entry("calc", "text/csv;")).unwrap();
+        fs::write(
+            system.join("removed.desktop"),
+            entry("removed", "image/png;"),
+        )
+        .unwrap();
+        fs::write(user.join("calc.desktop"), "[Desktop Entry]\nHidden=true\n").unwrap();
+        fs::write(
+            dir.join("system/mime/globs2"),
+            "50:text/csv:*.csv\n50:text/x-python:*.py\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("xdg/mimeapps.list"),
+            "[Default Applications]\ntext/plain=missing.desktop;gedit.desktop;\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config/mimeapps.list"),
+            "[Default Applications]\ntext/plain=missing.desktop;\n\n[Removed Associations]\nimage/png=removed.desktop;\n",
+        )
+        .unwrap();
+
+        let backend = temporary_backend(&dir)
+            .data_dirs([dir.join("system")])
+            .config_dirs([dir.join("xdg")]);
+        let default = |file_type: &str| {
+            backend
+                .default_application_for(&FileType::parse(file_type).unwrap())
+                .unwrap()
+                .map(|descriptor| descriptor.id)
+        };
+        // Defaults of less important lists are used when the ones of more important lists aren't installed
+        assert_eq!(default("text/plain").as_deref(), Some("gedit"));
+        // Without a default, the first application associated with the type is used, unless it's hidden or removed
+        assert_eq!(default(".csv").as_deref(), Some("kde4-kate"));
+        assert_eq!(default("image/png"), None);
+        // Parents of the type are used last
+        assert_eq!(default("py").as_deref(), Some("gedit"));
+        assert_eq!(default(".unknown"), None);
+
+        let gedit = backend
+            .default_application_for(&FileType::parse("text/plain").unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(gedit.name, "gedit");
+        assert_eq!(gedit.icon_path, "gedit");
+        assert_eq!(
+            gedit.primary_verb().unwrap().command,
+            CommandTemplate::new("gedit").arg(Arg::File)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mimeapps() {
+        let dir = temporary_dir("mimeapps");
+        let mimeapps = dir.join("config/mimeapps.list");
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(
+            &mimeapps,
+            "[Added Associations]\ntext/plain=gedit.desktop;\n\n# Keep this\n[Removed Associations]\ntext/html=org.example.Test.desktop;\n",
+        )
+        .unwrap();
+        let backend = temporary_backend(&dir);
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test"),
+            ..Default::default()
+        };
+        backend.create("/usr/bin/test", &descriptor).unwrap();
+
+        let plain = FileType::parse("text/plain").unwrap();
+        let html = FileType::parse("text/html").unwrap();
+        backend.add_association("org.example.Test", &plain).unwrap();
+        backend.add_association("org.example.Test", &plain).unwrap();
+        backend.associate("org.example.Test", &html, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(&mimeapps).unwrap(),
+            "[Added Associations]\ntext/plain=gedit.desktop;org.example.Test.desktop;\n\n# Keep this\n\n[Default Applications]\ntext/html=org.example.Test.desktop;\n"
+        );
+
+        backend
+            .hide_association("org.example.Test", &plain)
+            .unwrap();
+        backend.hide_association("org.example.Test", &html).unwrap();
+        assert_eq!(
+            fs::read_to_string(&mimeapps).unwrap(),
+            "[Added Associations]\ntext/plain=gedit.desktop;\n\n# Keep this\n\n[Removed Associations]\ntext/plain=org.example.Test.desktop;\ntext/html=org.example.Test.desktop;\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn verbs() {
+        let dir = temporary_dir("verbs");
+        let backend = temporary_backend(&dir);
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test"),
+            verbs: vec![
+                Verb {
+                    name: String::from("open"),
+                    label: String::new(),
+                    command: CommandTemplate::new("/usr/bin/test").arg(Arg::Urls),
+                },
+                Verb {
+                    name: String::from("new-window"),
+                    label: String::from("New Window"),
+                    command: CommandTemplate::new("/usr/bin/test")
+                        .arg("--new-window")
+                        .arg(Arg::Urls),
+                },
+            ],
+            ..Default::default()
+        };
+
+        backend.create("/usr/bin/test", &descriptor).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("data/applications/org.example.Test.desktop")).unwrap(),
+            "[Desktop Entry]\nType=Application\nName=Test\nExec=/usr/bin/test %U\nActions=new-window;\n\n[Desktop Action new-window]\nName=New Window\nExec=/usr/bin/test --new-window %U\n"
+        );
+        assert_eq!(backend.get("org.example.Test").unwrap(), Some(descriptor));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn subdirectory_id() {
+        let dir = temporary_dir("subdirectory");
+        let applications = dir.join("data/applications/kde4");
+        fs::create_dir_all(&applications).unwrap();
+        fs::write(
+            applications.join("kate.desktop"),
+            "# Comment\n[Desktop Entry]\nName=Kate\n\n[Desktop Action new]\nName=New\n",
+        )
+        .unwrap();
+
+        let backend = temporary_backend(&dir);
+        assert_eq!(backend.get("kde4-kate").unwrap().unwrap().name, "Kate");
+
+        backend
+            .associate("kde4-kate", &FileType::parse("text/plain").unwrap(), false)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(applications.join("kate.desktop")).unwrap(),
+            "# Comment\n[Desktop Entry]\nName=Kate\nMimeType=text/plain;\n\n[Desktop Action new]\nName=New\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn default_application() {
+        let dir = temporary_dir("default");
+        let mimeapps = dir.join("config/mimeapps.list");
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(
+            &mimeapps,
+            "# Managed by hand\n[Default Applications]\ntext/plain=gedit.desktop;\nimage/png=eog.desktop;\n",
+        )
+        .unwrap();
+
+        let backend = temporary_backend(&dir);
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test"),
+            ..Default::default()
+        };
+        backend.create("/usr/bin/test", &descriptor).unwrap();
+
+        let plain = FileType::parse("text/plain").unwrap();
+        assert_eq!(
+            backend.associate("org.example.Test", &plain, true).unwrap(),
+            Some(String::from("gedit"))
+        );
+        assert_eq!(
+            backend.associate("org.example.Test", &plain, true).unwrap(),
+            None
+        );
+        backend
+            .associate(
+                "org.example.Test",
+                &FileType::parse("text/csv").unwrap(),
+                true,
+            )
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&mimeapps).unwrap(),
+            "# Managed by hand\n[Default Applications]\ntext/plain=org.example.Test.desktop;gedit.desktop;\nimage/png=eog.desktop;\ntext/csv=org.example.Test.desktop;\n"
+        );
+
+        backend.dissociate("org.example.Test", &plain).unwrap();
+        backend
+            .dissociate("org.example.Test", &FileType::parse("text/csv").unwrap())
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&mimeapps).unwrap(),
+            "# Managed by hand\n[Default Applications]\ntext/plain=gedit.desktop;\nimage/png=eog.desktop;\n"
+        );
+        assert!(backend.enumerate("org.example.Test").unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn strict() {
+        let dir = temporary_dir("strict");
+        let options = crate::app::OpenOptions::current()
+            .write(true)
+            .strict(true)
+            .backend(temporary_backend(&dir));
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test Application"),
+            ..Default::default()
+        };
+
+        let result = options.clone().create(ApplicationDescriptor {
+            name: String::new(),
+            verbs: vec![Verb {
+                name: String::from("new window"),
+                command: CommandTemplate::new("/usr/bin/app").arg("--new-window"),
+                ..Default::default()
+            }],
+            ..descriptor.clone()
+        });
+        let Err(Error::InvalidDesktopEntry(errors)) = result else {
+            panic!("{result:?}");
+        };
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(!dir
+            .join("data/applications/org.example.Test.desktop")
+            .exists());
+
+        options.create(descriptor).unwrap();
+        assert!(
+            DesktopEntry::read(dir.join("data/applications/org.example.Test.desktop"))
+                .unwrap()
+                .is_valid()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mime_packages() {
+        let dir = temporary_dir("mime-packages");
+        let backend = temporary_backend(&dir);
+        let package = MimePackage::new().mime_type(
+            MimeTypeDefinition::new(
+                MimeType::parse("application/x-fileext-test").unwrap(),
+                "Test document",
+            )
+            .glob("*.fileext-test")
+            .sub_class_of(MimeType::parse("text/plain").unwrap()),
+        );
+
+        backend
+            .register_mime_package("org.example.Test", &package)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("data/mime/packages/org.example.Test.xml")).unwrap(),
+            package.to_string()
+        );
+        let database = backend.mime_database().unwrap();
+        let mime_type = MimeType::parse("application/x-fileext-test").unwrap();
+        assert_eq!(
+            database.mime_types_for_path("document.fileext-test"),
+            vec![mime_type.clone()]
+        );
+        assert!(database.is_subclass_of(&mime_type, &MimeType::parse("text/plain").unwrap()));
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test Application"),
+            ..Default::default()
+        };
+        backend.create("/usr/bin/app", &descriptor).unwrap();
+        backend
+            .associate(
+                "org.example.Test",
+                &FileType::extension("fileext-test"),
+                true,
+            )
+            .unwrap();
+        assert_eq!(
+            backend.enumerate("org.example.Test").unwrap(),
+            vec!["application/x-fileext-test"]
+        );
+
+        backend
+            .unregister_mime_package("org.example.Test", &package)
+            .unwrap();
+        assert!(!dir.join("data/mime/packages/org.example.Test.xml").exists());
+        assert!(backend
+            .mime_database()
+            .unwrap()
+            .mime_types_for_path("document.fileext-test")
+            .is_empty());
+        backend
+            .unregister_mime_package("org.example.Test", &package)
+            .unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn desktop_database() {
+        let dir = temporary_dir("desktop-database");
+        let backend = temporary_backend(&dir).update_desktop_database(true);
+        let cache = dir.join("data/applications").join(MIMEINFO_CACHE);
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test Application"),
+            ..Default::default()
+        };
+        let mime_type = FileType::MimeType(MimeType::parse("text/plain").unwrap());
+
+        backend.create("/usr/bin/app", &descriptor).unwrap();
+        assert_eq!(fs::read_to_string(&cache).unwrap(), "[MIME Cache]\n");
+        backend
+            .associate("org.example.Test", &mime_type, false)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&cache).unwrap(),
+            "[MIME Cache]\ntext/plain=org.example.Test.desktop;\n"
+        );
+        backend.dissociate("org.example.Test", &mime_type).unwrap();
+        assert_eq!(fs::read_to_string(&cache).unwrap(), "[MIME Cache]\n");
+        backend
+            .associate("org.example.Test", &mime_type, false)
+            .unwrap();
+        backend.delete("org.example.Test").unwrap();
+        assert_eq!(fs::read_to_string(&cache).unwrap(), "[MIME Cache]\n");
+
+        let backend = temporary_backend(&dir);
+        backend.create("/usr/bin/app", &descriptor).unwrap();
+        backend
+            .associate("org.example.Test", &mime_type, false)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&cache).unwrap(), "[MIME Cache]\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn icons() {
+        let dir = temporary_dir("icons");
+        let backend = temporary_backend(&dir);
+        let theme = backend.icon_theme_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend([0, 0, 0, 48, 0, 0, 0, 48]);
+        fs::write(dir.join("icon.png"), &png).unwrap();
+        fs::write(dir.join("icon.svg"), "<svg/>").unwrap();
+        let png = IconImage::new(dir.join("icon.png")).unwrap();
+        let svg = IconImage::new(dir.join("icon.svg")).unwrap();
+
+        let mut descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test Application"),
+            icon_path: String::from("/unused/icon.png"),
+            icon_images: vec![png.clone(), svg.clone()],
+            ..Default::default()
+        };
+        backend.create("/usr/bin/app", &descriptor).unwrap();
+        assert!(theme.join("48x48/apps/org.example.Test.png").is_file());
+        assert!(theme.join("scalable/apps/org.example.Test.svg").is_file());
+        let installed = backend.get("org.example.Test").unwrap().unwrap();
+        assert_eq!(installed.icon_path, "org.example.Test");
+
+        descriptor.icon_images = vec![png.clone()];
+        backend.create("/usr/bin/app", &descriptor).unwrap();
+        assert!(theme.join("48x48/apps/org.example.Test.png").is_file());
+        assert!(!theme.join("scalable/apps/org.example.Test.svg").exists());
+        descriptor.icon_images = vec![IconImage::png(dir.join("icon.png"), 64)];
+        assert!(matches!(
+            backend.create("/usr/bin/app", &descriptor),
+            Err(Error::InvalidIcon(_))
+        ));
+        assert!(theme.join("48x48/apps/org.example.Test.png").is_file());
+
+        let package = MimePackage::new().mime_type(
+            MimeTypeDefinition::new(
+                MimeType::parse("application/x-fileext-test").unwrap(),
+                "Test document",
+            )
+            .icon_image(svg),
+        );
+        backend
+            .register_mime_package("org.example.Test", &package)
+            .unwrap();
+        assert!(theme
+            .join("scalable/mimetypes/application-x-fileext-test.svg")
+            .is_file());
+        assert_eq!(
+            fs::read_to_string(dir.join("data/mime/icons")).unwrap(),
+            "application/x-fileext-test:application-x-fileext-test\n"
+        );
+        backend
+            .unregister_mime_package("org.example.Test", &package)
+            .unwrap();
+        assert!(!theme
+            .join("scalable/mimetypes/application-x-fileext-test.svg")
+            .exists());
+
+        backend.delete("org.example.Test").unwrap();
+        assert!(!theme.join("48x48/apps/org.example.Test.png").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scopes() {
+        let dir = temporary_dir("scopes");
+        let user = temporary_backend(&dir)
+            .data_dirs([dir.join("system")])
+            .config_dirs([dir.join("xdg")])
+            .system_dirs(dir.join("system"), dir.join("xdg"));
+        let system = user.scoped(Scope::System).unwrap();
+        let descriptor = ApplicationDescriptor {
+            id: String::from("org.example.Test"),
+            name: String::from("Test Application"),
+            ..Default::default()
+        };
+        let plain = FileType::parse("text/plain").unwrap();
+        let csv = FileType::parse("text/csv").unwrap();
+
+        system.create("/usr/bin/app", &descriptor).unwrap();
+        system.associate("org.example.Test", &plain, true).unwrap();
+        assert!(dir
+            .join("system/applications/org.example.Test.desktop")
+            .is_file());
+        assert!(dir.join("xdg/mimeapps.list").is_file());
+        assert_eq!(user.get("org.example.Test").unwrap(), None);
+        assert_eq!(
+            user.default_application_for(&plain).unwrap().unwrap().id,
+            "org.example.Test"
+        );
+
+        user.create("/usr/bin/app", &descriptor).unwrap();
+        user.associate("org.example.Test", &csv, false).unwrap();
+        assert!(dir
+            .join("data/applications/org.example.Test.desktop")
+            .is_file());
+        assert_eq!(
+            user.associations("org.example.Test").unwrap(),
+            vec![
+                Association {
+                    file_type: String::from("text/csv"),
+                    scope: Scope::User,
+                },
+                Association {
+                    file_type: String::from("text/plain"),
+                    scope: Scope::System,
+                },
+            ]
+        );
+        assert_eq!(
+            system.associations("org.example.Test").unwrap(),
+            vec![Association {
+                file_type: String::from("text/plain"),
+                scope: Scope::System,
+            }]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn current_desktop() {
+        let dir = temporary_dir("current-desktop");
+        let applications = dir.join(".local/share/applications");
+        fs::create_dir_all(&applications).unwrap();
+        fs::create_dir_all(dir.join(".config")).unwrap();
+        fs::create_dir_all(dir.join("xdg")).unwrap();
+        for name in ["gedit", "kate", "kwrite"] {
+            fs::write(
+                applications.join(format!("{name}.desktop")),
+                format!("[Desktop Entry]\nType=Application\nName={name}\nExec={name} %f\n"),
+            )
+            .unwrap();
+        }
+        let default_for =
+            |list: &str| format!("[Default Applications]\ntext/plain={list}.desktop;\n");
+        fs::write(dir.join(".config/mimeapps.list"), default_for("gedit")).unwrap();
+        fs::write(dir.join("xdg/kde-mimeapps.list"), default_for("kwrite")).unwrap();
+
+        let backend = XdgBackend::with_base_dirs(BaseDirs::from_vars([
+            (OsString::from("HOME"), dir.clone().into_os_string()),
+            (
+                OsString::from("XDG_DATA_DIRS"),
+                dir.join("share").into_os_string(),
+            ),
+            (
+                OsString::from("XDG_CONFIG_DIRS"),
+                dir.join("xdg").into_os_string(),
+            ),
+            (OsString::from("XDG_CURRENT_DESKTOP"), OsString::from("KDE")),
+        ]));
+        let default = |backend: &XdgBackend| {
+            backend
+                .default_application_for(&FileType::parse("text/plain").unwrap())
+                .unwrap()
+                .map(|descriptor| descriptor.id)
+        };
+        // Desktop-specific lists only take precedence over the plain list of the same directory
+        assert_eq!(default(&backend).as_deref(), Some("gedit"));
+        fs::write(dir.join(".config/kde-mimeapps.list"), default_for("kate")).unwrap();
+        assert_eq!(default(&backend).as_deref(), Some("kate"));
+        let backend = backend.current_desktops(["GNOME"]);
+        assert_eq!(default(&backend).as_deref(), Some("gedit"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_run() {
+        let dir = temporary_dir("dry-run");
+        let backend = temporary_backend(&dir);
+        let plain = FileType::parse("text/plain").unwrap();
+        backend
+            .create(
+                "/usr/bin/old",
+                &ApplicationDescriptor {
+                    id: String::from("org.example.Old"),
+                    name: String::from("Old"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        backend.associate("org.example.Old", &plain, true).unwrap();
+        let desktop_file = dir.join("data/applications/org.example.Test.desktop");
+        let mimeapps_list = dir.join("config/mimeapps.list");
+        let mimeapps = fs::read_to_string(&mimeapps_list).unwrap();
+
+        let options = crate::app::OpenOptions::new("/usr/bin/test")
+            .write(true)
+            .dry_run(true)
+            .backend(backend);
+        let app = options
+            .clone()
+            .create(ApplicationDescriptor {
+                id: String::from("org.example.Test"),
+                name: String::from("Test"),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            app.set_file_type_association(&plain, true)
+                .unwrap()
+                .as_deref(),
+            Some("org.example.Old")
+        );
+        let plan = app.plan().unwrap().clone();
+        app.delete().unwrap();
+        assert!(!desktop_file.exists());
+        assert_eq!(fs::read_to_string(&mimeapps_list).unwrap(), mimeapps);
+
+        let changes = plan.changes();
+        let targets: Vec<&Target> = changes.iter().map(|change| &change.target).collect();
+        assert_eq!(
+            targets,
+            [
+                &Target::File(desktop_file.clone()),
+                &Target::File(mimeapps_list.clone()),
+                &Target::File(desktop_file.clone()),
+                &Target::File(mimeapps_list.clone()),
+                &Target::File(desktop_file.clone()),
+            ]
+        );
+        assert_eq!(changes[0].before, None);
+        assert_eq!(changes[1].before, Some(Value::Text(mimeapps.clone())));
+        assert_eq!(changes[3].after, Some(Value::Text(mimeapps)));
+        assert_eq!(changes[4].after, None);
+        let diff = plan.to_string();
+        assert!(diff.contains(&format!("--- /dev/null\n+++ {}\n", desktop_file.display())));
+        assert!(
+            diff.contains(
+                "-text/plain=org.example.Old.desktop;\n+text/plain=org.example.Test.desktop;"
+            ),
+            "{diff}"
+        );
+        assert!(diff.contains("+MimeType=text/plain;\n"), "{diff}");
+
+        // Applications which are already registered are opened in a plan of their own
+        let app = options.get("org.example.Old").unwrap().unwrap();
+        let plan = app.plan().unwrap().clone();
+        app.delete().unwrap();
+        assert!(dir
+            .join("data/applications/org.example.Old.desktop")
+            .exists());
+        assert_eq!(
+            plan.changes()
+                .into_iter()
+                .map(|change| change.target)
+                .collect::<Vec<_>>(),
+            [
+                Target::File(mimeapps_list),
+                Target::File(dir.join("data/applications/org.example.Old.desktop")),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot() {
+        let dir = temporary_dir("snapshot");
+        let backend = temporary_backend(&dir);
+        let plain = FileType::parse("text/plain").unwrap();
+        let old = ApplicationDescriptor {
+            id: String::from("org.example.Old"),
+            name: String::from("Old"),
+            ..Default::default()
+        };
+        backend.create("/usr/bin/old", &old).unwrap();
+        backend.associate("org.example.Old", &plain, true).unwrap();
+        let mimeapps_list = dir.join("config/mimeapps.list");
+        let mimeapps = fs::read(&mimeapps_list).unwrap();
+        let options = crate::app::OpenOptions::new("/usr/bin/test")
+            .read(true)
+            .write(true)
+            .backend(backend.clone());
+        let before = options
+            .clone()
+            .snapshot(["org.example.Test"], ["text/plain"])
+            .unwrap();
+        let before = Snapshot::parse(&before.to_string()).unwrap();
+
+        let app = options
+            .clone()
+            .create(ApplicationDescriptor {
+                id: String::from("org.example.Test"),
+                name: String::from("Test"),
+                ..Default::default()
+            })
+            .unwrap();
+        app.set_file_type_association("text/plain", true).unwrap();
+        app.add_associations(["text/csv"]).unwrap();
+        app.register_mime_types(&MimePackage::new().mime_type(MimeTypeDefinition::new(
+            MimeType::parse("application/x-fileext-test").unwrap(),
+            "Test document",
+        )))
+        .unwrap();
+        let after = options
+            .clone()
+            .snapshot(["org.example.Test"], ["text/plain"])
+            .unwrap();
+        let desktop_file = dir.join("data/applications/org.example.Test.desktop");
+        let installed = fs::read(&desktop_file).unwrap();
+        let associated = fs::read(&mimeapps_list).unwrap();
+        assert!(after.get(&Target::File(desktop_file.clone())).is_some());
+        assert_eq!(
+            after
+                .get(&Target::KeyFileEntry {
+                    path: mimeapps_list.clone(),
+                    group: String::from(ADDED_ASSOCIATIONS_GROUP),
+                    key: String::from("text/csv"),
+                })
+                .unwrap()
+                .value,
+            Some(Value::Text(String::from("org.example.Test.desktop;")))
+        );
+
+        options.clone().restore(&before).unwrap();
+        assert!(!desktop_file.exists());
+        assert!(!dir.join("data/mime/packages/org.example.Test.xml").exists());
+        assert!(backend
+            .mime_database()
+            .unwrap()
+            .mime_types_for_path("file.fileext-test")
+            .is_empty());
+        assert_eq!(
+            backend
+                .default_application_for(&plain)
+                .unwrap()
+                .map(|app| app.id),
+            Some(String::from("org.example.Old"))
+        );
+        assert_eq!(fs::read(&mimeapps_list).unwrap(), mimeapps);
+
+        let mut outside = after.clone();
+        outside.push(Target::File(dir.join("data/../outside")), None);
+        assert!(matches!(
+            options.clone().restore(&outside),
+            Err(Error::Io(why)) if why.kind() == io::ErrorKind::InvalidData
+        ));
+        assert!(!desktop_file.exists());
+
+        options.clone().restore(&after).unwrap();
+        assert_eq!(fs::read(&desktop_file).unwrap(), installed);
+        assert_eq!(fs::read(&mimeapps_list).unwrap(), associated);
+        assert_eq!(
+            backend.enumerate("org.example.Test").unwrap(),
+            vec!["text/plain"]
+        );
+        assert!(matches!(
+            options
+                .read(false)
+                .snapshot(["org.example.Test"], ["text/plain"]),
+            Err(Error::ReadPermissionRequired)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,921 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+//! Validation of desktop entries, checking the rules [`desktop-file-validate`](https://www.freedesktop.org/wiki/Software/desktop-file-utils/) checks.
+//!
+//! Desktop environments silently ignore entries which break the [Desktop Entry Specification](https://specifications.freedesktop.org/desktop-entry-spec/latest/),
+//! so an entry should be validated before it's installed.
+use super::desktop_entry::DesktopEntry;
+use super::key_file::{group_name, is_blank_or_comment, split_entry, split_list, unescape_value};
+use super::{split_exec, DESKTOP_ACTION_GROUP_PREFIX, DESKTOP_ENTRY_GROUP};
+use crate::mime::MimeType;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+
+/// Types of desktop entries.
+pub const TYPES: &[&str] = &["Application", "Link", "Directory"];
+/// Versions of the Desktop Entry Specification.
+pub const VERSIONS: &[&str] = &["1.0", "1.1", "1.2", "1.3", "1.4", "1.5"];
+/// Keys of the `[Desktop Entry]` group registered by the Desktop Entry Specification.
+pub const KEYS: &[&str] = &[
+    "Type",
+    "Version",
+    "Name",
+    "GenericName",
+    "NoDisplay",
+    "Comment",
+    "Icon",
+    "Hidden",
+    "OnlyShowIn",
+    "NotShowIn",
+    "DBusActivatable",
+    "TryExec",
+    "Exec",
+    "Path",
+    "Terminal",
+    "Actions",
+    "MimeType",
+    "Categories",
+    "Implements",
+    "Keywords",
+    "StartupNotify",
+    "StartupWMClass",
+    "URL",
+    "PrefersNonDefaultGPU",
+    "SingleMainWindow",
+];
+/// Keys which used to be part of the Desktop Entry Specification, or were never part of it but used by old desktop environments.
+pub const DEPRECATED_KEYS: &[&str] = &[
+    "Encoding",
+    "MiniIcon",
+    "TerminalOptions",
+    "DocPath",
+    "SwallowTitle",
+    "SwallowExec",
+    "SortOrder",
+    "FilePattern",
+    "Protocols",
+    "Extensions",
+    "BinaryPattern",
+    "MapNotify",
+    "Patterns",
+    "DefaultApp",
+    "Dev",
+    "FSType",
+    "MountPoint",
+    "ReadOnly",
+    "UnmountIcon",
+];
+/// Main categories of the [Desktop Menu Specification](https://specifications.freedesktop.org/menu-spec/latest/category-registry.html).
+pub const MAIN_CATEGORIES: &[&str] = &[
+    "AudioVideo",
+    "Audio",
+    "Video",
+    "Development",
+    "Education",
+    "Game",
+    "Graphics",
+    "Network",
+    "Office",
+    "Science",
+    "Settings",
+    "System",
+    "Utility",
+];
+/// Additional categories of the Desktop Menu Specification.
+pub const ADDITIONAL_CATEGORIES: &[&str] = &[
+    "Building",
+    "Debugger",
+    "IDE",
+    "GUIDesigner",
+    "Profiling",
+    "RevisionControl",
+    "Translation",
+    "Calendar",
+    "ContactManagement",
+    "Database",
+    "Dictionary",
+    "Chart",
+    "Email",
+    "Finance",
+    "FlowChart",
+    "PDA",
+    "ProjectManagement",
+    "Presentation",
+    "Spreadsheet",
+    "WordProcessor",
+    "2DGraphics",
+    "VectorGraphics",
+    "RasterGraphics",
+    "3DGraphics",
+    "Scanning",
+    "OCR",
+    "Photography",
+    "Publishing",
+    "Viewer",
+    "TextTools",
+    "DesktopSettings",
+    "HardwareSettings",
+    "Printing",
+    "PackageManager",
+    "Dialup",
+    "InstantMessaging",
+    "Chat",
+    "IRCClient",
+    "Feed",
+    "FileTransfer",
+    "HamRadio",
+    "News",
+    "P2P",
+    "RemoteAccess",
+    "Telephony",
+    "TelephonyTools",
+    "VideoConference",
+    "WebBrowser",
+    "WebDevelopment",
+    "Midi",
+    "Mixer",
+    "Sequencer",
+    "Tuner",
+    "TV",
+    "AudioVideoEditing",
+    "Player",
+    "Recorder",
+    "DiscBurning",
+    "ActionGame",
+    "AdventureGame",
+    "ArcadeGame",
+    "BoardGame",
+    "BlocksGame",
+    "CardGame",
+    "KidsGame",
+    "LogicGame",
+    "RolePlaying",
+    "Shooter",
+    "Simulation",
+    "SportsGame",
+    "StrategyGame",
+    "Art",
+    "Construction",
+    "Music",
+    "Languages",
+    "ArtificialIntelligence",
+    "Astronomy",
+    "Biology",
+    "Chemistry",
+    "ComputerScience",
+    "DataVisualization",
+    "Economy",
+    "Electricity",
+    "Geography",
+    "Geology",
+    "Geoscience",
+    "History",
+    "Humanities",
+    "ImageProcessing",
+    "Literature",
+    "Maps",
+    "Math",
+    "NumericalAnalysis",
+    "MedicalSoftware",
+    "Physics",
+    "Robotics",
+    "Spirituality",
+    "Sports",
+    "ParallelComputing",
+    "Amusement",
+    "Archiving",
+    "Compression",
+    "Electronics",
+    "Emulator",
+    "Engineering",
+    "FileTools",
+    "FileManager",
+    "TerminalEmulator",
+    "Filesystem",
+    "Monitor",
+    "Security",
+    "Accessibility",
+    "Calculator",
+    "Clock",
+    "TextEditor",
+    "Documentation",
+    "Adult",
+    "Core",
+    "KDE",
+    "GNOME",
+    "XFCE",
+    "DDE",
+    "GTK",
+    "Qt",
+    "Motif",
+    "Java",
+    "ConsoleOnly",
+];
+/// Categories reserved for desktop-specific entries, which may only be used together with `OnlyShowIn`.
+pub const RESERVED_CATEGORIES: &[&str] = &["Screensaver", "TrayIcon", "Applet", "Shell"];
+
+/// Keys whose values are localestrings, the only ones which may have a locale suffix.
+const LOCALIZABLE_KEYS: &[&str] = &["Name", "GenericName", "Comment", "Icon", "Keywords"];
+const BOOLEAN_KEYS: &[&str] = &[
+    "NoDisplay",
+    "Hidden",
+    "DBusActivatable",
+    "Terminal",
+    "StartupNotify",
+    "PrefersNonDefaultGPU",
+    "SingleMainWindow",
+];
+/// Keys which only have a meaning in entries of the `Application` type.
+const APPLICATION_KEYS: &[&str] = &[
+    "DBusActivatable",
+    "TryExec",
+    "Exec",
+    "Path",
+    "Terminal",
+    "Actions",
+    "MimeType",
+    "Categories",
+    "Implements",
+    "Keywords",
+    "StartupNotify",
+    "StartupWMClass",
+    "PrefersNonDefaultGPU",
+    "SingleMainWindow",
+];
+const DEPRECATED_CATEGORIES: &[&str] = &["Application", "Applications"];
+const ACTION_KEYS: &[&str] = &["Name", "Icon", "Exec"];
+
+/// How serious an [Issue] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The entry works, but uses something deprecated or meaningless.
+    Warning,
+    /// The entry breaks the specification, and desktop environments may ignore it.
+    Error,
+}
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// Problem found by [DesktopEntry::validate].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Issue {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// Number of the line the problem is on, starting at 1, or [None] if it's about something missing.
+    pub line: Option<usize>,
+    /// Description of the problem.
+    pub message: String,
+}
+impl Issue {
+    /// Check whether the issue is an [error](Severity::Error).
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Key of a group, as found by [Validator::scan].
+struct Entry<'a> {
+    line: usize,
+    key: &'a str,
+    locale: Option<&'a str>,
+    raw_value: &'a str,
+}
+
+#[derive(Default)]
+struct Validator<'a> {
+    issues: Vec<Issue>,
+    /// Groups with the line of their header and the first occurrence of each of their keys.
+    groups: BTreeMap<&'a str, (usize, Vec<Entry<'a>>)>,
+}
+impl<'a> Validator<'a> {
+    fn report(&mut self, severity: Severity, line: Option<usize>, message: String) {
+        self.issues.push(Issue {
+            severity,
+            line,
+            message,
+        });
+    }
+    fn error(&mut self, line: impl Into<Option<usize>>, message: String) {
+        self.report(Severity::Error, line.into(), message);
+    }
+    fn warning(&mut self, line: impl Into<Option<usize>>, message: String) {
+        self.report(Severity::Warning, line.into(), message);
+    }
+
+    /// Check the syntax of every line, and collect groups and keys.
+    fn scan(&mut self, lines: impl Iterator<Item = &'a str>) {
+        let mut group: Option<&'a str> = None;
+        for (i, line) in lines.enumerate() {
+            let number = i + 1;
+            if is_blank_or_comment(line) {
+                continue;
+            }
+            if let Some(name) = group_name(line) {
+                if name.is_empty() || name.contains(|c: char| c.is_ascii_control()) {
+                    self.error(number, format!("group name \"{name}\" is invalid"));
+                }
+                if self.groups.is_empty() && name != DESKTOP_ENTRY_GROUP {
+                    self.error(
+                        number,
+                        format!("first group must be \"{DESKTOP_ENTRY_GROUP}\", not \"{name}\""),
+                    );
+                }
+                if self.groups.contains_key(name) {
+                    self.error(
+                        number,
+                        format!("file contains multiple groups named \"{name}\""),
+                    );
+                    // Keys of the duplicate aren't looked at, like desktop environments do
+                    group = None;
+                    continue;
+                }
+                self.groups.insert(name, (number, Vec::new()));
+                group = Some(name);
+                continue;
+            }
+            let Some((key, raw_value)) = split_entry(line) else {
+                self.error(
+                    number,
+                    format!("\"{line}\" isn't a group header, a key-value pair or a comment"),
+                );
+                continue;
+            };
+            let (key, locale) = match key.strip_suffix(']').and_then(|key| key.split_once('[')) {
+                Some((key, locale)) => (key, Some(locale)),
+                None => (key, None),
+            };
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                self.error(number, format!("key \"{key}\" contains invalid characters"));
+                continue;
+            }
+            if locale.is_some_and(|locale| locale.is_empty() || locale.contains(['[', ']'])) {
+                self.error(number, format!("locale of key \"{key}\" is invalid"));
+                continue;
+            }
+            match group {
+                Some(name) => {
+                    let keys = &mut self.groups.get_mut(name).expect("group was inserted").1;
+                    if keys
+                        .iter()
+                        .any(|entry| entry.key == key && entry.locale == locale)
+                    {
+                        let full_key = line.split_once('=').map_or(key, |(key, _)| key.trim());
+                        self.error(
+                            number,
+                            format!("key \"{full_key}\" is set multiple times in group \"{name}\""),
+                        );
+                    } else {
+                        keys.push(Entry {
+                            line: number,
+                            key,
+                            locale,
+                            raw_value,
+                        });
+                    }
+                }
+                None if self.groups.is_empty() => {
+                    self.error(number, format!("key \"{key}\" isn't in any group"));
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn entries(&self, group: &str) -> &[Entry<'a>] {
+        self.groups
+            .get(group)
+            .map_or(&[], |(_, entries)| entries.as_slice())
+    }
+    fn find(&self, group: &str, key: &str) -> Option<&Entry<'a>> {
+        self.entries(group)
+            .iter()
+            .find(|entry| entry.key == key && entry.locale.is_none())
+    }
+
+    fn check_main_group(&mut self) {
+        let mut issues = Validator::default();
+        let group = DESKTOP_ENTRY_GROUP;
+        let entry_type = self.find(group, "Type").map(|entry| entry.raw_value);
+
+        for entry in self.entries(group) {
+            let key = entry.key;
+            if key.starts_with("X-") {
+                continue;
+            }
+            if DEPRECATED_KEYS.contains(&key) {
+                issues.warning(entry.line, format!("key \"{key}\" is deprecated"));
+                continue;
+            }
+            if !KEYS.contains(&key) {
+                issues.error(
+                    entry.line,
+                    format!("key \"{key}\" in group \"{group}\" is not registered, extensions need an \"X-\" prefix"),
+                );
+                continue;
+            }
+            check_value(&mut issues, group, entry);
+            if entry_type.is_some_and(|entry_type| entry_type != "Application")
+                && APPLICATION_KEYS.contains(&key)
+            {
+                issues.warning(
+                    entry.line,
+                    format!("key \"{key}\" is only used by entries of type \"Application\""),
+                );
+            }
+            if entry_type.is_some_and(|entry_type| entry_type != "Link") && key == "URL" {
+                issues.warning(
+                    entry.line,
+                    format!("key \"{key}\" is only used by entries of type \"Link\""),
+                );
+            }
+        }
+
+        match self.find(group, "Type") {
+            None => issues.error(
+                None,
+                format!("required key \"Type\" in group \"{group}\" is missing"),
+            ),
+            Some(entry) if !TYPES.contains(&entry.raw_value) => issues.error(
+                entry.line,
+                format!(
+                    "value \"{}\" for key \"Type\" is not a registered type",
+                    entry.raw_value
+                ),
+            ),
+            Some(_) => {}
+        }
+        if self.find(group, "Name").is_none() {
+            issues.error(
+                None,
+                format!("required key \"Name\" in group \"{group}\" is missing"),
+            );
+        }
+        if let Some(entry) = self.find(group, "Version") {
+            if !VERSIONS.contains(&entry.raw_value) {
+                issues.warning(
+                    entry.line,
+                    format!(
+                        "value \"{}\" for key \"Version\" is not a known version",
+                        entry.raw_value
+                    ),
+                );
+            }
+        }
+        let dbus_activatable = self
+            .find(group, "DBusActivatable")
+            .is_some_and(|entry| entry.raw_value == "true");
+        if entry_type == Some("Application")
+            && !dbus_activatable
+            && self.find(group, "Exec").is_none()
+        {
+            issues.error(
+                None,
+                format!("required key \"Exec\" in group \"{group}\" is missing, since the entry isn't \"DBusActivatable\""),
+            );
+        }
+        if entry_type == Some("Link") && self.find(group, "URL").is_none() {
+            issues.error(
+                None,
+                format!("required key \"URL\" in group \"{group}\" is missing"),
+            );
+        }
+        if let (Some(_), Some(entry)) = (
+            self.find(group, "OnlyShowIn"),
+            self.find(group, "NotShowIn"),
+        ) {
+            issues.error(
+                entry.line,
+                String::from("keys \"OnlyShowIn\" and \"NotShowIn\" can't be used together"),
+            );
+        }
+        if let Some(entry) = self.find(group, "Categories") {
+            let only_show_in = self.find(group, "OnlyShowIn").is_some();
+            check_categories(&mut issues, entry, only_show_in);
+        }
+        self.issues.append(&mut issues.issues);
+    }
+
+    fn check_actions(&mut self) {
+        let mut issues = Validator::default();
+        let actions = self
+            .find(DESKTOP_ENTRY_GROUP, "Actions")
+            .map(|entry| (entry.line, split_list(entry.raw_value)));
+        let listed: HashSet<String> = actions
+            .iter()
+            .flat_map(|(_, actions)| actions.iter().cloned())
+            .collect();
+
+        if let Some((line, actions)) = &actions {
+            for action in actions {
+                if !action
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                {
+                    issues.error(
+                        *line,
+                        format!("action name \"{action}\" contains invalid characters"),
+                    );
+                }
+                if !self
+                    .groups
+                    .contains_key(format!("{DESKTOP_ACTION_GROUP_PREFIX}{action}").as_str())
+                {
+                    issues.error(*line, format!("group \"{DESKTOP_ACTION_GROUP_PREFIX}{action}\" of action \"{action}\" is missing"));
+                }
+            }
+        }
+
+        let dbus_activatable = self
+            .find(DESKTOP_ENTRY_GROUP, "DBusActivatable")
+            .is_some_and(|entry| entry.raw_value == "true");
+        for (&group, (line, entries)) in &self.groups {
+            if group == DESKTOP_ENTRY_GROUP || group.starts_with("X-") {
+                continue;
+            }
+            let Some(action) = group.strip_prefix(DESKTOP_ACTION_GROUP_PREFIX) else {
+                issues.error(
+                    *line,
+                    format!(
+                        "group \"{group}\" is not registered, extensions need an \"X-\" prefix"
+                    ),
+                );
+                continue;
+            };
+            if !listed.contains(action) {
+                issues.warning(
+                    *line,
+                    format!("action \"{action}\" isn't listed in key \"Actions\""),
+                );
+            }
+            for entry in entries {
+                if !entry.key.starts_with("X-") && !ACTION_KEYS.contains(&entry.key) {
+                    issues.error(
+                        entry.line,
+                        format!("key \"{}\" in group \"{group}\" is not registered, extensions need an \"X-\" prefix", entry.key),
+                    );
+                    continue;
+                }
+                check_value(&mut issues, group, entry);
+            }
+            if self.find(group, "Name").is_none() {
+                issues.error(
+                    *line,
+                    format!("required key \"Name\" in group \"{group}\" is missing"),
+                );
+            }
+            if !dbus_activatable && self.find(group, "Exec").is_none() {
+                issues.error(
+                    *line,
+                    format!("required key \"Exec\" in group \"{group}\" is missing"),
+                );
+            }
+        }
+        self.issues.append(&mut issues.issues);
+    }
+}
+
+/// Check the value of a registered key.
+fn check_value(issues: &mut Validator, group: &str, entry: &Entry) {
+    let (key, raw_value, line) = (entry.key, entry.raw_value, entry.line);
+    if entry.locale.is_some() && !LOCALIZABLE_KEYS.contains(&key) {
+        issues.error(
+            line,
+            format!("key \"{key}\" in group \"{group}\" can't be localized"),
+        );
+    }
+    if BOOLEAN_KEYS.contains(&key) && !matches!(raw_value, "true" | "false") {
+        issues.error(
+            line,
+            format!("value \"{raw_value}\" for boolean key \"{key}\" in group \"{group}\" isn't \"true\" or \"false\""),
+        );
+    }
+    match key {
+        "Name" | "Exec" | "Type" if raw_value.is_empty() => {
+            issues.error(
+                line,
+                format!("value for key \"{key}\" in group \"{group}\" is empty"),
+            );
+        }
+        "Exec" => {
+            for (severity, message) in check_exec(&unescape_value(raw_value)) {
+                issues.report(
+                    severity,
+                    Some(line),
+                    format!("value for key \"Exec\" in group \"{group}\" {message}"),
+                );
+            }
+        }
+        "MimeType" => {
+            for mime_type in split_list(raw_value) {
+                if MimeType::parse(&mime_type).is_err() || mime_type.contains(['*', ' ']) {
+                    issues.error(
+                        line,
+                        format!(
+                            "value \"{mime_type}\" in key \"MimeType\" is not a valid MIME type"
+                        ),
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_categories(issues: &mut Validator, entry: &Entry, only_show_in: bool) {
+    let categories = split_list(entry.raw_value);
+    for category in &categories {
+        let category = category.as_str();
+        if category.starts_with("X-") {
+            continue;
+        }
+        if DEPRECATED_CATEGORIES.contains(&category) {
+            issues.warning(entry.line, format!("category \"{category}\" is deprecated"));
+        } else if RESERVED_CATEGORIES.contains(&category) {
+            if !only_show_in {
+                issues.error(
+                    entry.line,
+                    format!("reserved category \"{category}\" can only be used together with \"OnlyShowIn\""),
+                );
+            }
+        } else if !MAIN_CATEGORIES.contains(&category) && !ADDITIONAL_CATEGORIES.contains(&category)
+        {
+            issues.error(
+                entry.line,
+                format!(
+                    "category \"{category}\" is not registered, extensions need an \"X-\" prefix"
+                ),
+            );
+        }
+    }
+    if !categories.is_empty()
+        && !categories
+            .iter()
+            .any(|category| MAIN_CATEGORIES.contains(&category.as_str()))
+    {
+        issues.warning(
+            entry.line,
+            String::from("key \"Categories\" doesn't contain a main category"),
+        );
+    }
+}
+
+/// Check quoting and field codes of an unescaped `Exec` value, returning issues with messages to be prefixed by the key.
+fn check_exec(exec: &str) -> Vec<(Severity, String)> {
+    let mut issues = Vec::new();
+    let Some(args) = split_exec(exec) else {
+        issues.push((
+            Severity::Error,
+            String::from("contains an unterminated quote"),
+        ));
+        return issues;
+    };
+    if args.is_empty() {
+        issues.push((Severity::Error, String::from("has no program")));
+    }
+
+    let mut file_codes = 0;
+    for arg in &args {
+        let mut chars = arg.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                continue;
+            }
+            match chars.next() {
+                Some('%' | 'i' | 'c' | 'k') => {}
+                Some('f' | 'u') => file_codes += 1,
+                Some(code @ ('F' | 'U')) => {
+                    file_codes += 1;
+                    if arg.len() != 2 {
+                        issues.push((
+                            Severity::Error,
+                            format!("uses field code \"%{code}\" within an argument, but it must be an argument of its own"),
+                        ));
+                    }
+                }
+                Some(code @ ('d' | 'D' | 'n' | 'N' | 'v' | 'm')) => issues.push((
+                    Severity::Warning,
+                    format!("uses deprecated field code \"%{code}\""),
+                )),
+                Some(code) => issues.push((
+                    Severity::Error,
+                    format!("uses invalid field code \"%{code}\", \"%\" must be written as \"%%\""),
+                )),
+                None => issues.push((
+                    Severity::Error,
+                    String::from("ends with \"%\", which must be written as \"%%\""),
+                )),
+            }
+        }
+    }
+    if file_codes > 1 {
+        issues.push((
+            Severity::Error,
+            String::from("uses more than one of the field codes \"%f\", \"%F\", \"%u\" and \"%U\""),
+        ));
+    }
+    issues
+}
+
+impl DesktopEntry {
+    /// Check the entry against the Desktop Entry Specification, the way `desktop-file-validate` does, returning the problems found
+    /// in the order of the file. The entry is valid if none of them is an [error](Severity::Error).
+    ///
+    /// Checked are the syntax of lines, duplicate groups and keys, required keys, a registered `Type`, registered and deprecated keys,
+    /// boolean values, field codes of `Exec`, the syntax of `MimeType`, registered `Categories` and `[Desktop Action]` groups.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut validator = Validator::default();
+        validator.scan(self.key_file().lines());
+        if !validator.groups.contains_key(DESKTOP_ENTRY_GROUP) {
+            validator.error(
+                None,
+                format!("required group \"{DESKTOP_ENTRY_GROUP}\" is missing"),
+            );
+        } else {
+            validator.check_main_group();
+            validator.check_actions();
+        }
+        validator
+            .issues
+            .sort_by_key(|issue| issue.line.unwrap_or(usize::MAX));
+        validator.issues
+    }
+    /// Check whether [validation](DesktopEntry::validate) finds no errors.
+    pub fn is_valid(&self) -> bool {
+        self.validate().iter().all(|issue| !issue.is_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(contents: &str) -> Vec<String> {
+        DesktopEntry::parse(contents)
+            .validate()
+            .into_iter()
+            .filter(Issue::is_error)
+            .map(|issue| issue.message)
+            .collect()
+    }
+    fn warnings(contents: &str) -> Vec<String> {
+        DesktopEntry::parse(contents)
+            .validate()
+            .into_iter()
+            .filter(|issue| !issue.is_error())
+            .map(|issue| issue.message)
+            .collect()
+    }
+
+    const VALID: &str = "# Comment\n[Desktop Entry]\nType=Application\nVersion=1.5\nName=Text Editor\nName[de]=Texteditor\nExec=editor --new %F\nMimeType=text/plain;x-scheme-handler/editor;\nCategories=Utility;TextEditor;X-Custom;\nActions=new-window;\nX-GNOME-UsesNotifications=true\n\n[Desktop Action new-window]\nName=New Window\nExec=editor --new-window\n\n[X-Custom Group]\nAnything=goes\n";
+
+    #[test]
+    fn valid() {
+        let entry = DesktopEntry::parse(VALID);
+        assert_eq!(entry.validate(), Vec::new());
+        assert!(entry.is_valid());
+    }
+
+    #[test]
+    fn structure() {
+        assert_eq!(
+            errors("Type=Application\n"),
+            vec![
+                "key \"Type\" isn't in any group",
+                "required group \"Desktop Entry\" is missing"
+            ]
+        );
+        let issues = DesktopEntry::parse(
+            "[Other]\n[Desktop Entry]\nType=Application\nName=App\nName=Again\nExec=app\nbroken\n[Desktop Entry]\n",
+        )
+        .validate();
+        let lines: Vec<Option<usize>> = issues.iter().map(|issue| issue.line).collect();
+        assert_eq!(lines, vec![Some(1), Some(1), Some(5), Some(7), Some(8)]);
+        assert!(issues.iter().all(Issue::is_error));
+        assert_eq!(
+            issues[2].to_string(),
+            "line 5: error: key \"Name\" is set multiple times in group \"Desktop Entry\""
+        );
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            errors("[Desktop Entry]\nType=Program\nIcon[de]=app\nComment[de]=Kommentar\nExec[de]=app\nTerminal=yes\nCustom=1\n"),
+            vec![
+                "value \"Program\" for key \"Type\" is not a registered type",
+                "key \"Exec\" in group \"Desktop Entry\" can't be localized",
+                "value \"yes\" for boolean key \"Terminal\" in group \"Desktop Entry\" isn't \"true\" or \"false\"",
+                "key \"Custom\" in group \"Desktop Entry\" is not registered, extensions need an \"X-\" prefix",
+                "required key \"Name\" in group \"Desktop Entry\" is missing",
+            ]
+        );
+        assert_eq!(
+            errors("[Desktop Entry]\nType=Application\nName=App\n"),
+            vec!["required key \"Exec\" in group \"Desktop Entry\" is missing, since the entry isn't \"DBusActivatable\""]
+        );
+        assert_eq!(
+            errors("[Desktop Entry]\nType=Application\nName=App\nDBusActivatable=true\n"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            warnings("[Desktop Entry]\nType=Link\nName=Site\nURL=https://example.org\nEncoding=UTF-8\nMimeType=text/plain;\nVersion=0.9\n"),
+            vec![
+                "key \"Encoding\" is deprecated",
+                "key \"MimeType\" is only used by entries of type \"Application\"",
+                "value \"0.9\" for key \"Version\" is not a known version",
+            ]
+        );
+    }
+
+    #[test]
+    fn values() {
+        let entry = |exec: &str, extra: &str| {
+            format!("[Desktop Entry]\nType=Application\nName=App\nExec={exec}\n{extra}")
+        };
+        assert_eq!(
+            errors(&entry("app %f %U --size=100%", "MimeType=text/plain;text;image/*;\n")),
+            vec![
+                "value for key \"Exec\" in group \"Desktop Entry\" ends with \"%\", which must be written as \"%%\"",
+                "value for key \"Exec\" in group \"Desktop Entry\" uses more than one of the field codes \"%f\", \"%F\", \"%u\" and \"%U\"",
+                "value \"text\" in key \"MimeType\" is not a valid MIME type",
+                "value \"image/*\" in key \"MimeType\" is not a valid MIME type",
+            ]
+        );
+        assert_eq!(
+            errors(&entry("\"app --files=%F", "")),
+            vec![
+                "value for key \"Exec\" in group \"Desktop Entry\" contains an unterminated quote"
+            ]
+        );
+        assert_eq!(
+            errors(&entry("app --files=%F %x", "")),
+            vec![
+                "value for key \"Exec\" in group \"Desktop Entry\" uses field code \"%F\" within an argument, but it must be an argument of its own",
+                "value for key \"Exec\" in group \"Desktop Entry\" uses invalid field code \"%x\", \"%\" must be written as \"%%\"",
+            ]
+        );
+        assert_eq!(
+            warnings(&entry("app %d 100%%", "Categories=Application;TextEditor;\n")),
+            vec![
+                "value for key \"Exec\" in group \"Desktop Entry\" uses deprecated field code \"%d\"",
+                "category \"Application\" is deprecated",
+                "key \"Categories\" doesn't contain a main category",
+            ]
+        );
+        assert_eq!(
+            errors(&entry(
+                "app",
+                "Categories=Utility;Editors;TrayIcon;\nOnlyShowIn=KDE;\nNotShowIn=GNOME;\n"
+            )),
+            vec![
+                "category \"Editors\" is not registered, extensions need an \"X-\" prefix",
+                "keys \"OnlyShowIn\" and \"NotShowIn\" can't be used together",
+            ]
+        );
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(
+            errors("[Desktop Entry]\nType=Application\nName=App\nExec=app\nActions=new;bad name;\n[Desktop Action new]\nIcon=new\nTerminal=false\n[Menu]\n"),
+            vec![
+                "action name \"bad name\" contains invalid characters",
+                "group \"Desktop Action bad name\" of action \"bad name\" is missing",
+                "required key \"Name\" in group \"Desktop Action new\" is missing",
+                "required key \"Exec\" in group \"Desktop Action new\" is missing",
+                "key \"Terminal\" in group \"Desktop Action new\" is not registered, extensions need an \"X-\" prefix",
+                "group \"Menu\" is not registered, extensions need an \"X-\" prefix",
+            ]
+        );
+        assert_eq!(
+            warnings("[Desktop Entry]\nType=Application\nName=App\nExec=app\n[Desktop Action unlisted]\nName=Unlisted\nExec=app\n"),
+            vec!["action \"unlisted\" isn't listed in key \"Actions\""]
+        );
+    }
+}
@@ -13,6 +13,7 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with fileext. If not, see <http://www.gnu.org/licenses/>.
 
+//! Error handling.
 use std::error;
 use std::fmt::Display;
 use std::io;
@@ -26,6 +27,29 @@ pub enum Error {
     WritePermissionRequired,
     /// The executable which the application is trying to get doesn't exist in the filesystem.
     ExecutableDoesntExist,
+    /// The executable of the application exists, but the current user isn't allowed to execute it.
+    ExecutableNotExecutable,
+    /// The application is already registered with another executable, stored in this variant.
+    ExecutableMismatch(String),
+    /// The icon file stored in this variant doesn't exist in the filesystem.
+    IconDoesntExist(String),
+    /// The icon image stored in this variant isn't a PNG or SVG image of a size the icon theme has a directory for.
+    InvalidIcon(String),
+    /// The application ID stored in this variant isn't well-formed.
+    InvalidId(String),
+    /// The command line stored in this variant can't be parsed.
+    InvalidCommand(String),
+    /// The MIME type stored in this variant isn't valid.
+    InvalidMimeType(String),
+    /// The desktop entry generated for the application fails validation, with the errors stored in this variant.
+    InvalidDesktopEntry(Vec<String>),
+    /// The operation isn't supported by the backend or the operating system.
+    Unsupported,
+    /// A [transaction](crate::transaction::Transaction) failed with the first error, and undoing the steps
+    /// which had already run failed with the others, leaving the system partially changed.
+    RollbackFailed(Box<Error>, Vec<Error>),
+    /// The text of a [snapshot](crate::snapshot::Snapshot) is malformed at the line stored in this variant, counting from 1.
+    InvalidSnapshot(usize),
     /// Operating System I/O Error
     Io(io::Error),
 }
@@ -33,21 +57,39 @@ pub enum Error {
 // Display traits
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Error::{ExecutableDoesntExist, Io, ReadPermissionRequired, WritePermissionRequired};
+        use Error::{
+            ExecutableDoesntExist, ExecutableMismatch, ExecutableNotExecutable, IconDoesntExist,
+            InvalidCommand, InvalidDesktopEntry, InvalidIcon, InvalidId, InvalidMimeType,
+            InvalidSnapshot, Io, ReadPermissionRequired, RollbackFailed, Unsupported,
+            WritePermissionRequired,
+        };
 
-        if let Io(why) = self {
-            why.fmt(f)
-        } else {
-            write!(
-                f,
-                "{}",
-                match self {
-                    ReadPermissionRequired => "read permission required",
-                    WritePermissionRequired => "write permission required",
-                    ExecutableDoesntExist => "executable doesn't exist",
-                    _ => unreachable!(),
-                }
-            )
+        match self {
+            ReadPermissionRequired => write!(f, "read permission required"),
+            WritePermissionRequired => write!(f, "write permission required"),
+            ExecutableDoesntExist => write!(f, "executable doesn't exist"),
+            ExecutableNotExecutable => write!(f, "executable isn't executable"),
+            ExecutableMismatch(registered) => {
+                write!(
+                    f,
+                    "application is registered with another executable: {registered}"
+                )
+            }
+            IconDoesntExist(path) => write!(f, "icon doesn't exist: {path}"),
+            InvalidIcon(path) => write!(f, "invalid icon image: {path}"),
+            InvalidId(id) => write!(f, "invalid application ID: {id:?}"),
+            InvalidCommand(command) => write!(f, "invalid command line: {command:?}"),
+            InvalidMimeType(mime_type) => write!(f, "invalid MIME type: {mime_type:?}"),
+            InvalidDesktopEntry(errors) => {
+                write!(f, "invalid desktop entry: {}", errors.join("; "))
+            }
+            InvalidSnapshot(line) => write!(f, "invalid snapshot at line {line}"),
+            Unsupported => write!(f, "operation not supported"),
+            RollbackFailed(error, rollback) => {
+                let rollback: Vec<String> = rollback.iter().map(ToString::to_string).collect();
+                write!(f, "{error}; rollback failed: {}", rollback.join("; "))
+            }
+            Io(why) => why.fmt(f),
         }
     }
 }
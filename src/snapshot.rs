@@ -0,0 +1,354 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Snapshots of application registrations and file type handlers, which can be saved as text and restored later,
+//! e.g. to undo an installation or to reset a machine between test runs.
+//!
+//! A [Snapshot] holds the raw state a backend stores, as the [targets](Target) of a [plan](crate::plan) with their
+//! values: the ProgID keys and the handlers of file extensions on Windows, the desktop entry, icons, MIME package and
+//! `mimeapps.list` entries on Linux. Restoring it puts back every captured value, and removes the targets which
+//! didn't exist when the snapshot was taken.
+use crate::app::FileType;
+use crate::backend::Scope;
+use crate::plan::{Target, Value};
+use crate::result::{Error, Result};
+use std::fmt::{self, Display, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// First line of the [text form](Snapshot::parse) of a snapshot, carrying the version of the format.
+pub const SNAPSHOT_HEADER: &str = "fileext-snapshot 1";
+
+/// [Target] captured in a [Snapshot], with its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// What was captured.
+    pub target: Target,
+    /// Value of the target, [None] if it didn't exist.
+    ///
+    /// A [Target::RegistryKey] with a value only stands for the key existing, its values and subkeys being entries of
+    /// their own. Without a value it stands for the whole tree of the key, which restoring deletes.
+    pub value: Option<Value>,
+}
+
+/// State of applications and file type handlers, taken by [OpenOptions::snapshot](crate::app::OpenOptions::snapshot).
+///
+/// The text form of a snapshot is written by [Display] and read by [Snapshot::parse]. It has a line per item,
+/// whose fields are separated by tabs, with backslashes, tabs and line breaks in them escaped like in Rust strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Scope the snapshot was taken in.
+    pub scope: Scope,
+    /// IDs of the applications whose registrations were captured.
+    pub applications: Vec<String>,
+    /// File types whose handlers were captured.
+    pub file_types: Vec<FileType>,
+    /// Captured targets, in the order they're restored.
+    pub entries: Vec<Entry>,
+}
+impl Snapshot {
+    /// Create an empty snapshot of `scope`.
+    pub fn new(scope: Scope) -> Self {
+        Self {
+            scope,
+            ..Default::default()
+        }
+    }
+    /// Get the entry of a target, if it was captured.
+    pub fn get(&self, target: &Target) -> Option<&Entry> {
+        self.entries.iter().find(|entry| &entry.target == target)
+    }
+    /// Capture a target, unless it's captured already.
+    pub fn push(&mut self, target: Target, value: Option<Value>) {
+        if self.get(&target).is_none() {
+            self.entries.push(Entry { target, value });
+        }
+    }
+
+    /// Parse the text form of a snapshot.
+    ///
+    /// # Errors
+    /// - [Error::InvalidSnapshot] with the number of the first malformed line, counting from 1.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, SNAPSHOT_HEADER)) => (),
+            _ => return Err(Error::InvalidSnapshot(1)),
+        }
+
+        let mut snapshot = Self::default();
+        for (index, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+            parse_line(&mut snapshot, line).ok_or(Error::InvalidSnapshot(index + 1))?;
+        }
+        Ok(snapshot)
+    }
+}
+impl FromStr for Snapshot {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Self::parse(text)
+    }
+}
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{SNAPSHOT_HEADER}")?;
+        writeln!(f, "scope\t{}", self.scope)?;
+        for id in &self.applications {
+            writeln!(f, "application\t{}", escape(id))?;
+        }
+        for file_type in &self.file_types {
+            writeln!(f, "file-type\t{}", escape(&file_type.to_string()))?;
+        }
+        for entry in &self.entries {
+            match &entry.target {
+                Target::File(path) => {
+                    write!(f, "file\t{}", escape(&path.to_string_lossy()))?;
+                }
+                Target::RegistryKey(key) => write!(f, "registry-key\t{}", escape(key))?,
+                Target::RegistryValue { key, name } => {
+                    write!(f, "registry-value\t{}\t{}", escape(key), escape(name))?;
+                }
+                Target::KeyFileEntry { path, group, key } => write!(
+                    f,
+                    "key-file-entry\t{}\t{}\t{}",
+                    escape(&path.to_string_lossy()),
+                    escape(group),
+                    escape(key)
+                )?,
+            }
+            writeln!(f, "\t{}", format_value(entry.value.as_ref()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a line following the header into `snapshot`, returning [None] if it's malformed.
+fn parse_line(snapshot: &mut Snapshot, line: &str) -> Option<()> {
+    let fields = line
+        .split('\t')
+        .map(unescape)
+        .collect::<Option<Vec<String>>>()?;
+    let (kind, fields) = fields.split_first()?;
+    match (kind.as_str(), fields) {
+        ("scope", [scope]) => {
+            snapshot.scope = match scope.as_str() {
+                "user" => Scope::User,
+                "system" => Scope::System,
+                _ => return None,
+            };
+        }
+        ("application", [id]) => snapshot.applications.push(id.clone()),
+        ("file-type", [file_type]) => snapshot.file_types.push(FileType::parse(file_type).ok()?),
+        ("file", [path, value]) => snapshot.entries.push(Entry {
+            target: Target::File(PathBuf::from(path)),
+            value: parse_value(value)?,
+        }),
+        ("registry-key", [key, value]) => snapshot.entries.push(Entry {
+            target: Target::RegistryKey(key.clone()),
+            value: parse_value(value)?,
+        }),
+        ("registry-value", [key, name, value]) => snapshot.entries.push(Entry {
+            target: Target::RegistryValue {
+                key: key.clone(),
+                name: name.clone(),
+            },
+            value: parse_value(value)?,
+        }),
+        ("key-file-entry", [path, group, key, value]) => snapshot.entries.push(Entry {
+            target: Target::KeyFileEntry {
+                path: PathBuf::from(path),
+                group: group.clone(),
+                key: key.clone(),
+            },
+            value: parse_value(value)?,
+        }),
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Format a value as a field: `absent`, `empty`, `text:<text>`, `binary:<hex>` or `typed:<type>:<hex>`.
+fn format_value(value: Option<&Value>) -> String {
+    match value {
+        None => String::from("absent"),
+        Some(Value::Empty) => String::from("empty"),
+        Some(Value::Text(text)) => format!("text:{}", escape(text)),
+        Some(Value::Binary(bytes)) => format!("binary:{}", hex(bytes)),
+        Some(Value::Typed(value_type, bytes)) => format!("typed:{value_type}:{}", hex(bytes)),
+    }
+}
+/// Parse a value [formatted](format_value) as a field, which has been unescaped already.
+fn parse_value(field: &str) -> Option<Option<Value>> {
+    match field {
+        "absent" => return Some(None),
+        "empty" => return Some(Some(Value::Empty)),
+        _ => (),
+    }
+    let value = if let Some(text) = field.strip_prefix("text:") {
+        Value::Text(text.to_string())
+    } else if let Some(bytes) = field.strip_prefix("binary:") {
+        Value::Binary(unhex(bytes)?)
+    } else {
+        let (value_type, bytes) = field.strip_prefix("typed:")?.split_once(':')?;
+        Value::Typed(value_type.parse().ok()?, unhex(bytes)?)
+    };
+    Some(Some(value))
+}
+
+pub(crate) fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+pub(crate) fn unescape(field: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_form() {
+        let mut snapshot = Snapshot::new(Scope::System);
+        snapshot.applications.push(String::from("org.example.Test"));
+        snapshot.file_types.push(FileType::extension("txt"));
+        snapshot
+            .file_types
+            .push(FileType::parse("text/plain").unwrap());
+        snapshot.push(
+            Target::File(PathBuf::from(
+                "/usr/share/applications/org.example.Test.desktop",
+            )),
+            Some(Value::Text(String::from(
+                "[Desktop Entry]\nName=Tab\there\\\n",
+            ))),
+        );
+        snapshot.push(
+            Target::File(PathBuf::from(
+                "/usr/share/mime/packages/org.example.Test.xml",
+            )),
+            None,
+        );
+        snapshot.push(
+            Target::File(PathBuf::from(
+                "/usr/share/mime/packages/org.example.Test.xml",
+            )),
+            Some(Value::Empty),
+        );
+        snapshot.push(
+            Target::KeyFileEntry {
+                path: PathBuf::from("/etc/xdg/mimeapps.list"),
+                group: String::from("Default Applications"),
+                key: String::from("text/plain"),
+            },
+            Some(Value::Text(String::from("org.example.Test.desktop;"))),
+        );
+        snapshot.push(
+            Target::RegistryKey(String::from(r"HKEY_LOCAL_MACHINE\Software\Classes\Test")),
+            Some(Value::Empty),
+        );
+        snapshot.push(
+            Target::RegistryValue {
+                key: String::from(r"HKEY_LOCAL_MACHINE\Software\Classes\Test\DefaultIcon"),
+                name: String::new(),
+            },
+            Some(Value::Typed(2, vec![0x25, 0, 0, 0])),
+        );
+        snapshot.push(
+            Target::RegistryValue {
+                key: String::from(r"HKEY_LOCAL_MACHINE\Software\Classes\Test"),
+                name: String::from("Data"),
+            },
+            Some(Value::Binary(vec![0xde, 0xad])),
+        );
+        assert_eq!(snapshot.entries.len(), 6);
+
+        let text = snapshot.to_string();
+        assert_eq!(
+            text.lines().take(5).collect::<Vec<_>>(),
+            [
+                SNAPSHOT_HEADER,
+                "scope\tsystem",
+                "application\torg.example.Test",
+                "file-type\t.txt",
+                "file-type\ttext/plain",
+            ]
+        );
+        assert!(text.contains(
+            "file\t/usr/share/applications/org.example.Test.desktop\ttext:[Desktop Entry]\\nName=Tab\\there\\\\\\n\n"
+        ));
+        assert!(text.contains("\ttyped:2:25000000\n"));
+        assert_eq!(Snapshot::parse(&text).unwrap(), snapshot);
+
+        assert!(matches!(
+            Snapshot::parse("scope\tuser\n"),
+            Err(Error::InvalidSnapshot(1))
+        ));
+        assert!(matches!(
+            Snapshot::parse(&format!(
+                "{SNAPSHOT_HEADER}\nscope\tuser\nfile\t/a\tbinary:abc\n"
+            )),
+            Err(Error::InvalidSnapshot(3))
+        ));
+        assert!(matches!(
+            format!("{SNAPSHOT_HEADER}\napplication\ta\\q\n").parse::<Snapshot>(),
+            Err(Error::InvalidSnapshot(2))
+        ));
+    }
+}
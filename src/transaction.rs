@@ -0,0 +1,683 @@
+// Copyright (C) 2023 Egor Poleshko
+//
+// fileext is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// fileext is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with fileext. If not, see <http://www.gnu.org/licenses/>.
+
+//! Batches of registry or desktop entry changes which are applied together, or not at all.
+//!
+//! Every step of a [Transaction] reads what it's about to change and records how to undo itself before running,
+//! so that a failing step rolls back the steps which already ran instead of leaving the system half-configured.
+use crate::app::{bitflag_eq, validate_descriptor, ApplicationDescriptor, FileType, APP_STRICT};
+use crate::backend::Backend;
+use crate::mime::MimePackage;
+use crate::plan::Plan;
+use crate::result::{Error, Result};
+use crate::snapshot::Snapshot;
+use std::sync::Arc;
+
+/// Change queued in a [Transaction].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Register an application, overwriting the one with the same ID.
+    Create(ApplicationDescriptor),
+    /// Make an application a handler of a file type, and the default one if `default` is set.
+    Associate {
+        /// ID of the application.
+        id: String,
+        /// File extension or MIME type.
+        file_type: FileType,
+        /// Whether the application becomes the default one.
+        default: bool,
+    },
+    /// Stop an application from handling a file type.
+    Dissociate {
+        /// ID of the application.
+        id: String,
+        /// File extension or MIME type.
+        file_type: FileType,
+    },
+    /// Declare the MIME types of a package.
+    RegisterMimeTypes {
+        /// ID the package is registered under.
+        id: String,
+        /// Package declaring the MIME types.
+        package: MimePackage,
+    },
+    /// Remove the MIME types declared by a package.
+    UnregisterMimeTypes {
+        /// ID the package is registered under.
+        id: String,
+        /// Package declaring the MIME types.
+        package: MimePackage,
+    },
+    /// Unregister an application.
+    Delete(String),
+}
+
+/// Inverse of a step which ran, recorded before running it.
+#[derive(Debug)]
+enum Undo {
+    /// Unregister an application which didn't exist before.
+    Delete(String),
+    /// Put back what a snapshot captured, as it was.
+    Restore(Snapshot),
+    /// Register an application again as it was, together with its associations and the file types it was the default
+    /// one for, for backends which can't take snapshots.
+    Recreate {
+        executable: String,
+        descriptor: ApplicationDescriptor,
+        associations: Vec<(FileType, bool)>,
+    },
+    Associate {
+        id: String,
+        file_type: FileType,
+        default: bool,
+    },
+    Dissociate {
+        id: String,
+        file_type: FileType,
+    },
+    RegisterMimeTypes {
+        id: String,
+        package: MimePackage,
+    },
+    UnregisterMimeTypes {
+        id: String,
+        package: MimePackage,
+    },
+}
+
+/// Queue of [operations](Operation) which are [committed](Transaction::commit) together, created by
+/// [OpenOptions::transaction](crate::app::OpenOptions::transaction).
+///
+/// Applications are registered with the executable of the options, in their [scope](crate::app::OpenOptions::scope).
+///
+/// Undoing goes through the [Backend] like every other change. Applications a step overwrites or deletes, and the
+/// handlers of file types a step associates or dissociates, are captured in a [Snapshot] first and restored from it,
+/// files and registry values included, by backends which can take snapshots. Other backends get back what they can
+/// read: applications, the file types they handle and the ones they open by default, which is put back by making the
+/// previous default application the default one again. Details a backend doesn't expose, like a MIME package replaced
+/// by one with the same ID, aren't restored.
+#[derive(Debug)]
+pub struct Transaction {
+    pub(crate) backend: Arc<dyn Backend>,
+    pub(crate) plan: Option<Plan>,
+    pub(crate) path: String,
+    pub(crate) flags: u32,
+    pub(crate) operations: Vec<Operation>,
+}
+impl Transaction {
+    /// Queue an operation.
+    pub fn push(mut self, operation: Operation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+    /// Queue registering an application.
+    pub fn create(self, descriptor: ApplicationDescriptor) -> Self {
+        self.push(Operation::Create(descriptor))
+    }
+    /// Queue associating a file type with an application, making it the default one if `default` is set.
+    pub fn associate(self, id: impl ToString, file_type: FileType, default: bool) -> Self {
+        self.push(Operation::Associate {
+            id: id.to_string(),
+            file_type,
+            default,
+        })
+    }
+    /// Queue removing the association of a file type with an application.
+    pub fn dissociate(self, id: impl ToString, file_type: FileType) -> Self {
+        self.push(Operation::Dissociate {
+            id: id.to_string(),
+            file_type,
+        })
+    }
+    /// Queue declaring the MIME types of a package registered under `id`.
+    pub fn register_mime_types(self, id: impl ToString, package: MimePackage) -> Self {
+        self.push(Operation::RegisterMimeTypes {
+            id: id.to_string(),
+            package,
+        })
+    }
+    /// Queue removing the MIME types of a package registered under `id`.
+    pub fn unregister_mime_types(self, id: impl ToString, package: MimePackage) -> Self {
+        self.push(Operation::UnregisterMimeTypes {
+            id: id.to_string(),
+            package,
+        })
+    }
+    /// Queue unregistering an application.
+    pub fn delete(self, id: impl ToString) -> Self {
+        self.push(Operation::Delete(id.to_string()))
+    }
+    /// Get the queued operations, in the order they run.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+    /// Get the changes [committing](Transaction::commit) records in [dry-run mode](crate::app::OpenOptions::dry_run),
+    /// [None] if it isn't enabled. A rollback is recorded as well, as the changes undoing the steps which ran.
+    pub fn plan(&self) -> Option<&Plan> {
+        self.plan.as_ref()
+    }
+
+    /// Run the queued operations in order.
+    ///
+    /// If a step fails, the steps which already ran are undone in reverse order, and so is whatever the failing step
+    /// changed before failing, as far as its undo record reaches.
+    ///
+    /// # Errors
+    /// - Any error of [strict mode](crate::app::OpenOptions::strict) validation of the applications to create,
+    ///   if it's enabled. Nothing runs in that case.
+    /// - The error of the failing step, once the transaction has been rolled back.
+    /// - [Error::RollbackFailed] if undoing a step failed as well.
+    pub fn commit(self) -> Result<()> {
+        if bitflag_eq(self.flags, APP_STRICT) {
+            for operation in &self.operations {
+                if let Operation::Create(descriptor) = operation {
+                    validate_descriptor(self.backend.as_ref(), &self.path, descriptor)?;
+                }
+            }
+        }
+
+        let mut steps: Vec<Vec<Undo>> = Vec::with_capacity(self.operations.len());
+        for operation in &self.operations {
+            let mut undo = Vec::new();
+            let result = self.apply(operation, &mut undo);
+            steps.push(undo);
+            if let Err(error) = result {
+                return Err(self.rollback(steps, error));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a single operation, recording the undo records of what it changes in `undo` before changing it.
+    fn apply(&self, operation: &Operation, undo: &mut Vec<Undo>) -> Result<()> {
+        let backend = self.backend.as_ref();
+        match operation {
+            Operation::Create(descriptor) => {
+                undo.push(
+                    self.restore_record(&descriptor.id)?
+                        .unwrap_or_else(|| Undo::Delete(descriptor.id.clone())),
+                );
+                backend.create(&self.path, descriptor)
+            }
+            Operation::Associate {
+                id,
+                file_type,
+                default,
+            } => {
+                let was_associated = backend.handles(id, file_type)?;
+                // Undone last, putting back the previous default application of the file type as well
+                let snapshot = take_snapshot(backend, &[], std::slice::from_ref(file_type))?;
+                let restores = snapshot.is_some();
+                undo.extend(snapshot.map(Undo::Restore));
+                if !was_associated {
+                    undo.push(Undo::Dissociate {
+                        id: id.clone(),
+                        file_type: file_type.clone(),
+                    });
+                }
+                if restores {
+                    return backend.associate(id, file_type, *default).map(drop);
+                }
+                let was_default = backend.is_default_for(id, file_type)?;
+                let previous = backend.associate(id, file_type, *default)?;
+                if *default && !was_default {
+                    match previous {
+                        Some(previous) => undo.push(Undo::Associate {
+                            id: previous,
+                            file_type: file_type.clone(),
+                            default: true,
+                        }),
+                        None if was_associated => {
+                            // Only dissociating takes the default away again
+                            undo.push(Undo::Dissociate {
+                                id: id.clone(),
+                                file_type: file_type.clone(),
+                            });
+                            undo.push(Undo::Associate {
+                                id: id.clone(),
+                                file_type: file_type.clone(),
+                                default: false,
+                            });
+                        }
+                        None => (),
+                    }
+                }
+                Ok(())
+            }
+            Operation::Dissociate { id, file_type } => {
+                // Undone last, putting back the default application of the file type as well
+                let snapshot = take_snapshot(backend, &[], std::slice::from_ref(file_type))?;
+                let restores = snapshot.is_some();
+                undo.extend(snapshot.map(Undo::Restore));
+                if backend.handles(id, file_type)? {
+                    let default = !restores && backend.is_default_for(id, file_type)?;
+                    undo.push(Undo::Associate {
+                        id: id.clone(),
+                        file_type: file_type.clone(),
+                        default,
+                    });
+                }
+                backend.dissociate(id, file_type)
+            }
+            Operation::RegisterMimeTypes { id, package } => {
+                undo.push(Undo::UnregisterMimeTypes {
+                    id: id.clone(),
+                    package: package.clone(),
+                });
+                backend.register_mime_package(id, package)
+            }
+            Operation::UnregisterMimeTypes { id, package } => {
+                undo.push(Undo::RegisterMimeTypes {
+                    id: id.clone(),
+                    package: package.clone(),
+                });
+                backend.unregister_mime_package(id, package)
+            }
+            Operation::Delete(id) => {
+                if let Some(restore) = self.restore_record(id)? {
+                    undo.push(restore);
+                }
+                backend.delete(id)
+            }
+        }
+    }
+
+    /// Record how to put back an application as it is now, returning [None] if it isn't registered.
+    fn restore_record(&self, id: &str) -> Result<Option<Undo>> {
+        let backend = self.backend.as_ref();
+        let Some(descriptor) = backend.get(id)? else {
+            return Ok(None);
+        };
+        if let Some(snapshot) = take_snapshot(backend, &[id.to_string()], &[])? {
+            return Ok(Some(Undo::Restore(snapshot)));
+        }
+        let executable = backend.executable(id)?.unwrap_or_else(|| self.path.clone());
+        let mut associations = Vec::new();
+        for file_type in backend.enumerate(id)? {
+            // File types the backend reports but can't parse back can't be associated again either
+            let Ok(file_type) = FileType::parse(&file_type) else {
+                continue;
+            };
+            let default = backend.is_default_for(id, &file_type)?;
+            associations.push((file_type, default));
+        }
+        Ok(Some(Undo::Recreate {
+            executable,
+            descriptor,
+            associations,
+        }))
+    }
+
+    /// Undo the steps which ran, from the last one to the first one, after `error` stopped the transaction.
+    ///
+    /// The last step is the failing one: undoing it is a best effort, since it may have failed before changing anything.
+    fn rollback(&self, steps: Vec<Vec<Undo>>, error: Error) -> Error {
+        let mut errors = Vec::new();
+        for (i, step) in steps.into_iter().rev().enumerate() {
+            for undo in step {
+                if let Err(why) = self.undo(undo) {
+                    if i > 0 {
+                        errors.push(why);
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            error
+        } else {
+            Error::RollbackFailed(Box::new(error), errors)
+        }
+    }
+    fn undo(&self, undo: Undo) -> Result<()> {
+        let backend = self.backend.as_ref();
+        match undo {
+            Undo::Delete(id) => backend.delete(&id),
+            Undo::Restore(snapshot) => backend.restore(&snapshot),
+            Undo::Recreate {
+                executable,
+                descriptor,
+                associations,
+            } => {
+                backend.create(&executable, &descriptor)?;
+                for (file_type, default) in associations {
+                    backend.associate(&descriptor.id, &file_type, default)?;
+                }
+                Ok(())
+            }
+            Undo::Associate {
+                id,
+                file_type,
+                default,
+            } => backend.associate(&id, &file_type, default).map(drop),
+            Undo::Dissociate { id, file_type } => backend.dissociate(&id, &file_type),
+            Undo::RegisterMimeTypes { id, package } => backend.register_mime_package(&id, &package),
+            Undo::UnregisterMimeTypes { id, package } => {
+                backend.unregister_mime_package(&id, &package)
+            }
+        }
+    }
+}
+
+/// Capture applications and the handlers of file types, returning [None] if the backend can't take snapshots.
+fn take_snapshot(
+    backend: &dyn Backend,
+    ids: &[String],
+    file_types: &[FileType],
+) -> Result<Option<Snapshot>> {
+    match backend.snapshot(ids, file_types) {
+        Ok(snapshot) => Ok(Some(snapshot)),
+        Err(Error::Unsupported) => Ok(None),
+        Err(why) => Err(why),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::OpenOptions;
+    use crate::app::Verb;
+    use crate::backend::MemoryBackend;
+    use crate::command::{Arg, CommandTemplate};
+    use crate::icon::IconImage;
+    use crate::mime::{MimeType, MimeTypeDefinition};
+    use crate::platform::xdg::XdgBackend;
+    use std::{env, fs, io, process};
+
+    /// [MemoryBackend] refusing to associate applications with `.fail` files.
+    #[derive(Debug, Clone)]
+    struct FailingBackend(MemoryBackend);
+    impl Backend for FailingBackend {
+        fn create(&self, executable: &str, descriptor: &ApplicationDescriptor) -> Result<()> {
+            self.0.create(executable, descriptor)
+        }
+        fn get(&self, id: &str) -> Result<Option<ApplicationDescriptor>> {
+            self.0.get(id)
+        }
+        fn executable(&self, id: &str) -> Result<Option<String>> {
+            self.0.executable(id)
+        }
+        fn delete(&self, id: &str) -> Result<()> {
+            self.0.delete(id)
+        }
+        fn enumerate(&self, id: &str) -> Result<Vec<String>> {
+            self.0.enumerate(id)
+        }
+        fn associate(
+            &self,
+            id: &str,
+            file_type: &FileType,
+            default: bool,
+        ) -> Result<Option<String>> {
+            if file_type.to_string() == ".fail" {
+                return Err(io::Error::from(io::ErrorKind::PermissionDenied).into());
+            }
+            self.0.associate(id, file_type, default)
+        }
+        fn dissociate(&self, id: &str, file_type: &FileType) -> Result<()> {
+            self.0.dissociate(id, file_type)
+        }
+        fn register_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+            self.0.register_mime_package(id, package)
+        }
+        fn unregister_mime_package(&self, id: &str, package: &MimePackage) -> Result<()> {
+            self.0.unregister_mime_package(id, package)
+        }
+        fn default_application_for(
+            &self,
+            file_type: &FileType,
+        ) -> Result<Option<ApplicationDescriptor>> {
+            self.0.default_application_for(file_type)
+        }
+    }
+
+    fn descriptor(id: &str) -> ApplicationDescriptor {
+        ApplicationDescriptor {
+            id: String::from(id),
+            name: String::from("fileext crate"),
+            ..Default::default()
+        }
+    }
+    fn file_type(file_type: &str) -> FileType {
+        FileType::parse(file_type).unwrap()
+    }
+
+    #[test]
+    fn commit() {
+        let backend = MemoryBackend::new();
+        OpenOptions::current()
+            .write(true)
+            .backend(backend.clone())
+            .transaction()
+            .unwrap()
+            .create(descriptor("Fileext.Test"))
+            .associate("Fileext.Test", file_type(".txt"), true)
+            .associate("Fileext.Test", file_type(".csv"), false)
+            .commit()
+            .unwrap();
+
+        assert!(backend.application("Fileext.Test").is_some());
+        assert_eq!(
+            backend.default_application(".txt").as_deref(),
+            Some("Fileext.Test")
+        );
+        assert_eq!(backend.enumerate("Fileext.Test").unwrap(), [".csv", ".txt"]);
+        assert!(matches!(
+            OpenOptions::current().backend(backend).transaction(),
+            Err(Error::WritePermissionRequired)
+        ));
+    }
+
+    #[test]
+    fn rollback() {
+        let backend = MemoryBackend::new();
+        let options = OpenOptions::current()
+            .write(true)
+            .backend(FailingBackend(backend.clone()));
+        options
+            .clone()
+            .transaction()
+            .unwrap()
+            .create(descriptor("Other.App"))
+            .associate("Other.App", file_type(".txt"), true)
+            .associate("Other.App", file_type(".csv"), true)
+            .commit()
+            .unwrap();
+
+        let package = MimePackage::new().mime_type(MimeTypeDefinition::new(
+            MimeType::parse("application/x-fileext-test").unwrap(),
+            "Test document",
+        ));
+        let result = options
+            .transaction()
+            .unwrap()
+            .create(descriptor("Fileext.Test"))
+            .associate("Fileext.Test", file_type(".txt"), true)
+            .register_mime_types("Fileext.Test", package)
+            .dissociate("Other.App", file_type(".csv"))
+            .create(ApplicationDescriptor {
+                name: String::from("Renamed"),
+                ..descriptor("Other.App")
+            })
+            .associate("Fileext.Test", file_type(".fail"), false)
+            .commit();
+
+        assert!(
+            matches!(result, Err(Error::Io(why)) if why.kind() == io::ErrorKind::PermissionDenied)
+        );
+        assert_eq!(backend.application("Fileext.Test"), None);
+        assert_eq!(backend.mime_package("Fileext.Test"), None);
+        assert_eq!(
+            backend.application("Other.App").unwrap().descriptor,
+            descriptor("Other.App")
+        );
+        assert_eq!(
+            backend.default_application(".txt").as_deref(),
+            Some("Other.App")
+        );
+        assert_eq!(
+            backend.default_application(".csv").as_deref(),
+            Some("Other.App")
+        );
+        assert!(!backend
+            .file_type(".txt")
+            .unwrap()
+            .handlers
+            .contains("Fileext.Test"));
+    }
+
+    #[test]
+    fn xdg() {
+        let dir = env::temp_dir().join(format!("fileext-transaction-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let backend = XdgBackend::with_dirs(dir.join("data"), dir.join("config"));
+        let result = OpenOptions::new("/usr/bin/app")
+            .write(true)
+            .backend(backend.clone())
+            .transaction()
+            .unwrap()
+            .create(descriptor("org.example.Test"))
+            .associate("org.example.Test", file_type("text/plain"), true)
+            // The backend can't resolve extensions without a MIME database
+            .associate("org.example.Test", file_type(".unknown"), false)
+            .commit();
+
+        assert!(matches!(result, Err(Error::Unsupported)));
+        assert_eq!(backend.desktop_file("org.example.Test"), None);
+        assert_eq!(
+            backend
+                .default_application_for(&file_type("text/plain"))
+                .unwrap(),
+            None
+        );
+
+        // Deleted applications get back the files the backend can't read back into a descriptor
+        fs::write(dir.join("icon.svg"), "<svg/>").unwrap();
+        let options = OpenOptions::new("/usr/bin/app")
+            .write(true)
+            .backend(backend.clone());
+        options
+            .clone()
+            .transaction()
+            .unwrap()
+            .create(ApplicationDescriptor {
+                icon_images: vec![IconImage::svg(dir.join("icon.svg"))],
+                verbs: vec![Verb {
+                    name: String::from("edit"),
+                    label: String::from("Edit"),
+                    command: CommandTemplate::new("/usr/bin/app").arg(Arg::File),
+                }],
+                default_verb: Some(String::from("edit")),
+                ..descriptor("org.example.Test")
+            })
+            .associate("org.example.Test", file_type("text/plain"), true)
+            .commit()
+            .unwrap();
+        let desktop_file = backend.desktop_file("org.example.Test").unwrap();
+        let contents = fs::read_to_string(&desktop_file)
+            .unwrap()
+            .replace("Type=Application\n", "Type=Application\nX-Vendor=kept\n");
+        fs::write(&desktop_file, &contents).unwrap();
+        let icon = dir.join("data/icons/hicolor/scalable/apps/org.example.Test.svg");
+        assert!(icon.exists());
+
+        let result = options
+            .transaction()
+            .unwrap()
+            .delete("org.example.Test")
+            .associate("org.example.Other", file_type(".unknown"), false)
+            .commit();
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&desktop_file).unwrap(), contents);
+        assert_eq!(fs::read_to_string(&icon).unwrap(), "<svg/>");
+        assert_eq!(
+            backend
+                .default_application_for(&file_type("text/plain"))
+                .unwrap()
+                .map(|descriptor| descriptor.id)
+                .as_deref(),
+            Some("org.example.Test")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn xdg_file_types() {
+        let dir = env::temp_dir().join(format!("fileext-transaction-types-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("system/applications")).unwrap();
+        fs::create_dir_all(dir.join("system/mime")).unwrap();
+        fs::write(dir.join("system/mime/globs2"), "50:text/plain:*.txt\n").unwrap();
+        let backend = XdgBackend::with_dirs(dir.join("data"), dir.join("config"))
+            .data_dirs([dir.join("system")]);
+        let options = OpenOptions::new("/usr/bin/app")
+            .write(true)
+            .backend(backend.clone());
+        options
+            .clone()
+            .transaction()
+            .unwrap()
+            .create(descriptor("org.example.Test"))
+            .associate("org.example.Test", file_type("text/plain"), false)
+            .commit()
+            .unwrap();
+
+        // Associating by extension finds the existing association
+        let result = options
+            .clone()
+            .transaction()
+            .unwrap()
+            .associate("org.example.Test", file_type(".txt"), false)
+            .associate("org.example.Test", file_type(".unknown"), false)
+            .commit();
+        assert!(matches!(result, Err(Error::Unsupported)));
+        assert_eq!(
+            backend.enumerate("org.example.Test").unwrap(),
+            vec!["text/plain"]
+        );
+
+        // The previous default application lives outside of the scope and is left alone
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(
+            dir.join("system/applications/org.example.System.desktop"),
+            "[Desktop Entry]\nType=Application\nName=System\nExec=system %f\nMimeType=text/plain;\n",
+        )
+        .unwrap();
+        fs::write(
+            backend.mimeapps_list(),
+            "[Default Applications]\ntext/plain=org.example.System.desktop;\n",
+        )
+        .unwrap();
+        let result = options
+            .transaction()
+            .unwrap()
+            .associate("org.example.Test", file_type("text/plain"), true)
+            .associate("org.example.Test", file_type(".unknown"), false)
+            .commit();
+        assert!(matches!(result, Err(Error::Unsupported)));
+        assert_eq!(
+            fs::read_to_string(backend.mimeapps_list()).unwrap(),
+            "[Default Applications]\ntext/plain=org.example.System.desktop;\n"
+        );
+        assert_eq!(
+            backend
+                .default_application_for(&file_type("text/plain"))
+                .unwrap()
+                .map(|descriptor| descriptor.id)
+                .as_deref(),
+            Some("org.example.System")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}